@@ -1,8 +1,13 @@
-use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
 use yaml_rust::Yaml;
 
 use crate::loader::PgColumnDfn;
-use crate::utils::Named;
+#[cfg(feature = "slog")]
+use crate::log_warn;
+use crate::utils::{Enveloped, Named};
 
 impl Named for Column {
     fn get_name(&self) -> String {
@@ -10,6 +15,10 @@ impl Named for Column {
     }
 }
 
+impl Enveloped for Trig {
+    const ENVELOPE: &'static str = "trigger";
+}
+
 impl Named for Trig {
     fn get_name(&self) -> String {
         self.name.clone()
@@ -26,11 +35,27 @@ impl Default for Column {
             description: "".to_string(),
             sql: "".to_string(),
             index: None,
+            identity: None,
+            identity_seq_options: "".to_string(),
+            storage: None,
+            statistics: None,
+            security_label: None,
+            generated_as: None,
+            rename_from: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// the action following the ON UPDATE or ON DELETE clause of a foreign key sql suffix
+fn sql_action(sql: &str, clause: &str) -> Option<FkAction> {
+    let sql = sql.to_uppercase();
+    let sql = sql.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let words: Vec<&str> = sql[sql.find(clause)? + clause.len()..].split_whitespace().take(2).collect();
+    FkAction::parse(&words.join(" ")).or_else(|| words.first().and_then(|w| FkAction::parse(w)))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Column {
     pub name: String,
     #[serde(rename = "type")]
@@ -46,88 +71,429 @@ pub struct Column {
     pub sql: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub index: Option<Index>,
+    /// GENERATED ... AS IDENTITY instead of serial
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<IdentityMode>,
+    /// sequence options of the identity, i.e. START 1 INCREMENT 1
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub identity_seq_options: String,
+    /// SET STORAGE strategy, the type default if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<ColumnStorage>,
+    /// SET STATISTICS target for the planner, -1 for the system default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statistics: Option<i16>,
+    /// SECURITY LABEL, requires the security_labels feature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub security_label: Option<SecurityLabel>,
+    /// GENERATED ALWAYS AS (expression) STORED, the column is always nullable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generated_as: Option<String>,
+    /// RENAME COLUMN from this name if the column is not found, but the old one is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rename_from: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityMode {
+    Always,
+    ByDefault,
+}
+
+impl IdentityMode {
+    /// as in yaml or information_schema.columns.identity_generation
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_uppercase().replace('_', " ").as_str() {
+            "ALWAYS" => Some(IdentityMode::Always),
+            "BY DEFAULT" => Some(IdentityMode::ByDefault),
+            _ => None,
+        }
+    }
+
+    pub fn sql(&self) -> &'static str {
+        match self {
+            IdentityMode::Always => "ALWAYS",
+            IdentityMode::ByDefault => "BY DEFAULT",
+        }
+    }
+}
+
+/// storage strategy of a column value
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnStorage {
+    /// inline, uncompressed
+    Plain,
+    /// out-of-line, uncompressed
+    External,
+    /// out-of-line, compressed
+    Extended,
+    /// inline, compressed
+    Main,
+}
+
+impl ColumnStorage {
+    /// as in yaml
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_uppercase().as_str() {
+            "PLAIN" => Some(ColumnStorage::Plain),
+            "EXTERNAL" => Some(ColumnStorage::External),
+            "EXTENDED" => Some(ColumnStorage::Extended),
+            "MAIN" => Some(ColumnStorage::Main),
+            _ => None,
+        }
+    }
+
+    /// pg_attribute.attstorage
+    pub fn code(&self) -> char {
+        match self {
+            ColumnStorage::Plain => 'p',
+            ColumnStorage::External => 'e',
+            ColumnStorage::Extended => 'x',
+            ColumnStorage::Main => 'm',
+        }
+    }
+
+    pub fn sql(&self) -> &'static str {
+        match self {
+            ColumnStorage::Plain => "PLAIN",
+            ColumnStorage::External => "EXTERNAL",
+            ColumnStorage::Extended => "EXTENDED",
+            ColumnStorage::Main => "MAIN",
+        }
+    }
+}
+
+/// SECURITY LABEL FOR provider of a table or column
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecurityLabel {
+    /// label provider, i.e. selinux
+    pub provider: String,
+    pub label: String,
+}
+
+impl SecurityLabel {
+    pub(crate) fn new(input: &Yaml) -> Option<Self> {
+        let provider = crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "provider"));
+        let label = crate::utils::as_str(input, "label", "");
+        if provider.len() > 0 && label.len() > 0 {
+            Some(SecurityLabel { provider, label })
+        } else {
+            None
+        }
+    }
+
+    /// statement on the object, i.e. TABLE schema.table or COLUMN schema.table.column
+    pub fn sql(&self, object: &str) -> String {
+        format!("SECURITY LABEL FOR {} ON {} IS '{}'", self.provider, object, self.label.replace('\'', "''"))
+    }
+}
+
+/// referential action of a foreign key
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FkAction {
+    NoAction,
+    Restrict,
+    Cascade,
+    SetNull,
+    SetDefault,
+}
+
+impl FkAction {
+    /// as in yaml, i.e. set_null or set null, or information_schema.referential_constraints rules
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_uppercase().replace('_', " ").as_str() {
+            "NO ACTION" => Some(FkAction::NoAction),
+            "RESTRICT" => Some(FkAction::Restrict),
+            "CASCADE" => Some(FkAction::Cascade),
+            "SET NULL" => Some(FkAction::SetNull),
+            "SET DEFAULT" => Some(FkAction::SetDefault),
+            _ => None,
+        }
+    }
+
+    pub fn sql(&self) -> &'static str {
+        match self {
+            FkAction::NoAction => "NO ACTION",
+            FkAction::Restrict => "RESTRICT",
+            FkAction::Cascade => "CASCADE",
+            FkAction::SetNull => "SET NULL",
+            FkAction::SetDefault => "SET DEFAULT",
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// MATCH type of a foreign key
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FkMatch {
+    Full,
+    Partial,
+    /// default
+    Simple,
+}
+
+impl FkMatch {
+    /// as in yaml or information_schema.referential_constraints.match_option, where NONE is simple
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_uppercase().as_str() {
+            "FULL" => Some(FkMatch::Full),
+            "PARTIAL" => Some(FkMatch::Partial),
+            "SIMPLE" | "NONE" => Some(FkMatch::Simple),
+            _ => None,
+        }
+    }
+
+    /// nothing for the default simple
+    pub fn sql(&self) -> &'static str {
+        match self {
+            FkMatch::Full => "MATCH FULL",
+            FkMatch::Partial => "MATCH PARTIAL",
+            FkMatch::Simple => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Index {
     #[serde(skip_serializing_if = "String::is_empty")]
     pub name: String,
     #[serde(skip_serializing_if = "String::is_empty")]
     pub sql: String,
+    /// partial index predicate
+    #[serde(rename = "where", skip_serializing_if = "Option::is_none")]
+    pub where_clause: Option<String>,
+    /// expression to index instead of the column, i.e. lower(email)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expr: Option<String>,
+    /// non key columns of a covering index
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// access method, btree if not set, i.e. gin
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub using: String,
+    /// non default operator class of the column, i.e. text_pattern_ops or gin_trgm_ops with using: gin
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub opclass: String,
+    /// sort order of the column: ASC or DESC, ASC if not set
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub order: String,
+    /// nulls sort position: FIRST or LAST, the default is LAST for ASC and FIRST for DESC
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub nulls: String,
+    /// non default collation of the column in the index, i.e. C
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub collate: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tablespace: Option<String>,
+    /// storage parameters of WITH (...), i.e. fillfactor: 90
+    #[serde(rename = "with", skip_serializing_if = "BTreeMap::is_empty")]
+    pub with_options: BTreeMap<String, String>,
+    /// CREATE UNIQUE INDEX
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub unique: bool,
+    /// NULLS NOT DISTINCT of a unique index, requires the pg15 feature
+    #[serde(rename = "nullsNotDistinct", skip_serializing_if = "std::ops::Not::not")]
+    pub nulls_not_distinct: bool,
+    /// CREATE INDEX CONCURRENTLY on an existing table after the migration commit,
+    /// if allow_concurrent_index set
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub concurrently: bool,
+    /// COMMENT ON INDEX
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub description: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Constr {
     #[serde(rename = "primaryKey", skip_serializing_if = "Option::is_none")]
     pub primary_key: Option<bool>,
     pub nullable: bool,
     #[serde(rename = "foreignKey", skip_serializing_if = "Option::is_none")]
     pub foreign_key: Option<ForeignKey>,
+    /// CHECK (expression) on the column
+    #[serde(rename = "check", skip_serializing_if = "Option::is_none")]
+    pub check_expr: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ForeignKey {
     pub references: String,
     //fk_table
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub sql: String, //-- some SQL suffix on new FK create- on delete no action on update no action
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deferrable: Option<bool>,
+    #[serde(rename = "initiallyDeferred", default, skip_serializing_if = "Option::is_none")]
+    pub initially_deferred: Option<bool>,
+    /// fk_{schema}_{table}_{fktable} if not set
+    #[serde(rename = "name", default, skip_serializing_if = "Option::is_none")]
+    pub constraint_name: Option<String>,
+    /// used instead of the actions in sql if set
+    #[serde(rename = "onDelete", default, skip_serializing_if = "Option::is_none")]
+    pub on_delete: Option<FkAction>,
+    #[serde(rename = "onUpdate", default, skip_serializing_if = "Option::is_none")]
+    pub on_update: Option<FkAction>,
+    #[serde(rename = "match", default, skip_serializing_if = "Option::is_none")]
+    pub match_type: Option<FkMatch>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl ForeignKey {
+    /// constraint name as set or generated
+    pub fn name(&self, schema: &str, table: &str) -> String {
+        match &self.constraint_name {
+            Some(name) => name.clone(),
+            None => fk_name(schema, table, &self.references),
+        }
+    }
+
+    /// referenced schema and table, the schema of the column if not set
+    pub fn target(&self, schema: &str) -> (String, String) {
+        match self.references.find('.') {
+            None => (schema.to_string(), self.references.clone()),
+            Some(i) => (self.references[0..i].to_string(), self.references[i + 1..].to_string()),
+        }
+    }
+
+    /// initially deferred implies deferrable
+    #[inline]
+    pub fn is_deferrable(&self) -> bool {
+        self.deferrable.unwrap_or(false) || self.is_initially_deferred()
+    }
+
+    #[inline]
+    pub fn is_initially_deferred(&self) -> bool {
+        self.initially_deferred.unwrap_or(false)
+    }
+
+    /// the actions are set as onUpdate or onDelete
+    #[inline]
+    pub fn has_actions(&self) -> bool {
+        self.on_update.is_some() || self.on_delete.is_some()
+    }
+
+    /// ON UPDATE action as set, as in the sql suffix otherwise, NO ACTION by default
+    pub fn update_action(&self) -> FkAction {
+        if self.has_actions() { self.on_update } else { sql_action(&self.sql, "ON UPDATE") }.unwrap_or(FkAction::NoAction)
+    }
+
+    /// ON DELETE action as set, as in the sql suffix otherwise, NO ACTION by default
+    pub fn delete_action(&self) -> FkAction {
+        if self.has_actions() { self.on_delete } else { sql_action(&self.sql, "ON DELETE") }.unwrap_or(FkAction::NoAction)
+    }
+
+    /// ON UPDATE and ON DELETE if set, the sql suffix otherwise
+    pub fn actions_sql(&self) -> String {
+        if !self.has_actions() {
+            return self.sql.clone();
+        }
+        let mut sql = String::new();
+        if let Some(a) = self.on_update {
+            sql.push_str("ON UPDATE ");
+            sql.push_str(a.sql());
+        }
+        if let Some(a) = self.on_delete {
+            if sql.len() > 0 {
+                sql.push(' ');
+            }
+            sql.push_str("ON DELETE ");
+            sql.push_str(a.sql());
+        }
+        sql
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Trig {
     pub name: String,
     //uniq_name_of_trigger
     pub event: String,
     // before update
-    pub when: String,
-    //  for each row
+    /// FOR EACH ROW or FOR EACH STATEMENT (default)
+    #[serde(default, alias = "when")]
+    pub for_each: String,
+    /// WHEN (expression), compared to the one deparsed by pg_get_triggerdef,
+    /// so a rewritten expression causes the trigger replaced on each run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when_condition: Option<String>,
     pub proc: String,  //  -- p()
+    /// CREATE OR REPLACE TRIGGER, PostgreSQL 14+
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub or_replace: bool,
 }
 
 impl Column {
     pub(crate) fn new(input: &Yaml) -> Self {
         let constraint = &input["constraint"];
         let foreign_key = &constraint["foreignKey"];
-        let references = crate::utils::as_str_esc(foreign_key, "references");
+        let references = crate::utils::sql_qualified_name(crate::utils::as_str_esc(foreign_key, "references"));
         let (foreign_key, fk_set) = if references.len() == 0 {
             (None, false)
         } else {
             (
                 Some(ForeignKey {
                     references,
-                    sql: crate::utils::as_str_esc(foreign_key, "sql"),
+                    sql: crate::utils::as_multiline(foreign_key, "sql"),
+                    deferrable: foreign_key["deferrable"].as_bool(),
+                    initially_deferred: foreign_key["initiallyDeferred"].as_bool(),
+                    constraint_name: Some(crate::utils::safe_sql_name(crate::utils::as_str_esc(foreign_key, "name")))
+                        .filter(|n| n.len() > 0),
+                    on_delete: FkAction::parse(crate::utils::as_str_esc(foreign_key, "onDelete").as_str()),
+                    on_update: FkAction::parse(crate::utils::as_str_esc(foreign_key, "onUpdate").as_str()),
+                    match_type: FkMatch::parse(crate::utils::as_str_esc(foreign_key, "match").as_str()),
                 }),
                 true,
             )
         };
         let primary_key = crate::utils::as_bool(constraint, "primaryKey", false);
         let nullable = crate::utils::as_bool(constraint, "nullable", true);
-        let constraint = if primary_key || !nullable || fk_set {
+        let check_expr = crate::utils::as_str_esc(constraint, "check");
+        let constraint = if primary_key || !nullable || fk_set || check_expr.len() > 0 {
             Some(Constr {
                 primary_key: if primary_key { Some(true) } else { None },
                 nullable,
                 foreign_key,
+                check_expr: if check_expr.len() > 0 { Some(check_expr) } else { None },
             })
         } else {
             None
         };
         let index = &input["index"];
+        let identity_seq_options = crate::utils::as_str_esc(input, "identity_seq_options");
 
         Column {
-            name: crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "name")),
+            name: crate::utils::sql_identifier(crate::utils::as_str_esc(input, "name")),
             column_type: crate::utils::as_str_esc(input, "type"),
             default_value: input["defaultValue"].as_str().map(|s| crate::utils::as_esc(s)),
             description: crate::utils::as_str_esc(input, "description"),
-            sql: crate::utils::as_str_esc(input, "sql"),
+            sql: crate::utils::as_multiline(input, "sql"),
             constraint,
-            index: if index.is_null() {
+            index: if index.is_null() || index.is_badvalue() {
                 None
             } else {
                 Some(Index::new(index))
             },
+            identity: IdentityMode::parse(crate::utils::as_str_esc(input, "identity").as_str()),
+            identity_seq_options: identity_seq_options.trim().trim_start_matches('(').trim_end_matches(')').to_string(),
+            storage: ColumnStorage::parse(crate::utils::as_str_esc(input, "storage").as_str()),
+            statistics: input["statistics"].as_i64().map(|n| n.clamp(i16::MIN as i64, i16::MAX as i64) as i16),
+            security_label: SecurityLabel::new(&input["security_label"]),
+            generated_as: Some(crate::utils::as_str_esc(input, "generated_as"))
+                .map(|g| g.trim().to_string())
+                .filter(|g| g.len() > 0),
+            rename_from: Some(crate::utils::sql_identifier(crate::utils::as_str_esc(input, "rename_from")))
+                .filter(|n| n.len() > 0),
         }
     }
 
+    /// drop the column of a table
+    pub fn drop_sql(schema: &str, table_name: &str, col_name: &str) -> String {
+        format!("ALTER TABLE {}.{} DROP COLUMN IF EXISTS {}", schema, table_name, col_name)
+    }
+
     // #[cfg(test)]
     pub fn newt(name: &str, ctype: &str, primary_key: bool, nullable: bool) -> Self {
         let constraint = if primary_key || !nullable {
@@ -135,6 +501,7 @@ impl Column {
                 primary_key: if primary_key { Some(true) } else { None },
                 nullable,
                 foreign_key: None,
+                check_expr: None,
             })
         } else {
             None
@@ -147,6 +514,13 @@ impl Column {
             sql: "".to_string(),
             constraint,
             index: None,
+            identity: None,
+            identity_seq_options: "".to_string(),
+            storage: None,
+            statistics: None,
+            security_label: None,
+            generated_as: None,
+            rename_from: None,
         }
     }
 
@@ -157,6 +531,36 @@ impl Column {
         }
     }
 
+    /// foreign key constraint name if any
+    pub fn fk_name(&self, schema: &str, table: &str) -> Option<String> {
+        self.constraint.as_ref()
+            .and_then(|c| c.foreign_key.as_ref())
+            .map(|fk| fk.name(schema, table))
+    }
+
+    /// NOT NULL as set in yaml, implied by primary key, identity or serial type,
+    /// a generated column is nullable
+    pub(crate) fn is_not_null(&self) -> bool {
+        self.generated_as.is_none() && self.constraint.as_ref().map_or(false, |c| !c.nullable)
+            || self.is_pk() || self.identity.is_some() || self.is_serial()
+    }
+
+    /// serial types have a sequence default
+    #[inline]
+    pub(crate) fn is_serial(&self) -> bool {
+        self.cast_type() != self.column_type.trim()
+    }
+
+    /// type to cast a text value into, serial types are not castable, so replaced by underlying integer types
+    pub(crate) fn cast_type(&self) -> String {
+        match self.column_type.trim().to_lowercase().as_str() {
+            "serial" | "serial4" => "integer".to_string(),
+            "bigserial" | "serial8" => "bigint".to_string(),
+            "smallserial" | "serial2" => "smallint".to_string(),
+            _ => self.column_type.trim().to_string(),
+        }
+    }
+
     #[inline]
     pub(crate) fn column_def(
         &self,
@@ -177,23 +581,141 @@ impl Column {
                 column_default: self.default_value.clone(),
                 sql: Some(self.sql.trim().into()),
                 pk: c.as_ref().map_or(false, |c| c.primary_key.unwrap_or(false)),
-                nullable: c.as_ref().map_or(true, |c| c.nullable),
+                nullable: self.generated_as.is_some() || c.as_ref().map_or(true, |c| c.nullable),
+                check: c.as_ref().map_or(None, |c| c.check_expr.clone()),
                 fk: c.map_or(None, |cs| cs.foreign_key
                     .map_or(None, |fk| Some((fk.references.trim().into(), fk.sql.trim().into())))),
                 sort_order: 0,
-                column_comment: None,
+                column_comment: if self.description.len() > 0 { Some(self.description.clone()) } else { None },
+                identity: self.identity,
+                identity_seq_options: self.identity_seq_options.clone(),
+                // set after the column created
+                storage: None,
+                statistics_target: None,
+                security_labels: HashMap::new(),
+                generated_as: self.generated_as.clone(),
             })
         }
     }
 }
 
+/// generation expression to compare: no type casts, whitespaces and parentheses around a single name,
+/// i.e. (price * (qty)::numeric) as deparsed by PostgreSQL for price * qty
+pub(crate) fn normalize_generated(expr: &str) -> String {
+    let e: Vec<char> = crate::index::normalize_expr(&crate::loader::normalize_default(expr)).chars().collect();
+    let mut res = String::new();
+    let mut i = 0;
+    while i < e.len() {
+        // not a function call
+        if e[i] == '(' && !res.ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+            if let Some(len) = e[i + 1..].iter().position(|c| *c == ')') {
+                if len > 0 && e[i + 1..i + 1 + len].iter().all(|c| c.is_alphanumeric() || *c == '_' || *c == '.') {
+                    res.extend(&e[i + 1..i + 1 + len]);
+                    i += len + 2;
+                    continue;
+                }
+            }
+        }
+        res.push(e[i]);
+        i += 1;
+    }
+    res
+}
+
+/// column node of yaml, checked for the name, statistics range and generated column conflicts
+impl TryFrom<&Yaml> for Column {
+    type Error = String;
+
+    fn try_from(input: &Yaml) -> Result<Self, Self::Error> {
+        let c = Column::new(input);
+        if c.name.len() == 0 {
+            return Err("no column name set".to_string());
+        }
+        if let Some(n) = c.statistics.filter(|n| !(-1..=10000).contains(n)) {
+            return Err(format!("column {} statistics {} out of range -1 to 10000", c.name, n));
+        }
+        if c.generated_as.is_some() && (c.default_value.is_some() || c.identity.is_some()) {
+            return Err(format!("generated column {} can not have a default or identity", c.name));
+        }
+        if let Some(idx) = &c.index {
+            if !["", "ASC", "DESC"].contains(&idx.order.as_str()) {
+                return Err(format!("column {} index order {} must be ASC or DESC", c.name, idx.order));
+            }
+            if !["", "FIRST", "LAST"].contains(&idx.nulls.as_str()) {
+                return Err(format!("column {} index nulls {} must be FIRST or LAST", c.name, idx.nulls));
+            }
+        }
+        Ok(c)
+    }
+}
+
+/// column definition as loaded from DB
+impl From<&PgColumnDfn> for Column {
+    fn from(pg: &PgColumnDfn) -> Self {
+        let foreign_key = pg.fk.as_ref().map(|(references, sql)| ForeignKey { references: references.clone(), sql: sql.clone(), deferrable: None, initially_deferred: None, constraint_name: None, on_delete: None, on_update: None, match_type: None });
+        let constraint = if pg.pk || !pg.nullable || foreign_key.is_some() || pg.check.is_some() {
+            Some(Constr {
+                primary_key: if pg.pk { Some(true) } else { None },
+                nullable: pg.nullable,
+                foreign_key,
+                check_expr: pg.check.clone(),
+            })
+        } else {
+            None
+        };
+        Column {
+            name: pg.column_name.clone(),
+            column_type: pg.column_type.clone(),
+            default_value: pg.column_default.clone(),
+            constraint,
+            description: pg.column_comment.clone().unwrap_or_default(),
+            // primary key index is unique as well
+            sql: if pg.pk { "".to_string() } else { pg.sql.clone().unwrap_or_default() },
+            index: None,
+            identity: pg.identity,
+            identity_seq_options: "".to_string(),
+            // the default of the type is not known
+            storage: None,
+            statistics: pg.statistics_target.filter(|n| *n >= 0),
+            security_label: pg.security_labels.iter().min()
+                .map(|(provider, label)| SecurityLabel { provider: provider.clone(), label: label.clone() }),
+            generated_as: pg.generated_as.clone(),
+            rename_from: None,
+        }
+    }
+}
+
 impl Trig {
     pub(crate) fn new(input: &Yaml) -> Self {
+        // either a string or a list of events, i.e. [before insert, update]
+        let event = match input["event"].as_vec() {
+            Some(_) => crate::utils::as_vec_str(input, "event").iter()
+                .map(|e| crate::utils::as_esc(e))
+                .filter(|e| e.len() > 0)
+                .collect::<Vec<String>>()
+                .join(" OR "),
+            None => crate::utils::as_str_esc(input, "event"),
+        };
+        let name = crate::utils::sql_identifier(crate::utils::as_str_esc(input, "name"));
+        let mut for_each = crate::utils::as_str_esc(input, "for_each");
+        let mut when_condition = crate::utils::as_str_esc(input, "when_condition");
+        if for_each.len() == 0 && when_condition.len() == 0 {
+            // deprecated: for each row when (expression)
+            let when = crate::utils::as_str_esc(input, "when");
+            if when.len() > 0 {
+                #[cfg(feature = "slog")] log_warn(format!("trigger {}: 'when' is deprecated, use 'for_each' and 'when_condition'", name));
+                let (f, w) = split_when(&when);
+                for_each = f;
+                when_condition = w;
+            }
+        }
         Trig {
-            name: crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "name")),
-            event: crate::utils::as_str_esc(input, "event"),
-            when: crate::utils::as_str_esc(input, "when"),
-            proc: crate::utils::as_str_esc(input, "proc"),
+            name,
+            event,
+            for_each,
+            when_condition: if when_condition.len() > 0 { Some(when_condition) } else { None },
+            proc: crate::utils::as_multiline(input, "proc"),
+            or_replace: crate::utils::as_bool(input, "or_replace", false),
         }
     }
 
@@ -202,21 +724,226 @@ impl Trig {
     pub(crate) fn trig_def(&self, schema: &String, table_name: &String) -> Option<String> {
         // let proc = as_str_esc(input, "proc");
         if self.proc.len() > 0 {
+            let when = match &self.when_condition {
+                Some(w) => format!(" WHEN ({})", w),
+                None => "".to_string(),
+            };
             Some(format!(
-                "CREATE TRIGGER {} {} ON {}.{} {} EXECUTE PROCEDURE {};",
-                self.name, self.event, schema, table_name, self.when, self.proc
+                "CREATE {}TRIGGER {} {} ON {}.{} {}{} EXECUTE PROCEDURE {};",
+                if self.or_replace { "OR REPLACE " } else { "" },
+                self.name, self.event, schema, table_name, self.for_each, when, self.proc
             ))
         } else {
             None
         }
     }
+
+    /// compare to pg_get_triggerdef, ignoring case, spaces, events order and schema qualification
+    pub(crate) fn is_same_def(&self, existing: &str, schema: &String, table_name: &String) -> bool {
+        let for_each = if self.for_each.len() > 0 { self.for_each.clone() } else { "FOR EACH STATEMENT".to_string() };
+        let trig = Trig { event: pg_event_order(&self.event), for_each, ..self.clone() };
+        match trig.trig_def(schema, table_name) {
+            None => true,
+            Some(def) => normalize_trig_def(&def, schema) == normalize_trig_def(existing, schema),
+        }
+    }
+}
+
+/// foreign key name generated on deploy, cut to the identifier length limit
+pub fn fk_name(schema: &str, table: &str, references: &str) -> String {
+    let fk_table = match references.find('.') {
+        None => references,
+        Some(i) => &references[i + 1..],
+    };
+    crate::utils::pg_identifier(format!("fk_{}_{}_{}", schema, crate::utils::name_part(table), crate::utils::name_part(fk_table)))
+}
+
+/// the deprecated merged format: for each row when (expression)
+fn split_when(when: &str) -> (String, String) {
+    let lower = when.to_lowercase();
+    let at = if lower.starts_with("when") { Some(0) } else { lower.find(" when").map(|i| i + 1) };
+    match at {
+        None => (when.trim().to_string(), "".to_string()),
+        Some(i) => {
+            let cond = when[i + 4..].trim();
+            let cond = if cond.starts_with('(') && cond.ends_with(')') { &cond[1..cond.len() - 1] } else { cond };
+            (when[..i].trim().to_string(), cond.trim().to_string())
+        }
+    }
+}
+
+/// events in the order of pg_get_triggerdef: insert, delete, update, truncate
+fn pg_event_order(event: &str) -> String {
+    let event = event.to_lowercase();
+    let mut events: Vec<&str> = event.split(" or ").map(|e| e.trim()).collect();
+    let mut timing = "";
+    if let Some(first) = events.first_mut() {
+        for t in &["before ", "after ", "instead of "] {
+            if first.starts_with(t) {
+                timing = t;
+                *first = first[t.len()..].trim();
+            }
+        }
+    }
+    let rank = |e: &&str| ["insert", "delete", "update", "truncate"].iter().position(|k| e.starts_with(k)).unwrap_or(4);
+    events.sort_by_key(rank);
+    format!("{}{}", timing, events.join(" or "))
+}
+
+fn normalize_trig_def(def: &str, schema: &str) -> String {
+    def.to_lowercase()
+        .replace(['"', '(', ')'], "")
+        .replace(format!("{}.", schema.to_lowercase()).as_str(), "")
+        .replace("public.", "")
+        .replace("create or replace trigger", "create trigger")
+        .replace("execute procedure", "execute function")
+        .trim()
+        .trim_end_matches(';')
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
 }
 
 impl Index {
     pub(crate) fn new(input: &Yaml) -> Self {
+        let where_clause = crate::utils::as_str_esc(input, "where");
+        let expr = crate::utils::as_str_esc(input, "expr");
+        let include = crate::utils::as_vec_str(input, "include").into_iter()
+            .map(crate::utils::sql_identifier)
+            .collect();
+        let tablespace = crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "tablespace"));
         Index {
-            name: crate::utils::as_str_esc(input, "name"),
-            sql: crate::utils::as_str_esc(input, "sql"),
+            name: crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "name")),
+            sql: crate::utils::as_multiline(input, "sql"),
+            where_clause: if where_clause.len() > 0 { Some(where_clause) } else { None },
+            expr: if expr.len() > 0 { Some(expr) } else { None },
+            include,
+            using: crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "using")).to_lowercase(),
+            opclass: crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "opclass")),
+            order: crate::utils::as_str_esc(input, "order").to_uppercase(),
+            nulls: crate::utils::as_str_esc(input, "nulls").to_uppercase(),
+            collate: crate::utils::as_str_esc(input, "collate").replace('"', ""),
+            tablespace: if tablespace.len() > 0 { Some(tablespace) } else { None },
+            with_options: crate::utils::as_storage_params(input, "with"),
+            unique: crate::utils::as_bool(input, "unique", false),
+            nulls_not_distinct: crate::utils::as_bool(input, "nullsNotDistinct", false),
+            concurrently: crate::utils::as_bool(input, "concurrently", false),
+            description: crate::utils::as_str_esc(input, "description"),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, feature = "db"))]
+mod tests {
+    use crate::{load_schema_from_src, parse_yaml_schema};
+
+    #[test]
+    fn test_triggers() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+          triggers:
+            - trigger:
+                name: tg_audit
+                event: [before insert, update, delete]
+                for_each: for each row
+                proc: test_schema.audit()
+                or_replace: true
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        let tg = t.triggers.list.get(0).unwrap();
+        let s = "test_schema".to_string();
+        assert_eq!(tg.trig_def(&s, &t.table_name).unwrap().as_str(),
+                   "CREATE OR REPLACE TRIGGER tg_audit before insert OR update OR delete ON test_schema.test_table for each row EXECUTE PROCEDURE test_schema.audit();");
+        assert!(tg.is_same_def("CREATE TRIGGER tg_audit BEFORE INSERT OR DELETE OR UPDATE ON test_schema.test_table FOR EACH ROW EXECUTE FUNCTION test_schema.audit()", &s, &t.table_name));
+        assert!(!tg.is_same_def("CREATE TRIGGER tg_audit AFTER INSERT OR DELETE OR UPDATE ON test_schema.test_table FOR EACH ROW EXECUTE FUNCTION test_schema.audit()", &s, &t.table_name));
+        assert!(!tg.is_same_def("CREATE TRIGGER tg_audit BEFORE INSERT ON test_schema.test_table FOR EACH ROW EXECUTE FUNCTION test_schema.audit()", &s, &t.table_name));
+    }
+
+    #[test]
+    fn test_trigger_when() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: status
+                type: integer
+          triggers:
+            - trigger:
+                name: tg_status
+                event: after update
+                for_each: for each row
+                when_condition: OLD.status IS DISTINCT FROM NEW.status
+                proc: test_schema.audit()
+            - trigger:
+                name: tg_old
+                event: after update
+                when: for each row when (OLD.status > 0)
+                proc: test_schema.audit()
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        let s = "test_schema".to_string();
+        let tg = t.triggers.list.get(0).unwrap();
+        assert_eq!(tg.trig_def(&s, &t.table_name).unwrap().as_str(),
+                   "CREATE TRIGGER tg_status after update ON test_schema.test_table for each row WHEN (OLD.status IS DISTINCT FROM NEW.status) EXECUTE PROCEDURE test_schema.audit();");
+        assert!(tg.is_same_def("CREATE TRIGGER tg_status AFTER UPDATE ON test_schema.test_table FOR EACH ROW WHEN ((old.status IS DISTINCT FROM new.status)) EXECUTE FUNCTION test_schema.audit()", &s, &t.table_name));
+        let tg = t.triggers.list.get(1).unwrap();
+        assert_eq!(tg.for_each.as_str(), "for each row");
+        assert_eq!(tg.when_condition.as_ref().unwrap().as_str(), "OLD.status > 0");
+    }
+
+    #[test]
+    fn test_fk_name() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema_with_a_rather_long_name
+    tables:
+      - table:
+          tableName: parent_table_with_a_long_name
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+      - table:
+          tableName: child_table_with_a_long_name
+          columns:
+            - column:
+                name: first_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: parent_table_with_a_long_name
+            - column:
+                name: second_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: parent_table_with_a_long_name
+                    name: fk_child_second
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let s = r.list.get(0).unwrap();
+        let t = s.tables.get(&"child_table_with_a_long_name".to_string()).unwrap();
+        let first = t.columns.list.get(0).unwrap().fk_name(&s.schema_name, &t.table_name).unwrap();
+        assert_eq!(first.len(), 63);
+        assert!(first.starts_with("fk_test_schema_with_a_rather_long_name_child_table_"));
+        assert_ne!(first, crate::utils::pg_identifier(format!("{}_x", first)));
+        assert_eq!(t.columns.list.get(1).unwrap().fk_name(&s.schema_name, &t.table_name).unwrap().as_str(), "fk_child_second");
+        assert_eq!(crate::utils::pg_identifier("fk_short".to_string()).as_str(), "fk_short");
+    }
+}
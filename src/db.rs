@@ -0,0 +1,118 @@
+use std::io::Write;
+
+use postgres::types::ToSql;
+use postgres::{Error, Row, Transaction};
+
+/// statements used to load and deploy the schema, run in the migration transaction
+pub trait DbExec {
+    fn query(&mut self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error>;
+
+    fn execute(&mut self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64, Error>;
+
+    fn batch_execute(&mut self, sql: &str) -> Result<(), Error>;
+
+    /// COPY ... FROM STDIN of the data, return count of rows copied
+    fn copy_in(&mut self, sql: &str, data: &[u8]) -> Result<u64, String>;
+}
+
+impl DbExec for Transaction<'_> {
+    #[inline]
+    fn query(&mut self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error> {
+        Transaction::query(self, sql, params)
+    }
+
+    #[inline]
+    fn execute(&mut self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64, Error> {
+        Transaction::execute(self, sql, params)
+    }
+
+    #[inline]
+    fn batch_execute(&mut self, sql: &str) -> Result<(), Error> {
+        Transaction::batch_execute(self, sql)
+    }
+
+    fn copy_in(&mut self, sql: &str, data: &[u8]) -> Result<u64, String> {
+        let mut writer = Transaction::copy_in(self, sql).map_err(|e| e.to_string())?;
+        writer.write_all(data).map_err(|e| e.to_string())?;
+        writer.finish().map_err(|e| e.to_string())
+    }
+}
+
+/// no database behind, for dry run only
+#[cfg(test)]
+pub(crate) struct NoDb;
+
+#[cfg(test)]
+impl DbExec for NoDb {
+    fn query(&mut self, _: &str, _: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error> {
+        Ok(Vec::new())
+    }
+
+    fn execute(&mut self, _: &str, _: &[&(dyn ToSql + Sync)]) -> Result<u64, Error> {
+        Ok(0)
+    }
+
+    fn batch_execute(&mut self, _: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn copy_in(&mut self, _: &str, _: &[u8]) -> Result<u64, String> {
+        Ok(0)
+    }
+}
+
+/// result of a deploy along with the statements passed to its dry run callback, one entry per statement batch
+#[cfg(test)]
+pub(crate) fn dry_run<T>(deploy: impl FnOnce(&dyn Fn(Vec<String>) -> Result<(), String>) -> Result<T, String>) -> Result<(T, Vec<String>), String> {
+    let out = std::cell::RefCell::new(Vec::new());
+    let store = |v: Vec<String>| { out.borrow_mut().extend(v); Ok(()) };
+    let r = deploy(&store)?;
+    Ok((r, out.into_inner()))
+}
+
+/// transaction of a pooled async connection, the statements awaited by block_on of the runtime handle,
+/// so to be used on a blocking thread of the runtime, i.e. in spawn_blocking
+#[cfg(feature = "bb8")]
+pub struct AsyncTransaction<'a, 'b> {
+    tx: &'a tokio_postgres::Transaction<'b>,
+    handle: tokio::runtime::Handle,
+}
+
+#[cfg(feature = "bb8")]
+impl<'a, 'b> AsyncTransaction<'a, 'b> {
+    /// error out of a tokio runtime
+    pub fn new(tx: &'a tokio_postgres::Transaction<'b>) -> Result<Self, String> {
+        let handle = tokio::runtime::Handle::try_current().map_err(|e| e.to_string())?;
+        Ok(AsyncTransaction { tx, handle })
+    }
+}
+
+#[cfg(feature = "bb8")]
+impl DbExec for AsyncTransaction<'_, '_> {
+    fn query(&mut self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error> {
+        let (tx, handle) = (self.tx, &self.handle);
+        handle.block_on(tx.query(sql, params))
+    }
+
+    fn execute(&mut self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64, Error> {
+        let (tx, handle) = (self.tx, &self.handle);
+        handle.block_on(tx.execute(sql, params))
+    }
+
+    fn batch_execute(&mut self, sql: &str) -> Result<(), Error> {
+        let (tx, handle) = (self.tx, &self.handle);
+        handle.block_on(tx.batch_execute(sql))
+    }
+
+    fn copy_in(&mut self, sql: &str, data: &[u8]) -> Result<u64, String> {
+        use futures_util::SinkExt;
+        let (tx, handle) = (self.tx, &self.handle);
+        let data = bytes::Bytes::copy_from_slice(data);
+        handle.block_on(async move {
+            let sink = tx.copy_in::<_, bytes::Bytes>(sql).await.map_err(|e| e.to_string())?;
+            futures_util::pin_mut!(sink);
+            sink.send(data).await.map_err(|e| e.to_string())?;
+            sink.finish().await.map_err(|e| e.to_string())
+        })
+    }
+}
@@ -0,0 +1,598 @@
+use std::fmt;
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::column::Column;
+use crate::index::IndexBuilder;
+use crate::loader::normalize_pg_type;
+use crate::schema::Schema;
+use crate::table::Table;
+use crate::utils::OrderedHashMap;
+
+/// changes to turn one schema definition into another
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct SchemaDiff {
+    pub changes: Vec<SchemaChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum SchemaChange {
+    AddSchema(Schema),
+    DropSchema(String),
+    AlterSchema { name: String, changes: Vec<TableChange> },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum TableChange {
+    AddTable(Table),
+    DropTable(String),
+    AddColumn { table: String, column: Column },
+    DropColumn { table: String, column: String },
+    /// type, default or nullable changed
+    AlterColumn { table: String, before: Column, after: Column },
+    /// foreign key on the column: constraint name, references, SQL suffix
+    AddFk { table: String, column: String, name: String, references: String, sql: String },
+    DropFk { table: String, column: String, name: String, references: String },
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// compare two schema definitions, i.e. the one loaded from DB (before) and the one from yaml (after)
+pub fn compute(before: &OrderedHashMap<Schema>, after: &OrderedHashMap<Schema>) -> SchemaDiff {
+    let mut changes = Vec::new();
+    for s in &after.list {
+        match before.get(&s.schema_name) {
+            None => changes.push(SchemaChange::AddSchema(s.clone())),
+            Some(b) => {
+                let tc = compute_tables(b, s);
+                if tc.len() > 0 {
+                    changes.push(SchemaChange::AlterSchema { name: s.schema_name.clone(), changes: tc });
+                }
+            }
+        }
+    }
+    for s in &before.list {
+        if after.get(&s.schema_name).is_none() {
+            changes.push(SchemaChange::DropSchema(s.schema_name.clone()));
+        }
+    }
+    SchemaDiff { changes }
+}
+
+fn compute_tables(before: &Schema, after: &Schema) -> Vec<TableChange> {
+    let mut changes = Vec::new();
+    for t in &after.tables.list {
+        match before.tables.get(&t.table_name) {
+            None => changes.push(TableChange::AddTable(t.clone())),
+            Some(b) => {
+                for c in &t.columns.list {
+                    match b.columns.get(&c.name) {
+                        None => changes.push(TableChange::AddColumn { table: t.table_name.clone(), column: c.clone() }),
+                        Some(bc) => {
+                            if column_changed(bc, c) {
+                                changes.push(TableChange::AlterColumn { table: t.table_name.clone(), before: bc.clone(), after: c.clone() });
+                            }
+                            let name = |c: &Column, schema: &str| c.fk_name(schema, &t.table_name).unwrap_or_default();
+                            let (bn, n) = (name(bc, &before.schema_name), name(c, &after.schema_name));
+                            match (fk(bc), fk(c)) {
+                                (None, Some((r, sql))) => changes.push(TableChange::AddFk {
+                                    table: t.table_name.clone(), column: c.name.clone(), name: n, references: r, sql,
+                                }),
+                                (Some((r, _)), None) => changes.push(TableChange::DropFk {
+                                    table: t.table_name.clone(), column: c.name.clone(), name: bn, references: r,
+                                }),
+                                (Some((br, _)), Some((r, sql))) => if br != r || bn != n {
+                                    changes.push(TableChange::DropFk { table: t.table_name.clone(), column: c.name.clone(), name: bn, references: br });
+                                    changes.push(TableChange::AddFk { table: t.table_name.clone(), column: c.name.clone(), name: n, references: r, sql });
+                                }
+                                (None, None) => {}
+                            }
+                        }
+                    }
+                }
+                for bc in &b.columns.list {
+                    if t.columns.get(&bc.name).is_none() {
+                        changes.push(TableChange::DropColumn { table: t.table_name.clone(), column: bc.name.clone() });
+                    }
+                }
+            }
+        }
+    }
+    for b in &before.tables.list {
+        if after.tables.get(&b.table_name).is_none() {
+            changes.push(TableChange::DropTable(b.table_name.clone()));
+        }
+    }
+    changes
+}
+
+#[inline]
+fn nullable(c: &Column) -> bool {
+    c.constraint.as_ref().map_or(true, |c| c.nullable)
+}
+
+#[inline]
+fn fk(c: &Column) -> Option<(String, String)> {
+    c.constraint.as_ref()
+        .and_then(|c| c.foreign_key.as_ref())
+        .map(|fk| (fk.references.trim().to_string(), fk.sql.trim().to_string()))
+}
+
+fn column_changed(before: &Column, after: &Column) -> bool {
+    normalize_pg_type(&before.column_type) != normalize_pg_type(&after.column_type)
+        || before.default_value != after.default_value
+        || nullable(before) != nullable(after)
+}
+
+/// full name of the referenced table
+fn references(schema: &str, references: &str) -> String {
+    if references.contains('.') { references.to_string() } else { format!("{}.{}", schema, references) }
+}
+
+fn create_table(schema: &str, t: &Table, sql: &mut Vec<String>, fks: &mut Vec<String>) {
+    let schema_name = schema.to_string();
+    let pks: Vec<&Column> = t.columns.list.iter().filter(|c| c.is_pk()).collect();
+    let mut columns = String::new();
+    for c in &t.columns.list {
+        if columns.len() > 0 {
+            columns.push_str(", ");
+        }
+        match c.column_def(&schema_name, &t.table_name, "") {
+            Ok(cd) => columns.push_str(cd.def(pks.len() > 1).as_str()),
+            Err(_) => columns.push_str(c.name.as_str()),
+        }
+        if let Some((r, s)) = fk(c) {
+            fks.push(add_fk(schema, &t.table_name, &c.name, &c.fk_name(schema, &t.table_name).unwrap_or_default(), &r, &s));
+        }
+    }
+    if pks.len() > 1 {
+        let _ = write!(columns, ", PRIMARY KEY ({})", pks.iter().map(|c| c.name.as_str()).collect::<Vec<&str>>().join(", "));
+    }
+    sql.push(t.create_sql(&schema_name, &columns, false).trim().trim_end_matches(';').to_string());
+}
+
+fn add_fk(schema: &str, table: &str, column: &str, name: &str, refs: &str, suffix: &str) -> String {
+    let mut sql = format!("ALTER TABLE {}.{} ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {}",
+                          schema, table, name, column, references(schema, refs));
+    if suffix.len() > 0 {
+        let _ = write!(sql, " {}", suffix);
+    }
+    sql
+}
+
+/// ordered statements: drop FKs, create, alter, drop, then add FKs
+pub fn to_sql(diff: &SchemaDiff) -> Vec<String> {
+    let mut drop_fks = Vec::new();
+    let mut creates = Vec::new();
+    let mut drops = Vec::new();
+    let mut add_fks = Vec::new();
+    for change in &diff.changes {
+        match change {
+            SchemaChange::AddSchema(s) => {
+                creates.push(format!("CREATE SCHEMA IF NOT EXISTS {}", s.schema_name));
+                for t in &s.tables.list {
+                    create_table(&s.schema_name, t, &mut creates, &mut add_fks);
+                }
+            }
+            SchemaChange::DropSchema(name) => drops.push(format!("DROP SCHEMA {}", name)),
+            SchemaChange::AlterSchema { name, changes } => {
+                for tc in changes {
+                    match tc {
+                        TableChange::AddTable(t) => create_table(name, t, &mut creates, &mut add_fks),
+                        TableChange::DropTable(t) => drops.push(format!("DROP TABLE {}.{}", name, t)),
+                        TableChange::AddColumn { table, column } => {
+                            let def = match column.column_def(name, table, "") {
+                                Ok(cd) => cd.def(false),
+                                Err(_) => column.name.clone(),
+                            };
+                            creates.push(format!("ALTER TABLE {}.{} ADD COLUMN {}", name, table, def));
+                            if let Some((r, s)) = fk(column) {
+                                add_fks.push(add_fk(name, table, &column.name, &column.fk_name(name, table).unwrap_or_default(), &r, &s));
+                            }
+                        }
+                        TableChange::DropColumn { table, column } =>
+                            drops.push(format!("ALTER TABLE {}.{} DROP COLUMN {}", name, table, column)),
+                        TableChange::AlterColumn { table, before, after } => {
+                            let alter = format!("ALTER TABLE {}.{} ALTER COLUMN {}", name, table, after.name);
+                            if normalize_pg_type(&before.column_type) != normalize_pg_type(&after.column_type) {
+                                creates.push(format!("{} TYPE {}", alter, after.column_type));
+                            }
+                            if before.default_value != after.default_value {
+                                creates.push(match &after.default_value {
+                                    Some(d) => format!("{} SET DEFAULT {}", alter, d),
+                                    None => format!("{} DROP DEFAULT", alter),
+                                });
+                            }
+                            if nullable(before) != nullable(after) {
+                                creates.push(format!("{} {} NOT NULL", alter, if nullable(after) { "DROP" } else { "SET" }));
+                            }
+                        }
+                        TableChange::AddFk { table, column, name: fk, references, sql } =>
+                            add_fks.push(add_fk(name, table, column, fk, references, sql)),
+                        TableChange::DropFk { table, name: fk, .. } =>
+                            drop_fks.push(format!("ALTER TABLE {}.{} DROP CONSTRAINT IF EXISTS {}", name, table, fk)),
+                    }
+                }
+            }
+        }
+    }
+    let mut sql = drop_fks;
+    sql.append(&mut creates);
+    sql.append(&mut drops);
+    sql.append(&mut add_fks);
+    sql
+}
+
+/// human readable summary in yaml format
+pub fn to_yaml(diff: &SchemaDiff) -> String {
+    if diff.is_empty() {
+        return "changes: []\n".to_string();
+    }
+    let mut yaml = "changes:\n".to_string();
+    for change in &diff.changes {
+        match change {
+            SchemaChange::AddSchema(s) => {
+                let _ = writeln!(yaml, "  - add_schema: {}", s.schema_name);
+                if s.tables.len() > 0 {
+                    let _ = writeln!(yaml, "    tables:");
+                    for t in &s.tables.list {
+                        let _ = writeln!(yaml, "      - {}", t.table_name);
+                    }
+                }
+            }
+            SchemaChange::DropSchema(name) => {
+                let _ = writeln!(yaml, "  - drop_schema: {}", name);
+            }
+            SchemaChange::AlterSchema { name, changes } => {
+                let _ = writeln!(yaml, "  - alter_schema: {}", name);
+                let _ = writeln!(yaml, "    changes:");
+                for tc in changes {
+                    let _ = match tc {
+                        TableChange::AddTable(t) => writeln!(yaml, "      - add_table: {}", t.table_name),
+                        TableChange::DropTable(t) => writeln!(yaml, "      - drop_table: {}", t),
+                        TableChange::AddColumn { table, column } =>
+                            writeln!(yaml, "      - add_column: {}.{} {}", table, column.name, column.column_type),
+                        TableChange::DropColumn { table, column } =>
+                            writeln!(yaml, "      - drop_column: {}.{}", table, column),
+                        TableChange::AlterColumn { table, before, after } =>
+                            writeln!(yaml, "      - alter_column: {}.{} {} -> {}", table, after.name, before.column_type, after.column_type),
+                        TableChange::AddFk { table, column, references, .. } =>
+                            writeln!(yaml, "      - add_fk: {}.{} -> {}", table, column, references),
+                        TableChange::DropFk { table, column, references, .. } =>
+                            writeln!(yaml, "      - drop_fk: {}.{} -> {}", table, column, references),
+                    };
+                }
+            }
+        }
+    }
+    yaml
+}
+
+/// differences of two schema snapshots, i.e. yaml exports of production and staging
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct SchemaComparison {
+    /// schema.table
+    pub only_in_left: Vec<String>,
+    /// schema.table
+    pub only_in_right: Vec<String>,
+    /// tables in both with differences
+    pub changed: Vec<TableComparison>,
+}
+
+/// differences of a table in both snapshots, a value is empty if missed on the side
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct TableComparison {
+    /// schema.table
+    pub table: String,
+    pub columns_only_in_left: Vec<String>,
+    pub columns_only_in_right: Vec<String>,
+    /// column, left type, right type
+    pub types_changed: Vec<(String, String, String)>,
+    /// column, left referenced table, right referenced table
+    pub fks_changed: Vec<(String, String, String)>,
+    /// index name, left and right CREATE INDEX
+    pub indexes_changed: Vec<(String, String, String)>,
+}
+
+impl SchemaComparison {
+    pub fn is_empty(&self) -> bool {
+        self.only_in_left.is_empty() && self.only_in_right.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl TableComparison {
+    pub fn is_empty(&self) -> bool {
+        self.columns_only_in_left.is_empty() && self.columns_only_in_right.is_empty()
+            && self.types_changed.is_empty() && self.fks_changed.is_empty() && self.indexes_changed.is_empty()
+    }
+}
+
+/// in the diff -u manner: left lines with -, right lines with +, a changed table under @@
+impl fmt::Display for SchemaComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- left")?;
+        writeln!(f, "+++ right")?;
+        for t in &self.only_in_left {
+            writeln!(f, "-table {}", t)?;
+        }
+        for t in &self.only_in_right {
+            writeln!(f, "+table {}", t)?;
+        }
+        for t in &self.changed {
+            writeln!(f, "@@ {} @@", t.table)?;
+            for c in &t.columns_only_in_left {
+                writeln!(f, "-column {}", c)?;
+            }
+            for c in &t.columns_only_in_right {
+                writeln!(f, "+column {}", c)?;
+            }
+            for (c, left, right) in &t.types_changed {
+                writeln!(f, "-column {} {}", c, left)?;
+                writeln!(f, "+column {} {}", c, right)?;
+            }
+            for (c, left, right) in &t.fks_changed {
+                if left.len() > 0 {
+                    writeln!(f, "-foreign key {} references {}", c, left)?;
+                }
+                if right.len() > 0 {
+                    writeln!(f, "+foreign key {} references {}", c, right)?;
+                }
+            }
+            for (_, left, right) in &t.indexes_changed {
+                if left.len() > 0 {
+                    writeln!(f, "-{}", left)?;
+                }
+                if right.len() > 0 {
+                    writeln!(f, "+{}", right)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// compare two schema snapshots without a database: tables, columns, types, foreign keys and indexes
+pub fn compare_schemas(left: &OrderedHashMap<Schema>, right: &OrderedHashMap<Schema>) -> SchemaComparison {
+    let mut res = SchemaComparison::default();
+    let missed = |a: &OrderedHashMap<Schema>, b: &OrderedHashMap<Schema>| -> Vec<String> {
+        a.list.iter()
+            .flat_map(|s| s.tables.list.iter()
+                .filter(move |t| b.get(&s.schema_name).and_then(|bs| bs.tables.get(&t.table_name)).is_none())
+                .map(move |t| format!("{}.{}", s.schema_name, t.table_name)))
+            .collect()
+    };
+    res.only_in_left = missed(left, right);
+    res.only_in_right = missed(right, left);
+    for s in &left.list {
+        for t in &s.tables.list {
+            if let Some(rt) = right.get(&s.schema_name).and_then(|rs| rs.tables.get(&t.table_name)) {
+                let tc = compare_table(&s.schema_name, t, rt);
+                if !tc.is_empty() {
+                    res.changed.push(tc);
+                }
+            }
+        }
+    }
+    res
+}
+
+fn compare_table(schema: &str, left: &Table, right: &Table) -> TableComparison {
+    let mut res = TableComparison { table: format!("{}.{}", schema, left.table_name), ..Default::default() };
+    for c in &left.columns.list {
+        match right.columns.get(&c.name) {
+            None => res.columns_only_in_left.push(c.name.clone()),
+            Some(rc) => {
+                if normalize_pg_type(&c.column_type) != normalize_pg_type(&rc.column_type) {
+                    res.types_changed.push((c.name.clone(), c.column_type.clone(), rc.column_type.clone()));
+                }
+                let refs = |c: &Column| fk(c).map_or("".to_string(), |(r, _)| references(schema, &r));
+                if refs(c) != refs(rc) {
+                    res.fks_changed.push((c.name.clone(), refs(c), refs(rc)));
+                }
+            }
+        }
+    }
+    res.columns_only_in_right = right.columns.list.iter()
+        .filter(|c| left.columns.get(&c.name).is_none())
+        .map(|c| c.name.clone())
+        .collect();
+    let indexes = |t: &Table| -> Vec<(String, String)> {
+        IndexBuilder::new(t).indexes.iter()
+            .map(|i| (i.name.clone(), IndexBuilder::build_create_index_sql(schema, &left.table_name, i)))
+            .collect()
+    };
+    let (li, ri) = (indexes(left), indexes(right));
+    for (name, sql) in &li {
+        match ri.iter().find(|(n, _)| n == name) {
+            None => res.indexes_changed.push((name.clone(), sql.clone(), "".to_string())),
+            Some((_, rsql)) => if rsql != sql {
+                res.indexes_changed.push((name.clone(), sql.clone(), rsql.clone()));
+            }
+        }
+    }
+    for (name, rsql) in &ri {
+        if !li.iter().any(|(n, _)| n == name) {
+            res.indexes_changed.push((name.clone(), "".to_string(), rsql.clone()));
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{load_schema_from_src, parse_yaml_schema};
+    use crate::diff::{compare_schemas, compute, SchemaChange, TableChange, to_sql, to_yaml};
+
+    fn parse(src: &str) -> crate::utils::OrderedHashMap<crate::schema::Schema> {
+        parse_yaml_schema(load_schema_from_src(src.to_string()).unwrap(), "").unwrap()
+    }
+
+    #[test]
+    fn diff_test() {
+        let before = parse(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: users
+          columns:
+            - column:
+                name: id
+                type: integer
+            - column:
+                name: legacy
+                type: text
+"#);
+        let after = parse(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: users
+          columns:
+            - column:
+                name: id
+                type: bigint
+            - column:
+                name: email
+                type: text
+                constraint:
+                  nullable: false
+      - table:
+          tableName: orders
+          columns:
+            - column:
+                name: user_id
+                type: bigint
+                constraint:
+                  foreignKey:
+                    references: users
+"#);
+        let diff = compute(&before, &after);
+        assert_eq!(diff.changes.len(), 1);
+        match &diff.changes[0] {
+            SchemaChange::AlterSchema { name, changes } => {
+                assert_eq!(name.as_str(), "test_schema");
+                assert_eq!(changes.len(), 4);
+                assert!(matches!(changes[0], TableChange::AlterColumn { .. }));
+                assert!(matches!(changes[3], TableChange::AddTable(_)));
+            }
+            _ => panic!("alter schema expected"),
+        }
+        assert_eq!(to_sql(&diff), vec![
+            "ALTER TABLE test_schema.users ALTER COLUMN id TYPE bigint".to_string(),
+            "ALTER TABLE test_schema.users ADD COLUMN email text not null".to_string(),
+            "CREATE TABLE test_schema.orders (user_id bigint)".to_string(),
+            "ALTER TABLE test_schema.users DROP COLUMN legacy".to_string(),
+            "ALTER TABLE test_schema.orders ADD CONSTRAINT fk_test_schema_orders_users FOREIGN KEY (user_id) REFERENCES test_schema.users".to_string(),
+        ]);
+        assert!(to_yaml(&diff).contains("      - add_column: users.email text\n"));
+        assert!(compute(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn diff_fk_name_test() {
+        let schema = |fk: &str| parse(&format!(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: users
+          columns:
+            - column:
+                name: id
+                type: integer
+      - table:
+          tableName: orders
+          columns:
+            - column:
+                name: user_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: users{}
+"#, fk));
+        let generated = schema("");
+        let named = schema("\n                    name: orders_user_fk");
+        assert_eq!(to_sql(&compute(&generated, &named)), vec![
+            "ALTER TABLE test_schema.orders DROP CONSTRAINT IF EXISTS fk_test_schema_orders_users".to_string(),
+            "ALTER TABLE test_schema.orders ADD CONSTRAINT orders_user_fk FOREIGN KEY (user_id) REFERENCES test_schema.users".to_string(),
+        ]);
+        assert_eq!(to_sql(&compute(&named, &generated))[0].as_str(),
+                   "ALTER TABLE test_schema.orders DROP CONSTRAINT IF EXISTS orders_user_fk");
+        let diff = compute(&crate::utils::OrderedHashMap::new(), &named);
+        assert!(to_sql(&diff).last().unwrap().starts_with("ALTER TABLE test_schema.orders ADD CONSTRAINT orders_user_fk "));
+    }
+
+    #[test]
+    fn compare_schemas_test() {
+        let left = parse(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: users
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+            - column:
+                name: legacy
+                type: text
+      - table:
+          tableName: audit
+          columns:
+            - column:
+                name: id
+                type: integer
+"#);
+        let right = parse(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: users
+          columns:
+            - column:
+                name: id
+                type: bigint
+                constraint:
+                  primaryKey: true
+            - column:
+                name: email
+                type: text
+                index:
+                  name: idx_users_email
+      - table:
+          tableName: orders
+          columns:
+            - column:
+                name: user_id
+                type: bigint
+                constraint:
+                  foreignKey:
+                    references: users
+"#);
+        let cmp = compare_schemas(&left, &right);
+        assert_eq!(cmp.only_in_left, vec!["test_schema.audit".to_string()]);
+        assert_eq!(cmp.only_in_right, vec!["test_schema.orders".to_string()]);
+        assert_eq!(cmp.changed.len(), 1);
+        let users = &cmp.changed[0];
+        assert_eq!(users.columns_only_in_left, vec!["legacy".to_string()]);
+        assert_eq!(users.columns_only_in_right, vec!["email".to_string()]);
+        assert_eq!(users.types_changed, vec![("id".to_string(), "integer".to_string(), "bigint".to_string())]);
+        assert_eq!(users.indexes_changed.len(), 1);
+        assert_eq!(cmp.to_string(), "--- left\n+++ right\n-table test_schema.audit\n+table test_schema.orders\n\
+            @@ test_schema.users @@\n-column legacy\n+column email\n-column id integer\n+column id bigint\n\
+            +CREATE INDEX idx_users_email ON test_schema.users (email)\n");
+        assert!(serde_json::to_string(&cmp).unwrap().contains("\"only_in_left\":[\"test_schema.audit\"]"));
+        assert!(compare_schemas(&right, &right).is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use yaml_rust::Yaml;
+
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+
+/// extension to install before any schema object
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YamlExtension {
+    pub name: String,
+    /// public by default
+    pub schema: String,
+}
+
+/// extension name: schema installed in
+pub type InfoExtensionType = HashMap<String, String>;
+
+impl YamlExtension {
+    /// either the name or an object with name and schema
+    pub(crate) fn new(input: &Yaml) -> Self {
+        match input.as_str() {
+            Some(name) => YamlExtension { name: name.trim().to_string(), schema: "public".to_string() },
+            None => YamlExtension {
+                name: crate::utils::as_str_esc(input, "name").trim().to_string(),
+                schema: crate::utils::safe_sql_name(crate::utils::as_str(input, "schema", "public")),
+            },
+        }
+    }
+
+    /// statement to create the extension if not installed yet
+    pub fn deploy_sql(&self, existing: &InfoExtensionType) -> Option<String> {
+        if existing.contains_key(&self.name) {
+            None
+        } else {
+            Some(format!("CREATE EXTENSION IF NOT EXISTS \"{}\" SCHEMA {}", self.name.replace('"', ""), self.schema))
+        }
+    }
+}
+
+/// extensions listed on the top level of the yaml, next to the database
+pub fn parse_yaml_extensions(yaml: &Yaml) -> Result<Vec<YamlExtension>, String> {
+    let mut extensions: Vec<YamlExtension> = Vec::new();
+    if let Some(ee) = yaml["extensions"].as_vec() {
+        for e in ee {
+            let e = YamlExtension::new(e);
+            if e.name.len() == 0 {
+                return Err("empty extension name".to_string());
+            }
+            if extensions.iter().any(|x| x.name == e.name) {
+                return Err(format!("duplicate extension: {}", e.name));
+            }
+            extensions.push(e);
+        }
+    }
+    Ok(extensions)
+}
+
+#[cfg(feature = "db")]
+pub fn load_info_extensions(db: &mut dyn DbExec) -> Result<InfoExtensionType, String> {
+    let mut data = HashMap::new();
+    let result = db.query("SELECT e.extname::text, n.nspname::text FROM pg_extension e JOIN pg_namespace n ON n.oid = e.extnamespace", &[])
+        .map_err(|e| format!("on loading pg_extension: {}", e))?;
+    for r in result {
+        let name: &str = r.get(0);
+        let schema: &str = r.get(1);
+        data.insert(name.to_string(), schema.to_string());
+    }
+    Ok(data)
+}
+
+/// create missing extensions, return count of created
+#[cfg(feature = "db")]
+pub fn deploy_extensions(extensions: &Vec<YamlExtension>, existing: &mut InfoExtensionType, db: &mut dyn DbExec,
+                         dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+    let mut cnt = 0;
+    let mut sql = String::new();
+    for e in extensions {
+        if let Some(stmt) = e.deploy_sql(existing) {
+            cnt += 1;
+            let _ = writeln!(sql, "{};", stmt);
+            existing.insert(e.name.clone(), e.schema.clone());
+        }
+    }
+    if cnt == 0 {
+        return Ok(0);
+    }
+    match dry_run {
+        Some(store) => store(vec![sql]).map(|_| 0),
+        None => {
+            let _ = db.batch_execute(sql.as_str())
+                .map_err(|e| format!("DB execute [{}]: {}", sql, e))?;
+            Ok(cnt)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use yaml_rust::YamlLoader;
+
+    use crate::extension::parse_yaml_extensions;
+
+    #[test]
+    fn extension_test() {
+        let yaml = YamlLoader::load_from_str(r#"
+extensions:
+  - uuid-ossp
+  - name: pgcrypto
+    schema: ext
+database:
+  - schemaName: test_schema
+"#).unwrap().remove(0);
+        let ee = parse_yaml_extensions(&yaml).unwrap();
+        assert_eq!(ee.len(), 2);
+        let mut existing = HashMap::new();
+        assert_eq!(ee[0].deploy_sql(&existing).unwrap().as_str(), "CREATE EXTENSION IF NOT EXISTS \"uuid-ossp\" SCHEMA public");
+        assert_eq!(ee[1].deploy_sql(&existing).unwrap().as_str(), "CREATE EXTENSION IF NOT EXISTS \"pgcrypto\" SCHEMA ext");
+        existing.insert("pgcrypto".to_string(), "ext".to_string());
+        assert!(ee[1].deploy_sql(&existing).is_none());
+    }
+}
@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use yaml_rust::Yaml;
+
+use crate::column::Column;
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+use crate::utils::{Enveloped, Named};
+
+/// foreign server of a wrapper, i.e. postgres_fdw, created after the extensions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YamlServer {
+    pub name: String,
+    /// foreign data wrapper name
+    pub wrapper: String,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub options: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub user_mappings: Vec<YamlUserMapping>,
+}
+
+/// user mapping on the server, user as role name or PUBLIC
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YamlUserMapping {
+    pub user: String,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub options: HashMap<String, String>,
+}
+
+/// foreign server loaded from DB
+#[derive(Debug, Clone, Serialize)]
+pub struct PgServer {
+    pub wrapper: String,
+    pub options: HashMap<String, String>,
+    /// user: options, not visible without privileges on the server
+    pub user_mappings: HashMap<String, Option<HashMap<String, String>>>,
+}
+
+/// server name: server
+pub type InfoServerType = HashMap<String, PgServer>;
+
+/// foreign table defined in yaml, not deployed as a table
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YamlForeignTable {
+    pub name: String,
+    /// the table created in, the enclosing schemaName by default
+    pub schema: String,
+    pub server: String,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub options: HashMap<String, String>,
+    pub columns: Vec<Column>,
+}
+
+/// foreign table loaded from DB
+#[derive(Debug, Clone, Serialize)]
+pub struct PgForeignTable {
+    pub server: String,
+    pub options: HashMap<String, String>,
+    /// column names in order
+    pub columns: Vec<String>,
+}
+
+/// schema.name: foreign table
+pub type InfoForeignTableType = HashMap<String, PgForeignTable>;
+
+impl Enveloped for YamlForeignTable {
+    const ENVELOPE: &'static str = "foreign_table";
+}
+
+impl Named for YamlForeignTable {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl YamlServer {
+    pub(crate) fn new(input: &Yaml) -> Self {
+        YamlServer {
+            name: crate::utils::sql_identifier(crate::utils::as_str_esc(input, "name")),
+            wrapper: crate::utils::sql_identifier(crate::utils::as_str_esc(input, "wrapper")),
+            options: as_options(&input["options"]),
+            user_mappings: input["user_mappings"].as_vec().map_or(Vec::new(), |um| um.iter()
+                .map(|u| YamlUserMapping {
+                    user: crate::utils::safe_sql_name(crate::utils::as_str_esc(u, "user")),
+                    options: as_options(&u["options"]),
+                })
+                .collect()),
+        }
+    }
+
+    /// statements to create the server and user mappings or alter its options comparing to loaded from DB
+    pub fn deploy_sql(&self, existing: &InfoServerType) -> Result<Vec<String>, String> {
+        let mut sql = Vec::new();
+        let pg = existing.get(&self.name);
+        match pg {
+            None => sql.push(format!("CREATE SERVER IF NOT EXISTS {} FOREIGN DATA WRAPPER {}{}",
+                                     self.name, self.wrapper, create_options_sql(&self.options))),
+            Some(pg) => {
+                if pg.wrapper != self.wrapper {
+                    return Err(format!("foreign data wrapper of server {} can not be changed from {} to {}",
+                                       self.name, pg.wrapper, self.wrapper));
+                }
+                if let Some(o) = alter_options_sql(&self.options, &pg.options) {
+                    sql.push(format!("ALTER SERVER {}{}", self.name, o));
+                }
+            }
+        }
+        for um in &self.user_mappings {
+            match pg.and_then(|pg| pg.user_mappings.get(&um.user.to_lowercase())) {
+                None => sql.push(format!("CREATE USER MAPPING IF NOT EXISTS FOR {} SERVER {}{}",
+                                         um.user, self.name, create_options_sql(&um.options))),
+                Some(None) => {}
+                Some(Some(options)) => if let Some(o) = alter_options_sql(&um.options, options) {
+                    sql.push(format!("ALTER USER MAPPING FOR {} SERVER {}{}", um.user, self.name, o));
+                }
+            }
+        }
+        Ok(sql)
+    }
+}
+
+impl YamlForeignTable {
+    pub(crate) fn new(input: &Yaml, schema: &str) -> Self {
+        YamlForeignTable {
+            name: crate::utils::sql_identifier(crate::utils::as_str_esc(input, "name")),
+            schema: crate::utils::safe_sql_name(crate::utils::as_str(input, "schema", schema)),
+            server: crate::utils::sql_identifier(crate::utils::as_str_esc(input, "server")),
+            options: as_options(&input["options"]),
+            columns: input["columns"].as_vec().map_or(Vec::new(), |cc| cc.iter()
+                .map(|c| Column::new(&c["column"]))
+                .collect()),
+        }
+    }
+
+    /// full name as schema.name
+    #[inline]
+    pub fn full_name(&self) -> String {
+        format!("{}.{}", self.schema, self.name)
+    }
+
+    /// statements to create the foreign table or add columns and alter options comparing to loaded from DB,
+    /// recreated on the server change
+    pub fn deploy_sql(&self, existing: &InfoForeignTableType) -> Result<Vec<String>, String> {
+        if self.server.len() == 0 {
+            return Err(format!("no server set on foreign table: {}", self.full_name()));
+        }
+        let mut sql = Vec::new();
+        let full_name = self.full_name();
+        let pg = existing.get(&full_name);
+        if let Some(pg) = pg {
+            if pg.server != self.server {
+                sql.push(format!("DROP FOREIGN TABLE {}", full_name));
+            }
+        }
+        match pg.filter(|pg| pg.server == self.server) {
+            None => {
+                let columns: Vec<String> = self.columns.iter().map(column_sql).collect();
+                sql.push(format!("CREATE FOREIGN TABLE IF NOT EXISTS {} ({}) SERVER {}{}",
+                                 full_name, columns.join(", "), self.server, create_options_sql(&self.options)));
+            }
+            Some(pg) => {
+                for c in &self.columns {
+                    if !pg.columns.contains(&c.name) {
+                        sql.push(format!("ALTER FOREIGN TABLE {} ADD COLUMN {}", full_name, column_sql(c)));
+                    }
+                }
+                if let Some(o) = alter_options_sql(&self.options, &pg.options) {
+                    sql.push(format!("ALTER FOREIGN TABLE {}{}", full_name, o));
+                }
+            }
+        }
+        Ok(sql)
+    }
+}
+
+/// column of the foreign table, no constraints other than NOT NULL and DEFAULT
+fn column_sql(c: &Column) -> String {
+    let mut sql = format!("{} {}", c.name, c.column_type);
+    if let Some(d) = &c.default_value {
+        let _ = write!(sql, " DEFAULT {}", d);
+    }
+    if c.is_not_null() {
+        sql.push_str(" NOT NULL");
+    }
+    sql
+}
+
+/// options as yaml mapping of scalars
+fn as_options(input: &Yaml) -> HashMap<String, String> {
+    let mut options = HashMap::new();
+    if let Some(h) = input.as_hash() {
+        for (k, v) in h {
+            let value = match v {
+                Yaml::String(s) => s.clone(),
+                Yaml::Integer(i) => i.to_string(),
+                Yaml::Real(r) => r.clone(),
+                Yaml::Boolean(b) => b.to_string(),
+                _ => continue,
+            };
+            if let Some(k) = k.as_str() {
+                options.insert(crate::utils::safe_sql_name(k.to_string()), value);
+            }
+        }
+    }
+    options
+}
+
+/// options as loaded from DB, i.e. {host=localhost,port=5432}
+fn parse_options(options: Vec<String>) -> HashMap<String, String> {
+    options.iter()
+        .filter_map(|o| o.split_once('=').map(|(k, v)| (k.to_string(), v.to_string())))
+        .collect()
+}
+
+#[inline]
+fn option_value(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// OPTIONS clause of create, keys in name order
+fn create_options_sql(options: &HashMap<String, String>) -> String {
+    if options.len() == 0 {
+        return "".to_string();
+    }
+    let mut keys: Vec<&String> = options.keys().collect();
+    keys.sort();
+    let oo: Vec<String> = keys.iter().map(|k| format!("{} {}", k, option_value(&options[*k]))).collect();
+    format!(" OPTIONS ({})", oo.join(", "))
+}
+
+/// OPTIONS clause of alter to add, set or drop the options, None if the same
+fn alter_options_sql(options: &HashMap<String, String>, existing: &HashMap<String, String>) -> Option<String> {
+    let mut keys: Vec<&String> = options.keys().chain(existing.keys().filter(|k| !options.contains_key(*k))).collect();
+    keys.sort();
+    let mut oo = Vec::new();
+    for k in keys {
+        match (options.get(k), existing.get(k)) {
+            (Some(v), None) => oo.push(format!("ADD {} {}", k, option_value(v))),
+            (Some(v), Some(pv)) => if v != pv {
+                oo.push(format!("SET {} {}", k, option_value(v)));
+            },
+            (None, Some(_)) => oo.push(format!("DROP {}", k)),
+            (None, None) => {}
+        }
+    }
+    if oo.len() == 0 {
+        None
+    } else {
+        Some(format!(" OPTIONS ({})", oo.join(", ")))
+    }
+}
+
+/// servers listed on the top level of the yaml, next to the database
+pub fn parse_yaml_servers(yaml: &Yaml) -> Result<Vec<YamlServer>, String> {
+    let mut servers: Vec<YamlServer> = Vec::new();
+    if let Some(ss) = yaml["servers"].as_vec() {
+        for s in ss {
+            let s = YamlServer::new(&s["server"]);
+            if s.name.len() == 0 || s.wrapper.len() == 0 {
+                return Err(format!("no name or wrapper set on server: {}", s.name));
+            }
+            if servers.iter().any(|x| x.name == s.name) {
+                return Err(format!("duplicate server: {}", s.name));
+            }
+            servers.push(s);
+        }
+    }
+    Ok(servers)
+}
+
+#[cfg(feature = "db")]
+pub fn load_info_servers(db: &mut dyn DbExec) -> Result<InfoServerType, String> {
+    let mut data = HashMap::new();
+    let result = db.query("SELECT s.srvname::text, w.fdwname::text, coalesce(s.srvoptions, '{}')
+ FROM pg_foreign_server s JOIN pg_foreign_data_wrapper w ON w.oid = s.srvfdw", &[])
+        .map_err(|e| format!("on loading pg_foreign_server: {}", e))?;
+    for r in result {
+        let name: &str = r.get(0);
+        let wrapper: &str = r.get(1);
+        data.insert(crate::utils::quote_if_needed(name), PgServer {
+            wrapper: crate::utils::quote_if_needed(wrapper),
+            options: parse_options(r.get(2)),
+            user_mappings: HashMap::new(),
+        });
+    }
+    let result = db.query("SELECT srvname::text, usename::text, umoptions FROM pg_user_mappings", &[])
+        .map_err(|e| format!("on loading pg_user_mappings: {}", e))?;
+    for r in result {
+        let name: &str = r.get(0);
+        let user: &str = r.get(1);
+        let options: Option<Vec<String>> = r.get(2);
+        if let Some(s) = data.get_mut(&crate::utils::quote_if_needed(name)) {
+            s.user_mappings.insert(user.to_lowercase(), options.map(parse_options));
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(feature = "db")]
+pub fn load_info_foreign_tables(db: &mut dyn DbExec) -> Result<InfoForeignTableType, String> {
+    let mut data = HashMap::new();
+    let result = db.query("SELECT n.nspname::text, c.relname::text, s.srvname::text, coalesce(ft.ftoptions, '{}'),
+ array(SELECT a.attname::text FROM pg_attribute a WHERE a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped ORDER BY a.attnum)
+ FROM pg_foreign_table ft
+ JOIN pg_class c ON c.oid = ft.ftrelid
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ JOIN pg_foreign_server s ON s.oid = ft.ftserver", &[])
+        .map_err(|e| format!("on loading pg_foreign_table: {}", e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = r.get(1);
+        let server: &str = r.get(2);
+        let columns: Vec<String> = r.get(4);
+        data.insert(format!("{}.{}", table_schema, crate::utils::quote_if_needed(table_name)), PgForeignTable {
+            server: crate::utils::quote_if_needed(server),
+            options: parse_options(r.get(3)),
+            columns: columns.iter().map(|c| crate::utils::quote_if_needed(c)).collect(),
+        });
+    }
+    Ok(data)
+}
+
+/// create missing servers and user mappings, alter its options, return count of changed servers
+#[cfg(feature = "db")]
+pub fn deploy_servers(servers: &Vec<YamlServer>, existing: &mut InfoServerType, db: &mut dyn DbExec,
+                      dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+    let mut cnt = 0;
+    let mut sql = String::new();
+    for s in servers {
+        let stmts = s.deploy_sql(existing)?;
+        if stmts.len() > 0 {
+            cnt += 1;
+            for stmt in stmts {
+                let _ = writeln!(sql, "{};", stmt);
+            }
+            existing.insert(s.name.clone(), PgServer {
+                wrapper: s.wrapper.clone(),
+                options: s.options.clone(),
+                user_mappings: s.user_mappings.iter().map(|u| (u.user.to_lowercase(), Some(u.options.clone()))).collect(),
+            });
+        }
+    }
+    if cnt == 0 {
+        return Ok(0);
+    }
+    match dry_run {
+        Some(store) => store(vec![sql]).map(|_| 0),
+        None => {
+            let _ = db.batch_execute(sql.as_str())
+                .map_err(|e| format!("DB execute [{}]: {}", sql, e))?;
+            Ok(cnt)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use yaml_rust::YamlLoader;
+
+    use crate::foreign::{parse_yaml_servers, PgForeignTable, PgServer, YamlForeignTable};
+
+    #[test]
+    fn server_test() {
+        let yaml = YamlLoader::load_from_str(r#"
+servers:
+  - server:
+      name: remote
+      wrapper: postgres_fdw
+      options:
+        host: db.local
+        port: 5432
+      user_mappings:
+        - user: public
+          options:
+            user: reader
+database:
+  - schemaName: test_schema
+"#).unwrap().remove(0);
+        let ss = parse_yaml_servers(&yaml).unwrap();
+        assert_eq!(ss.len(), 1);
+        let mut existing = HashMap::new();
+        assert_eq!(ss[0].deploy_sql(&existing).unwrap(), vec![
+            "CREATE SERVER IF NOT EXISTS remote FOREIGN DATA WRAPPER postgres_fdw OPTIONS (host 'db.local', port '5432')".to_string(),
+            "CREATE USER MAPPING IF NOT EXISTS FOR public SERVER remote OPTIONS (user 'reader')".to_string(),
+        ]);
+        existing.insert("remote".to_string(), PgServer {
+            wrapper: "postgres_fdw".to_string(),
+            options: HashMap::from([("host".to_string(), "db.old".to_string()), ("dbname".to_string(), "x".to_string())]),
+            user_mappings: HashMap::from([("public".to_string(), None)]),
+        });
+        assert_eq!(ss[0].deploy_sql(&existing).unwrap(), vec![
+            "ALTER SERVER remote OPTIONS (DROP dbname, SET host 'db.local', ADD port '5432')".to_string(),
+        ]);
+        existing.get_mut("remote").unwrap().wrapper = "file_fdw".to_string();
+        assert!(ss[0].deploy_sql(&existing).is_err());
+    }
+
+    #[test]
+    fn foreign_table_test() {
+        let yaml = YamlLoader::load_from_str(r#"
+name: remote_users
+server: remote
+options:
+  schema_name: public
+  table_name: users
+columns:
+  - column:
+      name: id
+      type: integer
+      constraint:
+        nullable: false
+  - column:
+      name: name
+      type: text
+"#).unwrap().remove(0);
+        let ft = YamlForeignTable::new(&yaml, "test_schema");
+        let mut existing = HashMap::new();
+        assert_eq!(ft.deploy_sql(&existing).unwrap(), vec![
+            "CREATE FOREIGN TABLE IF NOT EXISTS test_schema.remote_users (id integer NOT NULL, name text) SERVER remote OPTIONS (schema_name 'public', table_name 'users')".to_string(),
+        ]);
+        existing.insert("test_schema.remote_users".to_string(), PgForeignTable {
+            server: "remote".to_string(),
+            options: ft.options.clone(),
+            columns: vec!["id".to_string()],
+        });
+        assert_eq!(ft.deploy_sql(&existing).unwrap(), vec!["ALTER FOREIGN TABLE test_schema.remote_users ADD COLUMN name text".to_string()]);
+        existing.get_mut("test_schema.remote_users").unwrap().columns.push("name".to_string());
+        assert!(ft.deploy_sql(&existing).unwrap().is_empty());
+
+        existing.get_mut("test_schema.remote_users").unwrap().server = "other".to_string();
+        let sql = ft.deploy_sql(&existing).unwrap();
+        assert_eq!(sql.len(), 2);
+        assert_eq!(sql[0].as_str(), "DROP FOREIGN TABLE test_schema.remote_users");
+    }
+}
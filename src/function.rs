@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use yaml_rust::Yaml;
+
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+use crate::loader::normalize_pg_type;
+use crate::utils::{Enveloped, Named};
+
+/// function defined in yaml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YamlFunction {
+    pub name: String,
+    /// the function created in, the enclosing schemaName by default
+    pub schema: String,
+    /// plpgsql by default
+    pub language: String,
+    /// void by default
+    pub returns: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<YArg>,
+    pub body: String,
+    /// VOLATILE (default), STABLE or IMMUTABLE
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub volatility: String,
+    pub security_definer: bool,
+}
+
+/// function argument
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YArg {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub arg_type: String,
+}
+
+/// function loaded from DB
+#[derive(Debug, Clone, Serialize)]
+pub struct PgFunction {
+    /// pg_get_functiondef
+    pub definition: String,
+    pub language: String,
+    /// VOLATILE, STABLE or IMMUTABLE
+    pub volatility: String,
+    pub security_definer: bool,
+    /// pg_get_function_result
+    pub returns: String,
+}
+
+/// schema.name(argument types): function
+pub type InfoFunctionType = HashMap<String, PgFunction>;
+
+impl Enveloped for YamlFunction {
+    const ENVELOPE: &'static str = "function";
+}
+
+impl Named for YamlFunction {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl YamlFunction {
+    pub(crate) fn new(input: &Yaml, schema: &str) -> Self {
+        let mut args = Vec::new();
+        if let Some(aa) = input["args"].as_vec() {
+            for a in aa {
+                args.push(YArg {
+                    name: crate::utils::safe_sql_name(crate::utils::as_str_esc(a, "name")),
+                    arg_type: crate::utils::as_str_esc(a, "type"),
+                });
+            }
+        }
+        YamlFunction {
+            name: crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "name")),
+            schema: crate::utils::safe_sql_name(crate::utils::as_str(input, "schema", schema)),
+            language: crate::utils::as_str(input, "language", "plpgsql"),
+            returns: crate::utils::as_str(input, "returns", "void"),
+            args,
+            body: crate::utils::as_str(input, "body", ""),
+            volatility: crate::utils::as_str(input, "volatility", "").to_uppercase(),
+            security_definer: crate::utils::as_bool(input, "security_definer", false),
+        }
+    }
+
+    /// full name as schema.name
+    #[inline]
+    pub fn full_name(&self) -> String {
+        format!("{}.{}", self.schema, self.name)
+    }
+
+    /// schema.name(argument types) to identify an overloaded function, the types as normalize_arg_type
+    pub fn signature(&self) -> String {
+        signature(self.full_name().as_str(), self.args.iter().map(|a| a.arg_type.as_str()))
+    }
+
+    /// CREATE OR REPLACE statement if the function is missing or changed
+    pub fn deploy_sql(&self, existing: &InfoFunctionType) -> Result<Option<String>, String> {
+        if self.body.trim().len() == 0 {
+            return Err(format!("no body set on function: {}", self.full_name()));
+        }
+        let mut drop = None;
+        if let Some(pg) = existing.get(&self.signature()) {
+            let volatility = if self.volatility.len() > 0 { self.volatility.as_str() } else { "VOLATILE" };
+            if normalize_returns(&pg.returns) != normalize_returns(&self.returns) {
+                // the return type is not changed by CREATE OR REPLACE
+                drop = Some(format!("DROP FUNCTION {};\n", self.signature()));
+            } else if normalize_body(&pg.definition).contains(normalize_body(&self.body).as_str())
+                && pg.language.eq_ignore_ascii_case(&self.language)
+                && pg.volatility == volatility
+                && pg.security_definer == self.security_definer {
+                return Ok(None);
+            }
+        }
+        let args: Vec<String> = self.args.iter()
+            .map(|a| if a.name.len() > 0 { format!("{} {}", a.name, a.arg_type) } else { a.arg_type.clone() })
+            .collect();
+        let mut sql = drop.unwrap_or_default();
+        let _ = write!(sql, "CREATE OR REPLACE FUNCTION {}({}) RETURNS {} LANGUAGE {}",
+                       self.full_name(), args.join(", "), self.returns, self.language);
+        if self.volatility.len() > 0 {
+            let _ = write!(sql, " {}", self.volatility);
+        }
+        if self.security_definer {
+            sql.push_str(" SECURITY DEFINER");
+        }
+        let _ = write!(sql, " AS $function${}$function$", self.body);
+        Ok(Some(sql))
+    }
+}
+
+/// body to compare: single spaced, no trailing semicolon
+fn normalize_body(body: &str) -> String {
+    body.trim().trim_end_matches(';').split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// schema.name(argument types) of the function
+fn signature<'a>(full_name: &str, types: impl Iterator<Item=&'a str>) -> String {
+    let types: Vec<String> = types.map(normalize_arg_type).collect();
+    format!("{}({})", full_name, types.join(", "))
+}
+
+/// argument type as normalize_pg_type without the type modifier, ignored for a function argument,
+/// i.e. varchar(20) as varchar
+fn normalize_arg_type(t: &str) -> String {
+    let t = normalize_pg_type(t);
+    match (t.find('('), t.find(')')) {
+        (Some(open), Some(close)) if open < close => format!("{}{}", &t[..open], &t[close + 1..]),
+        _ => t,
+    }
+}
+
+/// return type to compare, i.e. SETOF int as setof integer, TABLE (a int) as table(a integer)
+fn normalize_returns(returns: &str) -> String {
+    let returns = returns.trim();
+    let lower = returns.to_lowercase();
+    if let Some(element) = lower.strip_prefix("setof ") {
+        return format!("setof {}", normalize_arg_type(element));
+    }
+    match (lower.strip_prefix("table").map(|c| c.trim_start()), returns.rfind(')')) {
+        (Some(columns), Some(close)) if columns.starts_with('(') => {
+            let columns: Vec<String> = returns[returns.find('(').unwrap_or(0) + 1..close].split(',')
+                .map(|c| {
+                    let c = c.trim();
+                    let i = c.find(char::is_whitespace).unwrap_or(c.len());
+                    format!("{} {}", c[..i].to_lowercase(), normalize_arg_type(&c[i..]))
+                })
+                .collect();
+            format!("table({})", columns.join(", "))
+        }
+        _ => normalize_arg_type(returns),
+    }
+}
+
+/// functions except the ones of extensions
+#[cfg(feature = "db")]
+pub fn load_info_functions(db_name: &str, db: &mut dyn DbExec) -> Result<InfoFunctionType, String> {
+    let mut data = HashMap::new();
+    let result = db.query("SELECT n.nspname, p.proname, oidvectortypes(p.proargtypes), pg_get_functiondef(p.oid), l.lanname,
+ CASE p.provolatile WHEN 'i' THEN 'IMMUTABLE' WHEN 's' THEN 'STABLE' ELSE 'VOLATILE' END, p.prosecdef,
+ pg_get_function_result(p.oid)
+ FROM pg_proc p
+ JOIN pg_namespace n ON n.oid = p.pronamespace
+ JOIN pg_language l ON l.oid = p.prolang
+ WHERE p.prokind = 'f' AND n.nspname not in ('pg_catalog', 'information_schema')
+  AND NOT EXISTS (SELECT 1 FROM pg_depend d WHERE d.objid = p.oid AND d.classid = 'pg_proc'::regclass AND d.deptype = 'e')", &[])
+        .map_err(|e| format!("on loading pg_proc [{}]: {}", db_name, e))?;
+    for r in result {
+        let function_schema: &str = r.get(0);
+        let function_name: &str = r.get(1);
+        let types: &str = r.get(2);
+        let definition: &str = r.get(3);
+        let language: &str = r.get(4);
+        let volatility: &str = r.get(5);
+        let security_definer: bool = r.get(6);
+        let returns: &str = r.get(7);
+        data.insert(signature(format!("{}.{}", function_schema, function_name).as_str(),
+                              types.split(", ").filter(|t| t.len() > 0)), PgFunction {
+            definition: definition.to_string(),
+            language: language.to_string(),
+            volatility: volatility.to_string(),
+            security_definer,
+            returns: returns.to_string(),
+        });
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::function::{normalize_returns, signature, PgFunction, YArg, YamlFunction};
+
+    #[test]
+    fn function_test() {
+        let mut f = YamlFunction {
+            name: "add".to_string(),
+            schema: "test_schema".to_string(),
+            language: "sql".to_string(),
+            returns: "integer".to_string(),
+            args: vec![YArg { name: "a".to_string(), arg_type: "integer".to_string() },
+                       YArg { name: "b".to_string(), arg_type: "integer".to_string() }],
+            body: "SELECT a + b".to_string(),
+            volatility: "IMMUTABLE".to_string(),
+            security_definer: false,
+        };
+        assert_eq!(f.signature().as_str(), "test_schema.add(integer, integer)");
+        let mut existing = HashMap::new();
+        assert_eq!(f.deploy_sql(&existing).unwrap().unwrap().as_str(),
+                   "CREATE OR REPLACE FUNCTION test_schema.add(a integer, b integer) RETURNS integer LANGUAGE sql IMMUTABLE AS $function$SELECT a + b$function$");
+        existing.insert("test_schema.add(integer, integer)".to_string(), PgFunction {
+            definition: "CREATE OR REPLACE FUNCTION test_schema.add(a integer, b integer)\n RETURNS integer\n LANGUAGE sql\n IMMUTABLE\nAS $function$SELECT a +  b$function$\n".to_string(),
+            language: "sql".to_string(),
+            volatility: "IMMUTABLE".to_string(),
+            security_definer: false,
+            returns: "integer".to_string(),
+        });
+        assert!(f.deploy_sql(&existing).unwrap().is_none());
+        f.body = "SELECT a - b".to_string();
+        assert!(f.deploy_sql(&existing).unwrap().is_some());
+        f.body = "SELECT a + b".to_string();
+        f.returns = "bigint".to_string();
+        assert_eq!(f.deploy_sql(&existing).unwrap().unwrap().as_str(),
+                   "DROP FUNCTION test_schema.add(integer, integer);\n\
+                   CREATE OR REPLACE FUNCTION test_schema.add(a integer, b integer) RETURNS bigint LANGUAGE sql IMMUTABLE AS $function$SELECT a + b$function$");
+    }
+
+    #[test]
+    fn signature_test() {
+        let f = YamlFunction {
+            name: "f".to_string(),
+            schema: "s".to_string(),
+            args: ["int", "varchar(20)", "bool", "timestamptz", "int4[]", "numeric(10, 2)"].iter()
+                .map(|t| YArg { name: "".to_string(), arg_type: t.to_string() })
+                .collect(),
+            ..Default::default()
+        };
+        let db = "integer, character varying, boolean, timestamp with time zone, integer[], numeric";
+        assert_eq!(f.signature(), signature("s.f", db.split(", ")));
+        assert_eq!(f.signature().as_str(), "s.f(integer, varchar, boolean, timestamp with time zone, integer[], numeric)");
+        assert_eq!(signature("s.g", "".split(", ").filter(|t| t.len() > 0)).as_str(), "s.g()");
+        assert_eq!(normalize_returns("SETOF int"), normalize_returns("SETOF integer"));
+        assert_eq!(normalize_returns("TABLE (id int, name varchar(10))"), normalize_returns("TABLE(id integer, name character varying)"));
+        assert_eq!(normalize_returns("timestamptz"), normalize_returns("timestamp with time zone"));
+        assert_ne!(normalize_returns("int"), normalize_returns("bigint"));
+    }
+}
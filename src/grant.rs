@@ -0,0 +1,435 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use yaml_rust::yaml::Array;
+
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+use crate::table::YGrant;
+
+/// privileges applicable to a table
+pub const TABLE_PRIVILEGES: [&str; 7] = ["SELECT", "INSERT", "UPDATE", "DELETE", "TRUNCATE", "REFERENCES", "TRIGGER"];
+
+/// privileges applicable to the columns of a table
+pub const COLUMN_PRIVILEGES: [&str; 4] = ["SELECT", "INSERT", "UPDATE", "REFERENCES"];
+
+/// privileges applicable to a sequence
+pub const SEQUENCE_PRIVILEGES: [&str; 3] = ["USAGE", "SELECT", "UPDATE"];
+
+/// privileges applicable to a schema
+pub const SCHEMA_PRIVILEGES: [&str; 2] = ["USAGE", "CREATE"];
+
+/// privileges applicable to a function or procedure
+pub const FUNCTION_PRIVILEGES: [&str; 1] = ["EXECUTE"];
+
+/// privileges applicable to a type or domain
+pub const TYPE_PRIVILEGES: [&str; 1] = ["USAGE"];
+
+/// scope of a schema grant on all the objects of a kind in the schema
+pub const BATCH_GRANT_SCOPES: [&str; 3] = ["all_tables", "all_sequences", "all_functions"];
+
+/// object types of the default privileges, as in SQL
+pub const DEFAULT_PRIVILEGE_TYPES: [&str; 5] = ["TABLES", "SEQUENCES", "FUNCTIONS", "ROUTINES", "TYPES"];
+
+/// (for role, schema, object type with ROUTINES as FUNCTIONS): grantee: privileges
+pub type InfoDefaultPrivilegeType = HashMap<(String, String, String), HashMap<String, PgGrant>>;
+
+/// privileges of a role on an object loaded from DB
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PgGrant {
+    pub grantee: String,
+    pub privileges: HashSet<String>,
+    pub with_grant_option: bool,
+}
+
+/// privileges to grant a role as defined in yaml
+#[derive(Debug, Clone, Default)]
+pub struct DesiredGrant {
+    pub privileges: BTreeSet<String>,
+    pub with_grant_option: bool,
+    /// GRANTED BY
+    pub by: String,
+}
+
+/// column privilege of a role loaded from DB, excluding the ones granted on the whole table
+#[derive(Debug, Clone, Serialize)]
+pub struct PgColumnGrant {
+    pub column_name: String,
+    pub privilege: String,
+    pub with_grant_option: bool,
+}
+
+/// ALTER DEFAULT PRIVILEGES of the objects created later in the schema
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YDefaultPrivilege {
+    /// role creating the objects, the current user if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub for_role: Option<String>,
+    /// the enclosing schema
+    #[serde(skip)]
+    pub in_schema: String,
+    /// TABLES, SEQUENCES, FUNCTIONS, ROUTINES or TYPES
+    pub object_type: String,
+    pub grant: Vec<YGrant>,
+}
+
+impl YDefaultPrivilege {
+    pub(crate) fn new(input: Option<&Array>, schema: &str) -> Result<Vec<Self>, String> {
+        let mut data = Vec::new();
+        if let Some(vv) = input {
+            for v in vv {
+                let object_type = crate::utils::as_str_esc(v, "object_type").trim().to_uppercase();
+                if !DEFAULT_PRIVILEGE_TYPES.contains(&object_type.as_str()) {
+                    return Err(format!("default privileges object type {} is not supported, expected any of: {}",
+                                       object_type, DEFAULT_PRIVILEGE_TYPES.join(", ")));
+                }
+                let for_role = crate::utils::sql_identifier(crate::utils::as_str_esc(v, "for_role"));
+                data.push(YDefaultPrivilege {
+                    for_role: if for_role.len() > 0 { Some(for_role) } else { None },
+                    in_schema: schema.to_string(),
+                    object_type,
+                    grant: YGrant::new(v["grant"].as_vec()),
+                });
+            }
+        }
+        Ok(data)
+    }
+
+    /// privileges applicable to the object type
+    fn applicable(&self) -> &'static [&'static str] {
+        match self.object_type.as_str() {
+            "TABLES" => &TABLE_PRIVILEGES,
+            "SEQUENCES" => &SEQUENCE_PRIVILEGES,
+            "TYPES" => &TYPE_PRIVILEGES,
+            _ => &FUNCTION_PRIVILEGES,
+        }
+    }
+
+    /// the key of the loaded default privileges
+    fn key(&self, current_user: &str) -> (String, String, String) {
+        (self.for_role.clone().unwrap_or_else(|| current_user.to_string()),
+         self.in_schema.clone(),
+         if self.object_type == "ROUTINES" { "FUNCTIONS".to_string() } else { self.object_type.clone() })
+    }
+
+    /// statements to alter the default privileges comparing to loaded from DB, the loaded ones updated
+    pub fn deploy_sql(&self, existing: &mut InfoDefaultPrivilegeType, current_user: &str, with_revoke: bool) -> Result<Vec<String>, String> {
+        if self.grant.iter().any(|g| g.by.len() > 0 || g.columns.len() > 0) {
+            return Err(format!("default privileges on {} in schema {} can not be granted by a role or on columns",
+                               self.object_type, self.in_schema));
+        }
+        let gb = GrantBuilder::new(&self.grant, self.applicable(), &[])
+            .map_err(|e| format!("{} on default privileges on {} in schema {}", e, self.object_type, self.in_schema))?;
+        let grants = existing.entry(self.key(current_user)).or_insert_with(HashMap::new);
+        let prefix = match &self.for_role {
+            None => format!("ALTER DEFAULT PRIVILEGES IN SCHEMA {}", self.in_schema),
+            Some(r) => format!("ALTER DEFAULT PRIVILEGES FOR ROLE {} IN SCHEMA {}", r, self.in_schema),
+        };
+        let sql = gb.generate_sql(self.object_type.as_str(), grants, None, with_revoke).into_iter()
+            .map(|g| format!("{} {}", prefix, g))
+            .collect();
+        gb.update_dbc(grants, with_revoke);
+        Ok(sql)
+    }
+}
+
+/// default privileges of the schemas, all if not set
+#[cfg(feature = "db")]
+pub fn load_info_default_privileges(db: &mut dyn DbExec, schemas: Option<&[&str]>) -> Result<InfoDefaultPrivilegeType, String> {
+    let result = db.query("SELECT r.rolname::text, n.nspname::text, d.defaclobjtype::text, coalesce(g.rolname, 'public')::text,
+ a.privilege_type, a.is_grantable
+ FROM pg_default_acl d
+ JOIN pg_roles r ON r.oid = d.defaclrole
+ JOIN pg_namespace n ON n.oid = d.defaclnamespace
+ CROSS JOIN LATERAL aclexplode(d.defaclacl) a
+ LEFT JOIN pg_roles g ON g.oid = a.grantee
+ WHERE ($1::text[] is null or n.nspname = any($1))", &[&schemas])
+        .map_err(|e| format!("on loading pg_default_acl: {}", e))?;
+    let mut data: InfoDefaultPrivilegeType = HashMap::new();
+    for r in result {
+        let role: &str = r.get(0);
+        let schema: &str = r.get(1);
+        let object_type = match r.get::<_, &str>(2) {
+            "r" => "TABLES",
+            "S" => "SEQUENCES",
+            "f" => "FUNCTIONS",
+            "T" => "TYPES",
+            _ => continue,
+        };
+        let grantee: &str = r.get(3);
+        let privilege: &str = r.get(4);
+        let is_grantable: bool = r.get(5);
+        let g = data.entry((role.to_string(), schema.to_string(), object_type.to_string()))
+            .or_insert_with(HashMap::new)
+            .entry(grantee.to_string())
+            .or_insert_with(|| PgGrant { grantee: grantee.to_string(), ..Default::default() });
+        g.privileges.insert(privilege.to_string());
+        g.with_grant_option |= is_grantable;
+    }
+    Ok(data)
+}
+
+/// build GRANT and REVOKE SQL of an object comparing to grants loaded from DB
+#[derive(Debug, Clone)]
+pub struct GrantBuilder {
+    /// role: privileges
+    pub grants: BTreeMap<String, DesiredGrant>,
+    /// role: privilege: columns
+    pub column_grants: BTreeMap<String, BTreeMap<String, BTreeSet<String>>>,
+}
+
+impl GrantBuilder {
+    /// collect the yaml grants by role, the privileges must be applicable to the object,
+    /// the ones with columns must be applicable to the columns
+    pub fn new(grants: &Vec<YGrant>, applicable: &[&str], column_applicable: &[&str]) -> Result<Self, String> {
+        let mut desired: BTreeMap<String, DesiredGrant> = BTreeMap::new();
+        let mut column_grants: BTreeMap<String, BTreeMap<String, BTreeSet<String>>> = BTreeMap::new();
+        for g in grants {
+            let applicable = if g.columns.len() > 0 { column_applicable } else { applicable };
+            for (privilege, roles) in g.privileges() {
+                let privileges: Vec<&str> = if privilege == "ALL" {
+                    applicable.to_vec()
+                } else if applicable.contains(&privilege) {
+                    vec![privilege]
+                } else {
+                    return Err(format!("privilege {} {}is not applicable, expected any of: {}", privilege,
+                                       if g.columns.len() > 0 { "on columns " } else { "" }, applicable.join(", ")));
+                };
+                for role in roles.split(',').map(|r| r.trim()).filter(|r| r.len() > 0) {
+                    if g.columns.len() > 0 {
+                        let cg = column_grants.entry(role.to_string()).or_insert_with(BTreeMap::new);
+                        for p in &privileges {
+                            cg.entry(p.to_string()).or_insert_with(BTreeSet::new).extend(g.columns.iter().cloned());
+                        }
+                        continue;
+                    }
+                    let dg = desired.entry(role.to_string()).or_insert_with(DesiredGrant::default);
+                    dg.privileges.extend(privileges.iter().map(|p| p.to_string()));
+                    dg.with_grant_option |= g.with_grant_option;
+                    if g.by.len() > 0 {
+                        dg.by = g.by.clone();
+                    }
+                }
+            }
+        }
+        Ok(GrantBuilder { grants: desired, column_grants })
+    }
+
+    /// statements to grant missing column privileges on the table, i.e. TABLE schema.table,
+    /// and to revoke the ones not in yaml if with_revoke
+    pub fn generate_column_sql(&self, object: &str, existing: &HashMap<String, Vec<PgColumnGrant>>, with_revoke: bool) -> Vec<String> {
+        let mut sql = Vec::new();
+        for (role, privileges) in &self.column_grants {
+            let pg = existing.get(role);
+            for (privilege, columns) in privileges {
+                let missing: Vec<&str> = columns.iter()
+                    .filter(|c| !pg.map_or(false, |pg| pg.iter().any(|g| &g.column_name == *c && &g.privilege == privilege)))
+                    .map(|c| c.as_str())
+                    .collect();
+                if missing.len() > 0 {
+                    sql.push(format!("GRANT {} ({}) ON {} TO {}", privilege, missing.join(", "), object, role));
+                }
+            }
+        }
+        if with_revoke {
+            let mut roles: Vec<&String> = existing.keys().collect();
+            roles.sort();
+            for role in roles {
+                let mut extra: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+                for g in &existing[role] {
+                    if !self.column_grants.get(role).and_then(|cg| cg.get(&g.privilege)).map_or(false, |cs| cs.contains(&g.column_name)) {
+                        extra.entry(g.privilege.as_str()).or_insert_with(Vec::new).push(g.column_name.as_str());
+                    }
+                }
+                for (privilege, mut columns) in extra {
+                    columns.sort();
+                    sql.push(format!("REVOKE {} ({}) ON {} FROM {}", privilege, columns.join(", "), object, role));
+                }
+            }
+        }
+        sql
+    }
+
+    /// keep loaded DB state of column privileges in sync after deploy
+    pub fn update_column_dbc(&self, existing: &mut HashMap<String, Vec<PgColumnGrant>>, with_revoke: bool) {
+        if with_revoke {
+            existing.clear();
+        }
+        for (role, privileges) in &self.column_grants {
+            let pg = existing.entry(role.clone()).or_insert_with(Vec::new);
+            for (privilege, columns) in privileges {
+                for c in columns {
+                    if !pg.iter().any(|g| &g.column_name == c && &g.privilege == privilege) {
+                        pg.push(PgColumnGrant { column_name: c.clone(), privilege: privilege.clone(), with_grant_option: false });
+                    }
+                }
+            }
+        }
+    }
+
+    /// statements to grant missing privileges on the object, i.e. TABLE schema.table,
+    /// and to revoke the ones not in yaml if with_revoke, except the owner's
+    pub fn generate_sql(&self, object: &str, existing: &HashMap<String, PgGrant>, owner: Option<&String>, with_revoke: bool) -> Vec<String> {
+        let mut sql = Vec::new();
+        for (role, dg) in &self.grants {
+            let pg = existing.get(role);
+            let missing: Vec<&str> = dg.privileges.iter()
+                .filter(|p| (dg.with_grant_option && !pg.map_or(false, |pg| pg.with_grant_option))
+                    || !pg.map_or(false, |pg| pg.privileges.contains(*p)))
+                .map(|p| p.as_str())
+                .collect();
+            if missing.len() > 0 {
+                let mut grant = format!("GRANT {} ON {} TO {}", missing.join(", "), object, role);
+                if dg.with_grant_option {
+                    grant.push_str(" WITH GRANT OPTION");
+                }
+                if dg.by.len() > 0 {
+                    let _ = write!(grant, " GRANTED BY {}", dg.by);
+                }
+                sql.push(grant);
+            }
+        }
+        if with_revoke {
+            let mut roles: Vec<&String> = existing.keys().collect();
+            roles.sort();
+            for role in roles {
+                if owner == Some(role) {
+                    continue;
+                }
+                let pg = &existing[role];
+                let mut extra: Vec<&str> = pg.privileges.iter()
+                    .filter(|p| !self.grants.get(role).map_or(false, |dg| dg.privileges.contains(*p)))
+                    .map(|p| p.as_str())
+                    .collect();
+                extra.sort();
+                if extra.len() > 0 {
+                    sql.push(format!("REVOKE {} ON {} FROM {}", extra.join(", "), object, role));
+                }
+            }
+        }
+        sql
+    }
+
+    /// keep loaded DB state in sync after deploy
+    pub fn update_dbc(&self, existing: &mut HashMap<String, PgGrant>, with_revoke: bool) {
+        if with_revoke {
+            existing.retain(|role, _| self.grants.contains_key(role));
+        }
+        for (role, dg) in &self.grants {
+            let pg = existing.entry(role.clone()).or_insert_with(|| PgGrant { grantee: role.clone(), ..Default::default() });
+            if with_revoke {
+                pg.privileges.clear();
+            }
+            pg.privileges.extend(dg.privileges.iter().cloned());
+            pg.with_grant_option |= dg.with_grant_option;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use yaml_rust::YamlLoader;
+
+    use crate::grant::{COLUMN_PRIVILEGES, GrantBuilder, PgColumnGrant, PgGrant, SCHEMA_PRIVILEGES, TABLE_PRIVILEGES, YDefaultPrivilege};
+    use crate::table::YGrant;
+
+    #[test]
+    fn default_privileges_test() {
+        let yaml = YamlLoader::load_from_str(r#"
+- object_type: tables
+  grant:
+    - select: reader
+- for_role: app_owner
+  object_type: routines
+  grant:
+    - execute: reader
+"#).unwrap().remove(0);
+        let dp = YDefaultPrivilege::new(yaml.as_vec(), "s").unwrap();
+        let mut existing = HashMap::new();
+        assert_eq!(dp[0].deploy_sql(&mut existing, "postgres", false).unwrap(),
+                   vec!["ALTER DEFAULT PRIVILEGES IN SCHEMA s GRANT SELECT ON TABLES TO reader".to_string()]);
+        assert_eq!(dp[1].deploy_sql(&mut existing, "postgres", false).unwrap(),
+                   vec!["ALTER DEFAULT PRIVILEGES FOR ROLE app_owner IN SCHEMA s GRANT EXECUTE ON ROUTINES TO reader".to_string()]);
+        assert!(existing.contains_key(&("app_owner".to_string(), "s".to_string(), "FUNCTIONS".to_string())));
+        assert!(dp[0].deploy_sql(&mut existing, "postgres", true).unwrap().is_empty());
+
+        existing.get_mut(&("postgres".to_string(), "s".to_string(), "TABLES".to_string())).unwrap()
+            .insert("writer".to_string(), PgGrant { grantee: "writer".to_string(), privileges: vec!["INSERT".to_string()].into_iter().collect(), with_grant_option: false });
+        assert_eq!(dp[0].deploy_sql(&mut existing, "postgres", true).unwrap(),
+                   vec!["ALTER DEFAULT PRIVILEGES IN SCHEMA s REVOKE INSERT ON TABLES FROM writer".to_string()]);
+
+        let yaml = YamlLoader::load_from_str("- object_type: views").unwrap().remove(0);
+        assert!(YDefaultPrivilege::new(yaml.as_vec(), "s").is_err());
+    }
+
+    #[test]
+    fn table_grant_test() {
+        let grants = vec![
+            YGrant { select: "reader,writer".to_string(), ..Default::default() },
+            YGrant { insert: "writer".to_string(), update: "writer".to_string(), ..Default::default() },
+        ];
+        let gb = GrantBuilder::new(&grants, &TABLE_PRIVILEGES, &COLUMN_PRIVILEGES).unwrap();
+        let mut existing = HashMap::new();
+        assert_eq!(gb.generate_sql("TABLE s.t", &existing, None, false), vec![
+            "GRANT SELECT ON TABLE s.t TO reader".to_string(),
+            "GRANT INSERT, SELECT, UPDATE ON TABLE s.t TO writer".to_string(),
+        ]);
+        gb.update_dbc(&mut existing, false);
+        assert!(gb.generate_sql("TABLE s.t", &existing, None, true).is_empty());
+
+        existing.insert("guest".to_string(), PgGrant {
+            grantee: "guest".to_string(),
+            privileges: vec!["SELECT".to_string()].into_iter().collect(),
+            with_grant_option: false,
+        });
+        existing.get_mut("writer").unwrap().privileges.insert("DELETE".to_string());
+        assert!(gb.generate_sql("TABLE s.t", &existing, None, false).is_empty());
+        assert_eq!(gb.generate_sql("TABLE s.t", &existing, None, true), vec![
+            "REVOKE SELECT ON TABLE s.t FROM guest".to_string(),
+            "REVOKE DELETE ON TABLE s.t FROM writer".to_string(),
+        ]);
+        assert_eq!(gb.generate_sql("TABLE s.t", &existing, Some(&"guest".to_string()), true).len(), 1);
+
+        let grants = vec![YGrant { usage: "reader".to_string(), ..Default::default() }];
+        assert!(GrantBuilder::new(&grants, &TABLE_PRIVILEGES, &COLUMN_PRIVILEGES).is_err());
+    }
+
+    #[test]
+    fn schema_grant_test() {
+        let grants = vec![YGrant { all: "writer".to_string(), usage: "reader".to_string(), ..Default::default() }];
+        let gb = GrantBuilder::new(&grants, &SCHEMA_PRIVILEGES, &[]).unwrap();
+        assert_eq!(gb.generate_sql("SCHEMA s", &HashMap::new(), None, false), vec![
+            "GRANT USAGE ON SCHEMA s TO reader".to_string(),
+            "GRANT CREATE, USAGE ON SCHEMA s TO writer".to_string(),
+        ]);
+        let grants = vec![YGrant { select: "reader".to_string(), ..Default::default() }];
+        assert!(GrantBuilder::new(&grants, &SCHEMA_PRIVILEGES, &[]).is_err());
+    }
+
+    #[test]
+    fn column_grant_test() {
+        let grants = vec![
+            YGrant { select: "reader".to_string(), columns: vec!["id".to_string(), "name".to_string()], ..Default::default() },
+            YGrant { select: "writer".to_string(), ..Default::default() },
+        ];
+        let gb = GrantBuilder::new(&grants, &TABLE_PRIVILEGES, &COLUMN_PRIVILEGES).unwrap();
+        assert_eq!(gb.generate_sql("TABLE s.t", &HashMap::new(), None, false), vec!["GRANT SELECT ON TABLE s.t TO writer".to_string()]);
+        let mut existing = HashMap::new();
+        existing.insert("reader".to_string(), vec![
+            PgColumnGrant { column_name: "id".to_string(), privilege: "SELECT".to_string(), with_grant_option: false },
+            PgColumnGrant { column_name: "secret".to_string(), privilege: "SELECT".to_string(), with_grant_option: false },
+        ]);
+        assert_eq!(gb.generate_column_sql("TABLE s.t", &existing, false), vec!["GRANT SELECT (name) ON TABLE s.t TO reader".to_string()]);
+        assert_eq!(gb.generate_column_sql("TABLE s.t", &existing, true), vec![
+            "GRANT SELECT (name) ON TABLE s.t TO reader".to_string(),
+            "REVOKE SELECT (secret) ON TABLE s.t FROM reader".to_string(),
+        ]);
+        let grants = vec![YGrant { truncate: "reader".to_string(), columns: vec!["id".to_string()], ..Default::default() }];
+        assert!(GrantBuilder::new(&grants, &TABLE_PRIVILEGES, &COLUMN_PRIVILEGES).is_err());
+        assert!(GrantBuilder::new(&grants, &SCHEMA_PRIVILEGES, &[]).is_err());
+    }
+}
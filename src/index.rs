@@ -0,0 +1,751 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::loader::{outer_parentheses, PgIndex, PgIndexColumn, PgTable, DEFAULT_TABLESPACE};
+use crate::options::MigrationOptions;
+use crate::table::Table;
+
+/// access method of CREATE INDEX without USING
+pub const DEFAULT_INDEX_METHOD: &str = "btree";
+
+/// index to create as defined on table's columns,
+/// multiple columns having same index name are the single index for the set of those columns
+#[derive(Debug, Clone)]
+pub struct DesiredIndex {
+    pub name: String,
+    pub columns: Vec<DesiredIndexColumn>,
+    /// non key columns of a covering index
+    pub include_columns: Vec<String>,
+    /// partial index predicate
+    pub where_clause: Option<String>,
+    /// access method, DEFAULT_INDEX_METHOD if not set in yaml
+    pub using: String,
+    /// suffix on index create
+    pub sql: String,
+    pub tablespace: Option<String>,
+    /// storage parameters of WITH (...)
+    pub with_options: BTreeMap<String, String>,
+    pub unique: bool,
+    /// NULLS NOT DISTINCT of a unique index
+    pub nulls_not_distinct: bool,
+    /// CREATE INDEX CONCURRENTLY after the migration commit, if allow_concurrent_index set
+    pub concurrently: bool,
+    /// COMMENT ON INDEX, not changed if empty
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct DesiredIndexColumn {
+    pub column_name: String,
+    /// index on expression instead of the column
+    pub expression: Option<String>,
+    /// non default operator class
+    pub opclass: String,
+    /// ASC or DESC, empty for the default ASC
+    pub order: String,
+    /// FIRST or LAST, empty for the default of the order
+    pub nulls: String,
+    /// non default collation
+    pub collate: String,
+}
+
+impl DesiredIndexColumn {
+    /// column or expression as appear in the index definition
+    fn key(&self) -> &String {
+        self.expression.as_ref().unwrap_or(&self.column_name)
+    }
+
+    /// DESC or empty for the default ASC, as loaded from DB
+    fn pg_order(&self) -> &str {
+        if self.order == "DESC" { "DESC" } else { "" }
+    }
+
+    /// FIRST or LAST if not the default of the order, as loaded from DB
+    fn pg_nulls(&self) -> &str {
+        match (self.pg_order(), self.nulls.as_str()) {
+            ("DESC", "LAST") => "LAST",
+            ("", "FIRST") => "FIRST",
+            _ => "",
+        }
+    }
+
+    /// the same column or expression, operator class, ordering and collation
+    fn matches(&self, pg: &PgIndexColumn) -> bool {
+        normalize_expr(self.key()) == normalize_expr(&pg.column_name) && self.opclass == pg.opclass
+            && self.pg_order() == pg.order && self.pg_nulls() == pg.nulls && self.collate == pg.collation
+    }
+}
+
+/// build index SQL of a table comparing to indexes loaded from DB
+#[derive(Debug, Clone)]
+pub struct IndexBuilder {
+    /// in order of first column appearance
+    pub indexes: Vec<DesiredIndex>,
+}
+
+impl IndexBuilder {
+    pub fn new(table: &Table) -> Self {
+        let mut indexes: Vec<DesiredIndex> = Vec::new();
+        for c in &table.columns.list {
+            if let Some(idx) = &c.index {
+                let name = if idx.name.len() > 0 {
+                    idx.name.clone()
+                } else {
+                    format!("idx_{}_{}", crate::utils::name_part(&table.table_name), crate::utils::name_part(&c.name))
+                };
+                let column = DesiredIndexColumn {
+                    column_name: c.name.clone(),
+                    expression: idx.expr.clone(),
+                    opclass: idx.opclass.clone(),
+                    order: idx.order.clone(),
+                    nulls: idx.nulls.clone(),
+                    collate: idx.collate.clone(),
+                };
+                match indexes.iter_mut().find(|i| i.name == name) {
+                    Some(di) => {
+                        di.columns.push(column);
+                        for ic in &idx.include {
+                            if !di.include_columns.contains(ic) {
+                                di.include_columns.push(ic.clone());
+                            }
+                        }
+                        if di.where_clause.is_none() {
+                            di.where_clause = idx.where_clause.clone();
+                        }
+                        if di.sql.len() == 0 {
+                            di.sql = idx.sql.clone();
+                        }
+                        if idx.using.len() > 0 {
+                            di.using = idx.using.clone();
+                        }
+                        if di.tablespace.is_none() {
+                            di.tablespace = idx.tablespace.clone();
+                        }
+                        for (k, v) in &idx.with_options {
+                            di.with_options.entry(k.clone()).or_insert_with(|| v.clone());
+                        }
+                        di.unique |= idx.unique;
+                        di.nulls_not_distinct |= idx.nulls_not_distinct;
+                        di.concurrently |= idx.concurrently;
+                        if di.description.len() == 0 {
+                            di.description = idx.description.clone();
+                        }
+                    }
+                    None => indexes.push(DesiredIndex {
+                        name,
+                        columns: vec![column],
+                        include_columns: idx.include.clone(),
+                        where_clause: idx.where_clause.clone(),
+                        using: if idx.using.len() > 0 { idx.using.clone() } else { DEFAULT_INDEX_METHOD.to_string() },
+                        sql: idx.sql.clone(),
+                        tablespace: idx.tablespace.clone(),
+                        with_options: idx.with_options.clone(),
+                        unique: idx.unique,
+                        nulls_not_distinct: idx.nulls_not_distinct,
+                        concurrently: idx.concurrently,
+                        description: idx.description.clone(),
+                    }),
+                }
+            }
+        }
+        IndexBuilder { indexes }
+    }
+
+    pub fn build_create_index_sql(schema: &str, table_name: &str, idx: &DesiredIndex) -> String {
+        IndexBuilder::create_sql(schema, table_name, idx, false)
+    }
+
+    fn create_sql(schema: &str, table_name: &str, idx: &DesiredIndex, concurrently: bool) -> String {
+        let mut columns = String::new();
+        for c in &idx.columns {
+            if columns.len() > 0 {
+                columns.push_str(", ");
+            }
+            match &c.expression {
+                None => columns.push_str(c.column_name.as_str()),
+                Some(e) => {
+                    let _ = write!(columns, "({})", e);
+                }
+            }
+            if c.collate.len() > 0 {
+                let _ = write!(columns, " COLLATE \"{}\"", c.collate);
+            }
+            if c.opclass.len() > 0 {
+                let _ = write!(columns, " {}", c.opclass);
+            }
+            if c.order.len() > 0 {
+                let _ = write!(columns, " {}", c.order);
+            }
+            if c.nulls.len() > 0 {
+                let _ = write!(columns, " NULLS {}", c.nulls);
+            }
+        }
+        let using = if idx.using != DEFAULT_INDEX_METHOD { format!(" USING {}", idx.using) } else { "".to_string() };
+        let mut sql = format!("CREATE {}INDEX {}{} ON {}.{}{} ({})", if idx.unique { "UNIQUE " } else { "" },
+                              if concurrently { "CONCURRENTLY " } else { "" }, idx.name, schema, table_name, using, columns);
+        if idx.include_columns.len() > 0 {
+            let _ = write!(sql, " INCLUDE ({})", idx.include_columns.join(", "));
+        }
+        if idx.unique && idx.nulls_not_distinct {
+            sql.push_str(" NULLS NOT DISTINCT");
+        }
+        if idx.with_options.len() > 0 {
+            let _ = write!(sql, " WITH ({})", crate::utils::storage_params_sql(&idx.with_options));
+        }
+        if idx.sql.len() > 0 {
+            let _ = write!(sql, " {}", idx.sql);
+        }
+        if let Some(ts) = &idx.tablespace {
+            let _ = write!(sql, " TABLESPACE {}", ts);
+        }
+        if let Some(w) = &idx.where_clause {
+            let _ = write!(sql, " WHERE {}", w);
+        }
+        sql
+    }
+
+    /// COMMENT ON INDEX of the description
+    pub fn comment_sql(schema: &str, idx: &DesiredIndex) -> String {
+        format!("COMMENT ON INDEX {}.{} IS '{}'", schema, idx.name, idx.description)
+    }
+
+    /// compare index defined in yaml to the one found in DB
+    pub fn index_matches(idx: &DesiredIndex, pg: &PgIndex) -> bool {
+        IndexBuilder::full_match(idx, pg) && IndexBuilder::comment_match(idx, pg)
+    }
+
+    /// the same definition: method, uniqueness, key columns with operator classes, ordering and collations,
+    /// include columns, predicate
+    pub fn structural_match(idx: &DesiredIndex, pg: &PgIndex) -> bool {
+        pg.is_unique == idx.unique && pg.nulls_not_distinct == idx.nulls_not_distinct
+            && pg.index_method == idx.using
+            && idx.columns.len() == pg.columns.len()
+            && idx.columns.iter().zip(pg.columns.iter()).all(|(d, p)| d.matches(p))
+            && idx.include_columns.iter().collect::<HashSet<_>>() == pg.include_columns.iter().collect::<HashSet<_>>()
+            && idx.where_clause.as_ref().map(|w| normalize_expr(w))
+            == pg.where_clause.as_ref().map(|w| normalize_expr(w))
+    }
+
+    /// the same definition, tablespace and storage parameters
+    pub fn full_match(idx: &DesiredIndex, pg: &PgIndex) -> bool {
+        IndexBuilder::structural_match(idx, pg)
+            && IndexBuilder::tablespace_match(idx, pg)
+            && crate::utils::changed_storage_params(&idx.with_options, &pg.with_options).is_empty()
+    }
+
+    #[inline]
+    fn comment_match(idx: &DesiredIndex, pg: &PgIndex) -> bool {
+        idx.description.len() == 0 || pg.comment.as_ref() == Some(&idx.description)
+    }
+
+    #[inline]
+    fn tablespace_match(idx: &DesiredIndex, pg: &PgIndex) -> bool {
+        idx.tablespace.is_none() || idx.tablespace == pg.tablespace
+    }
+
+    /// statements to create new or re-create changed indexes
+    pub fn generate_sql(&self, schema: &str, table_name: &str, existing_indexes: &HashMap<String, PgIndex>) -> Vec<String> {
+        self.generate_sql_split(schema, table_name, existing_indexes, &MigrationOptions::default()).0
+    }
+
+    /// statements to run in the migration transaction and the CONCURRENTLY ones to run after the commit,
+    /// all of them in the transaction without CONCURRENTLY if allow_concurrent_index not set;
+    /// an invalid index, i.e. of a failed CREATE INDEX CONCURRENTLY, dropped and created again;
+    /// an index of the same definition in another tablespace rebuilt by REINDEX if reindex_on_change set,
+    /// changed storage parameters set by ALTER INDEX, the comment set after create or if changed
+    pub fn generate_sql_split(&self, schema: &str, table_name: &str, existing_indexes: &HashMap<String, PgIndex>,
+                              opts: &MigrationOptions) -> (Vec<String>, Vec<String>) {
+        let mut in_tx_sql = Vec::new();
+        let mut post_tx_sql = Vec::new();
+        for idx in &self.indexes {
+            let concurrently = opts.allow_concurrent_index && idx.concurrently;
+            let sql = if concurrently { &mut post_tx_sql } else { &mut in_tx_sql };
+            let comment = idx.description.len() > 0;
+            match existing_indexes.get(&idx.name) {
+                None => {
+                    sql.push(IndexBuilder::create_sql(schema, table_name, idx, concurrently));
+                    if comment {
+                        sql.push(IndexBuilder::comment_sql(schema, idx));
+                    }
+                }
+                Some(pg) => if !pg.is_valid || !IndexBuilder::structural_match(idx, pg)
+                    || !(opts.reindex_on_change || IndexBuilder::tablespace_match(idx, pg)) {
+                    sql.push(format!("DROP INDEX {}IF EXISTS {}.{}", if concurrently { "CONCURRENTLY " } else { "" }, schema, idx.name));
+                    sql.push(IndexBuilder::create_sql(schema, table_name, idx, concurrently));
+                    if comment {
+                        sql.push(IndexBuilder::comment_sql(schema, idx));
+                    }
+                } else {
+                    if !IndexBuilder::tablespace_match(idx, pg) {
+                        sql.push(format!("REINDEX (TABLESPACE {}) INDEX {}{}.{}", idx.tablespace.as_deref().unwrap_or(DEFAULT_TABLESPACE),
+                                         if concurrently { "CONCURRENTLY " } else { "" }, schema, idx.name));
+                    }
+                    let params = crate::utils::changed_storage_params(&idx.with_options, &pg.with_options);
+                    if params.len() > 0 {
+                        sql.push(format!("ALTER INDEX {}.{} SET ({})", schema, idx.name, crate::utils::storage_params_sql(&params)));
+                    }
+                    if !IndexBuilder::comment_match(idx, pg) {
+                        sql.push(IndexBuilder::comment_sql(schema, idx));
+                    }
+                }
+            }
+        }
+        (in_tx_sql, post_tx_sql)
+    }
+
+    /// keep loaded DB state in sync after deploy
+    pub fn update_dbc(&self, table: &mut PgTable) {
+        for idx in &self.indexes {
+            table.indexes.insert(idx.name.clone(), PgIndex {
+                index_name: idx.name.clone(),
+                is_unique: idx.unique,
+                is_valid: true,
+                nulls_not_distinct: idx.nulls_not_distinct,
+                index_method: idx.using.clone(),
+                columns: idx.columns.iter()
+                    .map(|c| PgIndexColumn {
+                        column_name: c.key().clone(),
+                        opclass: c.opclass.clone(),
+                        order: c.pg_order().to_string(),
+                        nulls: c.pg_nulls().to_string(),
+                        collation: c.collate.clone(),
+                    }).collect(),
+                include_columns: idx.include_columns.clone(),
+                where_clause: idx.where_clause.clone(),
+                tablespace: idx.tablespace.clone(),
+                with_options: idx.with_options.clone(),
+                comment: if idx.description.len() > 0 { Some(idx.description.clone()) } else { None },
+            });
+        }
+    }
+}
+
+/// expression to compare: no outer parentheses, spaces and case insensitive
+pub(crate) fn normalize_expr(expr: &str) -> String {
+    let mut e: String = expr.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+    while e.starts_with('(') && e.ends_with(')') && outer_parentheses(&e) {
+        e = e[1..e.len() - 1].to_string();
+    }
+    e
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::{load_schema_from_src, parse_yaml_schema};
+    use crate::index::IndexBuilder;
+    use crate::loader::{PgIndex, PgIndexColumn, PgTable};
+    use crate::options::MigrationOptions;
+
+    fn builder() -> IndexBuilder {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+            - column:
+                name: email
+                type: text
+                index:
+                  name: idx_email
+                  where: deleted_at IS NULL
+                  include: [id]
+            - column:
+                name: deleted_at
+                type: timestamp
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        IndexBuilder::new(r.list.get(0).unwrap().tables.list.get(0).unwrap())
+    }
+
+    #[test]
+    fn partial_index_test() {
+        let ib = builder();
+        assert_eq!(ib.indexes.len(), 1);
+        let sql = ib.generate_sql("test_schema", "test_table", &HashMap::new());
+        assert_eq!(sql, vec!["CREATE INDEX idx_email ON test_schema.test_table (email) INCLUDE (id) WHERE deleted_at IS NULL".to_string()]);
+
+        let mut existing = HashMap::new();
+        existing.insert("idx_email".to_string(), PgIndex {
+            index_name: "idx_email".to_string(),
+            is_unique: false,
+            is_valid: true,
+            nulls_not_distinct: false,
+            index_method: "btree".to_string(),
+            columns: vec![PgIndexColumn { column_name: "email".to_string(), opclass: String::new(), ..Default::default() }],
+            include_columns: vec!["id".to_string()],
+            where_clause: Some("(deleted_at IS NULL)".to_string()),
+            tablespace: None,
+            with_options: BTreeMap::new(),
+            comment: None,
+        });
+        assert!(ib.generate_sql("test_schema", "test_table", &existing).is_empty());
+
+        existing.get_mut("idx_email").unwrap().index_method = "gin".to_string();
+        assert_eq!(ib.generate_sql("test_schema", "test_table", &existing).len(), 2);
+        existing.get_mut("idx_email").unwrap().index_method = "btree".to_string();
+
+        existing.get_mut("idx_email").unwrap().where_clause = None;
+        let sql = ib.generate_sql("test_schema", "test_table", &existing);
+        assert_eq!(sql.len(), 2);
+        assert_eq!(sql[0].as_str(), "DROP INDEX IF EXISTS test_schema.idx_email");
+    }
+
+    #[test]
+    fn include_index_test() {
+        let ib = builder();
+        assert_eq!(ib.indexes[0].include_columns, vec!["id".to_string()]);
+        let mut existing = HashMap::new();
+        existing.insert("idx_email".to_string(), PgIndex {
+            index_name: "idx_email".to_string(),
+            is_unique: false,
+            is_valid: true,
+            nulls_not_distinct: false,
+            index_method: "btree".to_string(),
+            columns: vec![PgIndexColumn { column_name: "email".to_string(), opclass: String::new(), ..Default::default() }],
+            include_columns: vec![],
+            where_clause: Some("deleted_at IS NULL".to_string()),
+            tablespace: None,
+            with_options: BTreeMap::new(),
+            comment: None,
+        });
+        let sql = ib.generate_sql("test_schema", "test_table", &existing);
+        assert_eq!(sql.len(), 2);
+        assert!(sql[1].contains(" INCLUDE (id) "));
+        existing.get_mut("idx_email").unwrap().include_columns.push("id".to_string());
+        assert!(ib.generate_sql("test_schema", "test_table", &existing).is_empty());
+    }
+
+    #[test]
+    fn expression_index_test() {
+        let mut ib = builder();
+        ib.indexes[0].columns[0].expression = Some("lower(email)".to_string());
+        let sql = ib.generate_sql("test_schema", "test_table", &HashMap::new());
+        assert_eq!(sql, vec!["CREATE INDEX idx_email ON test_schema.test_table ((lower(email))) INCLUDE (id) WHERE deleted_at IS NULL".to_string()]);
+
+        let mut existing = HashMap::new();
+        existing.insert("idx_email".to_string(), PgIndex {
+            index_name: "idx_email".to_string(),
+            is_unique: false,
+            is_valid: true,
+            nulls_not_distinct: false,
+            index_method: "btree".to_string(),
+            columns: vec![PgIndexColumn { column_name: "lower(email)".to_string(), opclass: String::new(), ..Default::default() }],
+            include_columns: vec!["id".to_string()],
+            where_clause: Some("(deleted_at IS NULL)".to_string()),
+            tablespace: None,
+            with_options: BTreeMap::new(),
+            comment: None,
+        });
+        assert!(ib.generate_sql("test_schema", "test_table", &existing).is_empty());
+
+        ib.indexes[0].columns[0].expression = Some("upper(email)".to_string());
+        let sql = ib.generate_sql("test_schema", "test_table", &existing);
+        assert_eq!(sql, vec!["DROP INDEX IF EXISTS test_schema.idx_email".to_string(),
+                             "CREATE INDEX idx_email ON test_schema.test_table ((upper(email))) INCLUDE (id) WHERE deleted_at IS NULL".to_string()]);
+    }
+
+    #[test]
+    fn tablespace_index_test() {
+        let mut ib = builder();
+        ib.indexes[0].tablespace = Some("fast_ssd".to_string());
+        let sql = ib.generate_sql("test_schema", "test_table", &HashMap::new());
+        assert_eq!(sql, vec!["CREATE INDEX idx_email ON test_schema.test_table (email) INCLUDE (id) TABLESPACE fast_ssd WHERE deleted_at IS NULL".to_string()]);
+
+        let mut existing = HashMap::new();
+        existing.insert("idx_email".to_string(), PgIndex {
+            index_name: "idx_email".to_string(),
+            is_unique: false,
+            is_valid: true,
+            nulls_not_distinct: false,
+            index_method: "btree".to_string(),
+            columns: vec![PgIndexColumn { column_name: "email".to_string(), opclass: String::new(), ..Default::default() }],
+            include_columns: vec!["id".to_string()],
+            where_clause: Some("(deleted_at IS NULL)".to_string()),
+            tablespace: None,
+            with_options: BTreeMap::new(),
+            comment: None,
+        });
+        assert_eq!(ib.generate_sql("test_schema", "test_table", &existing).len(), 2);
+        existing.get_mut("idx_email").unwrap().tablespace = Some("fast_ssd".to_string());
+        assert!(ib.generate_sql("test_schema", "test_table", &existing).is_empty());
+    }
+    #[test]
+    fn concurrent_index_test() {
+        let mut ib = builder();
+        ib.indexes[0].concurrently = true;
+        let (in_tx, post_tx) = ib.generate_sql_split("test_schema", "test_table", &HashMap::new(), &MigrationOptions::default());
+        assert_eq!(in_tx.len(), 1);
+        assert!(post_tx.is_empty());
+        let (in_tx, post_tx) = ib.generate_sql_split("test_schema", "test_table", &HashMap::new(), &MigrationOptions::default().allow_concurrent_index(true));
+        assert!(in_tx.is_empty());
+        assert_eq!(post_tx, vec!["CREATE INDEX CONCURRENTLY idx_email ON test_schema.test_table (email) INCLUDE (id) WHERE deleted_at IS NULL".to_string()]);
+
+        let mut existing = HashMap::new();
+        existing.insert("idx_email".to_string(), PgIndex {
+            index_name: "idx_email".to_string(),
+            is_unique: false,
+            is_valid: true,
+            nulls_not_distinct: false,
+            index_method: "btree".to_string(),
+            columns: vec![PgIndexColumn { column_name: "email".to_string(), opclass: String::new(), ..Default::default() }],
+            include_columns: vec![],
+            where_clause: None,
+            tablespace: None,
+            with_options: BTreeMap::new(),
+            comment: None,
+        });
+        let (_, post_tx) = ib.generate_sql_split("test_schema", "test_table", &existing, &MigrationOptions::default().allow_concurrent_index(true));
+        assert_eq!(post_tx[0].as_str(), "DROP INDEX CONCURRENTLY IF EXISTS test_schema.idx_email");
+
+        // the same definition left invalid by a failed CREATE INDEX CONCURRENTLY
+        let pg = existing.get_mut("idx_email").unwrap();
+        pg.include_columns = vec!["id".to_string()];
+        pg.where_clause = Some("(deleted_at IS NULL)".to_string());
+        assert!(ib.generate_sql("test_schema", "test_table", &existing).is_empty());
+        existing.get_mut("idx_email").unwrap().is_valid = false;
+        let (_, post_tx) = ib.generate_sql_split("test_schema", "test_table", &existing, &MigrationOptions::default().allow_concurrent_index(true));
+        assert_eq!(post_tx, vec!["DROP INDEX CONCURRENTLY IF EXISTS test_schema.idx_email".to_string(),
+                                 "CREATE INDEX CONCURRENTLY idx_email ON test_schema.test_table (email) INCLUDE (id) WHERE deleted_at IS NULL".to_string()]);
+    }
+
+    #[test]
+    fn unique_nulls_not_distinct_test() {
+        let mut ib = builder();
+        ib.indexes[0].unique = true;
+        ib.indexes[0].nulls_not_distinct = true;
+        let sql = ib.generate_sql("test_schema", "test_table", &HashMap::new());
+        assert_eq!(sql, vec!["CREATE UNIQUE INDEX idx_email ON test_schema.test_table (email) INCLUDE (id) NULLS NOT DISTINCT WHERE deleted_at IS NULL".to_string()]);
+
+        let mut existing = HashMap::new();
+        existing.insert("idx_email".to_string(), PgIndex {
+            index_name: "idx_email".to_string(),
+            is_unique: true,
+            is_valid: true,
+            nulls_not_distinct: false,
+            index_method: "btree".to_string(),
+            columns: vec![PgIndexColumn { column_name: "email".to_string(), opclass: String::new(), ..Default::default() }],
+            include_columns: vec!["id".to_string()],
+            where_clause: Some("(deleted_at IS NULL)".to_string()),
+            tablespace: None,
+            with_options: BTreeMap::new(),
+            comment: None,
+        });
+        assert_eq!(ib.generate_sql("test_schema", "test_table", &existing).len(), 2);
+        existing.get_mut("idx_email").unwrap().nulls_not_distinct = true;
+        assert!(ib.generate_sql("test_schema", "test_table", &existing).is_empty());
+
+        let err = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: email
+                type: text
+                index: {name: idx_email, nullsNotDistinct: true}
+"#.to_string()).unwrap(), "").unwrap_err();
+        if cfg!(feature = "pg15") {
+            assert!(err.contains("is for a unique index only"));
+        } else {
+            assert!(err.contains("requires the pg15 feature"));
+        }
+    }
+
+    #[test]
+    fn opclass_index_test() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: name
+                type: text
+                index:
+                  name: idx_name_trgm
+                  using: GIN
+                  opclass: gin_trgm_ops
+            - column:
+                name: code
+                type: text
+                index:
+                  opclass: text_pattern_ops
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let ib = IndexBuilder::new(r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap());
+        let sql = ib.generate_sql("test_schema", "test_table", &HashMap::new());
+        assert_eq!(sql, vec!["CREATE INDEX idx_name_trgm ON test_schema.test_table USING gin (name gin_trgm_ops)".to_string(),
+                             "CREATE INDEX idx_test_table_code ON test_schema.test_table (code text_pattern_ops)".to_string()]);
+
+        let mut existing = HashMap::new();
+        existing.insert("idx_name_trgm".to_string(), PgIndex {
+            index_name: "idx_name_trgm".to_string(),
+            is_unique: false,
+            is_valid: true,
+            nulls_not_distinct: false,
+            index_method: "gin".to_string(),
+            columns: vec![PgIndexColumn { column_name: "name".to_string(), opclass: "gin_trgm_ops".to_string(), ..Default::default() }],
+            include_columns: vec![],
+            where_clause: None,
+            tablespace: None,
+            with_options: BTreeMap::new(),
+            comment: None,
+        });
+        existing.insert("idx_test_table_code".to_string(), PgIndex {
+            index_name: "idx_test_table_code".to_string(),
+            is_unique: false,
+            is_valid: true,
+            nulls_not_distinct: false,
+            index_method: "btree".to_string(),
+            columns: vec![PgIndexColumn { column_name: "code".to_string(), opclass: String::new(), ..Default::default() }],
+            include_columns: vec![],
+            where_clause: None,
+            tablespace: None,
+            with_options: BTreeMap::new(),
+            comment: None,
+        });
+        let sql = ib.generate_sql("test_schema", "test_table", &existing);
+        assert_eq!(sql, vec!["DROP INDEX IF EXISTS test_schema.idx_test_table_code".to_string(),
+                             "CREATE INDEX idx_test_table_code ON test_schema.test_table (code text_pattern_ops)".to_string()]);
+        existing.get_mut("idx_test_table_code").unwrap().columns[0].opclass = "text_pattern_ops".to_string();
+        assert!(ib.generate_sql("test_schema", "test_table", &existing).is_empty());
+    }
+
+    #[test]
+    fn reindex_on_change_test() {
+        let mut ib = builder();
+        ib.indexes[0].tablespace = Some("fast_ssd".to_string());
+        let mut existing = HashMap::new();
+        existing.insert("idx_email".to_string(), PgIndex {
+            index_name: "idx_email".to_string(),
+            is_unique: false,
+            is_valid: true,
+            nulls_not_distinct: false,
+            index_method: "btree".to_string(),
+            columns: vec![PgIndexColumn { column_name: "email".to_string(), opclass: String::new(), ..Default::default() }],
+            include_columns: vec!["id".to_string()],
+            where_clause: Some("(deleted_at IS NULL)".to_string()),
+            tablespace: None,
+            with_options: BTreeMap::new(),
+            comment: None,
+        });
+        assert!(IndexBuilder::structural_match(&ib.indexes[0], &existing["idx_email"]));
+        assert!(!IndexBuilder::full_match(&ib.indexes[0], &existing["idx_email"]));
+        let opts = MigrationOptions::default().reindex_on_change(true);
+        let (in_tx, _) = ib.generate_sql_split("test_schema", "test_table", &existing, &opts);
+        assert_eq!(in_tx, vec!["REINDEX (TABLESPACE fast_ssd) INDEX test_schema.idx_email".to_string()]);
+        ib.indexes[0].concurrently = true;
+        let (in_tx, post_tx) = ib.generate_sql_split("test_schema", "test_table", &existing, &opts.allow_concurrent_index(true));
+        assert!(in_tx.is_empty());
+        assert_eq!(post_tx, vec!["REINDEX (TABLESPACE fast_ssd) INDEX CONCURRENTLY test_schema.idx_email".to_string()]);
+
+        // the column list changed, not applicable
+        ib.indexes[0].concurrently = false;
+        existing.get_mut("idx_email").unwrap().columns[0].column_name = "name".to_string();
+        let (in_tx, _) = ib.generate_sql_split("test_schema", "test_table", &existing, &MigrationOptions::default().reindex_on_change(true));
+        assert_eq!(in_tx.len(), 2);
+        assert!(in_tx[0].starts_with("DROP INDEX"));
+    }
+
+    #[test]
+    fn index_comment_test() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: email
+                type: text
+                index:
+                  name: idx_email
+                  description: lookup by email
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let ib = IndexBuilder::new(r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap());
+        assert_eq!(ib.generate_sql("test_schema", "test_table", &HashMap::new()), vec![
+            "CREATE INDEX idx_email ON test_schema.test_table (email)".to_string(),
+            "COMMENT ON INDEX test_schema.idx_email IS 'lookup by email'".to_string()]);
+
+        let mut table = PgTable::default();
+        ib.update_dbc(&mut table);
+        assert!(IndexBuilder::index_matches(&ib.indexes[0], &table.indexes["idx_email"]));
+        assert!(ib.generate_sql("test_schema", "test_table", &table.indexes).is_empty());
+
+        table.indexes.get_mut("idx_email").unwrap().comment = None;
+        assert!(!IndexBuilder::index_matches(&ib.indexes[0], &table.indexes["idx_email"]));
+        assert_eq!(ib.generate_sql("test_schema", "test_table", &table.indexes),
+                   vec!["COMMENT ON INDEX test_schema.idx_email IS 'lookup by email'".to_string()]);
+    }
+
+    #[test]
+    fn order_nulls_collate_test() {
+        let src = r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: name
+                type: text
+                index:
+                  name: idx_name_created
+                  collate: C
+                  unique: true
+                  using: btree
+                  concurrently: true
+            - column:
+                name: created_at
+                type: timestamp
+                index:
+                  name: idx_name_created
+                  order: desc
+                  nulls: last
+"#;
+        let r = parse_yaml_schema(load_schema_from_src(src.to_string()).unwrap(), "").unwrap();
+        let ib = IndexBuilder::new(r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap());
+        assert_eq!(ib.indexes.len(), 1);
+        let idx = &ib.indexes[0];
+        assert!(idx.unique && idx.concurrently);
+        assert_eq!(idx.using.as_str(), "btree");
+        assert_eq!((idx.columns[0].collate.as_str(), idx.columns[0].order.as_str()), ("C", ""));
+        assert_eq!((idx.columns[1].order.as_str(), idx.columns[1].nulls.as_str()), ("DESC", "LAST"));
+        assert_eq!(ib.generate_sql("test_schema", "test_table", &HashMap::new()), vec![
+            "CREATE UNIQUE INDEX idx_name_created ON test_schema.test_table (name COLLATE \"C\", created_at DESC NULLS LAST)".to_string()]);
+
+        let mut table = PgTable::default();
+        ib.update_dbc(&mut table);
+        assert_eq!(table.indexes["idx_name_created"].columns[1].nulls.as_str(), "LAST");
+        assert!(ib.generate_sql("test_schema", "test_table", &table.indexes).is_empty());
+        let existing = table.indexes.get_mut("idx_name_created").unwrap();
+        existing.columns[0].collation = String::new();
+        existing.columns[1].nulls = String::new();
+        assert!(!IndexBuilder::structural_match(&ib.indexes[0], &table.indexes["idx_name_created"]));
+
+        // the default ordering written explicitly is the same as loaded from DB
+        let r = parse_yaml_schema(load_schema_from_src(src.replace("desc", "asc")).unwrap(), "").unwrap();
+        let ib = IndexBuilder::new(r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap());
+        let pg = table.indexes.get_mut("idx_name_created").unwrap();
+        pg.columns[0].collation = "C".to_string();
+        pg.columns[1].order = String::new();
+        assert!(IndexBuilder::structural_match(&ib.indexes[0], pg));
+
+        let r = parse_yaml_schema(load_schema_from_src(src.replace("order: desc", "order: down")).unwrap(), "");
+        assert!(r.unwrap_err().starts_with("column created_at index order DOWN must be ASC or DESC"));
+    }
+}
@@ -1,35 +1,93 @@
+// without the db feature only the yaml parsing is in use, the deploy helpers are kept but not called
+#![cfg_attr(not(feature = "db"), allow(dead_code, unused_imports))]
 #[cfg(feature = "slog")]
 #[macro_use] extern crate slog;
 extern crate yaml_rust;
 
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fs;
+use std::path::Path;
 use std::result::Result;
 use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use lazy_static::lazy_static;
+#[cfg(feature = "db")]
 use postgres::Client;
 
 
+use serde::Serialize;
 use slog::Logger;
 use yaml_rust::YamlLoader;
 use yaml_validator::Validate;
 
 use schema::Schema;
 
-use crate::loader::load_info_schema;
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+#[cfg(feature = "db")]
+use crate::extension::{deploy_extensions, load_info_extensions, parse_yaml_extensions, YamlExtension};
+#[cfg(feature = "db")]
+use crate::foreign::{deploy_servers, load_info_foreign_tables, load_info_servers, parse_yaml_servers, YamlServer};
+#[cfg(feature = "db")]
+use crate::function::load_info_functions;
+#[cfg(feature = "db")]
+use crate::grant::load_info_default_privileges;
+#[cfg(feature = "db")]
+use crate::loader::{load_info_schema, load_info_schema_comments, load_info_schema_owner};
+use crate::loader::InfoSchemaType;
+use crate::options::MigrationOptions;
+use crate::plan::PlanEntry;
+#[cfg(feature = "db")]
+use crate::publication::{deploy_publications, load_info_publications, parse_yaml_publications, YamlPublication};
+#[cfg(feature = "bb8")]
+use crate::report::ProgressEvent;
+use crate::report::MigrationReport;
+#[cfg(feature = "db")]
+use crate::role::{deploy_roles, drop_roles, load_info_roles, parse_yaml_roles, YamlRole};
+#[cfg(feature = "db")]
+use crate::sequence::load_info_sequences;
+#[cfg(feature = "db")]
+use crate::types::TypesLoader;
 use crate::utils::OrderedHashMap;
+use crate::validate::SchemaWarning;
+#[cfg(feature = "db")]
+use crate::view::load_info_views;
 
 use self::yaml_rust::Yaml;
 
 pub mod loader;
+pub mod options;
+pub mod plan;
+pub mod publication;
+pub mod report;
+pub mod role;
 pub mod table;
 pub mod column;
+#[cfg(feature = "db")]
+pub mod db;
+pub mod diff;
+pub mod extension;
+pub mod foreign;
+pub mod function;
+pub mod grant;
+pub mod index;
 pub mod schema;
+pub mod sequence;
+pub mod types;
 pub mod utils;
+pub mod validate;
+pub mod view;
 
 static SCHEMA_YAML: &'static str = include_str!("schema.yaml");
 
+#[cfg(feature = "db")]
+/// interval to retry the advisory lock
+const LOCK_POLL_MS: u64 = 100;
+
 lazy_static! {
     pub(crate) static ref LOG: Arc<RwLock<Option<Logger>>> = Arc::new (RwLock::new(None));
 
@@ -52,42 +110,471 @@ pub(crate) fn log_debug(msg: String) {
     }
 }
 
+#[cfg(feature = "slog")]
+pub(crate) fn log_warn(msg: String) {
+    let log = LOG.read().unwrap();
+    if let Some(l) = &*log {
+        warn!(l, "{}", msg);
+    } else {
+        println!("{}", msg);
+    }
+}
+
 pub fn get_schema() -> Vec<Yaml> {
     SCHEMA_YAMLS.clone()
 }
 
 
+#[cfg(feature = "db")]
 /// simplified migrate
 pub fn migrate1(schema: Yaml, db: &mut Client) -> Result<usize, String> {
-    migrate(schema, db, false, None::<&dyn Fn(Vec<String>) -> Result<(), String>>, "")
+    migrate(schema, db, MigrationOptions::default(), "").map(|r| r.count())
 }
 
+#[cfg(feature = "db")]
 /// main entry point to apply schema from yaml to the database
-/// return statements to execute
+/// return changes made, empty on dry run
 ///
-pub fn migrate(schema: Yaml, dbc: &mut Client, retry: bool,
-               dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>, file_name: &str
-) -> Result<usize, String> {
+pub fn migrate(schema: Yaml, dbc: &mut Client, opts: MigrationOptions, file_name: &str) -> Result<MigrationReport, String> {
+    let started = Instant::now();
     let mut db = dbc.transaction().map_err(|e| format!("{}", e))?;
+    let mut report = migrate_tx(schema, &mut db, &opts, file_name)?;
+    if opts.check_only {
+        let _ = db.rollback().map_err(|e| format!("rollback error: {}", e))?;
+    } else {
+        let _ = db.commit().map_err(|e| format!("committing error: {}", e))?;
+    }
+    report.execute_post_tx(dbc);
+    report.duration_ms = started.elapsed().as_millis();
+    Ok(report)
+}
+
+#[cfg(feature = "db")]
+/// statements to apply by table, nothing changed on the database;
+/// the other objects, i.e. functions, are in the entries without a table
+pub fn migrate_plan(schema: Yaml, dbc: &mut Client, opts: MigrationOptions, file_name: &str) -> Result<Vec<PlanEntry>, String> {
+    let entries = RefCell::new(Vec::new());
+    let store = |sql: Vec<String>| { entries.borrow_mut().push(PlanEntry::statements(sql)); Ok(()) };
+    let plan = |entry: PlanEntry| { entries.borrow_mut().push(entry); Ok(()) };
+    let mut db = dbc.transaction().map_err(|e| format!("{}", e))?;
+    let _ = migrate_tx(schema, &mut db, &opts.dry_run(&store).plan(&plan), file_name)?;
+    let _ = db.rollback().map_err(|e| format!("rollback error: {}", e))?;
+    Ok(entries.into_inner().into_iter().filter(|e| !e.is_empty()).collect())
+}
+
+/// apply schema from yaml to the database using a connection of the pool;
+/// not async underneath: the migration runs on a blocking thread by spawn_blocking with the statements
+/// awaited there one by one, the option callbacks are called on the calling task meanwhile;
+/// cancelled after the commit, the report returned with the statements not run after the commit in post_tx_failed
+#[cfg(feature = "bb8")]
+pub async fn migrate_async(schema: Yaml, pool: &bb8::Pool<bb8_postgres::PostgresConnectionManager<tokio_postgres::NoTls>>,
+                           opts: MigrationOptions<'_>, file_name: &str) -> Result<MigrationReport, String> {
+    let started = Instant::now();
+    let dbc = pool.get_owned().await.map_err(|e| format!("DB pool error: {}", e))?;
+    let (callbacks, mut calls) = tokio::sync::mpsc::unbounded_channel();
+    let detached = opts.detached();
+    let file_name = file_name.to_string();
+    let migration = tokio::task::spawn_blocking(move || migrate_blocking(schema, dbc, detached, callbacks, &file_name));
+    // till the migration thread is done and the sender dropped
+    while let Some(call) = calls.recv().await {
+        match call {
+            Callback::DryRun(sql, done) => { let _ = done.send(opts.dry_run.map_or(Ok(()), |f| f(sql))); }
+            Callback::Plan(entry, done) => { let _ = done.send(opts.plan.map_or(Ok(()), |f| f(entry))); }
+            Callback::Progress(event) => opts.progress(event),
+        }
+    }
+    let mut report = migration.await.map_err(|e| format!("migration thread error: {}", e))??;
+    report.duration_ms = started.elapsed().as_millis();
+    Ok(report)
+}
+
+#[cfg(feature = "bb8")]
+/// option callback of migrate_async to call on the runtime thread, with the result channel
+enum Callback {
+    DryRun(Vec<String>, std::sync::mpsc::Sender<Result<(), String>>),
+    Plan(PlanEntry, std::sync::mpsc::Sender<Result<(), String>>),
+    Progress(ProgressEvent),
+}
+
+#[cfg(feature = "bb8")]
+/// the migration of migrate_async on a blocking thread of the runtime
+fn migrate_blocking(schema: Yaml, mut dbc: bb8::PooledConnection<'static, bb8_postgres::PostgresConnectionManager<tokio_postgres::NoTls>>,
+                    detached: crate::options::Detached, callbacks: tokio::sync::mpsc::UnboundedSender<Callback>,
+                    file_name: &str) -> Result<MigrationReport, String> {
+    let call = |callback: Callback, done: std::sync::mpsc::Receiver<Result<(), String>>| {
+        callbacks.send(callback).map_err(|_| "migration caller gone".to_string())?;
+        done.recv().map_err(|_| "migration caller gone".to_string())?
+    };
+    let store = |sql: Vec<String>| { let (done, result) = std::sync::mpsc::channel(); call(Callback::DryRun(sql, done), result) };
+    let plan = |entry: PlanEntry| { let (done, result) = std::sync::mpsc::channel(); call(Callback::Plan(entry, done), result) };
+    let on_progress = |event: ProgressEvent| { let _ = callbacks.send(Callback::Progress(event)); };
+    let mut opts: MigrationOptions = detached.opts;
+    if detached.dry_run {
+        opts.dry_run = Some(&store);
+    }
+    if detached.plan {
+        opts.plan = Some(&plan);
+    }
+    if detached.on_progress {
+        opts.on_progress = Some(&on_progress);
+    }
+    let handle = tokio::runtime::Handle::current();
+    let db = handle.block_on(dbc.transaction()).map_err(|e| format!("{}", e))?;
+    let mut report = match crate::db::AsyncTransaction::new(&db).and_then(|mut tx| migrate_tx(schema, &mut tx, &opts, file_name)) {
+        Ok(report) => report,
+        Err(e) => {
+            // i.e. cancelled by the cancel_token
+            let _ = handle.block_on(db.rollback()).map_err(|re| format!("{}, rollback error: {}", e, re))?;
+            return Err(e);
+        }
+    };
+    if opts.check_only {
+        let _ = handle.block_on(db.rollback()).map_err(|e| format!("rollback error: {}", e))?;
+    } else {
+        let _ = handle.block_on(db.commit()).map_err(|e| format!("committing error: {}", e))?;
+    }
+    // committed, so on cancel the rest reported as not executed
+    for sql in &report.post_tx_sql {
+        let done = match opts.check_cancelled() {
+            Ok(_) => handle.block_on(dbc.batch_execute(sql.as_str())).map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = done {
+            report.post_tx_failed.push((sql.clone(), e));
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(feature = "db")]
+/// apply all *.yaml and *.yml files of the directory as one schema, the files in name order
+/// with a numeric prefix first, i.e. 2_users.yaml before 10_orders.yaml;
+/// a table defined in more than one file is an error
+pub fn migrate_from_dir(dir: &Path, dbc: &mut Client, opts: MigrationOptions) -> Result<MigrationReport, String> {
+    let started = Instant::now();
+    let parsed = parse_yaml_dir(dir, opts.file_pattern.as_deref())?;
+    let mut db = dbc.transaction().map_err(|e| format!("{}", e))?;
+    let mut report = migrate_schemas_tx(&parsed, &mut db, &opts)?;
+    if opts.check_only {
+        let _ = db.rollback().map_err(|e| format!("rollback error: {}", e))?;
+    } else {
+        let _ = db.commit().map_err(|e| format!("committing error: {}", e))?;
+    }
+    report.execute_post_tx(dbc);
+    report.duration_ms = started.elapsed().as_millis();
+    Ok(report)
+}
+
+#[cfg(feature = "db")]
+/// database level objects and schemas parsed from yaml
+#[derive(Debug)]
+struct ParsedYaml {
+    extensions: Vec<YamlExtension>,
+    roles: Vec<YamlRole>,
+    servers: Vec<YamlServer>,
+    publications: Vec<YamlPublication>,
+    schemas: OrderedHashMap<Schema>,
+}
+
+#[cfg(feature = "db")]
+/// all the yaml files of the directory parsed together
+fn parse_yaml_dir(dir: &Path, file_pattern: Option<&str>) -> Result<ParsedYaml, String> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| format!("load error [{}]: {}", dir.display(), e))? {
+        let path = entry.map_err(|e| format!("load error [{}]: {}", dir.display(), e))?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
+        if path.is_file() && (name.ends_with(".yaml") || name.ends_with(".yml"))
+            && file_pattern.map_or(true, |p| crate::utils::wildcard_match(p, &name)) {
+            files.push((file_order(&name), path));
+        }
+    }
+    files.sort();
+    let mut extensions: Vec<YamlExtension> = Vec::new();
+    let mut roles: Vec<YamlRole> = Vec::new();
+    let mut servers: Vec<YamlServer> = Vec::new();
+    let mut publications: Vec<YamlPublication> = Vec::new();
+    let mut schemas = OrderedHashMap::new();
+    // schema.table: file defined in
+    let mut tables: HashMap<String, String> = HashMap::new();
+    for (_, path) in files {
+        let file_name = path.display().to_string();
+        let yaml = load_schema_from_file(&file_name)?;
+        for e in parse_yaml_extensions(&yaml)? {
+            if !extensions.iter().any(|x| x.name == e.name) {
+                extensions.push(e);
+            }
+        }
+        for r in parse_yaml_roles(&yaml)? {
+            if roles.iter().any(|x| x.name == r.name) {
+                return Err(format!("duplicate role: {} found in file: {}", r.name, file_name));
+            }
+            roles.push(r);
+        }
+        for s in parse_yaml_servers(&yaml)? {
+            if servers.iter().any(|x| x.name == s.name) {
+                return Err(format!("duplicate server: {} found in file: {}", s.name, file_name));
+            }
+            servers.push(s);
+        }
+        for p in parse_yaml_publications(&yaml)? {
+            if publications.iter().any(|x| x.name == p.name) {
+                return Err(format!("duplicate publication: {} found in file: {}", p.name, file_name));
+            }
+            publications.push(p);
+        }
+        for s in parse_yaml_schema(yaml, &file_name)? {
+            for t in &s.tables.list {
+                let name = format!("{}.{}", s.schema_name, t.table_name);
+                if let Some(f) = tables.get(&name) {
+                    return Err(format!("duplicate table definition: {} found in files: {} and {}", name, f, file_name));
+                }
+                tables.insert(name, file_name.clone());
+            }
+            match schemas.get_mut(&s.schema_name) {
+                None => schemas.append(s)?,
+                Some(ss) => ss.merge(s)?,
+            }
+        }
+    }
+    Ok(ParsedYaml { extensions, roles, servers, publications, schemas })
+}
+
+#[cfg(feature = "db")]
+/// numeric prefix of the file name first, then the name
+fn file_order(name: &str) -> (u64, String) {
+    let prefix: String = name.chars().take_while(|c| c.is_ascii_digit()).collect();
+    (prefix.parse().unwrap_or(u64::MAX), name.to_string())
+}
+
+#[cfg(feature = "db")]
+/// apply schema within the transaction, rolled back by the caller on error
+pub(crate) fn migrate_tx(schema: Yaml, db: &mut dyn DbExec, opts: &MigrationOptions, file_name: &str) -> Result<MigrationReport, String> {
+    let extensions = parse_yaml_extensions(&schema)?;
+    let roles = parse_yaml_roles(&schema)?;
+    let servers = parse_yaml_servers(&schema)?;
+    let publications = parse_yaml_publications(&schema)?;
+    let schemas = parse_yaml_schema(schema, file_name)?;
+    migrate_schemas_tx(&ParsedYaml { extensions, roles, servers, publications, schemas }, db, opts)
+}
+
+#[cfg(feature = "db")]
+/// dry run of the DDL, the data rows skipped, schema drift error with the statements to apply if any,
+/// so the report is empty on success
+fn check_drift(parsed: &ParsedYaml, db: &mut dyn DbExec, opts: &MigrationOptions) -> Result<MigrationReport, String> {
+    if opts.data_only {
+        return Err("check_only compares the schema, can not be data_only".to_string());
+    }
+    let statements = RefCell::new(Vec::new());
+    let store = |sql: Vec<String>| {
+        if let Some(dry_run) = opts.dry_run {
+            dry_run(sql.clone())?;
+        }
+        statements.borrow_mut().extend(sql.into_iter().filter(|s| s.trim().len() > 0));
+        Ok(())
+    };
+    let report = migrate_schemas_tx(parsed, db, &opts.clone().check_only(false).schema_only(true).dry_run(&store))?;
+    let statements = statements.into_inner();
+    if statements.len() > 0 {
+        return Err(format!("schema drift, {} statements to apply:\n{}", statements.len(), statements.join("")));
+    }
+    Ok(report)
+}
+
+#[cfg(feature = "db")]
+/// apply parsed schemas within the transaction
+fn migrate_schemas_tx(parsed: &ParsedYaml, db: &mut dyn DbExec, opts: &MigrationOptions) -> Result<MigrationReport, String> {
+    let schemas = &parsed.schemas;
+    if opts.schema_only && opts.data_only {
+        return Err("schema_only and data_only can not be both set".to_string());
+    }
+    if opts.check_only {
+        return check_drift(parsed, db, opts);
+    }
+    if opts.validate_types {
+        let unknown: Vec<String> = validate_column_types(schemas, &validate::known_types(schemas))
+            .iter().map(|w| w.to_string()).collect();
+        if unknown.len() > 0 {
+            return Err(format!("unknown column types:\n{}", unknown.join("\n")));
+        }
+    }
+    let retry = opts.retry;
+    let dry_run = opts.dry_run;
+    let mut report = MigrationReport::default();
     let mut cnt = 0;
+    if let Some(level) = isolation_level(schemas) {
+        let _ = db.batch_execute(format!("SET TRANSACTION ISOLATION LEVEL {}", level).as_str())
+            .map_err(|e| format!("on set isolation level {}: {}", level, e))?;
+    }
     // check db connection
     let db_name: String = db.query("select current_database()", &[])
         .map_err(|e| format!("DB connection error: {}", e))?[0].get(0);
-    // load schema
-    let mut info = load_info_schema(db_name.as_str(), &mut db)?;
-    let schemas = parse_yaml_schema(schema, file_name)?;
+    advisory_lock(db, opts)?;
+    if opts.data_only {
+        for s in &schemas.list {
+            report.merge(s.deploy_all_data(db, dry_run, opts)?);
+        }
+        return Ok(report);
+    }
+    // load schema, only the schemas defined in yaml
+    let schema_names: Vec<&str> = schemas.list.iter().map(|s| s.schema_name.as_str()).collect();
+    let mut info = load_info_schema(db_name.as_str(), db, Some(&schema_names))?;
+    let mut owners = load_info_schema_owner(db_name.as_str(), db)?;
+    let mut installed = load_info_extensions(db)?;
+    cnt += deploy_extensions(&parsed.extensions, &mut installed, db, dry_run)?;
+    let mut roles = if parsed.roles.len() > 0 || opts.drop_roles {
+        load_info_roles(db)?
+    } else {
+        HashMap::new()
+    };
+    if parsed.roles.len() > 0 {
+        cnt += deploy_roles(&parsed.roles, &mut roles, db, dry_run)?;
+    }
+    if parsed.servers.len() > 0 {
+        let mut existing = load_info_servers(db)?;
+        cnt += deploy_servers(&parsed.servers, &mut existing, db, dry_run)?;
+    }
+
+    let mut types = TypesLoader::load(db)?;
+    for s in &schemas.list {
+        cnt += s.deploy_types(&mut types, db, dry_run, opts)?;
+    }
+
+    let mut sequences = load_info_sequences(db_name.as_str(), db)?;
+    for s in &schemas.list {
+        cnt += s.deploy_sequences(&mut sequences, db, dry_run, opts)?;
+    }
+
+    let mut functions = load_info_functions(db_name.as_str(), db)?;
     for s in &schemas.list {
-        cnt += s.deploy_all_tables(&mut info, &mut db, retry, dry_run)?;
+        cnt += s.deploy_functions(&mut functions, db, dry_run)?;
+    }
+
+    if schemas.list.iter().any(|s| s.default_privileges.len() > 0) {
+        let current_user: String = db.query("select current_user::text", &[])
+            .map_err(|e| format!("on loading current user: {}", e))?[0].get(0);
+        let mut default_privileges = load_info_default_privileges(db, Some(&schema_names))?;
+        for s in &schemas.list {
+            cnt += s.deploy_default_privileges(&mut default_privileges, current_user.as_str(), db, dry_run, opts)?;
+        }
+    }
+
+    for s in &schemas.list {
+        report.merge(s.deploy_all_tables(&mut info, &mut owners, db, retry, dry_run, opts)?);
+    }
+
+    if schemas.list.iter().any(|s| s.description.len() > 0) {
+        let mut comments = load_info_schema_comments(db, Some(&schema_names))?;
+        for s in &schemas.list {
+            cnt += s.deploy_comment(&mut comments, db, dry_run)?;
+        }
     }
 
     for s in &schemas.list {
-        cnt += s.deploy_all_fk(&schemas, &mut info, &mut db, retry, dry_run)?;
+        cnt += s.deploy_sequences_owner(&mut sequences, db, dry_run)?;
+    }
+
+    for s in &schemas.list {
+        cnt += s.deploy_all_fk(schemas, &mut info, db, retry, dry_run, opts)?;
+    }
+
+    if schemas.list.iter().any(|s| s.foreign_tables.len() > 0) {
+        let mut foreign_tables = load_info_foreign_tables(db)?;
+        for s in &schemas.list {
+            cnt += s.deploy_foreign_tables(&mut foreign_tables, db, dry_run)?;
+        }
+    }
+
+    if parsed.publications.len() > 0 {
+        let mut publications = load_info_publications(db)?;
+        cnt += deploy_publications(&parsed.publications, &mut publications, db, dry_run)?;
+    }
+
+    let mut views = load_info_views(db_name.as_str(), db)?;
+    for s in &schemas.list {
+        cnt += s.deploy_views(&mut views, db, dry_run)?;
+    }
+
+    if opts.drop_functions {
+        for s in &schemas.list {
+            cnt += s.drop_functions(schemas, &mut functions, db, dry_run)?;
+        }
+    }
+
+    if opts.drop_roles {
+        cnt += drop_roles(&parsed.roles, &mut roles, db, dry_run)?;
+    }
+
+    report.objects_changed = cnt;
+    Ok(report)
+}
+
+#[cfg(feature = "db")]
+/// the strictest isolation level of the tables, the whole migration runs in one transaction,
+/// so set before any statement
+fn isolation_level(schemas: &OrderedHashMap<Schema>) -> Option<&'static str> {
+    schemas.list.iter()
+        .flat_map(|s| s.tables.list.iter())
+        .filter_map(|t| t.isolation_level())
+        .filter_map(|l| table::ISOLATION_LEVELS.iter().position(|x| *x == l))
+        .max()
+        .map(|i| table::ISOLATION_LEVELS[i])
+}
+
+#[cfg(feature = "db")]
+/// wait for other migrations on the database, the lock released on the transaction end
+fn advisory_lock(db: &mut dyn DbExec, opts: &MigrationOptions) -> Result<(), String> {
+    let lock_id = opts.lock_id();
+    let timeout = opts.lock_timeout_ms.map(Duration::from_millis);
+    let started = Instant::now();
+    loop {
+        let locked: bool = db.query("SELECT pg_try_advisory_xact_lock($1)", &[&lock_id])
+            .map_err(|e| format!("on advisory lock {}: {}", lock_id, e))?[0].get(0);
+        if locked {
+            return Ok(());
+        }
+        if timeout.map_or(false, |t| started.elapsed() >= t) {
+            return Err(format!("lock timeout: another migration holds the advisory lock {} over {}ms",
+                               lock_id, opts.lock_timeout_ms.unwrap_or_default()));
+        }
+        thread::sleep(Duration::from_millis(LOCK_POLL_MS));
+    }
+}
+
+/// wrapper to get the yaml file format
+#[derive(Serialize)]
+struct DatabaseVO<'a> {
+    database: &'a OrderedHashMap<Schema>,
+}
+
+#[cfg(feature = "db")]
+/// export the existing database schema as yaml, to bootstrap a schema file
+pub fn dump_schema(dbc: &mut Client, db_name: &str) -> Result<String, String> {
+    let mut db = dbc.transaction().map_err(|e| format!("{}", e))?;
+    let info = load_info_schema(db_name, &mut db, None)?;
+    let owners = load_info_schema_owner(db_name, &mut db)?;
+    let comments = load_info_schema_comments(&mut db, None)?;
+    let types = TypesLoader::load(&mut db)?;
+    let _ = db.rollback();
+    let mut schemas = info_to_schema(&info, |s| owners.get(s).map_or("".to_string(), |(o, _, _)| o.clone()));
+    for s in &mut schemas.list {
+        s.append_types(&types);
+        s.description = comments.get(&s.schema_name).cloned().unwrap_or_default();
     }
+    dump_yaml(&schemas)
+}
 
-    let _ = db.commit().map_err(|e| format!("committing error: {}", e))?;
-    Ok(cnt)
+/// convert information schema to the schema definitions, owner of a schema by name
+pub fn info_to_schema(info: &InfoSchemaType, owner: impl Fn(&str) -> String) -> OrderedHashMap<Schema> {
+    info.iter()
+        .map(|(name, tables)| Schema::from_info(name, owner(name).as_str(), tables))
+        .collect()
 }
 
+/// serialize the schema definitions in the format of load_schema_from_src
+pub fn dump_yaml(schemas: &OrderedHashMap<Schema>) -> Result<String, String> {
+    serde_yaml::to_string(&DatabaseVO { database: schemas })
+        .map_err(|e| format!("yaml serialization error: {}", e))
+}
 
 pub fn load_schema_from_file(filename_yaml: &str) -> Result<Yaml, String> {
     match fs::read_to_string(filename_yaml) {
@@ -96,6 +583,21 @@ pub fn load_schema_from_file(filename_yaml: &str) -> Result<Yaml, String> {
     }
 }
 
+/// the schema yaml as the value of the environment variable, i.e. injected from a ConfigMap
+pub fn load_schema_from_env(var_name: &str) -> Result<Yaml, String> {
+    match std::env::var(var_name) {
+        Ok(data) => load_schema_from_src(data),
+        Err(e) => Err(format!("load error [${}]: {}", var_name, e))
+    }
+}
+
+/// the schema yaml of the environment variable if set and not empty, otherwise of the file
+pub fn load_schema_from_env_or_file(env_var: &str, fallback_file: &str) -> Result<Yaml, String> {
+    match std::env::var_os(env_var) {
+        Some(v) if v.len() > 0 => load_schema_from_env(env_var),
+        _ => load_schema_from_file(fallback_file),
+    }
+}
 
 pub fn load_schema_from_src(data: String) -> Result<Yaml, String> {
      match YamlLoader::load_from_str(data.as_str()) {
@@ -116,7 +618,7 @@ pub fn load_schema_from_src(data: String) -> Result<Yaml, String> {
     }
 }
 
-/// filename is for logging reference only
+/// filename is for logging reference only; a foreign key cycle between the tables is an error
 pub fn parse_yaml_schema(yaml: Yaml, file_name: &str) -> Result<OrderedHashMap<Schema>, String> {
     match yaml["database"].as_vec() {
         None => Err("empty file".to_string()),
@@ -124,25 +626,61 @@ pub fn parse_yaml_schema(yaml: Yaml, file_name: &str) -> Result<OrderedHashMap<S
             let mut schema_schemas = OrderedHashMap::new();
 
             for s in schemas {
-                match schema_schemas.get_mut(&Schema::schema_name(s)) {
-                    None => {
-                        let mut ss = Schema::new(&s, file_name);
-                        let _ = ss.append(s)?;
-                        let _ = schema_schemas.append(ss);
-                    }
-                    Some(ss) => ss.append(s)?
+                let mut ss = Schema::new(&s, file_name);
+                let _ = ss.append(s)?;
+                match schema_schemas.get_mut(&ss.schema_name) {
+                    None => schema_schemas.append(ss)?,
+                    Some(existing) => existing.merge(ss)?,
                 }
             }
+            if let Some(path) = validate::detect_fk_cycles(&schema_schemas) {
+                return Err(format!("foreign key cycle: {}", path.join(" -> ")));
+            }
             Ok(schema_schemas)
         }
     }
 }
 
+/// semantic checks of the parsed schema without a database, i.e. FK targets, PK and index columns, FK cycles;
+/// the errors fail the deploy, the warnings are up to the caller
+pub fn validate_schema(schemas: &OrderedHashMap<Schema>) -> Vec<SchemaWarning> {
+    let mut warnings = validate::validate(schemas);
+    warnings.extend(validate_column_types(schemas, &validate::known_types(schemas)));
+    warnings
+}
 
+/// foreign keys to the tables not found in yaml, standalone without a database; validate_schema does it too:
+/// an error if the referenced schema defined in the same file, otherwise a warning
+pub fn validate_fk_targets(schemas: &OrderedHashMap<Schema>) -> Vec<SchemaWarning> {
+    validate::validate_fk_targets(schemas)
+}
 
-#[cfg(test)]
+/// the first foreign key cycle as schema.table path back to its first table, i.e. orders → customer → orders;
+/// parse_yaml_schema fails on a cycle, this one is for the schemas merged after parsing, i.e. of several files
+pub fn detect_fk_cycles(schemas: &OrderedHashMap<Schema>) -> Option<Vec<String>> {
+    validate::detect_fk_cycles(schemas)
+}
+
+/// columns of a type not in known_types, i.e. a typo as INTERGER;
+/// the standard PostgreSQL types with the types of the yaml are known_types of validate_schema
+pub fn validate_column_types(schemas: &OrderedHashMap<Schema>, known_types: &HashSet<String>) -> Vec<SchemaWarning> {
+    validate::validate_column_types(schemas, known_types)
+}
+
+#[cfg(all(test, feature = "db"))]
 mod tests {
-    use crate::{load_schema_from_file, parse_yaml_schema};
+    use std::collections::{BTreeMap, HashMap};
+    use std::convert::TryFrom;
+
+    use crate::column::Column;
+    use crate::{dump_yaml, info_to_schema, load_schema_from_env, load_schema_from_env_or_file, load_schema_from_file, load_schema_from_src,
+                parse_yaml_schema};
+    use crate::index::IndexBuilder;
+    use crate::loader::{InfoSchemaType, PgTable};
+    use crate::options::MigrationOptions;
+    use crate::schema::Schema;
+    use crate::table::Table;
+    use crate::utils::OrderedHashMap;
 
     #[test]
     fn test_schema() {
@@ -171,4 +709,255 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_serde_round_trip() {
+        let r = parse_yaml_schema(load_schema_from_file("tests/example.yaml").unwrap(), "").unwrap();
+        let json = serde_json::to_string(&r).unwrap();
+        let back: OrderedHashMap<Schema> = serde_json::from_str(json.as_str()).unwrap();
+        assert_eq!(json, serde_json::to_string(&back).unwrap());
+        let yaml = serde_yaml::to_string(&r).unwrap();
+        let back: OrderedHashMap<Schema> = serde_yaml::from_str(yaml.as_str()).unwrap();
+        let t = back.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap();
+        assert_eq!(t.columns.len(), 2);
+        assert!(t.columns.list.get(0).unwrap().is_pk());
+    }
+
+    #[test]
+    fn test_dump_round_trip() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: users
+          checks:
+            - id < 1000000
+          columns:
+            - column:
+                name: id
+                type: bigint
+                constraint:
+                  primaryKey: true
+                  nullable: false
+            - column:
+                name: email
+                type: text
+                defaultValue: "'none'"
+                sql: UNIQUE
+                index:
+                  name: idx_users_email
+                  where: email IS NOT NULL
+      - table:
+          tableName: orders
+          columns:
+            - column:
+                name: user_id
+                type: bigint
+                constraint:
+                  nullable: false
+                  foreignKey:
+                    references: test_schema.users
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let s = "test_schema".to_string();
+        let mut info = InfoSchemaType::new();
+        let mut tables = HashMap::new();
+        for t in &r.list.get(0).unwrap().tables {
+            let mut pt = PgTable { table_name: t.table_name.clone(), ..PgTable::default() };
+            for (i, c) in t.columns.list.iter().enumerate() {
+                let mut cd = c.column_def(&s, &t.table_name, "").unwrap();
+                cd.sort_order = i;
+                pt.columns.insert(c.name.clone(), cd);
+            }
+            for (i, c) in t.checks.iter().enumerate() {
+                pt.checks.insert(format!("chk_{}_{}", t.table_name, i + 1), c.clone());
+            }
+            IndexBuilder::new(t).update_dbc(&mut pt);
+            tables.insert(t.table_name.clone(), pt);
+        }
+        info.insert(s.clone(), tables);
+
+        let dump = dump_yaml(&info_to_schema(&info, |_| "postgres".to_string())).unwrap();
+        let back = parse_yaml_schema(load_schema_from_src(dump).unwrap(), "").unwrap();
+        let bs = back.list.get(0).unwrap();
+        assert_eq!(bs.owner.as_str(), "postgres");
+        for t in &r.list.get(0).unwrap().tables {
+            let bt = bs.tables.get(&t.table_name).unwrap();
+            assert_eq!(t.checks, bt.checks);
+            assert_eq!(t.columns.keys().collect::<Vec<&str>>(), bt.columns.keys().collect::<Vec<&str>>());
+            for c in &t.columns {
+                let bc = bt.columns.get(&c.name).unwrap();
+                assert_eq!(c.column_def(&s, &t.table_name, "").unwrap().def(false),
+                           bc.column_def(&s, &t.table_name, "").unwrap().def(false));
+                assert_eq!(c.index.as_ref().map(|i| i.name.clone()), bc.index.as_ref().map(|i| i.name.clone()));
+                assert_eq!(c.constraint.as_ref().and_then(|c| c.foreign_key.as_ref()).map(|f| f.references.clone()),
+                           bc.constraint.as_ref().and_then(|c| c.foreign_key.as_ref()).map(|f| f.references.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_yaml_dir() {
+        let dir = std::env::temp_dir().join(format!("schema_guard_dir_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let table = |schema: &str, table: &str| format!(r#"
+database:
+  - schemaName: {}
+    tables:
+      - table:
+          tableName: {}
+          columns:
+            - column:
+                name: id
+                type: integer
+"#, schema, table);
+        std::fs::write(dir.join("10_orders.yaml"), table("shop", "orders")).unwrap();
+        std::fs::write(dir.join("2_users.yaml"), format!("extensions:\n  - pgcrypto\n{}", table("shop", "users"))).unwrap();
+        std::fs::write(dir.join("audit.yml"), format!("extensions:\n  - pgcrypto\n{}", table("audit", "log"))).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a schema").unwrap();
+
+        let parsed = crate::parse_yaml_dir(&dir, None).unwrap();
+        assert_eq!(parsed.extensions.len(), 1);
+        let schemas = parsed.schemas;
+        assert_eq!(schemas.keys().collect::<Vec<&str>>(), vec!["shop", "audit"]);
+        let shop = schemas.get(&"shop".to_string()).unwrap();
+        assert_eq!(shop.tables.keys().collect::<Vec<&str>>(), vec!["users", "orders"]);
+        assert_eq!(shop.file, format!("{}, {}", dir.join("2_users.yaml").display(), dir.join("10_orders.yaml").display()));
+
+        let schemas = crate::parse_yaml_dir(&dir, Some("*_*.y?ml")).unwrap().schemas;
+        assert_eq!(schemas.keys().collect::<Vec<&str>>(), vec!["shop"]);
+
+        std::fs::write(dir.join("3_users.yaml"), table("shop", "users")).unwrap();
+        let err = crate::parse_yaml_dir(&dir, None).unwrap_err();
+        assert!(err.starts_with("duplicate table definition: shop.users found in files: "));
+        assert!(err.contains("2_users.yaml and ") && err.ends_with("3_users.yaml"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_isolation_level() {
+        let src = |transaction: &str, level: &str| format!(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: a
+          transaction: {}
+          isolation_level: {}
+          columns:
+            - column:
+                name: id
+                type: integer
+      - table:
+          tableName: b
+          transaction: table
+          isolation_level: read committed
+          columns:
+            - column:
+                name: id
+                type: integer
+"#, transaction, level);
+        let err = parse_yaml_schema(load_schema_from_src(src("table", "snapshot")).unwrap(), "").unwrap_err();
+        assert_eq!(err.as_str(), "table: a isolation level SNAPSHOT is not one of READ UNCOMMITTED, READ COMMITTED, REPEATABLE READ, SERIALIZABLE, found in file: ");
+        let r = parse_yaml_schema(load_schema_from_src(src("table", "serializable")).unwrap(), "").unwrap();
+        assert_eq!(crate::isolation_level(&r), Some("SERIALIZABLE"));
+        let r = parse_yaml_schema(load_schema_from_src(src("single", "serializable")).unwrap(), "").unwrap();
+        assert_eq!(crate::isolation_level(&r), Some("READ COMMITTED"));
+    }
+
+    #[test]
+    fn test_load_schema_from_env() {
+        let var = "SCHEMA_GUARD_TEST_LOAD_SCHEMA";
+        std::env::remove_var(var);
+        assert!(load_schema_from_env(var).unwrap_err().starts_with("load error [$SCHEMA_GUARD_TEST_LOAD_SCHEMA]"));
+        assert!(load_schema_from_env_or_file(var, "no_such_file.yaml").unwrap_err().starts_with("load error [no_such_file.yaml]"));
+        std::env::set_var(var, r#"
+database:
+  - schemaName: from_env
+    tables:
+      - table:
+          tableName: test_table
+"#);
+        let r = parse_yaml_schema(load_schema_from_env(var).unwrap(), "").unwrap();
+        assert_eq!(r.list[0].schema_name.as_str(), "from_env");
+        let r = parse_yaml_schema(load_schema_from_env_or_file(var, "no_such_file.yaml").unwrap(), "").unwrap();
+        assert_eq!(r.list[0].schema_name.as_str(), "from_env");
+        std::env::set_var(var, "database: [");
+        assert!(load_schema_from_env(var).unwrap_err().starts_with("parsing error"));
+        std::env::remove_var(var);
+    }
+
+    #[test]
+    fn test_try_from_yaml() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: shop
+    tables:
+      - table:
+          tableName: orders
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+            - column:
+                name: total
+                type: numeric
+"#.to_string()).unwrap();
+        let s = &yaml["database"][0];
+        let t = &s["tables"][0]["table"];
+        assert_eq!(Column::try_from(&t["columns"][1]["column"]).unwrap().column_type.as_str(), "numeric");
+        assert_eq!(Column::try_from(&t["columns"][9]["column"]).unwrap_err().as_str(), "no column name set");
+        let table = Table::try_from(t).unwrap();
+        assert_eq!(table.table_name.as_str(), "orders");
+        assert!(table.columns.get(&"id".to_string()).unwrap().is_pk());
+        assert_eq!(Table::try_from(&s["tables"][1]["table"]).unwrap_err().as_str(), "no table name set");
+        let schema = Schema::try_from(s).unwrap();
+        assert_eq!(schema.schema_name.as_str(), "shop");
+        assert_eq!(schema.tables.keys().collect::<Vec<&str>>(), vec!["orders"]);
+
+        let yaml = load_schema_from_src(r#"
+column:
+  name: total
+  type: numeric
+  statistics: -2
+"#.to_string()).unwrap();
+        assert_eq!(Column::try_from(&yaml["column"]).unwrap_err().as_str(), "column total statistics -2 out of range -1 to 10000");
+    }
+
+    #[test]
+    fn test_composite_type_dump() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    types:
+      - type:
+          name: address
+          kind: composite
+          attributes:
+            - name: street
+              type: text
+            - name: zip
+              type: varchar(10)
+"#.to_string()).unwrap(), "").unwrap();
+        let t = r.list[0].types.list[0].clone();
+        let opts = MigrationOptions::default();
+        let mut types = crate::types::TypesLoader::default();
+        assert_eq!(t.deploy_sql(&types, &opts).unwrap(), vec!["CREATE TYPE test_schema.address AS (street text, zip varchar(10))".to_string()]);
+
+        // as loaded from DB after the create
+        types.composites.insert(t.full_name(), t.attributes.iter()
+            .map(|a| crate::types::TypeAttribute { name: a.name.clone(), attr_type: a.attr_type.replace("varchar", "character varying") })
+            .collect());
+        let mut dumped = info_to_schema(&BTreeMap::from([("test_schema".to_string(), HashMap::new())]), |_| "".to_string());
+        dumped.list[0].append_types(&types);
+        let yaml = dump_yaml(&dumped).unwrap();
+        assert!(yaml.contains("type: character varying(10)"), "{}", yaml);
+        let r = parse_yaml_schema(load_schema_from_src(yaml).unwrap(), "").unwrap();
+        assert_eq!(r.list[0].types.list[0].attributes.len(), 2);
+        assert!(r.list[0].types.list[0].deploy_sql(&types, &opts).unwrap().is_empty());
+        assert!(t.deploy_sql(&types, &opts).unwrap().is_empty());
+    }
 }
@@ -1,20 +1,31 @@
+#[cfg(feature = "db")]
 extern crate postgres;
 
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
 use std::result::Result;
 
-use postgres::Transaction;
 use serde::Serialize;
 
-use crate::utils::{Named, OrderedHashMap};
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+use crate::column::{FkAction, FkMatch, IdentityMode};
+use crate::grant::{PgColumnGrant, PgGrant};
+use crate::table::ReplicaIdentity;
+use crate::utils::{quote_if_needed, Named, OrderedHashMap};
 
 /// information schema types: schema, table, column
 // BTreeMap use to keep dump schema order
 pub type InfoSchemaType = BTreeMap<String, HashMap<String, PgTable>>;
 pub type InfoSchemaTypeS = OrderedHashMap<OrderedHashMap<PgTable>>;
 
-/// information schema types: schema, (owner, table: name: owner)
-pub type InfoSchemaOwnerType = HashMap<String, (String, HashMap<String, String>)>;
+/// tablespace of the cluster, not dumped to yaml
+pub const DEFAULT_TABLESPACE: &str = "pg_default";
+
+/// information schema types: schema, (owner, table: name: owner, grantee: schema privileges)
+pub type InfoSchemaOwnerType = HashMap<String, (String, HashMap<String, String>, HashMap<String, PgGrant>)>;
+
+/// schema name: comment
+pub type InfoSchemaCommentType = HashMap<String, String>;
 
 #[derive(Debug, Clone, Serialize)]
 /// information schema data
@@ -41,14 +52,43 @@ pub struct PgTable {
     pub table_name: String,
     /// column name,
     pub columns: HashMap<String, PgColumnDfn>,
-    /// column name, foreign schema, table, column, fk name
+    /// constraint name, foreign schema, table, column
     pub fks: HashMap<String, FKTable>,
-    /// trigger name, trigger's schema
+    /// trigger name, pg_get_triggerdef
     pub triggers: HashMap<String, String>,
+    /// check constraint name, expression
+    pub checks: HashMap<String, String>,
+    /// exclusion constraint name, pg_get_constraintdef
+    pub excludes: HashMap<String, String>,
+    /// primary key constraint name
+    pub pk_name: Option<String>,
+    /// index name
+    pub indexes: HashMap<String, PgIndex>,
+    /// relrowsecurity
+    pub row_security: bool,
+    /// policy name
+    pub policies: HashMap<String, PgPolicy>,
+    /// pg_class.relpersistence: p = permanent, u = unlogged, t = temporary
+    pub persistence: char,
+    /// partitioned table key as pg_get_partkeydef, i.e. RANGE (created_at)
+    pub partition_key: Option<String>,
+    /// the database default resolved to its name, i.e. pg_default
+    pub tablespace: Option<String>,
+    /// schema.table of pg_inherits in inheritance order, except the partitioned parent
+    pub parents: Vec<String>,
+    /// pg_class.reloptions, the toast table ones prefixed by toast.
+    pub with_options: BTreeMap<String, String>,
+    /// pg_class.relreplident
+    pub replica_identity: ReplicaIdentity,
     pub sort_order: usize,
     pub table_comment: Option<String>,
     pub owner: Option<String>,
-    // pub grant: HashMap<String, String>,
+    /// grantee
+    pub grants: HashMap<String, PgGrant>,
+    /// grantee: privileges granted on the columns
+    pub column_grants: HashMap<String, Vec<PgColumnGrant>>,
+    /// provider: label
+    pub security_labels: HashMap<String, String>,
 }
 
 const _PRIVILEGES: [&str; 14] = [
@@ -68,6 +108,55 @@ const _PRIVILEGES: [&str; 14] = [
     "ALTER SYSTEM"
 ];
 
+/// index information loaded from DB
+#[derive(Debug, Clone, Serialize)]
+pub struct PgIndex {
+    pub index_name: String,
+    pub is_unique: bool,
+    /// pg_index.indisvalid, false if CREATE INDEX CONCURRENTLY failed
+    pub is_valid: bool,
+    /// pg_index.indnullsnotdistinct, false before PostgreSQL 15
+    pub nulls_not_distinct: bool,
+    /// access method, i.e. btree, gin
+    pub index_method: String,
+    pub columns: Vec<PgIndexColumn>,
+    /// non key columns of a covering index
+    pub include_columns: Vec<String>,
+    /// partial index predicate
+    pub where_clause: Option<String>,
+    /// the database default resolved to its name, i.e. pg_default
+    pub tablespace: Option<String>,
+    /// pg_class.reloptions
+    pub with_options: BTreeMap<String, String>,
+    /// pg_description of the index
+    pub comment: Option<String>,
+}
+
+/// row level security policy loaded from DB
+#[derive(Debug, Clone, Serialize)]
+pub struct PgPolicy {
+    pub name: String,
+    pub command: String,
+    pub permissive: bool,
+    pub roles: Vec<String>,
+    pub using_expr: Option<String>,
+    pub check_expr: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PgIndexColumn {
+    /// column name or an expression
+    pub column_name: String,
+    /// pg_opclass.opcname, empty for the default operator class of the column type
+    pub opclass: String,
+    /// DESC or empty for the default ASC
+    pub order: String,
+    /// FIRST or LAST if not the default of the order, empty otherwise
+    pub nulls: String,
+    /// pg_collation.collname, empty if the collation of the column
+    pub collation: String,
+}
+
 /// information schema column data
 #[derive(Debug, Clone, Serialize)]
 pub struct PgColumnDfn {
@@ -78,8 +167,22 @@ pub struct PgColumnDfn {
     pub fk: Option<(String, String)>,
     pub pk: bool,
     pub nullable: bool,
+    /// CHECK expression of a single column constraint
+    pub check: Option<String>,
     pub sort_order: usize,
     pub column_comment: Option<String>,
+    /// information_schema.columns.identity_generation
+    pub identity: Option<IdentityMode>,
+    /// sequence options of a new identity column
+    pub identity_seq_options: String,
+    /// pg_attribute.attstorage
+    pub storage: Option<char>,
+    /// pg_attribute.attstattarget, -1 for the system default
+    pub statistics_target: Option<i16>,
+    /// provider: label
+    pub security_labels: HashMap<String, String>,
+    /// information_schema.columns.generation_expression of a stored generated column
+    pub generated_as: Option<String>,
     // pub grant: list<String>,
 }
 
@@ -88,21 +191,36 @@ pub struct PgColumnDfn {
 pub struct FKTable {
     pub schema: String,
     pub table: String,
-    pub column: HashSet<String>,
+    /// referenced columns, in the order of local_columns
+    pub column: Vec<String>,
+    /// constraint name
     pub name: String,
+    /// columns of the referencing table
+    pub local_columns: Vec<String>,
     pub sql: String,
+    pub match_type: FkMatch,
+    pub on_update: FkAction,
+    pub on_delete: FkAction,
+    pub deferrable: bool,
+    pub initially_deferred: bool,
 }
 
 impl FKTable {
-    pub(crate) fn columns(&self) -> String {
-        let mut cs = String::new();
-        for c in &self.column {
-            if cs.len() > 0 {
-                cs.push_str(", ");
-            }
-            cs.push_str(c.as_str())
+    /// DEFERRABLE clause of the constraint
+    pub(crate) fn deferrable_sql(&self) -> &'static str {
+        match (self.deferrable, self.initially_deferred) {
+            (_, true) => "DEFERRABLE INITIALLY DEFERRED",
+            (true, false) => "DEFERRABLE INITIALLY IMMEDIATE",
+            _ => "",
         }
-        cs
+    }
+
+    pub(crate) fn columns(&self) -> String {
+        self.column.join(", ")
+    }
+
+    pub(crate) fn local_columns(&self) -> String {
+        self.local_columns.join(", ")
     }
 }
 
@@ -116,13 +234,26 @@ impl PgColumnDfn {
             fk: None,
             pk: false,
             nullable,
+            check: None,
             sort_order,
             column_comment: None,
+            identity: None,
+            identity_seq_options: "".to_string(),
+            storage: None,
+            statistics_target: None,
+            security_labels: HashMap::new(),
+            generated_as: None,
         }
     }
 
     pub(crate) fn def(&self, ignore_pk: bool) -> String {
         let mut sql = format!("{} {}", self.column_name, self.column_type);
+        if let Some(identity) = &self.identity {
+            sql.push_str(self.identity_sql(identity).as_str());
+        }
+        if let Some(expr) = &self.generated_as {
+            sql.push_str(format!(" GENERATED ALWAYS AS ({}) STORED", expr).as_str());
+        }
         if self.pk && !ignore_pk {
             sql.push_str(" primary key");
         }
@@ -135,40 +266,75 @@ impl PgColumnDfn {
                 sql.push_str(def.as_str());
             }
         }
+        if let Some(check) = &self.check {
+            if check.len() > 0 {
+                sql.push_str(" CHECK (");
+                sql.push_str(check.as_str());
+                sql.push_str(")");
+            }
+        }
         if let Some(ssql) = &self.sql {
             if ssql.len() > 0 {
+                sql.push(' ');
                 sql.push_str(ssql.as_str());
             }
         }
         sql
     }
+
+    /// GENERATED ... AS IDENTITY with the sequence options if any
+    pub(crate) fn identity_sql(&self, identity: &IdentityMode) -> String {
+        if self.identity_seq_options.len() > 0 {
+            format!(" GENERATED {} AS IDENTITY ({})", identity.sql(), self.identity_seq_options)
+        } else {
+            format!(" GENERATED {} AS IDENTITY", identity.sql())
+        }
+    }
 }
 
 
-pub fn load_info_schema(db_name: &str, db: &mut Transaction) -> Result<InfoSchemaType, String> {
-    let mut data = load_info_cc(db_name, db)?;
-    let _ = load_info_fk(db_name, db, &mut data)?;
-    let _ = load_info_tg(db_name, db, &mut data)?;
+/// tables of the schemas, all except the system ones if not set
+#[cfg(feature = "db")]
+pub fn load_info_schema(db_name: &str, db: &mut dyn DbExec, schemas: Option<&[&str]>) -> Result<InfoSchemaType, String> {
+    let mut data = load_info_cc(db_name, db, schemas)?;
+    let _ = load_info_fk(db_name, db, &mut data, schemas)?;
+    let _ = load_info_tg(db_name, db, &mut data, schemas)?;
+    let _ = load_info_ck(db_name, db, &mut data, schemas)?;
+    let _ = load_info_ix(db_name, db, &mut data, schemas)?;
+    let _ = load_info_policies(db_name, db, &mut data, schemas)?;
+    let _ = load_info_persistence(db_name, db, &mut data, schemas)?;
+    let _ = load_info_tablespace(db_name, db, &mut data, schemas)?;
+    let _ = load_info_reloptions(db_name, db, &mut data, schemas)?;
+    let _ = load_info_inherits(db_name, db, &mut data, schemas)?;
+    let _ = load_info_replica_identity(db_name, db, &mut data, schemas)?;
+    let _ = load_info_attributes(db_name, db, &mut data, schemas)?;
+    #[cfg(feature = "security_labels")]
+    let _ = load_info_security_labels(db_name, db, &mut data, schemas)?;
+    let _ = load_info_grants(db_name, db, &mut data, schemas)?;
     Ok(data)
 }
 
 
 // SELECT table_catalog, table_schema, table_name, column_name, column_default, is_nullable, data_type, udt_name, character_maximum_length, numeric_precision, numeric_scale, ordinal_position from information_schema.columns where table_schema not in ('pg_catalog', 'information_schema') and table_name = table_catalog = $1
 #[inline]
-fn load_info_cc(db_name: &str, db: &mut Transaction) -> Result<InfoSchemaType, String> {
+#[cfg(feature = "db")]
+fn load_info_cc(db_name: &str, db: &mut dyn DbExec, schemas: Option<&[&str]>) -> Result<InfoSchemaType, String> {
     let mut data: InfoSchemaType = Default::default();
     let result = db.query("SELECT table_catalog, table_schema, table_name, column_name, column_default, is_nullable, \
-    data_type, udt_name, character_maximum_length, numeric_precision, numeric_scale, ordinal_position \
-     from information_schema.columns where table_schema not in ('pg_catalog', 'information_schema') and table_catalog = $1 \
-      order by 1,2,3, ordinal_position", &[&db_name])
+    data_type, udt_name, character_maximum_length, numeric_precision, numeric_scale, ordinal_position, identity_generation, \
+    generation_expression, domain_schema, domain_name from information_schema.columns where table_schema not in ('pg_catalog', 'information_schema') and table_catalog = $1 \
+      and (table_schema, table_name) not in (select foreign_table_schema, foreign_table_name from information_schema.foreign_tables) \
+      and (table_schema, table_name) not in (select table_schema, table_name from information_schema.views) \
+      and ($2::text[] is null or table_schema = any($2)) \
+      order by 1,2,3, ordinal_position", &[&db_name, &schemas])
         .map_err(|e| format!("on loading information_schema [{}]: {}", db_name, e))?;
     let mut sort_order = 0;
     for r in result {
         sort_order += 1;
         let _table_catalog: &str = r.get(0);
         let table_schema: &str = r.get(1);
-        let table_name: &str = r.get(2);
-        let column_name: &str = r.get(3);
+        let table_name: &str = &quote_if_needed(r.get(2));
+        let column_name: &str = &quote_if_needed(r.get(3));
         let column_default: Option<&str> = r.get(4);
         let nullable: &str = r.get(5);
         let data_type: &str = r.get(6);
@@ -176,6 +342,10 @@ fn load_info_cc(db_name: &str, db: &mut Transaction) -> Result<InfoSchemaType, S
         let character_maximum_length: Option<i32> = r.get(8);
         let numeric_precision: Option<i32> = r.get(9);
         let numeric_scale: Option<i32> = r.get(10);
+        let identity_generation: Option<&str> = r.get(12);
+        let generation_expression: Option<&str> = r.get(13);
+        let domain_schema: Option<&str> = r.get(14);
+        let domain_name: Option<&str> = r.get(15);
         let mut data_type = if udt_name.len() == 0 { data_type.to_string() } else { udt_name.to_string() };
         if data_type.to_lowercase().as_str() == "varchar" {
             if let Some(varchar_len) = character_maximum_length {
@@ -190,14 +360,24 @@ fn load_info_cc(db_name: &str, db: &mut Transaction) -> Result<InfoSchemaType, S
                 }
             }
         }
+        if let Some(domain) = domain_name {
+            // the domain, not the underlying type as defined in yaml
+            data_type = match domain_schema.filter(|s| *s != table_schema) {
+                Some(s) => format!("{}.{}", quote_if_needed(s), quote_if_needed(domain)),
+                None => quote_if_needed(domain),
+            };
+        }
         #[cfg(debug_assertions)]
         {
             if column_name == "id" {
                 // println!("{}.id= {}", table_name, column_default.unwrap_or("NA"));
             }
         }
-        let column_data = PgColumnDfn::new(column_name, data_type,
+        let mut column_data = PgColumnDfn::new(column_name, data_type,
                                            column_default.unwrap_or(""), nullable.to_lowercase() == "yes", sort_order);
+        // identity columns have no default, so the serial heuristic below is not applied
+        column_data.identity = identity_generation.and_then(IdentityMode::parse);
+        column_data.generated_as = generation_expression.filter(|g| g.len() > 0).map(|g| g.to_string());
         match data.get_mut(table_schema) {
             None => {
                 let mut hd = HashMap::new();
@@ -219,27 +399,22 @@ fn load_info_cc(db_name: &str, db: &mut Transaction) -> Result<InfoSchemaType, S
 
     for (schema, tbls) in &mut data {
         let mut query = String::new();
-        let mut tables = String::new();
         for tn in tbls.keys() {
             if !query.is_empty() {
                 query.push(',');
-                tables.push(',');
             }
             query.push_str(format!("'{}.{}'::regclass", schema, tn).as_str());
-            tables.push_str(format!("'{}.{}'", schema, tn).as_str());
         }
         if !query.is_empty() {
-            let result = db.query(format!("SELECT * from
-(SELECT tabs.table_schema, tabs.table_name,
-    pg_catalog.obj_description(tabs.table_name::regclass::oid) as table_comment
-    FROM information_schema.tables tabs
-    WHERE tabs.table_schema not in ('pg_catalog', 'information_schema') AND tabs.table_catalog = $1
-     and tabs.table_name in ({})
-    ) as ist WHERE ist.table_comment is not null order by 1,2", tables).as_str(), &[&db_name])
-                .map_err(|e| format!("on loading table_comment from information_schema [{}]: {}", db_name, e))?;
+            let result = db.query("SELECT n.nspname, c.relname, d.description
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    JOIN pg_description d ON d.objoid = c.oid AND d.classoid = 'pg_class'::regclass AND d.objsubid = 0
+    WHERE n.nspname = $1", &[&schema])
+                .map_err(|e| format!("on loading table_comment [{}]: {}", db_name, e))?;
             for r in result {
                 // let table_schema: &str = r.get(0);
-                let table_name: &str = r.get(1);
+                let table_name: &str = &quote_if_needed(r.get(1));
                 let table_comment: &str = r.get(2);
                 if let Some(t) = tbls.get_mut(table_name) {
                     t.table_comment = Some(table_comment.to_string());
@@ -251,24 +426,24 @@ fn load_info_cc(db_name: &str, db: &mut Transaction) -> Result<InfoSchemaType, S
                 .map_err(|e| format!("on loading table_owner from information_schema [{}]: {}", db_name, e))?;
             for r in result {
                 // let table_schema: &str = r.get(0);
-                let table_name: &str = r.get(1);
+                let table_name: &str = &quote_if_needed(r.get(1));
                 let table_owner: &str = r.get(2);
                 if let Some(t) = tbls.get_mut(table_name) {
                     t.owner = Some(table_owner.to_string());
                 }
             }
 
-            let result = db.query(format!("select * from
-(SELECT cols.table_schema, cols.table_name, cols.column_name, pg_catalog.col_description(cols.table_name::regclass::oid, cols.ordinal_position::int) as column_comment
-FROM information_schema.columns cols
-WHERE cols.table_schema not in ('pg_catalog', 'information_schema')  AND cols.table_catalog = $1
- AND cols.table_name in ({})
-) as iss where iss.column_comment is not null", tables).as_str(), &[&db_name])
-                .map_err(|e| format!("on loading table_comment from information_schema [{}]: {}", db_name, e))?;
+            let result = db.query("SELECT n.nspname, c.relname, a.attname, d.description
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    JOIN pg_description d ON d.objoid = c.oid AND d.classoid = 'pg_class'::regclass AND d.objsubid > 0
+    JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum = d.objsubid
+    WHERE n.nspname = $1", &[&schema])
+                .map_err(|e| format!("on loading column_comment [{}]: {}", db_name, e))?;
             for r in result {
                 // let table_schema: &str = r.get(0);
-                let table_name: &str = r.get(1);
-                let column_name: &str = r.get(2);
+                let table_name: &str = &quote_if_needed(r.get(1));
+                let column_name: &str = &quote_if_needed(r.get(2));
                 let column_comment: &str = r.get(3);
                 if let Some(t) = tbls.get_mut(table_name) {
                     if let Some(c) = t.columns.get_mut(column_name) {
@@ -284,8 +459,8 @@ WHERE cols.table_schema not in ('pg_catalog', 'information_schema')  AND cols.ta
                     WHERE (i.indisprimary or i.indisunique) and i.indrelid in ({})", query).as_str(), &[])
                 .map_err(|e| format!("on loading information_schema pk/uniq: {}", e))?;
             for r in result {
-                let table_name: &str = r.get(0);
-                let col_name: &str = r.get(1);
+                let table_name: &str = &quote_if_needed(r.get(0));
+                let col_name: &str = &quote_if_needed(r.get(1));
                 let indisprimary: bool = r.get(2);
                 let indisunique: bool = r.get(3);
                 if let Some(st) = tbls.get_mut(table_name) {
@@ -314,6 +489,17 @@ WHERE cols.table_schema not in ('pg_catalog', 'information_schema')  AND cols.ta
                     }
                 }
             }
+
+            let result = db.query(format!("SELECT c.relname, pg_get_partkeydef(c.oid) FROM pg_class c
+                    WHERE c.relkind = 'p' and c.oid in ({})", query).as_str(), &[])
+                .map_err(|e| format!("on loading partition key: {}", e))?;
+            for r in result {
+                let table_name: &str = &quote_if_needed(r.get(0));
+                let partition_key: &str = r.get(1);
+                if let Some(st) = tbls.get_mut(table_name) {
+                    st.partition_key = Some(partition_key.to_string());
+                }
+            }
         }
     }
 
@@ -322,22 +508,22 @@ WHERE cols.table_schema not in ('pg_catalog', 'information_schema')  AND cols.ta
 }
 
 #[inline]
-fn load_info_tg(db_name: &str, db: &mut Transaction, data: &mut InfoSchemaType) -> Result<(), String> {
-    match db.query("SELECT trigger_catalog, trigger_schema, trigger_name, event_object_catalog, event_object_schema, event_object_table \
-        from information_schema.triggers where event_object_schema not in ('pg_catalog', 'information_schema') and trigger_catalog = $1 \
-        order by created", &[&db_name]) {
-        Err(e) => Err(format!("on loading information_schema.triggers: {}", e)),
+#[cfg(feature = "db")]
+fn load_info_tg(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    match db.query("SELECT t.tgname::text, n.nspname::text, c.relname::text, pg_get_triggerdef(t.oid) \
+        FROM pg_trigger t JOIN pg_class c ON c.oid = t.tgrelid JOIN pg_namespace n ON n.oid = c.relnamespace \
+        WHERE NOT t.tgisinternal AND n.nspname not in ('pg_catalog', 'information_schema') \
+        AND ($1::text[] is null or n.nspname = any($1)) \
+        order by t.oid", &[&schemas]) {
+        Err(e) => Err(format!("on loading pg_trigger [{}]: {}", db_name, e)),
         Ok(result) => {
             let mut sort_order = 0;
             for r in result {
                 sort_order += 1;
-                let trigger_catalog: &str = r.get(0);
-                let trigger_schema: &str = r.get(1);
-                let trigger_name: &str = r.get(2);
-                let event_object_catalog: &str = r.get(3);
-                let event_object_schema: &str = r.get(4);
-                let event_object_table: &str = r.get(5);
-                let trigger_data = format!("{} {} {}", trigger_catalog, trigger_schema, event_object_catalog);
+                let trigger_name: &str = &quote_if_needed(r.get(0));
+                let event_object_schema: &str = r.get(1);
+                let event_object_table: &str = &quote_if_needed(r.get(2));
+                let trigger_data: String = r.get(3);
                 match data.get_mut(event_object_schema) {
                     None => {
                         let mut hd = HashMap::new();
@@ -361,33 +547,816 @@ fn load_info_tg(db_name: &str, db: &mut Transaction, data: &mut InfoSchemaType)
     }
 }
 
+#[inline]
+#[cfg(feature = "db")]
+fn load_info_ck(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    match db.query("SELECT n.nspname, c.relname, con.conname, pg_get_constraintdef(con.oid), a.attname, con.contype = 'x', con.contype = 'p'
+ FROM pg_constraint con
+ JOIN pg_class c ON c.oid = con.conrelid
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ LEFT JOIN pg_attribute a ON a.attrelid = con.conrelid AND array_length(con.conkey, 1) = 1 AND a.attnum = con.conkey[1]
+ WHERE con.contype in ('c', 'x', 'p') and n.nspname not in ('pg_catalog', 'information_schema')
+   and ($1::text[] is null or n.nspname = any($1))", &[&schemas]) {
+        Err(e) => Err(format!("on loading check constraints [{}]: {}", db_name, e)),
+        Ok(result) => {
+            for r in result {
+                let table_schema: &str = r.get(0);
+                let table_name: &str = &quote_if_needed(r.get(1));
+                let constraint_name: &str = r.get(2);
+                let constraint_def: &str = r.get(3);
+                let column_name: Option<&str> = r.get(4);
+                let is_exclude: bool = r.get(5);
+                let is_pk: bool = r.get(6);
+                let check = constraint_def.trim_start_matches("CHECK ").to_string();
+                if let Some(s) = data.get_mut(table_schema) {
+                    if let Some(hd) = s.get_mut(table_name) {
+                        if is_pk {
+                            hd.pk_name = Some(constraint_name.to_string());
+                            continue;
+                        }
+                        if is_exclude {
+                            hd.excludes.insert(constraint_name.to_string(), constraint_def.to_string());
+                            continue;
+                        }
+                        if let Some(column_name) = column_name {
+                            if let Some(column) = hd.columns.get_mut(&quote_if_needed(column_name)) {
+                                column.check = Some(check.clone());
+                            }
+                        }
+                        hd.checks.insert(constraint_name.to_string(), check);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[inline]
+#[cfg(feature = "db")]
+fn load_info_ix(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    match db.query("SELECT n.nspname, t.relname, i.relname, pg_get_expr(ix.indpred, ix.indrelid),
+ pg_get_indexdef(ix.indexrelid, k.ord, true), k.ord > ix.indnkeyatts, ix.indisunique, am.amname, ts.spcname, i.reloptions,
+ coalesce((to_jsonb(ix) ->> 'indnullsnotdistinct')::boolean, false),
+ coalesce((SELECT opc.opcname::text FROM pg_opclass opc WHERE opc.oid = ix.indclass[k.ord - 1] and not opc.opcdefault), ''),
+ obj_description(ix.indexrelid, 'pg_class'), coalesce(ix.indoption[k.ord - 1], 0)::integer,
+ coalesce((SELECT co.collname::text FROM pg_collation co WHERE co.oid = ix.indcollation[k.ord - 1] and co.collname <> 'default'
+   and co.oid <> coalesce((SELECT a.attcollation FROM pg_attribute a WHERE a.attrelid = ix.indrelid and a.attnum = ix.indkey[k.ord - 1]), 0)), ''),
+ ix.indisvalid
+ FROM pg_index ix
+ JOIN pg_class i ON i.oid = ix.indexrelid
+ JOIN pg_am am ON am.oid = i.relam
+ LEFT JOIN pg_tablespace ts ON ts.oid = coalesce(nullif(i.reltablespace, 0),
+ (SELECT dattablespace FROM pg_database WHERE datname = current_database()))
+ JOIN pg_class t ON t.oid = ix.indrelid
+ JOIN pg_namespace n ON n.oid = t.relnamespace
+ CROSS JOIN LATERAL generate_series(1, ix.indnatts) AS k(ord)
+ WHERE n.nspname not in ('pg_catalog', 'information_schema') and t.relkind in ('r', 'p')
+   and not exists (SELECT 1 FROM pg_constraint x WHERE x.conindid = ix.indexrelid and x.contype = 'x')
+   and ($1::text[] is null or n.nspname = any($1))
+ ORDER BY 1, 2, 3, k.ord", &[&schemas]) {
+        Err(e) => Err(format!("on loading indexes [{}]: {}", db_name, e)),
+        Ok(result) => {
+            for r in result {
+                let table_schema: &str = r.get(0);
+                let table_name: &str = &quote_if_needed(r.get(1));
+                let index_name: &str = r.get(2);
+                let where_clause: Option<&str> = r.get(3);
+                let column_name: &str = r.get(4);
+                let included: bool = r.get(5);
+                let is_unique: bool = r.get(6);
+                let index_method: &str = r.get(7);
+                let tablespace: Option<&str> = r.get(8);
+                let with_options: Option<Vec<String>> = r.get(9);
+                let nulls_not_distinct: bool = r.get(10);
+                let opclass: &str = r.get(11);
+                let comment: Option<String> = r.get(12);
+                // pg_index.indoption bits: 1 is DESC, 2 is NULLS FIRST
+                let option: i32 = r.get(13);
+                let collation: &str = r.get(14);
+                let is_valid: bool = r.get(15);
+                if let Some(s) = data.get_mut(table_schema) {
+                    if let Some(hd) = s.get_mut(table_name) {
+                        if !hd.indexes.contains_key(index_name) {
+                            hd.indexes.insert(index_name.to_string(), PgIndex {
+                                index_name: index_name.to_string(),
+                                is_unique,
+                                is_valid,
+                                nulls_not_distinct,
+                                index_method: index_method.to_string(),
+                                columns: vec![],
+                                include_columns: vec![],
+                                where_clause: where_clause.map(|w| w.to_string()),
+                                tablespace: tablespace.map(|t| t.to_string()),
+                                with_options: crate::utils::parse_reloptions(with_options),
+                                comment,
+                            });
+                        }
+                        if let Some(ix) = hd.indexes.get_mut(index_name) {
+                            if included {
+                                ix.include_columns.push(column_name.to_string());
+                            } else {
+                                let desc = option & 1 != 0;
+                                let nulls_first = option & 2 != 0;
+                                ix.columns.push(PgIndexColumn {
+                                    column_name: column_name.to_string(),
+                                    opclass: opclass.to_string(),
+                                    order: if desc { "DESC".to_string() } else { "".to_string() },
+                                    nulls: if desc == nulls_first { "".to_string() } else if nulls_first { "FIRST".to_string() } else { "LAST".to_string() },
+                                    collation: collation.to_string(),
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[inline]
+#[cfg(feature = "db")]
+fn load_info_policies(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    let result = db.query("SELECT n.nspname, c.relname FROM pg_class c
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ WHERE c.relrowsecurity and n.nspname not in ('pg_catalog', 'information_schema')
+   and ($1::text[] is null or n.nspname = any($1))", &[&schemas])
+        .map_err(|e| format!("on loading row security [{}]: {}", db_name, e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = &quote_if_needed(r.get(1));
+        if let Some(s) = data.get_mut(table_schema) {
+            if let Some(hd) = s.get_mut(table_name) {
+                hd.row_security = true;
+            }
+        }
+    }
+    let result = db.query("SELECT schemaname::text, tablename::text, policyname::text, permissive, roles::text[], cmd, qual, with_check
+ FROM pg_policies WHERE schemaname not in ('pg_catalog', 'information_schema')
+   and ($1::text[] is null or schemaname = any($1))", &[&schemas])
+        .map_err(|e| format!("on loading pg_policies [{}]: {}", db_name, e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = &quote_if_needed(r.get(1));
+        let policy_name: &str = r.get(2);
+        let permissive: &str = r.get(3);
+        let roles: Vec<String> = r.get(4);
+        let command: &str = r.get(5);
+        let using_expr: Option<&str> = r.get(6);
+        let check_expr: Option<&str> = r.get(7);
+        if let Some(s) = data.get_mut(table_schema) {
+            if let Some(hd) = s.get_mut(table_name) {
+                hd.policies.insert(policy_name.to_string(), PgPolicy {
+                    name: policy_name.to_string(),
+                    command: command.to_string(),
+                    permissive: permissive == "PERMISSIVE",
+                    roles,
+                    using_expr: using_expr.map(|e| e.to_string()),
+                    check_expr: check_expr.map(|e| e.to_string()),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+#[cfg(feature = "db")]
+fn load_info_persistence(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    let result = db.query("SELECT n.nspname, c.relname, c.relpersistence::text FROM pg_class c
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ WHERE c.relpersistence <> 'p' and c.relkind in ('r', 'p') and n.nspname not in ('pg_catalog', 'information_schema')
+   and ($1::text[] is null or n.nspname = any($1))", &[&schemas])
+        .map_err(|e| format!("on loading table persistence [{}]: {}", db_name, e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = &quote_if_needed(r.get(1));
+        let persistence: &str = r.get(2);
+        if let Some(s) = data.get_mut(table_schema) {
+            if let Some(hd) = s.get_mut(table_name) {
+                hd.persistence = persistence.chars().next().unwrap_or('p');
+            }
+        }
+    }
+    Ok(())
+}
+
+/// the grantee in the form of quote_if_needed, information_schema lists the public pseudo role as PUBLIC
+fn grantee_name(grantee: &str) -> String {
+    if grantee == "PUBLIC" { "public".to_string() } else { quote_if_needed(grantee) }
+}
+
+#[inline]
+#[cfg(feature = "db")]
+fn load_info_tablespace(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    let result = db.query("SELECT n.nspname, c.relname, t.spcname FROM pg_class c
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ JOIN pg_tablespace t ON t.oid = coalesce(nullif(c.reltablespace, 0),
+ (SELECT dattablespace FROM pg_database WHERE datname = current_database()))
+ WHERE c.relkind in ('r', 'p') and n.nspname not in ('pg_catalog', 'information_schema')
+   and ($1::text[] is null or n.nspname = any($1))", &[&schemas])
+        .map_err(|e| format!("on loading table tablespace [{}]: {}", db_name, e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = &quote_if_needed(r.get(1));
+        let tablespace: &str = r.get(2);
+        if let Some(s) = data.get_mut(table_schema) {
+            if let Some(hd) = s.get_mut(table_name) {
+                hd.tablespace = Some(tablespace.to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+#[cfg(feature = "db")]
+fn load_info_reloptions(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    let result = db.query("SELECT n.nspname, c.relname,
+ coalesce(c.reloptions, '{}') || array(SELECT 'toast.' || o FROM unnest(tc.reloptions) o) FROM pg_class c
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ LEFT JOIN pg_class tc ON tc.oid = c.reltoastrelid
+ WHERE (c.reloptions is not null or tc.reloptions is not null) and c.relkind in ('r', 'p')
+   and n.nspname not in ('pg_catalog', 'information_schema')
+   and ($1::text[] is null or n.nspname = any($1))", &[&schemas])
+        .map_err(|e| format!("on loading table storage parameters [{}]: {}", db_name, e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = &quote_if_needed(r.get(1));
+        let with_options: Option<Vec<String>> = r.get(2);
+        if let Some(s) = data.get_mut(table_schema) {
+            if let Some(hd) = s.get_mut(table_name) {
+                hd.with_options = crate::utils::parse_reloptions(with_options);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+#[cfg(feature = "db")]
+fn load_info_inherits(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    let result = db.query("SELECT n.nspname, c.relname, pn.nspname, p.relname FROM pg_inherits i
+ JOIN pg_class c ON c.oid = i.inhrelid
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ JOIN pg_class p ON p.oid = i.inhparent
+ JOIN pg_namespace pn ON pn.oid = p.relnamespace
+ WHERE not c.relispartition and c.relkind = 'r' and n.nspname not in ('pg_catalog', 'information_schema')
+   and ($1::text[] is null or n.nspname = any($1))
+ ORDER BY 1, 2, i.inhseqno", &[&schemas])
+        .map_err(|e| format!("on loading table inheritance [{}]: {}", db_name, e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = &quote_if_needed(r.get(1));
+        let parent = format!("{}.{}", quote_if_needed(r.get(2)), quote_if_needed(r.get(3)));
+        if let Some(s) = data.get_mut(table_schema) {
+            if let Some(hd) = s.get_mut(table_name) {
+                hd.parents.push(parent);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+#[cfg(feature = "db")]
+fn load_info_replica_identity(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    let result = db.query("SELECT n.nspname, c.relname, c.relreplident::text, ri.relname::text FROM pg_class c
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ LEFT JOIN pg_index x ON x.indrelid = c.oid AND x.indisreplident
+ LEFT JOIN pg_class ri ON ri.oid = x.indexrelid
+ WHERE c.relreplident <> 'd' and c.relkind in ('r', 'p') and n.nspname not in ('pg_catalog', 'information_schema')
+   and ($1::text[] is null or n.nspname = any($1))", &[&schemas])
+        .map_err(|e| format!("on loading table replica identity [{}]: {}", db_name, e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = &quote_if_needed(r.get(1));
+        let relreplident: &str = r.get(2);
+        let index: Option<&str> = r.get(3);
+        if let Some(s) = data.get_mut(table_schema) {
+            if let Some(hd) = s.get_mut(table_name) {
+                hd.replica_identity = ReplicaIdentity::parse(relreplident, index);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+#[cfg(feature = "db")]
+fn load_info_attributes(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    let result = db.query("SELECT n.nspname, c.relname, a.attname, a.attstorage::text, coalesce(a.attstattarget, -1)::int2 FROM pg_attribute a
+ JOIN pg_class c ON c.oid = a.attrelid
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ WHERE a.attnum > 0 and not a.attisdropped and c.relkind in ('r', 'p') and n.nspname not in ('pg_catalog', 'information_schema')
+   and ($1::text[] is null or n.nspname = any($1))", &[&schemas])
+        .map_err(|e| format!("on loading column storage and statistics [{}]: {}", db_name, e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = &quote_if_needed(r.get(1));
+        let column_name: &str = &quote_if_needed(r.get(2));
+        let storage: &str = r.get(3);
+        let statistics_target: i16 = r.get(4);
+        if let Some(column) = data.get_mut(table_schema)
+            .and_then(|s| s.get_mut(table_name))
+            .and_then(|t| t.columns.get_mut(column_name)) {
+            column.storage = storage.chars().next();
+            column.statistics_target = Some(statistics_target);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "security_labels")]
+#[cfg(feature = "db")]
+fn load_info_security_labels(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    let result = db.query("SELECT n.nspname, c.relname, a.attname::text, l.provider, l.label FROM pg_seclabel l
+ JOIN pg_class c ON c.oid = l.objoid AND l.classoid = 'pg_class'::regclass
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ LEFT JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum = l.objsubid AND l.objsubid > 0
+ WHERE c.relkind in ('r', 'p') and n.nspname not in ('pg_catalog', 'information_schema')
+   and ($1::text[] is null or n.nspname = any($1))", &[&schemas])
+        .map_err(|e| format!("on loading security labels [{}]: {}", db_name, e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = &quote_if_needed(r.get(1));
+        let column_name: Option<&str> = r.get(2);
+        let provider: &str = r.get(3);
+        let label: &str = r.get(4);
+        if let Some(hd) = data.get_mut(table_schema).and_then(|s| s.get_mut(table_name)) {
+            let labels = match column_name {
+                None => &mut hd.security_labels,
+                Some(c) => match hd.columns.get_mut(&quote_if_needed(c)) {
+                    None => continue,
+                    Some(pc) => &mut pc.security_labels,
+                },
+            };
+            labels.insert(provider.to_string(), label.to_string());
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+#[cfg(feature = "db")]
+fn load_info_grants(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
+    // the ACL itself, information_schema lists the grants of the current user's roles only
+    let result = db.query("SELECT n.nspname, c.relname, coalesce(g.rolname, 'PUBLIC')::text, a.privilege_type, a.is_grantable
+ FROM pg_class c
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ CROSS JOIN LATERAL aclexplode(coalesce(c.relacl, acldefault('r', c.relowner))) a
+ LEFT JOIN pg_roles g ON g.oid = a.grantee
+ WHERE c.relkind in ('r', 'p') and n.nspname not in ('pg_catalog', 'information_schema')
+   and ($1::text[] is null or n.nspname = any($1))", &[&schemas])
+        .map_err(|e| format!("on loading pg_class.relacl [{}]: {}", db_name, e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = &quote_if_needed(r.get(1));
+        let grantee: &str = &grantee_name(r.get(2));
+        let privilege: &str = r.get(3);
+        let is_grantable: bool = r.get(4);
+        if let Some(s) = data.get_mut(table_schema) {
+            if let Some(hd) = s.get_mut(table_name) {
+                let g = hd.grants.entry(grantee.to_string())
+                    .or_insert_with(|| PgGrant { grantee: grantee.to_string(), ..Default::default() });
+                g.privileges.insert(privilege.to_string());
+                g.with_grant_option |= is_grantable;
+            }
+        }
+    }
+    // privileges on the whole table are listed on each column as well
+    let result = db.query("SELECT table_schema, table_name, grantee, column_name, privilege_type, is_grantable FROM information_schema.role_column_grants
+ WHERE table_schema not in ('pg_catalog', 'information_schema') AND table_catalog = $1
+   AND ($2::text[] is null or table_schema = any($2))", &[&db_name, &schemas])
+        .map_err(|e| format!("on loading information_schema.role_column_grants [{}]: {}", db_name, e))?;
+    for r in result {
+        let table_schema: &str = r.get(0);
+        let table_name: &str = &quote_if_needed(r.get(1));
+        let grantee: &str = &grantee_name(r.get(2));
+        let column_name: &str = &quote_if_needed(r.get(3));
+        let privilege: &str = r.get(4);
+        let is_grantable: &str = r.get(5);
+        if let Some(s) = data.get_mut(table_schema) {
+            if let Some(hd) = s.get_mut(table_name) {
+                if hd.grants.get(grantee).map_or(false, |g| g.privileges.contains(privilege)) {
+                    continue;
+                }
+                hd.column_grants.entry(grantee.to_string()).or_insert_with(Vec::new).push(PgColumnGrant {
+                    column_name: column_name.to_string(),
+                    privilege: privilege.to_string(),
+                    with_grant_option: is_grantable == "YES",
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// value or expression to compare: no type casts and quotes, i.e. 'active'::character varying as active,
+/// an expression in lower case
+pub fn normalize_default(s: &str) -> String {
+    let mut casted = String::new();
+    let mut quoted = false;
+    let mut rest = s.trim();
+    while let Some(c) = rest.chars().next() {
+        if c == '\'' {
+            quoted = !quoted;
+        } else if !quoted && rest.starts_with("::") {
+            rest = skip_type(&rest[2..]);
+            continue;
+        }
+        casted.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    let mut d = casted.trim();
+    while d.starts_with('(') && d.ends_with(')') && outer_parentheses(d) {
+        d = d[1..d.len() - 1].trim();
+    }
+    if d.len() > 1 && d.starts_with('\'') && d.ends_with('\'') && !d[1..d.len() - 1].replace("''", "").contains('\'') {
+        d[1..d.len() - 1].replace("''", "'")
+    } else {
+        d.to_lowercase()
+    }
+}
+
+/// type name to compare: lower case, the aliases as the sql names, i.e. int4 as integer,
+/// character varying(10) as varchar(10), the type modifier after the name without spaces
+pub fn normalize_pg_type(t: &str) -> String {
+    let t = t.trim().to_lowercase().replace('"', "");
+    if let Some(element) = t.strip_suffix("[]") {
+        return format!("{}[]", normalize_pg_type(element));
+    }
+    // udt name of an array
+    if let Some(element) = t.strip_prefix('_') {
+        return format!("{}[]", normalize_pg_type(element));
+    }
+    let (name, modifier) = match (t.find('('), t.find(')')) {
+        (Some(open), Some(close)) if open < close => (format!("{} {}", &t[..open], &t[close + 1..]),
+                                                      t[open + 1..close].replace(' ', "")),
+        _ => (t.clone(), String::new()),
+    };
+    let name = name.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let name = match name.as_str() {
+        "int2" => "smallint",
+        "int" | "int4" => "integer",
+        "int8" => "bigint",
+        "float4" => "real",
+        "float" | "float8" => "double precision",
+        "bool" => "boolean",
+        "decimal" => "numeric",
+        "timestamptz" => "timestamp with time zone",
+        "timestamp without time zone" => "timestamp",
+        "timetz" => "time with time zone",
+        "time without time zone" => "time",
+        "character varying" => "varchar",
+        "character" | "bpchar" => "char",
+        "bit varying" => "varbit",
+        "serial2" => "smallserial",
+        "serial4" => "serial",
+        "serial8" => "bigserial",
+        other => other,
+    };
+    if modifier.len() > 0 {
+        format!("{}({})", name, modifier)
+    } else {
+        name.to_string()
+    }
+}
+
+/// the yaml type is the type loaded from DB: serial as the integer type, a schema of the type not compared,
+/// nor a modifier of the yaml type not loaded from DB, i.e. the precision of timestamp(3)
+pub(crate) fn same_pg_type(yaml: &str, db: &str) -> bool {
+    let comparable = |t: &str| {
+        let t = normalize_pg_type(t);
+        let t = match t.find('(').map_or(t.as_str(), |i| &t[..i]).rfind('.') {
+            Some(i) => t[i + 1..].to_string(),
+            None => t,
+        };
+        match t.as_str() {
+            "smallserial" => "smallint".to_string(),
+            "serial" => "integer".to_string(),
+            "bigserial" => "bigint".to_string(),
+            _ => t,
+        }
+    };
+    let yaml = comparable(yaml);
+    let db = comparable(db);
+    yaml == db || !db.contains('(') && match (yaml.find('('), yaml.find(')')) {
+        (Some(open), Some(close)) => format!("{}{}", &yaml[..open], &yaml[close + 1..]) == db,
+        _ => false,
+    }
+}
+
+/// the rest after a type name, i.e. after character varying(10)[]
+fn skip_type(s: &str) -> &str {
+    let mut rest = s.trim_start();
+    loop {
+        let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.' || c == '"')).unwrap_or(rest.len());
+        rest = &rest[end..];
+        let next = rest.trim_start();
+        let word = next.split(|c: char| !c.is_alphanumeric()).next().unwrap_or("").to_lowercase();
+        if end > 0 && ["varying", "precision", "with", "without", "time", "zone"].contains(&word.as_str()) {
+            rest = next;
+        } else {
+            break;
+        }
+    }
+    if rest.starts_with('(') {
+        if let Some(i) = rest.find(')') {
+            rest = &rest[i + 1..];
+        }
+    }
+    while rest.starts_with("[]") {
+        rest = &rest[2..];
+    }
+    rest
+}
+
+/// true if the first parenthesis closed at the very end
+pub(crate) fn outer_parentheses(e: &str) -> bool {
+    let mut depth = 0;
+    for (i, c) in e.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i == e.len() - 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// check or exclusion constraint expression to compare with pg_get_constraintdef: no type casts,
+/// the parentheses PostgreSQL adds around the terms removed, IN and BETWEEN as PostgreSQL rewrites them,
+/// i.e. lo < hi AND hi IN (1, 2) for ((lo < hi) AND (hi = ANY (ARRAY[1, 2])))
+pub(crate) fn normalize_check(expr: &str) -> String {
+    let mut tokens = check_tokens(&normalize_default(expr)).into_iter();
+    join_terms(&simplify_terms(check_terms(&mut tokens)))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Token(String),
+    Group(Vec<Term>),
+}
+
+const OPERATOR_CHARS: &str = "<>=!~+-*/%^|&#@?";
+/// the operators binding weaker than the arithmetic
+const COMPARISONS: [&str; 15] = ["=", "<", ">", "<=", ">=", "<>", "~", "!~", "~*", "!~*", "is", "like", "ilike", "similar", "escape"];
+/// the words a parenthesis after is not a function call or a list
+const KEYWORDS: [&str; 14] = ["and", "or", "not", "case", "when", "then", "else", "end", "where", "is", "like", "ilike", "similar", "escape"];
+
+fn check_tokens(expr: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut token = c.to_string();
+        if c == '\'' || c == '"' {
+            // quoted literal or name, doubled quote as escape
+            while let Some(n) = chars.next() {
+                token.push(n);
+                if n == c {
+                    match chars.peek() {
+                        Some(q) if *q == c => token.push(chars.next().unwrap_or(c)),
+                        _ => break,
+                    }
+                }
+            }
+            let inner = &token[1..token.len() - 1];
+            if c == '\'' && inner.parse::<f64>().is_ok() {
+                token = inner.to_string();
+            }
+        } else if c.is_alphanumeric() || c == '_' {
+            while let Some(n) = chars.peek().filter(|n| n.is_alphanumeric() || ['_', '.', '$'].contains(n)) {
+                token.push(*n);
+                chars.next();
+            }
+        } else if OPERATOR_CHARS.contains(c) {
+            while let Some(n) = chars.peek().filter(|n| OPERATOR_CHARS.contains(**n)) {
+                token.push(*n);
+                chars.next();
+            }
+        }
+        // a negative number, as PostgreSQL deparses '-1'::integer
+        let unary = tokens.last().map_or(false, |p| p == "-") && tokens.len().checked_sub(2).map_or(true, |i|
+            !(tokens[i].ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '\'' || c == '"') || tokens[i] == ")" || tokens[i] == "]"));
+        match token.as_str() {
+            "!=" => tokens.push("<>".to_string()),
+            "~~" => tokens.push("like".to_string()),
+            "~~*" => tokens.push("ilike".to_string()),
+            "!~~" | "!~~*" => {
+                tokens.push("not".to_string());
+                tokens.push(if token.ends_with('*') { "ilike" } else { "like" }.to_string());
+            }
+            n if unary && n.parse::<f64>().is_ok() => {
+                let _ = tokens.pop();
+                tokens.push(format!("-{}", n));
+            }
+            _ => tokens.push(token),
+        }
+    }
+    tokens
+}
+
+fn check_terms(tokens: &mut std::vec::IntoIter<String>) -> Vec<Term> {
+    let mut terms = Vec::new();
+    while let Some(t) = tokens.next() {
+        match t.as_str() {
+            "(" => terms.push(Term::Group(check_terms(tokens))),
+            ")" => break,
+            _ => terms.push(Term::Token(t)),
+        }
+    }
+    terms
+}
+
+fn join_terms(terms: &[Term]) -> String {
+    terms.iter().map(|t| match t {
+        Term::Token(t) => t.clone(),
+        Term::Group(g) => format!("({})", join_terms(g)),
+    }).collect::<Vec<String>>().join(" ")
+}
+
+fn is_word(term: &Term, words: &[&str]) -> bool {
+    matches!(term, Term::Token(t) if words.contains(&t.as_str()))
+}
+
+/// a function name or IN, ANY and the like before a parenthesis
+fn is_call(term: &Term) -> bool {
+    matches!(term, Term::Token(t) if t.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '"')
+        && !KEYWORDS.contains(&t.as_str()))
+}
+
+/// the start of the operand ending before the index, a function call as one operand
+fn operand_start(terms: &[Term], end: usize) -> usize {
+    match terms.get(end.wrapping_sub(1)) {
+        Some(Term::Group(_)) if end >= 2 && is_call(&terms[end - 2]) => end - 2,
+        Some(_) => end - 1,
+        None => end,
+    }
+}
+
+/// the end of the operand starting at the index
+fn operand_end(terms: &[Term], start: usize) -> usize {
+    match (terms.get(start), terms.get(start + 1)) {
+        (Some(t), Some(Term::Group(_))) if is_call(t) => start + 2,
+        (Some(_), _) => start + 1,
+        _ => start,
+    }
+}
+
+/// x IN (a, b) as x = ANY (ARRAY[a, b]), x BETWEEN a AND b as x >= a AND x <= b
+fn rewrite_in_between(mut terms: Vec<Term>) -> Vec<Term> {
+    let mut i = 0;
+    while i < terms.len() {
+        let negated = i > 0 && is_word(&terms[i - 1], &["not"]);
+        let start = operand_start(&terms, if negated { i - 1 } else { i });
+        let operand: Vec<Term> = terms[start..if negated { i - 1 } else { i }].to_vec();
+        if operand.is_empty() {
+            i += 1;
+            continue;
+        }
+        if is_word(&terms[i], &["in"]) {
+            if let Some(Term::Group(list)) = terms.get(i + 1).filter(|t| !matches!(t, Term::Group(l) if l.first().map_or(false, |f| is_word(f, &["select"])))) {
+                let mut array = vec![Term::Token("array".to_string()), Term::Token("[".to_string())];
+                array.extend(list.iter().cloned());
+                array.push(Term::Token("]".to_string()));
+                let mut rewritten = operand;
+                rewritten.push(Term::Token(if negated { "<>" } else { "=" }.to_string()));
+                rewritten.push(Term::Token(if negated { "all" } else { "any" }.to_string()));
+                rewritten.push(Term::Group(array));
+                let _ = terms.splice(start..i + 2, [Term::Group(rewritten)]);
+                i = start + 1;
+                continue;
+            }
+        } else if is_word(&terms[i], &["between"]) {
+            let low_end = operand_end(&terms, i + 1);
+            if terms.get(low_end).map_or(false, |t| is_word(t, &["and"])) {
+                let high_end = operand_end(&terms, low_end + 1);
+                if high_end > low_end + 1 {
+                    let (low, high) = (terms[i + 1..low_end].to_vec(), terms[low_end + 1..high_end].to_vec());
+                    let (ge, and, le) = if negated { ("<", "or", ">") } else { (">=", "and", "<=") };
+                    let mut rewritten = operand.clone();
+                    rewritten.push(Term::Token(ge.to_string()));
+                    rewritten.extend(low);
+                    rewritten.push(Term::Token(and.to_string()));
+                    rewritten.extend(operand);
+                    rewritten.push(Term::Token(le.to_string()));
+                    rewritten.extend(high);
+                    let _ = terms.splice(start..high_end, [Term::Group(rewritten)]);
+                    i = start + 1;
+                    continue;
+                }
+            }
+        }
+        i += 1;
+    }
+    terms
+}
+
+/// the precedence of the terms inside parentheses: an operand, the comparison, NOT, AND, OR
+fn precedence(terms: &[Term]) -> i32 {
+    if terms.len() == 1 || (terms.len() == 2 && is_call(&terms[0]) && matches!(terms[1], Term::Group(_))) {
+        -1
+    } else if terms.iter().any(|t| is_word(t, &["or"])) {
+        4
+    } else if terms.iter().any(|t| is_word(t, &["and"])) {
+        3
+    } else if is_word(&terms[0], &["not"]) {
+        2
+    } else if terms.iter().any(|t| is_word(t, &COMPARISONS)) {
+        1
+    } else {
+        0
+    }
+}
+
+/// the weakest precedence the neighbour term allows without parentheses
+fn allowed(neighbour: Option<&Term>, before: bool) -> i32 {
+    match neighbour {
+        None => 4,
+        Some(Term::Group(_)) => -1,
+        Some(t) if is_word(t, &["or", ",", "[", "]", "case", "when", "then", "else", "end", "where"]) => 4,
+        Some(t) if is_word(t, &["and"]) => 3,
+        Some(t) if is_word(t, &["not"]) => if before { 1 } else { 0 },
+        Some(t) if is_word(t, &COMPARISONS) => 0,
+        Some(_) => -1,
+    }
+}
+
+fn simplify_terms(terms: Vec<Term>) -> Vec<Term> {
+    let terms: Vec<Term> = terms.into_iter().map(|t| match t {
+        Term::Group(g) => {
+            let mut g = simplify_terms(g);
+            // ((x)) as (x)
+            while let [Term::Group(inner)] = g.as_slice() {
+                g = inner.clone();
+            }
+            Term::Group(g)
+        }
+        t => t,
+    }).collect();
+    let mut terms = rewrite_in_between(terms);
+    let mut i = 0;
+    while i < terms.len() {
+        if let Term::Group(g) = &terms[i] {
+            let call = i > 0 && is_call(&terms[i - 1]);
+            let p = precedence(g);
+            if !call && g.len() > 0 && p <= allowed(i.checked_sub(1).map(|i| &terms[i]), true)
+                && p <= allowed(terms.get(i + 1), false) {
+                let g = g.clone();
+                let _ = terms.splice(i..i + 1, g);
+                i = 0;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    terms
+}
+
+#[cfg(feature = "db")]
 const NO_ACTION: &str = "NO ACTION";
 
 #[inline]
 // db: &mut Transaction,
 // db: &mut Client
-fn load_info_fk(db_name: &str, db: &mut Transaction, data: &mut InfoSchemaType) -> Result<(), String> {
+#[cfg(feature = "db")]
+fn load_info_fk(db_name: &str, db: &mut dyn DbExec, data: &mut InfoSchemaType, schemas: Option<&[&str]>) -> Result<(), String> {
     match db.query("SELECT tc.table_schema,  tc.table_name, kcu.column_name,
  ccu.table_schema AS foreign_schema_name, ccu.table_name AS foreign_table_name, ccu.column_name AS foreign_column_name, tc.constraint_name,
- rc.match_option, rc.update_rule, rc.delete_rule
+ rc.match_option, rc.update_rule, rc.delete_rule, tc.is_deferrable, tc.initially_deferred
  FROM information_schema.table_constraints AS tc
- JOIN information_schema.key_column_usage AS kcu ON tc.constraint_name = kcu.constraint_name
- JOIN information_schema.constraint_column_usage AS ccu ON ccu.constraint_name = tc.constraint_name
- join information_schema.referential_constraints as rc on tc.constraint_name = rc.constraint_name
- WHERE constraint_type = 'FOREIGN KEY' and tc.table_catalog = $1", &[&db_name]) {
+ JOIN information_schema.key_column_usage AS kcu ON tc.constraint_name = kcu.constraint_name AND tc.constraint_schema = kcu.constraint_schema
+ join information_schema.referential_constraints as rc on tc.constraint_name = rc.constraint_name AND tc.constraint_schema = rc.constraint_schema
+ JOIN information_schema.key_column_usage AS ccu ON ccu.constraint_name = rc.unique_constraint_name AND ccu.constraint_schema = rc.unique_constraint_schema
+   AND ccu.ordinal_position = kcu.position_in_unique_constraint
+ WHERE constraint_type = 'FOREIGN KEY' and tc.table_catalog = $1
+   and ($2::text[] is null or tc.table_schema = any($2))
+ ORDER BY kcu.ordinal_position", &[&db_name, &schemas]) {
         Err(e) => Err(format!("on loading information_schema.fk: {}", e)),
         Ok(result) => {
             for r in result {
                 let table_schema: &str = r.get(0);
-                let table_name: &str = r.get(1);
-                let column_name: &str = r.get(2);
+                let table_name: &str = &quote_if_needed(r.get(1));
+                let column_name: &str = &quote_if_needed(r.get(2));
                 let foreign_schema_name: &str = r.get(3);
-                let foreign_table_name: &str = r.get(4);
-                let foreign_column_name: &str = r.get(5);
+                let foreign_table_name: &str = &quote_if_needed(r.get(4));
+                let foreign_column_name: &str = &quote_if_needed(r.get(5));
                 let constraint_name: &str = r.get(6);
-                let _match_option: &str = r.get(7);
+                let match_option: &str = r.get(7);
                 let update_rule: &str = r.get(8);
                 let delete_rule: &str = r.get(9);
+                let deferrable: &str = r.get(10);
+                let initially_deferred: &str = r.get(11);
                 let sql = if update_rule == NO_ACTION && delete_rule == NO_ACTION {
                     "".to_string()
                 } else {
@@ -404,18 +1373,27 @@ fn load_info_fk(db_name: &str, db: &mut Transaction, data: &mut InfoSchemaType)
 
                         match hd.fks.get_mut(&constraint_name) {
                             None => {
-                                let mut column = HashSet::new();
-                                column.insert(foreign_column_name.to_string());
-                                hd.fks.insert(column_name.into(), FKTable {
+                                hd.fks.insert(constraint_name.clone(), FKTable {
                                     schema: foreign_schema_name.to_string(),
                                     table: foreign_table_name.to_string(),
-                                    column,
+                                    column: vec![foreign_column_name.to_string()],
                                     name: constraint_name.to_string(),
+                                    local_columns: vec![column_name.to_string()],
                                     sql,
+                                    match_type: FkMatch::parse(match_option).unwrap_or(FkMatch::Simple),
+                                    on_update: FkAction::parse(update_rule).unwrap_or(FkAction::NoAction),
+                                    on_delete: FkAction::parse(delete_rule).unwrap_or(FkAction::NoAction),
+                                    deferrable: deferrable == "YES",
+                                    initially_deferred: initially_deferred == "YES",
                                 });
                             }
                             Some(fks) => {
-                                fks.column.insert(foreign_column_name.to_string());
+                                if !fks.column.iter().any(|c| c == foreign_column_name) {
+                                    fks.column.push(foreign_column_name.to_string());
+                                }
+                                if !fks.local_columns.iter().any(|c| c == column_name) {
+                                    fks.local_columns.push(column_name.to_string());
+                                }
                             }
                         }
                     }
@@ -426,8 +1404,24 @@ fn load_info_fk(db_name: &str, db: &mut Transaction, data: &mut InfoSchemaType)
     }
 }
 
+/// comments of the schemas, the schemas without a comment skipped
+#[cfg(feature = "db")]
+pub fn load_info_schema_comments(db: &mut dyn DbExec, schemas: Option<&[&str]>) -> Result<InfoSchemaCommentType, String> {
+    let result = db.query("SELECT nspname::text, obj_description(oid, 'pg_namespace') FROM pg_namespace \
+        WHERE obj_description(oid, 'pg_namespace') is not null AND ($1::text[] is null or nspname = any($1))", &[&schemas])
+        .map_err(|e| format!("on loading schema comments: {}", e))?;
+    let mut data = HashMap::new();
+    for r in result {
+        let schema_name: &str = r.get(0);
+        let comment: &str = r.get(1);
+        data.insert(schema_name.to_string(), comment.to_string());
+    }
+    Ok(data)
+}
+
 #[inline]
-pub fn load_info_schema_owner(db_name: &str, db: &mut Transaction) -> Result<InfoSchemaOwnerType, String> {
+#[cfg(feature = "db")]
+pub fn load_info_schema_owner(db_name: &str, db: &mut dyn DbExec) -> Result<InfoSchemaOwnerType, String> {
     let mut res = HashMap::new();
     match db.query("select schema_name, schema_owner from information_schema.schemata where schema_name not in ('information_schema', 'pg_catalog')", &[]) {
         Ok(schemas) => {
@@ -443,18 +1437,35 @@ where t.table_schema = $1 and t.table_catalog = $2 ", &[&schema_name, &db_name])
                     Err(e) => { return Err(format!("on loading information_schema.owner: {}", e)); }
                     Ok(result) => {
                         for r in result {
-                            let table_name: &str = r.get(0);
+                            let table_name: &str = &quote_if_needed(r.get(0));
                             let table_owner: &str = r.get(1);
                             tables.insert(table_name.into(), table_owner.into());
                         }
                     }
                 }
-                res.insert(schema_name.into(), (schema_owner.into(), tables));
+                res.insert(schema_name.into(), (schema_owner.into(), tables, HashMap::new()));
             }
-            Ok(res)
         }
-        Err(e) => Err(format!("on loading information schema owners: {}", e)),
+        Err(e) => { return Err(format!("on loading information schema owners: {}", e)); }
     }
+    let result = db.query("SELECT n.nspname, coalesce(r.rolname, 'public'), a.privilege_type, a.is_grantable
+ FROM pg_namespace n, aclexplode(coalesce(n.nspacl, acldefault('n', n.nspowner))) a
+ LEFT JOIN pg_roles r ON r.oid = a.grantee
+ WHERE n.nspname not in ('information_schema', 'pg_catalog')", &[])
+        .map_err(|e| format!("on loading pg_namespace.nspacl: {}", e))?;
+    for r in result {
+        let schema_name: &str = r.get(0);
+        let grantee: &str = &quote_if_needed(r.get(1));
+        let privilege: &str = r.get(2);
+        let is_grantable: bool = r.get(3);
+        if let Some((_, _, grants)) = res.get_mut(schema_name) {
+            let g = grants.entry(grantee.to_string())
+                .or_insert_with(|| PgGrant { grantee: grantee.to_string(), ..Default::default() });
+            g.privileges.insert(privilege.to_string());
+            g.with_grant_option |= is_grantable;
+        }
+    }
+    Ok(res)
 }
 
 impl Default for PgTable {
@@ -464,9 +1475,24 @@ impl Default for PgTable {
             columns: Default::default(),
             fks: Default::default(),
             triggers: Default::default(),
+            checks: Default::default(),
+            excludes: Default::default(),
+            pk_name: None,
+            indexes: Default::default(),
+            row_security: false,
+            policies: Default::default(),
+            persistence: 'p',
+            partition_key: None,
+            tablespace: None,
+            parents: vec![],
+            with_options: Default::default(),
+            replica_identity: ReplicaIdentity::Default,
             sort_order: 0,
             table_comment: None,
             owner: None,
+            grants: Default::default(),
+            column_grants: Default::default(),
+            security_labels: Default::default(),
         }
     }
 }
@@ -504,7 +1530,9 @@ impl PgTable {
     pub(crate) fn pks(&self) -> Option<String> {
         let mut pks = ", PRIMARY KEY (".to_string();
         let mut cnt = 0;
-        for c in self.columns.values() {
+        let mut columns: Vec<&PgColumnDfn> = self.columns.values().collect();
+        columns.sort_by_key(|c| c.sort_order);
+        for c in columns {
             if c.pk {
                 cnt += 1;
                 if cnt > 1 {
@@ -520,4 +1548,192 @@ impl PgTable {
             None
         }
     }
+    /// keep the loaded state in line with RENAME COLUMN, so the column found by the new name
+    /// on the following deploy of the indexes and FKs, false if no such column
+    pub fn rename_column(&mut self, old: &str, new: &str) -> bool {
+        match self.columns.remove(old) {
+            None => return false,
+            Some(mut c) => {
+                c.column_name = new.to_string();
+                self.columns.insert(new.to_string(), c);
+            }
+        }
+        for fk in self.fks.values_mut() {
+            for c in fk.local_columns.iter_mut().filter(|c| c.as_str() == old) {
+                *c = new.to_string();
+            }
+        }
+        for i in self.indexes.values_mut() {
+            for c in i.columns.iter_mut().filter(|c| c.column_name == old) {
+                c.column_name = new.to_string();
+            }
+            for c in i.include_columns.iter_mut().filter(|c| c.as_str() == old) {
+                *c = new.to_string();
+            }
+        }
+        true
+    }
+
+    /// keep the loaded state in line with ALTER TABLE RENAME TO, the FKs referencing the table included,
+    /// false if no such table
+    pub fn rename_table(data: &mut InfoSchemaType, schema: &str, old: &str, new: &str) -> bool {
+        match data.get_mut(schema).and_then(|s| s.remove(old)) {
+            None => return false,
+            Some(mut t) => {
+                t.table_name = new.to_string();
+                if let Some(s) = data.get_mut(schema) {
+                    s.insert(new.to_string(), t);
+                }
+            }
+        }
+        for t in data.values_mut().flat_map(|s| s.values_mut()) {
+            for fk in t.fks.values_mut().filter(|fk| fk.schema == schema && fk.table == old) {
+                fk.table = new.to_string();
+            }
+        }
+        true
+    }
+}
+
+#[cfg(all(test, feature = "db"))]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::column::{FkAction, FkMatch};
+    use crate::db::NoDb;
+    use crate::{load_schema_from_src, parse_yaml_schema};
+    use crate::loader::{InfoSchemaType, PgTable};
+    use crate::options::MigrationOptions;
+
+    #[test]
+    fn test_rename_helpers() {
+        let mut child = PgTable { table_name: "test_child".to_string(), ..PgTable::default() };
+        child.fks.insert("test_child_parent_id_fkey".to_string(), crate::loader::FKTable {
+            schema: "test_schema".to_string(),
+            table: "test_parent".to_string(),
+            column: vec!["id".to_string()],
+            name: "test_child_parent_id_fkey".to_string(),
+            local_columns: vec!["parent_id".to_string()],
+            sql: "".to_string(),
+            match_type: FkMatch::Simple,
+            on_update: FkAction::NoAction,
+            on_delete: FkAction::NoAction,
+            deferrable: false,
+            initially_deferred: false,
+        });
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_child
+          columns:
+            - column:
+                name: parent_id
+                type: integer
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let c = r.list.get(0).unwrap().tables.list.get(0).unwrap().columns.list.get(0).unwrap();
+        child.columns.insert("parent_id".to_string(), c.column_def(&"test_schema".to_string(), &"test_child".to_string(), "").unwrap());
+
+        assert!(!child.rename_column("missing", "other"));
+        assert!(child.rename_column("parent_id", "owner_id"));
+        assert!(!child.columns.contains_key("parent_id"));
+        assert_eq!(child.columns["owner_id"].column_name.as_str(), "owner_id");
+        assert_eq!(child.fks["test_child_parent_id_fkey"].local_columns, vec!["owner_id".to_string()]);
+
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert("test_schema".to_string(), HashMap::from([
+            ("test_child".to_string(), child),
+            ("test_parent".to_string(), PgTable { table_name: "test_parent".to_string(), ..PgTable::default() }),
+        ]));
+        assert!(!PgTable::rename_table(&mut info, "test_schema", "missing", "other"));
+        assert!(!PgTable::rename_table(&mut info, "other_schema", "test_parent", "other"));
+        assert!(PgTable::rename_table(&mut info, "test_schema", "test_parent", "test_owner"));
+        let s = &info["test_schema"];
+        assert!(!s.contains_key("test_parent"));
+        assert_eq!(s["test_owner"].table_name.as_str(), "test_owner");
+        assert_eq!(s["test_child"].fks["test_child_parent_id_fkey"].table.as_str(), "test_owner");
+    }
+
+    #[test]
+    fn test_normalize_pg_type() {
+        use crate::loader::{normalize_pg_type, same_pg_type};
+        assert_eq!(normalize_pg_type("int4").as_str(), "integer");
+        assert_eq!(normalize_pg_type("INT8").as_str(), "bigint");
+        assert_eq!(normalize_pg_type("float8").as_str(), "double precision");
+        assert_eq!(normalize_pg_type("timestamptz"), normalize_pg_type("timestamp with time zone"));
+        assert_eq!(normalize_pg_type("character  varying(255)").as_str(), "varchar(255)");
+        assert_eq!(normalize_pg_type("NUMERIC(10, 2)"), normalize_pg_type("decimal(10,2)"));
+        assert_eq!(normalize_pg_type("_int4").as_str(), "integer[]");
+        assert_eq!(normalize_pg_type("bool[]").as_str(), "boolean[]");
+        assert!(same_pg_type("serial", "int4"));
+        assert!(same_pg_type("timestamp(3) with time zone", "timestamptz"));
+        assert!(same_pg_type("test_schema.status", "status"));
+        assert!(!same_pg_type("varchar(100)", "varchar(255)"));
+        assert!(!same_pg_type("bigint", "int4"));
+
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+            - column:
+                name: amount
+                type: double precision
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let s = "test_schema".to_string();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        let mut pt = PgTable { table_name: "test_table".to_string(), ..PgTable::default() };
+        for (c, db_type) in t.columns.list.iter().zip(["int4", "float4"]) {
+            let mut def = c.column_def(&s, &t.table_name, "").unwrap();
+            def.column_type = db_type.to_string();
+            pt.columns.insert(c.name.clone(), def);
+        }
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert(s.clone(), HashMap::from([("test_table".to_string(), pt)]));
+        let report = t.deploy(&mut info, &mut NoDb, &s, false, "", None, &MigrationOptions::default()).unwrap().unwrap();
+        assert!(!report.altered);
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].kind, crate::validate::WarningKind::ColumnTypeMismatch);
+        assert_eq!(report.warnings[0].location.as_str(), "test_schema.test_table.amount");
+    }
+
+    #[test]
+    fn normalize_check_test() {
+        use crate::loader::normalize_check;
+        // yaml as written, pg_get_constraintdef without CHECK
+        for (yaml, pg) in [
+            ("lo < hi AND hi < 100", "((lo < hi) AND (hi < 100))"),
+            ("price > 0 OR price IS NULL", "((price > (0)::numeric) OR (price IS NULL))"),
+            ("a > 0 AND b > 0 OR c > 0", "(((a > 0) AND (b > 0)) OR (c > 0))"),
+            ("NOT (a > 0 AND b > 0)", "(NOT ((a > 0) AND (b > 0)))"),
+            ("status IN ('new', 'done')", "((status)::text = ANY ((ARRAY['new'::character varying, 'done'::character varying])::text[]))"),
+            ("qty NOT IN (1, 2)", "(qty <> ALL (ARRAY[1, 2]))"),
+            ("qty BETWEEN 1 AND 10", "((qty >= 1) AND (qty <= 10))"),
+            ("length(name) > 0", "(length(name) > 0)"),
+            ("name != ''", "(name <> ''::text)"),
+            ("name LIKE 'a%'", "(name ~~ 'a%'::text)"),
+            ("qty > -1", "(qty > '-1'::integer)"),
+            ("(a + b) * 2 > c", "(((a + b) * 2) > c)"),
+            ("qty NOT BETWEEN 3 AND 4", "(((qty < 3) OR (qty > 4)))"),
+            ("name IS NOT NULL AND NOT name ILIKE 'x%'", "(((name IS NOT NULL) AND (NOT (name ~~* 'x%'::text))))"),
+        ] {
+            assert_eq!(normalize_check(yaml), normalize_check(pg), "{}", yaml);
+        }
+        for (a, b) in [
+            ("a > 0 AND (b > 0 OR c > 0)", "a > 0 AND b > 0 OR c > 0"),
+            ("(a + b) * 2 > c", "a + b * 2 > c"),
+            ("lo < hi", "lo <= hi"),
+            ("qty BETWEEN 1 AND 10", "qty BETWEEN 1 AND 20"),
+        ] {
+            assert_ne!(normalize_check(a), normalize_check(b), "{}", a);
+        }
+    }
 }
@@ -0,0 +1,472 @@
+use std::fmt;
+
+use crate::plan::PlanEntry;
+use crate::report::ProgressEvent;
+
+/// statements sink of a dry run, nothing executed on the database
+pub type DryRun<'a> = &'a dyn Fn(Vec<String>) -> Result<(), String>;
+
+/// statements of a table on a dry run, instead of the DryRun sink
+pub type PlanSink<'a> = &'a dyn Fn(PlanEntry) -> Result<(), String>;
+
+/// migration progress callback, i.e. to print to stderr
+pub type OnProgress<'a> = &'a dyn Fn(ProgressEvent);
+
+/// migration behaviour flags, all off by default except allow_default_change
+#[derive(Clone)]
+pub struct MigrationOptions<'a> {
+    /// wrap statements into retry on lock timeout
+    pub retry: bool,
+    /// pass statements to the callback instead of execute
+    pub dry_run: Option<DryRun<'a>>,
+    /// pass statements of a table to the callback with the table name and the sections, on a dry run only
+    pub plan: Option<PlanSink<'a>>,
+    /// switch an existing table between LOGGED and UNLOGGED to match the yaml,
+    /// otherwise such a mismatch is an error
+    pub allow_logged_change: bool,
+    /// set NOT NULL on an existing nullable column as defined in yaml, fails on the null values,
+    /// otherwise such a mismatch skipped; dropping NOT NULL is always done
+    pub allow_not_null_change: bool,
+    /// set or drop DEFAULT of an existing column as defined in yaml, on by default
+    pub allow_default_change: bool,
+    /// revoke privileges not granted in yaml, except the owner's
+    pub with_revoke: bool,
+    /// REASSIGN OWNED BY the previous owner of a schema to the owner in yaml, before the schema owner changed;
+    /// reassigns all the objects of the previous owner in the database, not only the ones in the schema
+    pub reassign_owned: bool,
+    /// skip the schema grants with scope, i.e. GRANT ON ALL TABLES IN SCHEMA, granted on every run otherwise
+    pub skip_batch_grants: bool,
+    /// drop functions of the schemas in yaml, which are not defined there
+    pub drop_functions: bool,
+    /// drop tables of the schemas in yaml, which are not defined there, except never_drop;
+    /// partitions not in yaml too, so list them in never_drop if created elsewhere
+    pub drop_tables: bool,
+    /// schema.table or table never dropped by drop_tables
+    pub never_drop: Vec<String>,
+    /// drop triggers of the tables in yaml, which are not defined there
+    pub drop_triggers: bool,
+    /// drop attributes of composite types, which are not defined in yaml, error otherwise
+    pub drop_type_attributes: bool,
+    /// drop roles created by a migration and not defined in yaml anymore, recognized by MANAGED_ROLE_COMMENT,
+    /// except the bootstrap superuser and the current user;
+    /// a role owning objects or granted privileges fails the migration
+    pub drop_roles: bool,
+    /// how long to wait for the concurrent migration to finish, wait forever if not set
+    pub lock_timeout_ms: Option<u64>,
+    /// separate lock namespace for an independent schema set, DEFAULT_ADVISORY_LOCK_ID if not set
+    pub advisory_lock_id: Option<i64>,
+    /// DDL only, skip the data rows
+    pub schema_only: bool,
+    /// let the tables with truncate_before_seed TRUNCATE, otherwise such a table fails the migration
+    pub allow_truncate: bool,
+    /// data rows only, skip all DDL, the tables expected to exist
+    pub data_only: bool,
+    /// fail if columns of an existing table ordered differently than in yaml,
+    /// otherwise such a mismatch reported as a warning; the columns never reordered
+    pub fail_on_column_reorder: bool,
+    /// file name pattern of migrate_from_dir with * and ? wildcards, i.e. 0*_core.yaml, all yaml files if not set
+    pub file_pattern: Option<String>,
+    /// schema drift detection: a dry run of the DDL on a rolled back transaction,
+    /// fails with the statements to apply if any
+    pub check_only: bool,
+    /// COPY the data rows of a table into a staging table, then insert from there with the conflict action,
+    /// if there are more rows than copy_threshold
+    pub use_copy_for_data: bool,
+    /// rows of a table to insert one by one, 1000 by default
+    pub copy_threshold: usize,
+    /// deploy each table within a savepoint, a failed table rolled back and reported in failed_tables,
+    /// the migration goes on
+    pub savepoint_per_table: bool,
+    /// create the indexes set as concurrently after the migration commit, out of the transaction,
+    /// otherwise these created in the transaction as usual
+    pub allow_concurrent_index: bool,
+    /// REINDEX an index of the same definition, but in another tablespace, instead of drop and create
+    pub reindex_on_change: bool,
+    /// add a NOT NULL column having a default as nullable with a NOT VALID check,
+    /// validated and set NOT NULL after the migration commit as post_tx_sql;
+    /// a volatile default rewrites the table on ADD COLUMN anyway
+    pub add_column_not_valid: bool,
+    /// lock_timeout of a statement wrapped into retry
+    pub retry_lock_timeout_ms: u64,
+    /// attempts of a statement wrapped into retry
+    pub retry_max_attempts: u32,
+    /// replace the retry block, the $EXECUTE_SQL$ placeholder is the statement as is
+    pub retry_template: Option<String>,
+    /// fail if a column type is neither a standard PostgreSQL type nor defined in yaml
+    pub validate_types: bool,
+    /// called before and after the deploy of each table and its foreign keys
+    pub on_progress: Option<OnProgress<'a>>,
+    /// CREATE TABLE IF NOT EXISTS of a table not loaded from DB, i.e. to replay after a partial failure
+    pub create_if_not_exists: bool,
+    /// stop the migration before the next table, the transaction rolled back
+    #[cfg(feature = "bb8")]
+    pub cancel_token: Option<tokio_util::sync::CancellationToken>,
+}
+
+impl<'a> Default for MigrationOptions<'a> {
+    fn default() -> Self {
+        MigrationOptions {
+            retry: false,
+            dry_run: None,
+            plan: None,
+            allow_logged_change: false,
+            allow_not_null_change: false,
+            allow_default_change: true,
+            with_revoke: false,
+            reassign_owned: false,
+            skip_batch_grants: false,
+            drop_functions: false,
+            drop_tables: false,
+            never_drop: Vec::new(),
+            drop_triggers: false,
+            drop_type_attributes: false,
+            drop_roles: false,
+            lock_timeout_ms: None,
+            advisory_lock_id: None,
+            schema_only: false,
+            allow_truncate: false,
+            data_only: false,
+            fail_on_column_reorder: false,
+            file_pattern: None,
+            check_only: false,
+            use_copy_for_data: false,
+            copy_threshold: 1000,
+            savepoint_per_table: false,
+            allow_concurrent_index: false,
+            reindex_on_change: false,
+            add_column_not_valid: false,
+            retry_lock_timeout_ms: 1000,
+            retry_max_attempts: 100,
+            retry_template: None,
+            validate_types: false,
+            on_progress: None,
+            create_if_not_exists: false,
+            #[cfg(feature = "bb8")]
+            cancel_token: None,
+        }
+    }
+}
+
+/// error of a migration stopped by the cancel_token
+pub const CANCELLED: &str = "migration cancelled";
+
+/// transaction level advisory lock to serialize migrations on the same database
+pub const DEFAULT_ADVISORY_LOCK_ID: i64 = hashname("schema_guard");
+
+/// FNV-1a of the name, positive
+const fn hashname(name: &str) -> i64 {
+    (crate::utils::fnv1a(name) % i64::MAX as u64) as i64
+}
+
+impl<'a> MigrationOptions<'a> {
+    #[inline]
+    pub fn lock_id(&self) -> i64 {
+        self.advisory_lock_id.unwrap_or(DEFAULT_ADVISORY_LOCK_ID)
+    }
+
+    pub fn with_retry(mut self, retry: bool) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn dry_run(mut self, store: DryRun<'a>) -> Self {
+        self.dry_run = Some(store);
+        self
+    }
+
+    pub fn plan(mut self, plan: PlanSink<'a>) -> Self {
+        self.plan = Some(plan);
+        self
+    }
+
+    pub fn allow_logged_change(mut self, allow: bool) -> Self {
+        self.allow_logged_change = allow;
+        self
+    }
+
+    pub fn allow_not_null_change(mut self, allow: bool) -> Self {
+        self.allow_not_null_change = allow;
+        self
+    }
+
+    pub fn allow_default_change(mut self, allow: bool) -> Self {
+        self.allow_default_change = allow;
+        self
+    }
+
+    pub fn with_revoke(mut self, revoke: bool) -> Self {
+        self.with_revoke = revoke;
+        self
+    }
+
+    pub fn reassign_owned(mut self, reassign: bool) -> Self {
+        self.reassign_owned = reassign;
+        self
+    }
+
+    pub fn skip_batch_grants(mut self, skip: bool) -> Self {
+        self.skip_batch_grants = skip;
+        self
+    }
+
+    pub fn drop_functions(mut self, drop: bool) -> Self {
+        self.drop_functions = drop;
+        self
+    }
+
+    pub fn drop_tables(mut self, drop: bool) -> Self {
+        self.drop_tables = drop;
+        self
+    }
+
+    pub fn never_drop(mut self, tables: Vec<String>) -> Self {
+        self.never_drop = tables;
+        self
+    }
+
+    pub fn drop_triggers(mut self, drop: bool) -> Self {
+        self.drop_triggers = drop;
+        self
+    }
+
+    pub fn drop_type_attributes(mut self, drop: bool) -> Self {
+        self.drop_type_attributes = drop;
+        self
+    }
+
+    pub fn drop_roles(mut self, drop: bool) -> Self {
+        self.drop_roles = drop;
+        self
+    }
+
+    pub fn lock_timeout_ms(mut self, timeout: u64) -> Self {
+        self.lock_timeout_ms = Some(timeout);
+        self
+    }
+
+    pub fn advisory_lock_id(mut self, lock_id: i64) -> Self {
+        self.advisory_lock_id = Some(lock_id);
+        self
+    }
+
+    pub fn schema_only(mut self, schema_only: bool) -> Self {
+        self.schema_only = schema_only;
+        self
+    }
+
+    pub fn allow_truncate(mut self, allow: bool) -> Self {
+        self.allow_truncate = allow;
+        self
+    }
+
+    pub fn data_only(mut self, data_only: bool) -> Self {
+        self.data_only = data_only;
+        self
+    }
+
+    pub fn fail_on_column_reorder(mut self, fail: bool) -> Self {
+        self.fail_on_column_reorder = fail;
+        self
+    }
+
+    pub fn file_pattern(mut self, pattern: &str) -> Self {
+        self.file_pattern = Some(pattern.to_string());
+        self
+    }
+
+    /// the options without the callbacks to run the migration on a blocking thread of migrate_async,
+    /// the callbacks are called on the runtime thread instead
+    #[cfg(feature = "bb8")]
+    pub(crate) fn detached(&self) -> Detached {
+        Detached {
+            opts: MigrationOptions {
+                retry: self.retry,
+                dry_run: None,
+                plan: None,
+                allow_logged_change: self.allow_logged_change,
+                allow_not_null_change: self.allow_not_null_change,
+                allow_default_change: self.allow_default_change,
+                with_revoke: self.with_revoke,
+                reassign_owned: self.reassign_owned,
+                skip_batch_grants: self.skip_batch_grants,
+                drop_functions: self.drop_functions,
+                drop_tables: self.drop_tables,
+                never_drop: self.never_drop.clone(),
+                drop_triggers: self.drop_triggers,
+                drop_type_attributes: self.drop_type_attributes,
+                drop_roles: self.drop_roles,
+                lock_timeout_ms: self.lock_timeout_ms,
+                advisory_lock_id: self.advisory_lock_id,
+                schema_only: self.schema_only,
+                allow_truncate: self.allow_truncate,
+                data_only: self.data_only,
+                fail_on_column_reorder: self.fail_on_column_reorder,
+                file_pattern: self.file_pattern.clone(),
+                check_only: self.check_only,
+                use_copy_for_data: self.use_copy_for_data,
+                copy_threshold: self.copy_threshold,
+                savepoint_per_table: self.savepoint_per_table,
+                allow_concurrent_index: self.allow_concurrent_index,
+                reindex_on_change: self.reindex_on_change,
+                add_column_not_valid: self.add_column_not_valid,
+                retry_lock_timeout_ms: self.retry_lock_timeout_ms,
+                retry_max_attempts: self.retry_max_attempts,
+                retry_template: self.retry_template.clone(),
+                validate_types: self.validate_types,
+                on_progress: None,
+                create_if_not_exists: self.create_if_not_exists,
+                cancel_token: self.cancel_token.clone(),
+            },
+            dry_run: self.dry_run.is_some(),
+            plan: self.plan.is_some(),
+            on_progress: self.on_progress.is_some(),
+        }
+    }
+
+    pub fn check_only(mut self, check_only: bool) -> Self {
+        self.check_only = check_only;
+        self
+    }
+
+    pub fn use_copy_for_data(mut self, use_copy: bool) -> Self {
+        self.use_copy_for_data = use_copy;
+        self
+    }
+
+    pub fn copy_threshold(mut self, rows: usize) -> Self {
+        self.copy_threshold = rows;
+        self
+    }
+
+    pub fn savepoint_per_table(mut self, savepoint: bool) -> Self {
+        self.savepoint_per_table = savepoint;
+        self
+    }
+
+    pub fn allow_concurrent_index(mut self, allow: bool) -> Self {
+        self.allow_concurrent_index = allow;
+        self
+    }
+
+    pub fn reindex_on_change(mut self, reindex: bool) -> Self {
+        self.reindex_on_change = reindex;
+        self
+    }
+
+    pub fn add_column_not_valid(mut self, not_valid: bool) -> Self {
+        self.add_column_not_valid = not_valid;
+        self
+    }
+
+    pub fn retry_lock_timeout_ms(mut self, timeout: u64) -> Self {
+        self.retry_lock_timeout_ms = timeout;
+        self
+    }
+
+    pub fn retry_max_attempts(mut self, attempts: u32) -> Self {
+        self.retry_max_attempts = attempts;
+        self
+    }
+
+    pub fn retry_template(mut self, template: &str) -> Self {
+        self.retry_template = Some(template.to_string());
+        self
+    }
+
+    pub fn validate_types(mut self, validate: bool) -> Self {
+        self.validate_types = validate;
+        self
+    }
+
+    pub fn on_progress(mut self, on_progress: OnProgress<'a>) -> Self {
+        self.on_progress = Some(on_progress);
+        self
+    }
+
+    pub fn create_if_not_exists(mut self, if_not_exists: bool) -> Self {
+        self.create_if_not_exists = if_not_exists;
+        self
+    }
+
+    #[cfg(feature = "bb8")]
+    pub fn cancel_token(mut self, token: tokio_util::sync::CancellationToken) -> Self {
+        self.cancel_token = Some(token);
+        self
+    }
+
+    /// CANCELLED error if the cancel_token is cancelled
+    #[inline]
+    pub(crate) fn check_cancelled(&self) -> Result<(), String> {
+        #[cfg(feature = "bb8")]
+        if self.cancel_token.as_ref().map_or(false, |t| t.is_cancelled()) {
+            return Err(CANCELLED.to_string());
+        }
+        Ok(())
+    }
+
+    /// pass the event to on_progress if set
+    #[inline]
+    pub(crate) fn progress(&self, event: ProgressEvent) {
+        if let Some(on_progress) = self.on_progress {
+            on_progress(event);
+        }
+    }
+}
+
+/// options of migrate_async moved to its blocking thread, the callbacks set replaced by the ones
+/// sending to the runtime thread
+#[cfg(feature = "bb8")]
+pub(crate) struct Detached {
+    pub opts: MigrationOptions<'static>,
+    pub dry_run: bool,
+    pub plan: bool,
+    pub on_progress: bool,
+}
+
+// the callbacks, the only fields not Send, are None
+#[cfg(feature = "bb8")]
+unsafe impl Send for Detached {}
+
+impl<'a> fmt::Debug for MigrationOptions<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("MigrationOptions");
+        d.field("retry", &self.retry)
+            .field("dry_run", &self.dry_run.is_some())
+            .field("plan", &self.plan.is_some())
+            .field("allow_logged_change", &self.allow_logged_change)
+            .field("allow_not_null_change", &self.allow_not_null_change)
+            .field("allow_default_change", &self.allow_default_change)
+            .field("with_revoke", &self.with_revoke)
+            .field("reassign_owned", &self.reassign_owned)
+            .field("skip_batch_grants", &self.skip_batch_grants)
+            .field("drop_functions", &self.drop_functions)
+            .field("drop_tables", &self.drop_tables)
+            .field("never_drop", &self.never_drop)
+            .field("drop_triggers", &self.drop_triggers)
+            .field("drop_type_attributes", &self.drop_type_attributes)
+            .field("drop_roles", &self.drop_roles)
+            .field("lock_timeout_ms", &self.lock_timeout_ms)
+            .field("advisory_lock_id", &self.advisory_lock_id)
+            .field("schema_only", &self.schema_only)
+            .field("allow_truncate", &self.allow_truncate)
+            .field("data_only", &self.data_only)
+            .field("fail_on_column_reorder", &self.fail_on_column_reorder)
+            .field("file_pattern", &self.file_pattern)
+            .field("check_only", &self.check_only)
+            .field("use_copy_for_data", &self.use_copy_for_data)
+            .field("copy_threshold", &self.copy_threshold)
+            .field("savepoint_per_table", &self.savepoint_per_table)
+            .field("allow_concurrent_index", &self.allow_concurrent_index)
+            .field("reindex_on_change", &self.reindex_on_change)
+            .field("add_column_not_valid", &self.add_column_not_valid)
+            .field("retry_lock_timeout_ms", &self.retry_lock_timeout_ms)
+            .field("retry_max_attempts", &self.retry_max_attempts)
+            .field("retry_template", &self.retry_template)
+            .field("validate_types", &self.validate_types)
+            .field("on_progress", &self.on_progress.is_some())
+            .field("create_if_not_exists", &self.create_if_not_exists);
+        #[cfg(feature = "bb8")]
+        d.field("cancel_token", &self.cancel_token);
+        d.finish()
+    }
+}
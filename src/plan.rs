@@ -0,0 +1,133 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+/// statements to apply on a table, or on the other objects if the table is empty
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlanEntry {
+    pub schema: String,
+    pub table: String,
+    /// yaml file the table defined in
+    pub source_file: String,
+    pub ddl: String,
+    pub comments: String,
+    pub data: String,
+    /// statements to run after the migration commit, i.e. CREATE INDEX CONCURRENTLY
+    pub post_tx: String,
+}
+
+impl PlanEntry {
+    /// statements of a dry run callback, not bound to a table
+    pub fn statements(sql: Vec<String>) -> Self {
+        PlanEntry { ddl: sql.concat(), ..Default::default() }
+    }
+
+    /// nothing to apply
+    pub fn is_empty(&self) -> bool {
+        self.ddl.trim().is_empty() && self.comments.trim().is_empty()
+            && self.data.trim().is_empty() && self.post_tx.trim().is_empty()
+    }
+}
+
+/// plan as a sql script, the sections of a table under the comment headers if include_headers
+pub fn format_plan(plan: &[PlanEntry], include_headers: bool) -> String {
+    let mut script = String::new();
+    for entry in plan.iter().filter(|e| !e.is_empty()) {
+        if include_headers && entry.table.len() > 0 {
+            let _ = write!(script, "-- Table: {}.{}", entry.schema, entry.table);
+            if entry.source_file.len() > 0 {
+                let _ = write!(script, " (source: {})", entry.source_file);
+            }
+            script.push('\n');
+        }
+        for (header, sql) in [("DDL", &entry.ddl), ("Comments", &entry.comments),
+            ("Data", &entry.data), ("After commit", &entry.post_tx)] {
+            if sql.trim().is_empty() {
+                continue;
+            }
+            if include_headers {
+                let _ = writeln!(script, "-- {}:", header);
+            }
+            script.push_str(sql.trim_end());
+            script.push('\n');
+        }
+        if include_headers {
+            script.push('\n');
+        }
+    }
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "db")]
+    use std::cell::RefCell;
+    #[cfg(feature = "db")]
+    use std::collections::{BTreeMap, HashMap};
+
+    #[cfg(feature = "db")]
+    use crate::db::NoDb;
+    #[cfg(feature = "db")]
+    use crate::{load_schema_from_src, parse_yaml_schema};
+    #[cfg(feature = "db")]
+    use crate::options::MigrationOptions;
+    use crate::plan::{format_plan, PlanEntry};
+
+    #[test]
+    fn format_plan_test() {
+        let plan = vec![
+            PlanEntry::statements(vec!["CREATE EXTENSION IF NOT EXISTS pg_trgm;\n".to_string()]),
+            PlanEntry::statements(vec!["".to_string()]),
+            PlanEntry {
+                schema: "s".to_string(),
+                table: "t".to_string(),
+                source_file: "t.yaml".to_string(),
+                ddl: "CREATE TABLE s.t (id integer);\n".to_string(),
+                data: "insert into s.t (id) values (1);\n".to_string(),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(format_plan(&plan, false),
+                   "CREATE EXTENSION IF NOT EXISTS pg_trgm;\nCREATE TABLE s.t (id integer);\ninsert into s.t (id) values (1);\n");
+        assert_eq!(format_plan(&plan, true),
+                   "-- DDL:\nCREATE EXTENSION IF NOT EXISTS pg_trgm;\n\n\
+                   -- Table: s.t (source: t.yaml)\n-- DDL:\nCREATE TABLE s.t (id integer);\n-- Data:\ninsert into s.t (id) values (1);\n\n");
+    }
+
+    #[cfg(feature = "db")]
+    #[test]
+    fn test_plan_entry() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          description: plan test
+          columns:
+            - column:
+                name: id
+                type: integer
+          data:
+            - [1]
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "test.yaml").unwrap();
+        let s = r.list.get(0).unwrap();
+        let entries = RefCell::new(Vec::new());
+        let store = |_: Vec<String>| -> Result<(), String> { panic!("the plan sink expected") };
+        let plan = |entry: PlanEntry| { entries.borrow_mut().push(entry); Ok(()) };
+        let opts = MigrationOptions::default().dry_run(&store).plan(&plan);
+        s.deploy_all_tables(&mut BTreeMap::new(), &mut HashMap::new(), &mut NoDb, false, opts.dry_run, &opts).unwrap();
+        let entries = entries.into_inner();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].schema.as_str(), "test_schema");
+        assert_eq!(entries[0].table.as_str(), "test_table");
+        assert_eq!(entries[0].source_file.as_str(), "test.yaml");
+        assert!(entries[0].ddl.contains("CREATE TABLE test_schema.test_table"));
+        assert!(entries[0].comments.contains("COMMENT ON TABLE test_schema.test_table IS 'plan test'"));
+        assert!(entries[0].data.starts_with("insert into test_schema.test_table (id)"));
+        let script = crate::plan::format_plan(&entries, true);
+        assert!(script.starts_with("-- Table: test_schema.test_table (source: test.yaml)\n-- DDL:\n"));
+        assert!(script.contains("\n-- Comments:\n") && script.contains("\n-- Data:\n"));
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use yaml_rust::Yaml;
+
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+
+/// operations published by default
+pub const PUBLISH_ALL: [&str; 4] = ["insert", "update", "delete", "truncate"];
+
+/// publication for logical replication, created after the tables
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YamlPublication {
+    pub name: String,
+    /// schema.table, public if no schema set
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tables: Vec<String>,
+    /// insert, update, delete, truncate; all if empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub publish: Vec<String>,
+    pub for_all_tables: bool,
+}
+
+/// publication loaded from DB
+#[derive(Debug, Clone, Serialize)]
+pub struct PgPublication {
+    pub all_tables: bool,
+    /// schema.table in name order
+    pub tables: Vec<String>,
+    /// operations in PUBLISH_ALL order
+    pub publish: Vec<String>,
+}
+
+/// publication name: publication
+pub type InfoPublicationType = HashMap<String, PgPublication>;
+
+impl YamlPublication {
+    pub(crate) fn new(input: &Yaml) -> Self {
+        let mut tables: Vec<String> = crate::utils::as_vec_str(input, "tables").into_iter()
+            .map(|t| {
+                let t = crate::utils::sql_qualified_name(t);
+                if t.contains('.') { t } else { format!("public.{}", t) }
+            })
+            .collect();
+        tables.sort();
+        tables.dedup();
+        let publish: Vec<String> = crate::utils::as_vec_str(input, "publish").iter()
+            .map(|p| p.trim().to_lowercase())
+            .collect();
+        YamlPublication {
+            name: crate::utils::sql_identifier(crate::utils::as_str_esc(input, "name")),
+            tables,
+            publish: PUBLISH_ALL.iter().filter(|p| publish.iter().any(|x| x == *p)).map(|p| p.to_string()).collect(),
+            for_all_tables: crate::utils::as_bool(input, "for_all_tables", false),
+        }
+    }
+
+    /// operations to publish, all if not set
+    fn publish(&self) -> Vec<String> {
+        if self.publish.len() == 0 {
+            PUBLISH_ALL.iter().map(|p| p.to_string()).collect()
+        } else {
+            self.publish.clone()
+        }
+    }
+
+    fn create_sql(&self) -> String {
+        let mut sql = format!("CREATE PUBLICATION {}", self.name);
+        if self.for_all_tables {
+            sql.push_str(" FOR ALL TABLES");
+        } else if self.tables.len() > 0 {
+            let _ = write!(sql, " FOR TABLE {}", self.tables.join(", "));
+        }
+        if self.publish.len() > 0 {
+            let _ = write!(sql, " WITH (publish = '{}')", self.publish.join(", "));
+        }
+        sql
+    }
+
+    /// statements to create the publication or alter its tables and operations comparing to loaded from DB,
+    /// recreated on the for_all_tables change
+    pub fn deploy_sql(&self, existing: &InfoPublicationType) -> Vec<String> {
+        let mut sql = Vec::new();
+        match existing.get(&self.name) {
+            None => sql.push(self.create_sql()),
+            Some(pg) if pg.all_tables != self.for_all_tables => {
+                sql.push(format!("DROP PUBLICATION {}", self.name));
+                sql.push(self.create_sql());
+            }
+            Some(pg) => {
+                if !self.for_all_tables && pg.tables != self.tables {
+                    if self.tables.len() > 0 {
+                        sql.push(format!("ALTER PUBLICATION {} SET TABLE {}", self.name, self.tables.join(", ")));
+                    } else {
+                        sql.push(format!("ALTER PUBLICATION {} DROP TABLE {}", self.name, pg.tables.join(", ")));
+                    }
+                }
+                let publish = self.publish();
+                if pg.publish != publish {
+                    sql.push(format!("ALTER PUBLICATION {} SET (publish = '{}')", self.name, publish.join(", ")));
+                }
+            }
+        }
+        sql
+    }
+}
+
+/// publications listed on the top level of the yaml, next to the database
+pub fn parse_yaml_publications(yaml: &Yaml) -> Result<Vec<YamlPublication>, String> {
+    let mut publications: Vec<YamlPublication> = Vec::new();
+    if let Some(pp) = yaml["publications"].as_vec() {
+        for p in pp {
+            let p = YamlPublication::new(&p["publication"]);
+            if p.name.len() == 0 {
+                return Err("empty publication name".to_string());
+            }
+            if p.for_all_tables && p.tables.len() > 0 {
+                return Err(format!("publication {} can not be both for all tables and for the tables", p.name));
+            }
+            if publications.iter().any(|x| x.name == p.name) {
+                return Err(format!("duplicate publication: {}", p.name));
+            }
+            publications.push(p);
+        }
+    }
+    Ok(publications)
+}
+
+#[cfg(feature = "db")]
+pub fn load_info_publications(db: &mut dyn DbExec) -> Result<InfoPublicationType, String> {
+    let mut data = HashMap::new();
+    let result = db.query("SELECT pubname::text, puballtables, pubinsert, pubupdate, pubdelete, pubtruncate FROM pg_publication", &[])
+        .map_err(|e| format!("on loading pg_publication: {}", e))?;
+    for r in result {
+        let name: &str = r.get(0);
+        let mut publish = Vec::new();
+        for (i, p) in PUBLISH_ALL.iter().enumerate() {
+            let on: bool = r.get(i + 2);
+            if on {
+                publish.push(p.to_string());
+            }
+        }
+        data.insert(crate::utils::quote_if_needed(name), PgPublication { all_tables: r.get(1), tables: Vec::new(), publish });
+    }
+    let result = db.query("SELECT p.pubname::text, n.nspname::text, c.relname::text FROM pg_publication_rel pr
+ JOIN pg_publication p ON p.oid = pr.prpubid
+ JOIN pg_class c ON c.oid = pr.prrelid
+ JOIN pg_namespace n ON n.oid = c.relnamespace", &[])
+        .map_err(|e| format!("on loading pg_publication_rel: {}", e))?;
+    for r in result {
+        let name: &str = r.get(0);
+        let table_schema: &str = r.get(1);
+        let table_name: &str = r.get(2);
+        if let Some(p) = data.get_mut(&crate::utils::quote_if_needed(name)) {
+            p.tables.push(format!("{}.{}", crate::utils::quote_if_needed(table_schema), crate::utils::quote_if_needed(table_name)));
+        }
+    }
+    for p in data.values_mut() {
+        p.tables.sort();
+    }
+    Ok(data)
+}
+
+/// create or alter publications after the tables, return count of changed publications
+#[cfg(feature = "db")]
+pub fn deploy_publications(publications: &Vec<YamlPublication>, existing: &mut InfoPublicationType, db: &mut dyn DbExec,
+                           dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+    let mut cnt = 0;
+    let mut sql = String::new();
+    for p in publications {
+        let stmts = p.deploy_sql(existing);
+        if stmts.len() > 0 {
+            cnt += 1;
+            for stmt in stmts {
+                let _ = writeln!(sql, "{};", stmt);
+            }
+            existing.insert(p.name.clone(), PgPublication {
+                all_tables: p.for_all_tables,
+                tables: if p.for_all_tables { Vec::new() } else { p.tables.clone() },
+                publish: p.publish(),
+            });
+        }
+    }
+    if cnt == 0 {
+        return Ok(0);
+    }
+    match dry_run {
+        Some(store) => store(vec![sql]).map(|_| 0),
+        None => {
+            let _ = db.batch_execute(sql.as_str())
+                .map_err(|e| format!("DB execute [{}]: {}", sql, e))?;
+            Ok(cnt)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use yaml_rust::YamlLoader;
+
+    use crate::publication::{parse_yaml_publications, PgPublication};
+
+    #[test]
+    fn publication_test() {
+        let yaml = YamlLoader::load_from_str(r#"
+publications:
+  - publication:
+      name: orders_pub
+      tables: [shop.orders, users]
+      publish: [UPDATE, insert]
+  - publication:
+      name: all_pub
+      for_all_tables: true
+database:
+  - schemaName: shop
+"#).unwrap().remove(0);
+        let pp = parse_yaml_publications(&yaml).unwrap();
+        assert_eq!(pp.len(), 2);
+        let mut existing = HashMap::new();
+        assert_eq!(pp[0].deploy_sql(&existing), vec!["CREATE PUBLICATION orders_pub FOR TABLE public.users, shop.orders WITH (publish = 'insert, update')".to_string()]);
+        assert_eq!(pp[1].deploy_sql(&existing), vec!["CREATE PUBLICATION all_pub FOR ALL TABLES".to_string()]);
+
+        existing.insert("orders_pub".to_string(), PgPublication {
+            all_tables: false,
+            tables: vec!["shop.orders".to_string()],
+            publish: vec!["insert".to_string(), "update".to_string()],
+        });
+        assert_eq!(pp[0].deploy_sql(&existing), vec!["ALTER PUBLICATION orders_pub SET TABLE public.users, shop.orders".to_string()]);
+        existing.get_mut("orders_pub").unwrap().tables.insert(0, "public.users".to_string());
+        assert!(pp[0].deploy_sql(&existing).is_empty());
+
+        existing.insert("all_pub".to_string(), PgPublication {
+            all_tables: true,
+            tables: vec![],
+            publish: vec!["insert".to_string()],
+        });
+        assert_eq!(pp[1].deploy_sql(&existing), vec!["ALTER PUBLICATION all_pub SET (publish = 'insert, update, delete, truncate')".to_string()]);
+        existing.get_mut("all_pub").unwrap().all_tables = false;
+        assert_eq!(pp[1].deploy_sql(&existing).len(), 2);
+    }
+}
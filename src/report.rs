@@ -0,0 +1,162 @@
+use serde::Serialize;
+
+use crate::validate::SchemaWarning;
+
+/// changes made on a table by deploy
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TableReport {
+    pub schema: String,
+    pub table: String,
+    pub created: bool,
+    pub altered: bool,
+    pub columns_added: Vec<String>,
+    pub indexes_created: usize,
+    pub indexes_dropped: usize,
+    pub grants_applied: usize,
+    pub grants_revoked: usize,
+    pub rows_inserted: usize,
+    pub warnings: Vec<SchemaWarning>,
+    /// statements to run after the migration commit, i.e. CREATE INDEX CONCURRENTLY
+    #[serde(skip)]
+    pub post_tx_sql: Vec<String>,
+}
+
+/// changes made by migrate, nothing counted on dry run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MigrationReport {
+    /// schema.table
+    pub tables_created: Vec<String>,
+    /// schema.table
+    pub tables_altered: Vec<String>,
+    /// schema.table, not in yaml, on drop_tables
+    pub tables_dropped: Vec<String>,
+    /// schema, table, column
+    pub columns_added: Vec<(String, String, String)>,
+    pub indexes_created: usize,
+    pub indexes_dropped: usize,
+    pub grants_applied: usize,
+    pub grants_revoked: usize,
+    pub rows_inserted: usize,
+    /// extensions, types, sequences, functions, foreign keys and views
+    pub objects_changed: usize,
+    /// found on deploy, i.e. columns order mismatch
+    pub warnings: Vec<SchemaWarning>,
+    /// schema.table, error; rolled back to the savepoint of the table, the others deployed
+    pub failed_tables: Vec<(String, String)>,
+    /// statements to run after the migration commit, i.e. CREATE INDEX CONCURRENTLY
+    #[serde(skip)]
+    pub post_tx_sql: Vec<String>,
+    /// statement, error; failed after the migration commit, so the other changes are applied
+    pub post_tx_failed: Vec<(String, String)>,
+    pub duration_ms: u128,
+}
+
+/// deploy of an existing table alters it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TableAction {
+    Create,
+    Alter,
+}
+
+/// migration step, passed to on_progress of the options
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ProgressEvent {
+    StartingTable { schema: String, table: String, action: TableAction },
+    /// changes as TableReport::count, 0 if nothing changed
+    FinishedTable { schema: String, table: String, changes: usize },
+    StartingFk { schema: String, table: String },
+    FinishedFk { schema: String, table: String },
+    /// the table or foreign keys deploy failed
+    Error { schema: String, table: String, message: String },
+}
+
+impl TableReport {
+    /// created or altered and the rows inserted
+    pub fn count(&self) -> usize {
+        (self.created || self.altered) as usize + self.rows_inserted
+    }
+
+    /// count the index statement by kind
+    pub(crate) fn count_index(&mut self, sql: &str) {
+        if sql.starts_with("DROP INDEX") {
+            self.indexes_dropped += 1;
+        } else if sql.starts_with("CREATE INDEX") || sql.starts_with("CREATE UNIQUE INDEX") {
+            self.indexes_created += 1;
+        }
+    }
+}
+
+impl MigrationReport {
+    pub fn add_table(&mut self, t: TableReport) {
+        let name = format!("{}.{}", t.schema, t.table);
+        if t.created {
+            self.tables_created.push(name);
+        } else if t.altered {
+            self.tables_altered.push(name);
+        }
+        for c in t.columns_added {
+            self.columns_added.push((t.schema.clone(), t.table.clone(), c));
+        }
+        self.indexes_created += t.indexes_created;
+        self.indexes_dropped += t.indexes_dropped;
+        self.grants_applied += t.grants_applied;
+        self.grants_revoked += t.grants_revoked;
+        self.rows_inserted += t.rows_inserted;
+        self.warnings.extend(t.warnings);
+        self.post_tx_sql.extend(t.post_tx_sql);
+    }
+
+    pub fn merge(&mut self, other: MigrationReport) {
+        self.tables_created.extend(other.tables_created);
+        self.tables_altered.extend(other.tables_altered);
+        self.tables_dropped.extend(other.tables_dropped);
+        self.columns_added.extend(other.columns_added);
+        self.indexes_created += other.indexes_created;
+        self.indexes_dropped += other.indexes_dropped;
+        self.grants_applied += other.grants_applied;
+        self.grants_revoked += other.grants_revoked;
+        self.rows_inserted += other.rows_inserted;
+        self.objects_changed += other.objects_changed;
+        self.warnings.extend(other.warnings);
+        self.failed_tables.extend(other.failed_tables);
+        self.post_tx_sql.extend(other.post_tx_sql);
+        self.post_tx_failed.extend(other.post_tx_failed);
+    }
+
+    /// run the statements deferred after the migration commit on the connection, not in a transaction;
+    /// a failed one reported in post_tx_failed, i.e. an invalid index left to re-create on the next run
+    #[cfg(feature = "db")]
+    pub fn execute_post_tx(&mut self, db: &mut postgres::Client) {
+        for sql in &self.post_tx_sql {
+            if let Err(e) = db.batch_execute(sql.as_str()) {
+                self.post_tx_failed.push((sql.clone(), e.to_string()));
+            }
+        }
+    }
+
+    /// count of created or altered tables and other changed objects
+    pub fn count(&self) -> usize {
+        self.tables_created.len() + self.tables_altered.len() + self.tables_dropped.len() + self.objects_changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::{MigrationReport, TableReport};
+
+    #[test]
+    fn report_test() {
+        let mut report = MigrationReport::default();
+        report.add_table(TableReport { schema: "s".to_string(), table: "a".to_string(), created: true, indexes_created: 2, rows_inserted: 3, ..Default::default() });
+        report.add_table(TableReport { schema: "s".to_string(), table: "b".to_string(), altered: true, columns_added: vec!["c".to_string()], ..Default::default() });
+        report.add_table(TableReport { schema: "s".to_string(), table: "d".to_string(), rows_inserted: 1, ..Default::default() });
+        assert_eq!(report.tables_created, vec!["s.a".to_string()]);
+        assert_eq!(report.tables_altered, vec!["s.b".to_string()]);
+        assert_eq!(report.columns_added, vec![("s".to_string(), "b".to_string(), "c".to_string())]);
+        assert_eq!(report.rows_inserted, 4);
+        let mut total = MigrationReport { objects_changed: 1, ..Default::default() };
+        total.merge(report);
+        assert_eq!(total.indexes_created, 2);
+        assert_eq!(total.count(), 3);
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use yaml_rust::Yaml;
+
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+
+/// comment set on the roles created by the migration, the only ones drop_roles drops
+pub const MANAGED_ROLE_COMMENT: &str = "managed by schema_guard";
+
+/// cluster role, created before the schemas, so usable as an owner or a grantee
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YamlRole {
+    pub name: String,
+    pub login: bool,
+    pub superuser: bool,
+    pub inherit: bool,
+    pub create_db: bool,
+    pub create_role: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection_limit: Option<i32>,
+    /// set on create only, the stored hash is not comparable; never echoed in a dry run or an error
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    /// roles granted this role
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<String>,
+}
+
+impl Default for YamlRole {
+    fn default() -> Self {
+        YamlRole {
+            name: "".to_string(),
+            login: false,
+            superuser: false,
+            inherit: true,
+            create_db: false,
+            create_role: false,
+            connection_limit: None,
+            password: None,
+            members: Vec::new(),
+        }
+    }
+}
+
+/// role loaded from DB
+#[derive(Debug, Clone, Serialize)]
+pub struct PgRole {
+    pub login: bool,
+    pub superuser: bool,
+    pub inherit: bool,
+    pub create_db: bool,
+    pub create_role: bool,
+    /// -1 for no limit
+    pub connection_limit: i32,
+    /// in name order
+    pub members: Vec<String>,
+    /// the bootstrap superuser or the current user, never dropped
+    pub system: bool,
+    /// created by the migration, commented with MANAGED_ROLE_COMMENT
+    pub managed: bool,
+}
+
+/// role name: role
+pub type InfoRoleType = HashMap<String, PgRole>;
+
+impl YamlRole {
+    pub(crate) fn new(input: &Yaml) -> Self {
+        let mut members: Vec<String> = crate::utils::as_vec_str(input, "members").into_iter()
+            .map(crate::utils::sql_identifier)
+            .filter(|m| m.len() > 0)
+            .collect();
+        members.sort();
+        members.dedup();
+        YamlRole {
+            name: crate::utils::sql_identifier(crate::utils::as_str_esc(input, "name")),
+            login: crate::utils::as_bool(input, "login", false),
+            superuser: crate::utils::as_bool(input, "superuser", false),
+            inherit: crate::utils::as_bool(input, "inherit", true),
+            create_db: crate::utils::as_bool(input, "create_db", false),
+            create_role: crate::utils::as_bool(input, "create_role", false),
+            connection_limit: input["connection_limit"].as_i64().map(|l| l as i32),
+            password: crate::utils::as_stro(input, "password"),
+            members,
+        }
+    }
+
+    /// the attributes differing from the role as loaded, all differing from the defaults for a new role
+    fn attributes(&self, existing: Option<&PgRole>) -> Vec<String> {
+        let mut attributes = Vec::new();
+        let flag = |on: bool, name: &str| if on { name.to_string() } else { format!("NO{}", name) };
+        for (on, was, name) in [(self.login, existing.map_or(false, |r| r.login), "LOGIN"),
+            (self.superuser, existing.map_or(false, |r| r.superuser), "SUPERUSER"),
+            (self.inherit, existing.map_or(true, |r| r.inherit), "INHERIT"),
+            (self.create_db, existing.map_or(false, |r| r.create_db), "CREATEDB"),
+            (self.create_role, existing.map_or(false, |r| r.create_role), "CREATEROLE")] {
+            if on != was {
+                attributes.push(flag(on, name));
+            }
+        }
+        let limit = self.connection_limit.unwrap_or(-1);
+        if limit != existing.map_or(-1, |r| r.connection_limit) {
+            attributes.push(format!("CONNECTION LIMIT {}", limit));
+        }
+        attributes
+    }
+
+    /// statements to create the role or alter its attributes comparing to loaded from DB,
+    /// the members are granted separately, after all the roles created
+    pub fn deploy_sql(&self, existing: &InfoRoleType) -> Option<String> {
+        let pg = existing.get(&self.name);
+        let attributes = self.attributes(pg);
+        match pg {
+            None => {
+                if attributes.len() == 0 {
+                    Some(format!("CREATE ROLE {}", self.name))
+                } else {
+                    Some(format!("CREATE ROLE {} WITH {}", self.name, attributes.join(" ")))
+                }
+            }
+            Some(_) if attributes.len() > 0 => Some(format!("ALTER ROLE {} WITH {}", self.name, attributes.join(" "))),
+            Some(_) => None,
+        }
+    }
+
+    /// ALTER ROLE PASSWORD of a new role, with the password redacted for a dry run and the errors
+    pub fn password_sql(&self, existing: &InfoRoleType) -> Option<(String, String)> {
+        match (&self.password, existing.contains_key(&self.name)) {
+            (Some(password), false) => Some((
+                format!("ALTER ROLE {} PASSWORD '{}'", self.name, password.replace('\'', "''")),
+                format!("ALTER ROLE {} PASSWORD '********'", self.name))),
+            _ => None,
+        }
+    }
+
+    /// GRANT role TO member for the members not granted yet
+    pub fn members_sql(&self, existing: &InfoRoleType) -> Vec<String> {
+        let granted = existing.get(&self.name).map_or(Vec::new(), |r| r.members.clone());
+        self.members.iter()
+            .filter(|m| !granted.contains(m))
+            .map(|m| format!("GRANT {} TO {}", self.name, m))
+            .collect()
+    }
+}
+
+/// roles listed on the top level of the yaml, next to the database
+pub fn parse_yaml_roles(yaml: &Yaml) -> Result<Vec<YamlRole>, String> {
+    let mut roles: Vec<YamlRole> = Vec::new();
+    if let Some(rr) = yaml["roles"].as_vec() {
+        for r in rr {
+            let r = YamlRole::new(&r["role"]);
+            if r.name.len() == 0 {
+                return Err("empty role name".to_string());
+            }
+            if roles.iter().any(|x| x.name == r.name) {
+                return Err(format!("duplicate role: {}", r.name));
+            }
+            roles.push(r);
+        }
+    }
+    Ok(roles)
+}
+
+/// DROP ROLE of the roles created by the migration and not in yaml anymore, except the system ones, in name order
+pub fn drop_roles_sql(roles: &[YamlRole], existing: &InfoRoleType) -> Vec<String> {
+    let mut names: Vec<&String> = existing.iter()
+        .filter(|(name, r)| r.managed && !r.system && !roles.iter().any(|x| &x.name == *name))
+        .map(|(name, _)| name)
+        .collect();
+    names.sort();
+    names.into_iter().map(|name| format!("DROP ROLE {}", name)).collect()
+}
+
+#[cfg(feature = "db")]
+pub fn load_info_roles(db: &mut dyn DbExec) -> Result<InfoRoleType, String> {
+    let mut data = HashMap::new();
+    let result = db.query("SELECT r.rolname::text, r.rolcanlogin, r.rolsuper, r.rolinherit, r.rolcreatedb, r.rolcreaterole, r.rolconnlimit,
+ array(SELECT m.rolname::text FROM pg_auth_members am JOIN pg_roles m ON m.oid = am.member WHERE am.roleid = r.oid ORDER BY 1),
+ r.oid = 10 OR r.rolname = current_user,
+ coalesce(shobj_description(r.oid, 'pg_authid') = $1, false)
+ FROM pg_roles r WHERE r.rolname !~ '^pg_'", &[&MANAGED_ROLE_COMMENT])
+        .map_err(|e| format!("on loading pg_roles: {}", e))?;
+    for r in result {
+        let name: &str = r.get(0);
+        let members: Vec<String> = r.get(7);
+        let mut members: Vec<String> = members.iter().map(|m| crate::utils::quote_if_needed(m)).collect();
+        members.sort();
+        data.insert(crate::utils::quote_if_needed(name), PgRole {
+            login: r.get(1),
+            superuser: r.get(2),
+            inherit: r.get(3),
+            create_db: r.get(4),
+            create_role: r.get(5),
+            connection_limit: r.get(6),
+            members,
+            system: r.get(8),
+            managed: r.get(9),
+        });
+    }
+    Ok(data)
+}
+
+/// create or alter the roles, then grant the members, return count of changed roles
+#[cfg(feature = "db")]
+pub fn deploy_roles(roles: &Vec<YamlRole>, existing: &mut InfoRoleType, db: &mut dyn DbExec,
+                    dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+    let mut cnt = 0;
+    let mut sql = String::new();
+    let mut passwords = Vec::new();
+    for r in roles {
+        if let Some(stmt) = r.deploy_sql(existing) {
+            cnt += 1;
+            let _ = writeln!(sql, "{};", stmt);
+            if !existing.contains_key(&r.name) {
+                let _ = writeln!(sql, "COMMENT ON ROLE {} IS '{}';", r.name, MANAGED_ROLE_COMMENT);
+            }
+            passwords.extend(r.password_sql(existing));
+            let members = existing.get(&r.name).map_or(Vec::new(), |pg| pg.members.clone());
+            let system = existing.get(&r.name).map_or(false, |pg| pg.system);
+            let managed = existing.get(&r.name).map_or(true, |pg| pg.managed);
+            existing.insert(r.name.clone(), PgRole {
+                login: r.login,
+                superuser: r.superuser,
+                inherit: r.inherit,
+                create_db: r.create_db,
+                create_role: r.create_role,
+                connection_limit: r.connection_limit.unwrap_or(-1),
+                members,
+                system,
+                managed,
+            });
+        }
+    }
+    for r in roles {
+        let stmts = r.members_sql(existing);
+        if stmts.len() > 0 {
+            cnt += 1;
+            for stmt in stmts {
+                let _ = writeln!(sql, "{};", stmt);
+            }
+            if let Some(pg) = existing.get_mut(&r.name) {
+                pg.members.extend(r.members.iter().filter(|m| !pg.members.contains(m)).cloned().collect::<Vec<String>>());
+                pg.members.sort();
+            }
+        }
+    }
+    let cnt = execute(sql, cnt, db, dry_run)?;
+    for (password, redacted) in passwords {
+        match dry_run {
+            Some(store) => store(vec![format!("{};\n", redacted)])?,
+            None => db.batch_execute(password.as_str())
+                .map_err(|e| format!("DB execute [{}]: {}", redacted, e))?,
+        }
+    }
+    Ok(cnt)
+}
+
+/// drop the roles not defined in yaml, return count of dropped
+#[cfg(feature = "db")]
+pub fn drop_roles(roles: &Vec<YamlRole>, existing: &mut InfoRoleType, db: &mut dyn DbExec,
+                  dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+    let stmts = drop_roles_sql(roles, existing);
+    let mut sql = String::new();
+    for stmt in &stmts {
+        let _ = writeln!(sql, "{};", stmt);
+    }
+    existing.retain(|name, r| !r.managed || r.system || roles.iter().any(|x| &x.name == name));
+    execute(sql, stmts.len(), db, dry_run)
+}
+
+#[cfg(feature = "db")]
+fn execute(sql: String, cnt: usize, db: &mut dyn DbExec,
+           dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+    if cnt == 0 {
+        return Ok(0);
+    }
+    match dry_run {
+        Some(store) => store(vec![sql]).map(|_| 0),
+        None => {
+            let _ = db.batch_execute(sql.as_str())
+                .map_err(|e| format!("DB execute [{}]: {}", sql, e))?;
+            Ok(cnt)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use yaml_rust::YamlLoader;
+
+    use crate::role::{drop_roles_sql, parse_yaml_roles, PgRole};
+
+    #[test]
+    fn role_test() {
+        let yaml = YamlLoader::load_from_str(r#"
+roles:
+  - role:
+      name: app_user
+      login: true
+      connection_limit: 10
+      password: "it's"
+      members: [report]
+  - role:
+      name: report
+      inherit: false
+database:
+  - schemaName: shop
+"#).unwrap().remove(0);
+        let rr = parse_yaml_roles(&yaml).unwrap();
+        assert_eq!(rr.len(), 2);
+        let mut existing = HashMap::new();
+        assert_eq!(rr[0].deploy_sql(&existing).unwrap().as_str(), "CREATE ROLE app_user WITH LOGIN CONNECTION LIMIT 10");
+        assert_eq!(rr[0].password_sql(&existing).unwrap(), ("ALTER ROLE app_user PASSWORD 'it''s'".to_string(),
+            "ALTER ROLE app_user PASSWORD '********'".to_string()));
+        assert!(rr[1].password_sql(&existing).is_none());
+        assert_eq!(rr[1].deploy_sql(&existing).unwrap().as_str(), "CREATE ROLE report WITH NOINHERIT");
+        assert_eq!(rr[0].members_sql(&existing), vec!["GRANT app_user TO report".to_string()]);
+
+        let pg = PgRole { login: true, superuser: false, inherit: true, create_db: false, create_role: false, connection_limit: -1,
+            members: vec!["report".to_string()], system: false, managed: true };
+        existing.insert("app_user".to_string(), pg.clone());
+        assert!(rr[0].password_sql(&existing).is_none());
+        assert_eq!(rr[0].deploy_sql(&existing).unwrap().as_str(), "ALTER ROLE app_user WITH CONNECTION LIMIT 10");
+        assert!(rr[0].members_sql(&existing).is_empty());
+        existing.get_mut("app_user").unwrap().connection_limit = 10;
+        assert!(rr[0].deploy_sql(&existing).is_none());
+
+        existing.insert("report".to_string(), PgRole { login: true, create_db: true, members: vec![], ..pg.clone() });
+        assert_eq!(rr[1].deploy_sql(&existing).unwrap().as_str(), "ALTER ROLE report WITH NOLOGIN NOINHERIT NOCREATEDB");
+
+        existing.insert("postgres".to_string(), PgRole { system: true, ..pg.clone() });
+        existing.insert("other".to_string(), PgRole { managed: false, ..pg.clone() });
+        existing.insert("old".to_string(), pg);
+        assert_eq!(drop_roles_sql(&rr, &existing), vec!["DROP ROLE old".to_string()]);
+
+        let yaml = YamlLoader::load_from_str("roles:\n  - role:\n      name: a\n  - role:\n      name: a\n").unwrap().remove(0);
+        assert_eq!(parse_yaml_roles(&yaml).unwrap_err().as_str(), "duplicate role: a");
+    }
+}
@@ -1,19 +1,55 @@
-use postgres::Transaction;
-use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
 use yaml_rust::Yaml;
 
-use crate::loader::InfoSchemaType;
-use crate::table::Table;
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+use crate::foreign::{InfoForeignTableType, PgForeignTable, YamlForeignTable};
+use crate::loader::{InfoSchemaCommentType, InfoSchemaOwnerType, InfoSchemaType, PgTable};
+use crate::options::MigrationOptions;
+use crate::report::{MigrationReport, ProgressEvent, TableAction, TableReport};
+use crate::sequence::{InfoSequenceType, PgSequence, YamlSequence};
+use crate::function::{InfoFunctionType, PgFunction, YamlFunction};
+use crate::grant::{BATCH_GRANT_SCOPES, FUNCTION_PRIVILEGES, GrantBuilder, InfoDefaultPrivilegeType, SCHEMA_PRIVILEGES, SEQUENCE_PRIVILEGES, TABLE_PRIVILEGES, YDefaultPrivilege};
+use crate::table::{Table, YGrant};
+use crate::types::{TypesLoader, YamlType};
 use crate::utils::{Named, OrderedHashMap};
+use crate::view::{InfoViewType, PgView, YamlView};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Schema {
     #[serde(rename = "schemaName")]
     pub schema_name: String,
     pub owner: String,
+    /// COMMENT ON SCHEMA
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub description: String,
     /// tableName: table(with name)
     #[serde(with = "crate::table::ytables")]
     pub tables: OrderedHashMap<Table>,
+    /// enums and domains
+    #[serde(with = "crate::utils::enveloped", skip_serializing_if = "OrderedHashMap::is_empty")]
+    pub types: OrderedHashMap<YamlType>,
+    #[serde(with = "crate::utils::enveloped", skip_serializing_if = "OrderedHashMap::is_empty")]
+    pub sequences: OrderedHashMap<YamlSequence>,
+    /// views and materialized views
+    #[serde(with = "crate::utils::enveloped", skip_serializing_if = "OrderedHashMap::is_empty")]
+    pub views: OrderedHashMap<YamlView>,
+    #[serde(with = "crate::utils::enveloped", skip_serializing_if = "OrderedHashMap::is_empty")]
+    pub functions: OrderedHashMap<YamlFunction>,
+    /// tables of a foreign server, deployed apart from the tables
+    #[serde(with = "crate::utils::enveloped", skip_serializing_if = "OrderedHashMap::is_empty")]
+    pub foreign_tables: OrderedHashMap<YamlForeignTable>,
+    /// privileges on the schema
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub grant: Vec<YGrant>,
+    /// privileges of the objects created later in the schema
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub default_privileges: Vec<YDefaultPrivilege>,
     /// the table definition loaded from file
     #[serde(skip)]
     pub file: String,
@@ -25,12 +61,31 @@ impl Named for Schema {
     }
 }
 
+/// schema node of yaml with all its objects, the file is not known for the error reference
+impl TryFrom<&Yaml> for Schema {
+    type Error = String;
+
+    fn try_from(input: &Yaml) -> Result<Self, Self::Error> {
+        let mut schema = Schema::new(input, "");
+        schema.append(input)?;
+        Ok(schema)
+    }
+}
+
 impl Default for Schema {
     fn default() -> Self {
         Schema {
             schema_name: "".to_string(),
             owner: "".to_string(),
+            description: "".to_string(),
             tables: OrderedHashMap::new(),
+            types: OrderedHashMap::new(),
+            sequences: OrderedHashMap::new(),
+            views: OrderedHashMap::new(),
+            functions: OrderedHashMap::new(),
+            foreign_tables: OrderedHashMap::new(),
+            grant: Vec::new(),
+            default_privileges: Vec::new(),
             file: "".to_string(),
         }
     }
@@ -49,14 +104,98 @@ impl Schema {
     pub fn new(input: &Yaml, file: &str) -> Self {
         Schema {
             schema_name: Schema::schema_name(input),
-            owner: crate::utils::as_str(input, "schemaName", ""),
+            owner: crate::utils::as_str(input, "owner", ""),
+            description: crate::utils::as_str(input, "description", ""),
             tables: OrderedHashMap::new(),
+            types: OrderedHashMap::new(),
+            sequences: OrderedHashMap::new(),
+            views: OrderedHashMap::new(),
+            functions: OrderedHashMap::new(),
+            foreign_tables: OrderedHashMap::new(),
+            grant: Vec::new(),
+            default_privileges: Vec::new(),
             file: file.to_string(),
         }
     }
 
+    /// schema definition as loaded from DB, tables in name order
+    pub fn from_info(schema_name: &str, owner: &str, info: &HashMap<String, PgTable>) -> Self {
+        let mut tables: Vec<&PgTable> = info.values().collect();
+        tables.sort_by(|a, b| a.table_name.cmp(&b.table_name));
+        Schema {
+            schema_name: schema_name.to_string(),
+            owner: owner.to_string(),
+            tables: tables.into_iter().map(|t| Table::from(t)).collect(),
+            ..Schema::default()
+        }
+    }
+
+    /// enums and domains of this schema as loaded from DB
+    pub fn append_types(&mut self, types: &TypesLoader) {
+        let prefix = format!("{}.", self.schema_name);
+        let mut names: Vec<&String> = types.enums.keys().chain(types.domains.keys()).chain(types.composites.keys())
+            .filter(|n| n.starts_with(prefix.as_str())).collect();
+        names.sort();
+        for n in names {
+            let _ = self.types.append(YamlType {
+                name: n[prefix.len()..].to_string(),
+                schema: self.schema_name.clone(),
+                enum_values: types.enums.get(n).cloned().unwrap_or_default(),
+                domain_base_type: types.domains.get(n).map(|d| d.base_type.clone()),
+                check_expr: types.domains.get(n).and_then(|d| d.check_expr()),
+                not_null: types.domains.get(n).map_or(false, |d| d.not_null),
+                default_value: types.domains.get(n).and_then(|d| d.default_value.clone()),
+                attributes: types.composites.get(n).cloned().unwrap_or_default(),
+            });
+        }
+    }
+
     #[inline]
     pub fn append(&mut self, input: &Yaml) -> Result<(), String> {
+        self.grant.extend(YGrant::new(input["grant"].as_vec()));
+        self.default_privileges.extend(YDefaultPrivilege::new(input["default_privileges"].as_vec(), &self.schema_name)
+            .map_err(|e| format!("{} in file: {}", e, self.file))?);
+        if let Some(tps) = input["types"].as_vec() {
+            for t in tps {
+                let t = YamlType::new(&t["type"], &self.schema_name);
+                if let Err(e) = self.types.append(t) {
+                    return Err(format!("{} (type name) found in file: {}", e, self.file));
+                }
+            }
+        }
+        if let Some(sqs) = input["sequences"].as_vec() {
+            for sq in sqs {
+                let sq = YamlSequence::new(&sq["sequence"], &self.schema_name)
+                    .map_err(|e| format!("{} in file: {}", e, self.file))?;
+                if let Err(e) = self.sequences.append(sq) {
+                    return Err(format!("{} (sequence name) found in file: {}", e, self.file));
+                }
+            }
+        }
+        if let Some(vws) = input["views"].as_vec() {
+            for v in vws {
+                let v = YamlView::new(&v["view"], &self.schema_name);
+                if let Err(e) = self.views.append(v) {
+                    return Err(format!("{} (view name) found in file: {}", e, self.file));
+                }
+            }
+        }
+        if let Some(fns) = input["functions"].as_vec() {
+            for f in fns {
+                let f = YamlFunction::new(&f["function"], &self.schema_name);
+                if let Err(e) = self.functions.append(f) {
+                    return Err(format!("{} (function name) found in file: {}", e, self.file));
+                }
+            }
+        }
+        if let Some(fts) = input["foreign_tables"].as_vec() {
+            for ft in fts {
+                let ft = YamlForeignTable::new(&ft["foreign_table"], &self.schema_name);
+                if let Err(e) = self.foreign_tables.append(ft) {
+                    return Err(format!("{} (foreign table name) found in file: {}", e, self.file));
+                }
+            }
+        }
         if let Some(tbls) = input["tables"].as_vec() {
             for t in tbls {
                 let t = &t["table"];
@@ -65,7 +204,8 @@ impl Schema {
                         return Err(format!("no table name set in file: {}", self.file));
                     }
                     Some(tn) => {
-                        if self.tables.map.contains_key(tn) {
+                        let name = crate::utils::sql_identifier(tn.to_string());
+                        if self.tables.map.contains_key(&name) || self.foreign_tables.map.contains_key(&name) {
                             return Err(format!(
                                 "duplicate table definition: {} found in file: {}",
                                 tn, self.file
@@ -84,28 +224,953 @@ impl Schema {
         Ok(())
     }
 
+    /// move all objects of the same schema defined elsewhere, i.e. in another file, into this one;
+    /// the first owner and description kept
+    pub fn merge(&mut self, other: Schema) -> Result<(), String> {
+        let file = other.file.clone();
+        let dup = |e: String, kind: &str| format!("{} ({}) found in file: {}", e, kind, file);
+        for t in other.tables {
+            if self.tables.map.contains_key(&t.table_name) || self.foreign_tables.map.contains_key(&t.table_name) {
+                return Err(format!("duplicate table definition: {} found in file: {}", t.table_name, file));
+            }
+            let _ = self.tables.append(t);
+        }
+        for t in other.types {
+            self.types.append(t).map_err(|e| dup(e, "type name"))?;
+        }
+        for sq in other.sequences {
+            self.sequences.append(sq).map_err(|e| dup(e, "sequence name"))?;
+        }
+        for v in other.views {
+            self.views.append(v).map_err(|e| dup(e, "view name"))?;
+        }
+        for f in other.functions {
+            self.functions.append(f).map_err(|e| dup(e, "function name"))?;
+        }
+        for ft in other.foreign_tables {
+            if self.tables.map.contains_key(&ft.name) {
+                return Err(format!("duplicate table definition: {} found in file: {}", ft.name, file));
+            }
+            self.foreign_tables.append(ft).map_err(|e| dup(e, "foreign table name"))?;
+        }
+        self.grant.extend(other.grant);
+        self.default_privileges.extend(other.default_privileges);
+        if self.owner.len() == 0 {
+            self.owner = other.owner;
+        } else if other.owner.len() > 0 && other.owner != self.owner {
+            #[cfg(feature = "slog")] crate::log_warn(format!("schema {} owner {} found in file: {} ignored, the owner is {}",
+                                                             self.schema_name, other.owner, file, self.owner));
+        }
+        if self.description.len() == 0 {
+            self.description = other.description;
+        }
+        if !self.file.split(", ").any(|f| f == file) {
+            self.file = format!("{}, {}", self.file, file);
+        }
+        Ok(())
+    }
+
     #[inline]
-    /// return statements to execute
-    pub fn deploy_all_tables(&self, schema: &mut InfoSchemaType, db: &mut Transaction, retry: bool, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+    /// create or alter types, return count of changed types
+    #[cfg(feature = "db")]
+    pub fn deploy_types(&self, types: &mut TypesLoader, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>, opts: &MigrationOptions) -> Result<usize, String> {
         let mut cnt = 0;
-        for t in &self.tables.list {
-            if t.deploy(schema, db, &self.schema_name, retry, self.file.as_str(), dry_run)? {
+        let mut sql = String::new();
+        for t in &self.types.list {
+            let stmts = t.deploy_sql(types, opts)?;
+            if stmts.len() > 0 {
                 cnt += 1;
+                for s in stmts {
+                    let _ = writeln!(sql, "{};", s);
+                }
+                if t.enum_values.len() > 0 {
+                    types.enums.insert(t.full_name(), t.enum_values.clone());
+                } else if t.attributes.len() > 0 {
+                    types.composites.insert(t.full_name(), t.attributes.clone());
+                } else {
+                    types.domains.insert(t.full_name(), t.pg_domain());
+                }
             }
         }
-        Ok(cnt)
+        self.execute(sql, cnt, true, db, dry_run)
+    }
+
+    #[inline]
+    /// create or alter sequences before the tables, so DEFAULT nextval('seq') resolves,
+    /// return count of changed sequences
+    #[cfg(feature = "db")]
+    pub fn deploy_sequences(&self, sequences: &mut InfoSequenceType, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>, opts: &MigrationOptions) -> Result<usize, String> {
+        let mut cnt = 0;
+        let mut sql = String::new();
+        for s in &self.sequences.list {
+            let mut changed = false;
+            if let Some(stmt) = s.deploy_sql(sequences) {
+                changed = true;
+                let _ = writeln!(sql, "{};", stmt);
+                let (owned_by, owner, grants) = sequences.remove(&s.full_name())
+                    .map_or((None, "".to_string(), HashMap::new()), |pg| (pg.owned_by, pg.owner, pg.grants));
+                sequences.insert(s.full_name(), PgSequence {
+                    start: s.start.unwrap_or_default(),
+                    increment: s.increment.unwrap_or_default(),
+                    min_value: s.min_value.unwrap_or_default(),
+                    max_value: s.max_value.unwrap_or_default(),
+                    cycle: s.cycle,
+                    owned_by,
+                    owner,
+                    grants,
+                });
+            }
+            for stmt in s.grant_sql(sequences, opts.with_revoke).map_err(|e| format!("{} source: {}", e, self.file))? {
+                changed = true;
+                let _ = writeln!(sql, "{};", stmt);
+            }
+            if changed {
+                cnt += 1;
+            }
+        }
+        self.execute(sql, cnt, true, db, dry_run)
+    }
+
+    #[inline]
+    /// set sequences OWNED BY after the tables created, return count of changed sequences
+    #[cfg(feature = "db")]
+    pub fn deploy_sequences_owner(&self, sequences: &mut InfoSequenceType, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+        let mut cnt = 0;
+        let mut sql = String::new();
+        for s in &self.sequences.list {
+            if let Some(stmt) = s.owner_sql(sequences) {
+                cnt += 1;
+                let _ = writeln!(sql, "{};", stmt);
+                if let Some(pg) = sequences.get_mut(&s.full_name()) {
+                    pg.owned_by = s.owned_by();
+                }
+            }
+        }
+        self.execute(sql, cnt, false, db, dry_run)
+    }
+
+    #[inline]
+    /// create, replace or refresh views after the tables, return count of changed views
+    #[cfg(feature = "db")]
+    pub fn deploy_views(&self, views: &mut InfoViewType, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+        let mut cnt = 0;
+        let mut sql = String::new();
+        for v in &self.views.list {
+            let stmts = v.deploy_sql(views).map_err(|e| format!("{} source: {}", e, self.file))?;
+            if stmts.len() > 0 {
+                cnt += 1;
+                for s in stmts {
+                    let _ = writeln!(sql, "{};", s);
+                }
+                views.insert(v.full_name(), PgView { hash: v.hash(), materialized: v.materialized });
+            }
+        }
+        self.execute(sql, cnt, true, db, dry_run)
+    }
+
+    #[inline]
+    /// create foreign tables or alter its columns and options, return count of changed foreign tables
+    #[cfg(feature = "db")]
+    pub fn deploy_foreign_tables(&self, foreign_tables: &mut InfoForeignTableType, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+        let mut cnt = 0;
+        let mut sql = String::new();
+        for ft in &self.foreign_tables.list {
+            let stmts = ft.deploy_sql(foreign_tables).map_err(|e| format!("{} source: {}", e, self.file))?;
+            if stmts.len() > 0 {
+                cnt += 1;
+                for s in stmts {
+                    let _ = writeln!(sql, "{};", s);
+                }
+                foreign_tables.insert(ft.full_name(), PgForeignTable {
+                    server: ft.server.clone(),
+                    options: ft.options.clone(),
+                    columns: ft.columns.iter().map(|c| c.name.clone()).collect(),
+                });
+            }
+        }
+        self.execute(sql, cnt, true, db, dry_run)
+    }
+
+    #[inline]
+    /// create or replace functions before the tables, so triggers and defaults resolve,
+    /// bodies are not validated until the tables created, return count of changed functions
+    #[cfg(feature = "db")]
+    pub fn deploy_functions(&self, functions: &mut InfoFunctionType, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+        let mut cnt = 0;
+        let mut sql = String::new();
+        for f in &self.functions.list {
+            if let Some(stmt) = f.deploy_sql(functions).map_err(|e| format!("{} source: {}", e, self.file))? {
+                cnt += 1;
+                let _ = writeln!(sql, "{};", stmt);
+                functions.insert(f.signature(), PgFunction {
+                    definition: stmt,
+                    language: f.language.clone(),
+                    volatility: if f.volatility.len() > 0 { f.volatility.clone() } else { "VOLATILE".to_string() },
+                    security_definer: f.security_definer,
+                    returns: f.returns.clone(),
+                });
+            }
+        }
+        if cnt > 0 {
+            sql.insert_str(0, "SET LOCAL check_function_bodies = off;\n");
+            sql.push_str("RESET check_function_bodies;\n");
+        }
+        self.execute(sql, cnt, true, db, dry_run)
+    }
+
+    #[inline]
+    /// drop functions of this schema not defined in any schema, return count of dropped functions
+    #[cfg(feature = "db")]
+    pub fn drop_functions(&self, schemas: &OrderedHashMap<Schema>, functions: &mut InfoFunctionType, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+        let prefix = format!("{}.", self.schema_name);
+        let mut signatures: Vec<String> = functions.keys()
+            .filter(|s| s.starts_with(prefix.as_str()))
+            .filter(|s| !schemas.list.iter().any(|ss| ss.functions.list.iter().any(|f| &f.signature() == *s)))
+            .cloned()
+            .collect();
+        signatures.sort();
+        let mut sql = String::new();
+        for s in &signatures {
+            let _ = writeln!(sql, "DROP FUNCTION {};", s);
+            functions.remove(s);
+        }
+        self.execute(sql, signatures.len(), false, db, dry_run)
+    }
+
+    /// drop the tables of this schema not defined in yaml, except never_drop,
+    /// a table referencing another one dropped before it; return schema.table of dropped
+    #[cfg(feature = "db")]
+    fn drop_tables(&self, schema: &mut InfoSchemaType, db: &mut dyn DbExec, retry: bool,
+                   dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>, opts: &MigrationOptions) -> Result<Vec<String>, String> {
+        let tables = match schema.get_mut(&self.schema_name) {
+            None => return Ok(Vec::new()),
+            Some(tables) => tables,
+        };
+        let never_drop = |t: &String| opts.never_drop.iter()
+            .any(|n| n == t || *n == format!("{}.{}", self.schema_name, t));
+        let mut names: Vec<String> = tables.keys()
+            .filter(|t| self.tables.get(t).is_none() && self.foreign_tables.get(t).is_none() && !never_drop(t))
+            .cloned()
+            .collect();
+        names.sort();
+        let mut ordered: Vec<String> = Vec::new();
+        while ordered.len() < names.len() {
+            let referenced = |t: &String| names.iter()
+                .filter(|n| !ordered.contains(n) && *n != t)
+                .any(|n| tables[n].fks.values().any(|fk| fk.schema == self.schema_name && &fk.table == t));
+            // the rest referenced in a cycle
+            let next = names.iter().find(|t| !ordered.contains(t) && !referenced(t))
+                .or_else(|| names.iter().find(|t| !ordered.contains(t)))
+                .cloned();
+            ordered.extend(next);
+        }
+        let mut sql = String::new();
+        for t in &ordered {
+            crate::table::append(Table::drop_sql(&self.schema_name, t, false).as_str(), &mut sql, retry, opts);
+            tables.remove(t);
+        }
+        self.execute(sql, ordered.len(), false, db, dry_run)?;
+        Ok(ordered.iter().map(|t| format!("{}.{}", self.schema_name, t)).collect())
+    }
+
+    /// execute or pass to dry run the statements batch
+    #[cfg(feature = "db")]
+    fn execute(&self, mut sql: String, cnt: usize, create_schema: bool, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+        if cnt == 0 {
+            return Ok(0);
+        }
+        if create_schema && self.schema_name.as_str() != "public" {
+            sql.insert_str(0, format!("CREATE SCHEMA IF NOT EXISTS {};\n", self.schema_name).as_str());
+        }
+        match dry_run {
+            Some(store) => store(vec![sql]).map(|_| 0),
+            None => {
+                let _ = db.batch_execute(sql.as_str())
+                    .map_err(|e| format!("DB execute [{}]: {} source: {}", sql, e, self.file))?;
+                Ok(cnt)
+            }
+        }
+    }
+
+    #[inline]
+    /// return changes made on the tables of the schema
+    #[cfg(feature = "db")]
+    pub fn deploy_all_tables(&self, schema: &mut InfoSchemaType, owners: &mut InfoSchemaOwnerType, db: &mut dyn DbExec, retry: bool, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>, opts: &MigrationOptions) -> Result<MigrationReport, String> {
+        let mut report = MigrationReport::default();
+        for t in self.deploy_order() {
+            opts.check_cancelled()?;
+            let action = if schema.get(&self.schema_name).map_or(false, |s| s.contains_key(&t.table_name)) {
+                TableAction::Alter
+            } else {
+                TableAction::Create
+            };
+            opts.progress(ProgressEvent::StartingTable { schema: self.schema_name.clone(), table: t.table_name.clone(), action });
+            if opts.savepoint_per_table && dry_run.is_none() {
+                self.deploy_table_savepoint(t, schema, db, retry, opts, &mut report)?;
+                continue;
+            }
+            match t.deploy(schema, db, &self.schema_name, retry, self.file.as_str(), dry_run, opts) {
+                Ok(tr) => {
+                    self.finished_table(t, tr.as_ref(), opts);
+                    if let Some(tr) = tr {
+                        report.add_table(tr);
+                    }
+                }
+                Err(e) => {
+                    self.failed_table(t, &e, opts);
+                    return Err(e);
+                }
+            }
+        }
+        if opts.drop_tables {
+            report.tables_dropped = self.drop_tables(schema, db, retry, dry_run, opts)?;
+        }
+        report.objects_changed += self.deploy_schema_owner(owners, db, dry_run, opts)?;
+        let (granted, revoked) = self.deploy_schema_grants(owners, db, dry_run, opts)?;
+        report.grants_applied += granted;
+        report.grants_revoked += revoked;
+        Ok(report)
+    }
+
+    /// deploy the table within a savepoint, on error rolled back to it and the table reported as failed,
+    /// so the other tables still deployed
+    #[cfg(feature = "db")]
+    fn deploy_table_savepoint(&self, t: &Table, schema: &mut InfoSchemaType, db: &mut dyn DbExec, retry: bool,
+                              opts: &MigrationOptions, report: &mut MigrationReport) -> Result<(), String> {
+        let savepoint = format!("sp_table_{}", t.table_name.replace(|c: char| !c.is_alphanumeric() && c != '_', "_"));
+        let loaded = schema.get(&self.schema_name).and_then(|s| s.get(&t.table_name)).cloned();
+        let _ = db.batch_execute(format!("SAVEPOINT {}", savepoint).as_str())
+            .map_err(|e| format!("DB execute [SAVEPOINT {}]: {}", savepoint, e))?;
+        match t.deploy(schema, db, &self.schema_name, retry, self.file.as_str(), None, opts) {
+            Ok(tr) => {
+                let _ = db.batch_execute(format!("RELEASE SAVEPOINT {}", savepoint).as_str())
+                    .map_err(|e| format!("DB execute [RELEASE SAVEPOINT {}]: {}", savepoint, e))?;
+                self.finished_table(t, tr.as_ref(), opts);
+                if let Some(tr) = tr {
+                    report.add_table(tr);
+                }
+            }
+            Err(e) => {
+                let _ = db.batch_execute(format!("ROLLBACK TO SAVEPOINT {0}; RELEASE SAVEPOINT {0}", savepoint).as_str())
+                    .map_err(|e| format!("DB execute [ROLLBACK TO SAVEPOINT {}]: {}", savepoint, e))?;
+                // the loaded state as before the table deploy
+                if let Some(tables) = schema.get_mut(&self.schema_name) {
+                    match loaded {
+                        Some(pt) => { tables.insert(t.table_name.clone(), pt); }
+                        None => { tables.remove(&t.table_name); }
+                    }
+                }
+                #[cfg(feature = "slog")] crate::log_warn(format!("table {}.{} failed, rolled back: {}", self.schema_name, t.table_name, e));
+                self.failed_table(t, &e, opts);
+                report.failed_tables.push((format!("{}.{}", self.schema_name, t.table_name), e));
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn finished_table(&self, t: &Table, tr: Option<&TableReport>, opts: &MigrationOptions) {
+        opts.progress(ProgressEvent::FinishedTable {
+            schema: self.schema_name.clone(),
+            table: t.table_name.clone(),
+            changes: tr.map_or(0, |tr| tr.count()),
+        });
+    }
+
+    #[inline]
+    fn failed_table(&self, t: &Table, e: &String, opts: &MigrationOptions) {
+        opts.progress(ProgressEvent::Error { schema: self.schema_name.clone(), table: t.table_name.clone(), message: e.clone() });
+    }
+
+    /// insert data rows of the tables, no DDL
+    #[cfg(feature = "db")]
+    pub fn deploy_all_data(&self, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>,
+                           opts: &MigrationOptions) -> Result<MigrationReport, String> {
+        let mut report = MigrationReport::default();
+        for t in self.deploy_order() {
+            if let Some(tr) = t.deploy_data(db, &self.schema_name, self.file.as_str(), dry_run, opts)? {
+                report.add_table(tr);
+            }
+        }
+        Ok(report)
+    }
+
+    /// tables by partition level, partitioned tables before its partitions, otherwise in yaml order
+    pub(crate) fn deploy_order(&self) -> Vec<&Table> {
+        let mut tables: Vec<(usize, &Table)> = self.tables.list.iter().map(|t| (t.partition_level(&self.tables), t)).collect();
+        tables.sort_by_key(|(level, _)| *level);
+        tables.into_iter().map(|(_, t)| t).collect()
+    }
+
+    /// set the owner of the schema as in yaml after the tables created it, if differs from the loaded one;
+    /// objects of the previous owner reassigned first if reassign_owned set, return count of statements
+    #[cfg(feature = "db")]
+    pub fn deploy_schema_owner(&self, owners: &mut InfoSchemaOwnerType, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>, opts: &MigrationOptions) -> Result<usize, String> {
+        if self.owner.len() == 0 {
+            return Ok(0);
+        }
+        let (owner, _, _) = owners.entry(self.schema_name.clone())
+            .or_insert_with(|| ("".to_string(), HashMap::new(), HashMap::new()));
+        if owner == &self.owner {
+            return Ok(0);
+        }
+        let mut sql = String::new();
+        let mut cnt = 1;
+        if opts.reassign_owned && owner.len() > 0 {
+            let _ = writeln!(sql, "REASSIGN OWNED BY {} TO {};", owner, self.owner);
+            cnt += 1;
+        }
+        let _ = writeln!(sql, "ALTER SCHEMA {} OWNER TO {};", self.schema_name, self.owner);
+        *owner = self.owner.clone();
+        self.execute(sql, cnt, true, db, dry_run)
+    }
+
+    #[inline]
+    /// grant privileges on the schema after the tables created it, revoke extra ones if with_revoke,
+    /// then the batch grants on all the objects in the schema unless skip_batch_grants;
+    /// return counts of granted and revoked
+    #[cfg(feature = "db")]
+    pub fn deploy_schema_grants(&self, owners: &mut InfoSchemaOwnerType, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>, opts: &MigrationOptions) -> Result<(usize, usize), String> {
+        let (batch, schema_grants): (Vec<YGrant>, Vec<YGrant>) = self.grant.iter().cloned().partition(|g| g.scope.len() > 0);
+        let gb = GrantBuilder::new(&schema_grants, &SCHEMA_PRIVILEGES, &[])
+            .map_err(|e| format!("{} on schema {} source: {}", e, self.schema_name, self.file))?;
+        let (owner, _, grants) = owners.entry(self.schema_name.clone())
+            .or_insert_with(|| (self.owner.clone(), HashMap::new(), HashMap::new()));
+        let owner = if owner.len() > 0 { Some(&*owner) } else { None };
+        let mut stmts = gb.generate_sql(format!("SCHEMA {}", self.schema_name).as_str(), grants, owner, opts.with_revoke);
+        gb.update_dbc(grants, opts.with_revoke);
+        if !opts.skip_batch_grants {
+            stmts.extend(self.batch_grant_sql(&batch)?);
+        }
+        let mut sql = String::new();
+        for s in &stmts {
+            let _ = writeln!(sql, "{};", s);
+        }
+        if self.execute(sql, stmts.len(), true, db, dry_run)? == 0 {
+            return Ok((0, 0));
+        }
+        let revoked = stmts.iter().filter(|s| s.starts_with("REVOKE")).count();
+        Ok((stmts.len() - revoked, revoked))
+    }
+
+    /// GRANT ON ALL TABLES, SEQUENCES or FUNCTIONS IN SCHEMA, the existing objects only;
+    /// not compared to the privileges of each object, so always granted, a granted privilege ignored by PostgreSQL
+    pub fn batch_grant_sql(&self, grants: &[YGrant]) -> Result<Vec<String>, String> {
+        let mut sql = Vec::new();
+        for g in grants {
+            let (objects, applicable): (&str, &[&str]) = match g.scope.as_str() {
+                "all_tables" => ("ALL TABLES", &TABLE_PRIVILEGES),
+                "all_sequences" => ("ALL SEQUENCES", &SEQUENCE_PRIVILEGES),
+                "all_functions" => ("ALL FUNCTIONS", &FUNCTION_PRIVILEGES),
+                scope => return Err(format!("grant scope {} is not one of {} on schema {} source: {}",
+                                            scope, BATCH_GRANT_SCOPES.join(", "), self.schema_name, self.file)),
+            };
+            let gb = GrantBuilder::new(&vec![g.clone()], applicable, &[])
+                .map_err(|e| format!("{} on {} in schema {} source: {}", e, objects, self.schema_name, self.file))?;
+            sql.extend(gb.generate_sql(format!("{} IN SCHEMA {}", objects, self.schema_name).as_str(), &HashMap::new(), None, false));
+        }
+        Ok(sql)
+    }
+
+    /// alter default privileges of the objects created later, before the tables, return count of statements
+    #[cfg(feature = "db")]
+    pub fn deploy_default_privileges(&self, existing: &mut InfoDefaultPrivilegeType, current_user: &str, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>, opts: &MigrationOptions) -> Result<usize, String> {
+        let mut cnt = 0;
+        let mut sql = String::new();
+        for dp in &self.default_privileges {
+            for stmt in dp.deploy_sql(existing, current_user, opts.with_revoke).map_err(|e| format!("{} source: {}", e, self.file))? {
+                cnt += 1;
+                let _ = writeln!(sql, "{};", stmt);
+            }
+        }
+        self.execute(sql, cnt, true, db, dry_run)
+    }
+
+    /// comment the schema if the description differs from the DB one, the schema created if not exists
+    #[cfg(feature = "db")]
+    pub fn deploy_comment(&self, comments: &mut InfoSchemaCommentType, db: &mut dyn DbExec, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+        if self.description.len() == 0 || comments.get(&self.schema_name) == Some(&self.description) {
+            return Ok(0);
+        }
+        let sql = format!("COMMENT ON SCHEMA {} IS '{}';\n", self.schema_name, self.description);
+        comments.insert(self.schema_name.clone(), self.description.clone());
+        self.execute(sql, 1, true, db, dry_run)
     }
 
     #[inline]
     /// return statements to execute
-    pub fn deploy_all_fk(&self, schemas: &OrderedHashMap<Schema>, schema: &mut InfoSchemaType, db: &mut Transaction, retry: bool, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>) -> Result<usize, String> {
+    #[cfg(feature = "db")]
+    pub fn deploy_all_fk(&self, schemas: &OrderedHashMap<Schema>, schema: &mut InfoSchemaType, db: &mut dyn DbExec, retry: bool, dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>, opts: &MigrationOptions) -> Result<usize, String> {
         let mut cnt = 0;
         for t in &self.tables.list {
-            if t.deploy_fk(schemas, schema, db, &self.schema_name, retry, self.file.as_str(), dry_run)? {
-                cnt += 1;
+            opts.progress(ProgressEvent::StartingFk { schema: self.schema_name.clone(), table: t.table_name.clone() });
+            match t.deploy_fk(schemas, schema, db, &self.schema_name, retry, self.file.as_str(), dry_run, opts) {
+                Ok(exec) => {
+                    opts.progress(ProgressEvent::FinishedFk { schema: self.schema_name.clone(), table: t.table_name.clone() });
+                    if exec {
+                        cnt += 1;
+                    }
+                }
+                Err(e) => {
+                    self.failed_table(t, &e, opts);
+                    return Err(e);
+                }
             }
         }
         Ok(cnt)
     }
 
 }
+
+#[cfg(all(test, feature = "db"))]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::{BTreeMap, HashMap};
+
+    use postgres::types::ToSql;
+    use postgres::{Error, Row};
+
+    use crate::column::{FkAction, FkMatch};
+    use crate::db::{DbExec, dry_run, NoDb};
+    use crate::{load_schema_from_src, parse_yaml_schema};
+    use crate::loader::{InfoSchemaOwnerType, InfoSchemaType, PgTable};
+    use crate::options::MigrationOptions;
+    use crate::table::Table;
+
+    #[test]
+    fn test_deploy_order() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: events_2024
+          partition_of:
+            parent: events
+            for_values: FROM ('2024-01-01') TO ('2025-01-01')
+      - table:
+          tableName: events_2025
+          partition_of:
+            parent: events
+            for_values: FROM ('2025-01-01') TO ('2026-01-01')
+      - table:
+          tableName: events
+          partition_by:
+            method: range
+            key: [created_at]
+          columns:
+            - column:
+                name: created_at
+                type: date
+      - table:
+          tableName: users
+          columns:
+            - column:
+                name: id
+                type: integer
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let s = r.list.get(0).unwrap();
+        let names: Vec<&str> = s.deploy_order().iter().map(|t| t.table_name.as_str()).collect();
+        assert_eq!(names, vec!["events", "users", "events_2024", "events_2025"]);
+    }
+
+    #[test]
+    fn test_schema_or_data_only() {
+        let src = r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+          data:
+            - [1]
+"#;
+        let r = parse_yaml_schema(load_schema_from_src(src.to_string()).unwrap(), "").unwrap();
+        let s = r.list.get(0).unwrap();
+
+        let opts = MigrationOptions::default().schema_only(true);
+        let (_, out) = dry_run(|store| s.deploy_all_tables(&mut BTreeMap::new(), &mut HashMap::new(), &mut NoDb, false, Some(store), &opts)).unwrap();
+        assert!(out.iter().any(|sql| sql.contains("CREATE TABLE test_schema.test_table")));
+        assert!(out.iter().all(|sql| !sql.contains("insert into")));
+
+        let (_, out) = dry_run(|store| s.deploy_all_data(&mut NoDb, Some(store), &opts)).unwrap();
+        assert!(out.iter().all(|sql| !sql.contains("CREATE TABLE")));
+        assert!(out[0].starts_with("insert into test_schema.test_table (id) values ($1::text::integer)"));
+
+        let opts = MigrationOptions::default().schema_only(true).data_only(true);
+        let err = crate::migrate_tx(load_schema_from_src(src.to_string()).unwrap(), &mut NoDb, &opts, "").unwrap_err();
+        assert_eq!(err.as_str(), "schema_only and data_only can not be both set");
+    }
+
+    #[test]
+    fn test_schema_comment() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    description: The test schema
+"#.to_string()).unwrap(), "").unwrap();
+        let ts = r.list.get(0).unwrap();
+        assert_eq!(ts.description.as_str(), "The test schema");
+        let mut comments = HashMap::new();
+        comments.insert("test_schema".to_string(), "The old schema".to_string());
+        let (_, out) = dry_run(|store| ts.deploy_comment(&mut comments, &mut NoDb, Some(store))).unwrap();
+        assert_eq!(out[0].as_str(), "CREATE SCHEMA IF NOT EXISTS test_schema;\nCOMMENT ON SCHEMA test_schema IS 'The test schema';\n");
+        let (n, out) = dry_run(|store| ts.deploy_comment(&mut comments, &mut NoDb, Some(store))).unwrap();
+        assert_eq!(n, 0);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_schema_owner_key() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    owner: app_owner
+  - schemaName: other_schema
+"#.to_string()).unwrap(), "").unwrap();
+        assert_eq!(r.list[0].owner.as_str(), "app_owner");
+        // not the schemaName
+        assert_eq!(r.list[1].owner.as_str(), "");
+    }
+
+    #[test]
+    fn test_copy_data() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: colors
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+            - column:
+                name: name
+                type: text
+          data:
+            - ['1', red]
+            - ['2', 'say "green"']
+            - ['3', blue]
+"#.to_string()).unwrap(), "").unwrap();
+        let s = r.list.get(0).unwrap();
+        let opts = MigrationOptions::default().use_copy_for_data(true).copy_threshold(3);
+        let (_, out) = dry_run(|store| s.deploy_all_data(&mut NoDb, Some(store), &opts)).unwrap();
+        assert!(out[0].starts_with("insert into test_schema.colors (id, name) values"));
+
+        let opts = opts.copy_threshold(2);
+        let (_, out) = dry_run(|store| s.deploy_all_data(&mut NoDb, Some(store), &opts)).unwrap();
+        assert_eq!(out[0].as_str(), r#"CREATE TEMPORARY TABLE schema_guard_copy ON COMMIT DROP AS SELECT id, name FROM test_schema.colors WITH NO DATA;
+\COPY schema_guard_copy (id, name) FROM STDIN CSV
+"1","red"
+"2","say ""green"""
+"3","blue"
+\.
+insert into test_schema.colors (id, name) select id, name from schema_guard_copy ON CONFLICT (id) DO NOTHING;
+DROP TABLE schema_guard_copy;
+"#);
+    }
+
+    #[test]
+    fn test_savepoint_per_table() {
+        /// statements executed
+        struct Recorder(Vec<String>);
+
+        impl DbExec for Recorder {
+            fn query(&mut self, _: &str, _: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error> {
+                Ok(Vec::new())
+            }
+
+            fn execute(&mut self, _: &str, _: &[&(dyn ToSql + Sync)]) -> Result<u64, Error> {
+                Ok(0)
+            }
+
+            fn batch_execute(&mut self, sql: &str) -> Result<(), Error> {
+                self.0.push(sql.trim().to_string());
+                Ok(())
+            }
+
+            fn copy_in(&mut self, _: &str, _: &[u8]) -> Result<u64, String> {
+                Ok(0)
+            }
+        }
+
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: logs
+          unlogged: true
+          columns:
+            - column:
+                name: id
+                type: integer
+      - table:
+          tableName: users
+          columns:
+            - column:
+                name: id
+                type: integer
+"#.to_string()).unwrap(), "").unwrap();
+        let s = r.list.get(0).unwrap();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        // deploy on an empty db, so the loaded state of the logs table is as created
+        s.deploy_all_tables(&mut info, &mut HashMap::new(), &mut NoDb, false, None, &MigrationOptions::default()).unwrap();
+        info.get_mut("test_schema").unwrap().get_mut("logs").unwrap().persistence = 'p';
+        info.get_mut("test_schema").unwrap().remove("users");
+
+        let mut db = Recorder(Vec::new());
+        let opts = MigrationOptions::default().savepoint_per_table(true);
+        let report = s.deploy_all_tables(&mut info, &mut HashMap::new(), &mut db, false, None, &opts).unwrap();
+        assert_eq!(report.failed_tables.len(), 1);
+        assert_eq!(report.failed_tables[0].0.as_str(), "test_schema.logs");
+        assert_eq!(report.tables_created, vec!["test_schema.users".to_string()]);
+        assert_eq!(db.0[0].as_str(), "SAVEPOINT sp_table_logs");
+        assert_eq!(db.0[1].as_str(), "ROLLBACK TO SAVEPOINT sp_table_logs; RELEASE SAVEPOINT sp_table_logs");
+        assert_eq!(db.0[2].as_str(), "SAVEPOINT sp_table_users");
+        assert!(db.0.iter().any(|sql| sql.as_str() == "RELEASE SAVEPOINT sp_table_users"));
+        assert_eq!(info["test_schema"]["logs"].persistence, 'p');
+    }
+
+    #[test]
+    fn test_schema_merge() {
+        let parse = |src: &str, file: &str| parse_yaml_schema(load_schema_from_src(src.to_string()).unwrap(), file).unwrap()
+            .list.remove(0);
+        let mut s = parse(r#"
+database:
+  - schemaName: shop
+    owner: shop_owner
+    tables:
+      - table:
+          tableName: orders
+          columns:
+            - column:
+                name: id
+                type: integer
+"#, "orders.yaml");
+        let other = parse(r#"
+database:
+  - schemaName: shop
+    owner: somebody
+    description: the shop
+    sequences:
+      - sequence:
+          name: order_seq
+    tables:
+      - table:
+          tableName: users
+          columns:
+            - column:
+                name: id
+                type: integer
+"#, "users.yaml");
+        s.merge(other.clone()).unwrap();
+        assert_eq!(s.tables.list.iter().map(|t| t.table_name.as_str()).collect::<Vec<_>>(), vec!["orders", "users"]);
+        assert!(s.sequences.get(&"order_seq".to_string()).is_some());
+        assert_eq!(s.owner.as_str(), "shop_owner");
+        assert_eq!(s.description.as_str(), "the shop");
+        assert_eq!(s.file.as_str(), "orders.yaml, users.yaml");
+        let err = s.merge(other).unwrap_err();
+        assert_eq!(err.as_str(), "duplicate table definition: users found in file: users.yaml");
+
+        // the blocks of the same schema within a file
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: shop
+    tables:
+      - table:
+          tableName: orders
+  - schemaName: shop
+    owner: shop_owner
+    tables:
+      - table:
+          tableName: users
+"#.to_string()).unwrap(), "shop.yaml").unwrap();
+        assert_eq!(r.list.len(), 1);
+        assert_eq!(r.list[0].tables.list.len(), 2);
+        assert_eq!(r.list[0].owner.as_str(), "shop_owner");
+        assert_eq!(r.list[0].file.as_str(), "shop.yaml");
+    }
+
+    #[test]
+    fn test_on_progress() {
+        use crate::report::{ProgressEvent, TableAction};
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+      - table:
+          tableName: test_new
+          columns:
+            - column:
+                name: id
+                type: integer
+"#.to_string()).unwrap(), "").unwrap();
+        let s = r.list.get(0).unwrap();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert("test_schema".to_string(), HashMap::from([("test_table".to_string(), PgTable { table_name: "test_table".to_string(), ..PgTable::default() })]));
+        let events = RefCell::new(Vec::new());
+        let on_progress = |e: ProgressEvent| events.borrow_mut().push(e);
+        let store = |_: Vec<String>| Ok(());
+        let opts = MigrationOptions::default().on_progress(&on_progress);
+        s.deploy_all_tables(&mut info, &mut HashMap::new(), &mut NoDb, false, Some(&store), &opts).unwrap();
+        let started = |table: &str, action| ProgressEvent::StartingTable { schema: "test_schema".to_string(), table: table.to_string(), action };
+        let finished = |table: &str| ProgressEvent::FinishedTable { schema: "test_schema".to_string(), table: table.to_string(), changes: 0 };
+        assert_eq!(events.borrow().clone(), vec![
+            started("test_table", TableAction::Alter), finished("test_table"),
+            started("test_new", TableAction::Create), finished("test_new"),
+        ]);
+
+        events.borrow_mut().clear();
+        s.deploy_all_fk(&r, &mut info, &mut NoDb, false, Some(&store), &opts).unwrap();
+        assert_eq!(events.borrow().len(), 4);
+        assert_eq!(events.borrow()[1], ProgressEvent::FinishedFk { schema: "test_schema".to_string(), table: "test_table".to_string() });
+    }
+
+    #[cfg(feature = "bb8")]
+    #[test]
+    fn test_cancel_token() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+"#.to_string()).unwrap(), "").unwrap();
+        let s = r.list.get(0).unwrap();
+        let token = tokio_util::sync::CancellationToken::new();
+        let opts = MigrationOptions::default().cancel_token(token.clone());
+        let store = |_: Vec<String>| Ok(());
+        assert!(s.deploy_all_tables(&mut BTreeMap::new(), &mut HashMap::new(), &mut NoDb, false, Some(&store), &opts).is_ok());
+        token.cancel();
+        let err = s.deploy_all_tables(&mut BTreeMap::new(), &mut HashMap::new(), &mut NoDb, false, Some(&store), &opts).unwrap_err();
+        assert_eq!(err.as_str(), crate::options::CANCELLED);
+        let opts = opts.savepoint_per_table(true);
+        let err = s.deploy_all_tables(&mut BTreeMap::new(), &mut HashMap::new(), &mut NoDb, false, None, &opts).unwrap_err();
+        assert_eq!(err.as_str(), crate::options::CANCELLED);
+    }
+
+    #[test]
+    fn test_drop_tables() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+"#.to_string()).unwrap(), "").unwrap();
+        let s = r.list.get(0).unwrap();
+        let table = |name: &str| (name.to_string(), PgTable { table_name: name.to_string(), ..PgTable::default() });
+        let mut child = table("test_child");
+        child.1.fks.insert("test_child_parent_id_fkey".to_string(), crate::loader::FKTable {
+            schema: "test_schema".to_string(),
+            table: "test_parent".to_string(),
+            column: vec!["id".to_string()],
+            name: "test_child_parent_id_fkey".to_string(),
+            local_columns: vec!["parent_id".to_string()],
+            sql: "".to_string(),
+            match_type: FkMatch::Simple,
+            on_update: FkAction::NoAction,
+            on_delete: FkAction::NoAction,
+            deferrable: false,
+            initially_deferred: false,
+        });
+        let info = || -> InfoSchemaType {
+            BTreeMap::from([("test_schema".to_string(), HashMap::from([
+                table("test_table"), table("test_parent"), child.clone(), table("test_audit")]))])
+        };
+
+        let (_, out) = dry_run(|store| s.deploy_all_tables(&mut info(), &mut HashMap::new(), &mut NoDb, false, Some(store), &MigrationOptions::default())).unwrap();
+        assert!(!out.concat().contains("DROP TABLE"));
+
+        let opts = MigrationOptions::default().drop_tables(true).never_drop(vec!["test_schema.test_audit".to_string()]);
+        let mut db_info = info();
+        let (report, out) = dry_run(|store| s.deploy_all_tables(&mut db_info, &mut HashMap::new(), &mut NoDb, false, Some(store), &opts)).unwrap();
+        assert!(out.concat().ends_with("\nDROP TABLE IF EXISTS test_schema.test_child;\nDROP TABLE IF EXISTS test_schema.test_parent;\n"));
+        assert_eq!(report.tables_dropped, vec!["test_schema.test_child".to_string(), "test_schema.test_parent".to_string()]);
+        assert!(db_info["test_schema"].contains_key("test_audit"));
+        assert!(!db_info["test_schema"].contains_key("test_parent"));
+
+        assert_eq!(Table::drop_sql("s", "t", true), "DROP TABLE IF EXISTS s.t CASCADE");
+        assert_eq!(crate::column::Column::drop_sql("s", "t", "c"), "ALTER TABLE s.t DROP COLUMN IF EXISTS c");
+    }
+
+    #[test]
+    fn test_batch_grants() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    grant:
+      - usage: reader
+      - select: reader
+        scope: all_tables
+      - usage: reader
+        scope: ALL_SEQUENCES
+      - execute: reader
+        scope: all_functions
+"#.to_string()).unwrap(), "").unwrap();
+        let s = r.list.get(0).unwrap();
+        let (_, out) = dry_run(|store| s.deploy_schema_grants(&mut HashMap::new(), &mut NoDb, Some(store), &MigrationOptions::default())).unwrap();
+        assert_eq!(out[0].as_str(), "CREATE SCHEMA IF NOT EXISTS test_schema;\n\
+            GRANT USAGE ON SCHEMA test_schema TO reader;\n\
+            GRANT SELECT ON ALL TABLES IN SCHEMA test_schema TO reader;\n\
+            GRANT USAGE ON ALL SEQUENCES IN SCHEMA test_schema TO reader;\n\
+            GRANT EXECUTE ON ALL FUNCTIONS IN SCHEMA test_schema TO reader;\n");
+
+        let (_, out) = dry_run(|store| s.deploy_schema_grants(&mut HashMap::new(), &mut NoDb, Some(store), &MigrationOptions::default().skip_batch_grants(true))).unwrap();
+        assert_eq!(out[0].as_str(), "CREATE SCHEMA IF NOT EXISTS test_schema;\nGRANT USAGE ON SCHEMA test_schema TO reader;\n");
+
+        let mut s = s.clone();
+        s.grant[1].scope = "all_views".to_string();
+        let err = dry_run(|store| s.deploy_schema_grants(&mut HashMap::new(), &mut NoDb, Some(store), &MigrationOptions::default())).unwrap_err();
+        assert!(err.starts_with("grant scope all_views is not one of all_tables, all_sequences, all_functions"), "{}", err);
+        s.grant[1].scope = "all_functions".to_string();
+        assert!(dry_run(|store| s.deploy_schema_grants(&mut HashMap::new(), &mut NoDb, Some(store), &MigrationOptions::default())).is_err());
+    }
+
+    #[test]
+    fn test_schema_owner() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    owner: app_owner
+"#.to_string()).unwrap(), "").unwrap();
+        let s = r.list.get(0).unwrap();
+        let owners = || -> InfoSchemaOwnerType {
+            HashMap::from([("test_schema".to_string(), ("legacy_owner".to_string(), HashMap::new(), HashMap::new()))])
+        };
+        let (_, out) = dry_run(|store| s.deploy_schema_owner(&mut owners(), &mut NoDb, Some(store), &MigrationOptions::default())).unwrap();
+        assert_eq!(out.concat(), "CREATE SCHEMA IF NOT EXISTS test_schema;\nALTER SCHEMA test_schema OWNER TO app_owner;\n");
+
+        let mut loaded = owners();
+        let (_, out) = dry_run(|store| s.deploy_schema_owner(&mut loaded, &mut NoDb, Some(store), &MigrationOptions::default().reassign_owned(true))).unwrap();
+        assert_eq!(out.concat(), "CREATE SCHEMA IF NOT EXISTS test_schema;\n\
+            REASSIGN OWNED BY legacy_owner TO app_owner;\nALTER SCHEMA test_schema OWNER TO app_owner;\n");
+
+        // the same owner now
+        let (_, out) = dry_run(|store| s.deploy_schema_owner(&mut loaded, &mut NoDb, Some(store), &MigrationOptions::default().reassign_owned(true))).unwrap();
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use yaml_rust::Yaml;
+
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+use crate::grant::{GrantBuilder, PgGrant, SEQUENCE_PRIVILEGES};
+use crate::table::YGrant;
+use crate::utils::{Enveloped, Named};
+
+/// sequence defined in yaml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YamlSequence {
+    pub name: String,
+    /// the sequence created in, the enclosing schemaName by default
+    pub schema: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub increment: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_value: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_value: Option<i64>,
+    pub cycle: bool,
+    /// OWNED BY table.column, the table might be in other schema as schema.table
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_table: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_column: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub grant: Vec<YGrant>,
+}
+
+/// sequence information loaded from DB
+#[derive(Debug, Clone, Serialize)]
+pub struct PgSequence {
+    pub start: i64,
+    pub increment: i64,
+    pub min_value: i64,
+    pub max_value: i64,
+    pub cycle: bool,
+    /// schema.table.column
+    pub owned_by: Option<String>,
+    /// role owns the sequence
+    pub owner: String,
+    /// grantee
+    pub grants: HashMap<String, PgGrant>,
+}
+
+/// schema.name: sequence
+pub type InfoSequenceType = HashMap<String, PgSequence>;
+
+impl Enveloped for YamlSequence {
+    const ENVELOPE: &'static str = "sequence";
+}
+
+impl Named for YamlSequence {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[inline]
+fn as_i64(input: &Yaml, field: &str) -> Result<Option<i64>, String> {
+    let v = crate::utils::as_str(input, field, "");
+    if v.len() == 0 {
+        Ok(None)
+    } else {
+        v.parse::<i64>().map(|v| Some(v))
+            .map_err(|e| format!("wrong sequence {}: {} {}", field, v, e))
+    }
+}
+
+impl YamlSequence {
+    pub(crate) fn new(input: &Yaml, schema: &str) -> Result<Self, String> {
+        let owner_table = crate::utils::as_str_esc(input, "owner_table");
+        let owner_column = crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "owner_column"));
+        Ok(YamlSequence {
+            name: crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "name")),
+            schema: crate::utils::safe_sql_name(crate::utils::as_str(input, "schema", schema)),
+            start: as_i64(input, "start")?,
+            increment: as_i64(input, "increment")?,
+            min_value: as_i64(input, "min_value")?,
+            max_value: as_i64(input, "max_value")?,
+            cycle: crate::utils::as_bool(input, "cycle", false),
+            owner_table: if owner_table.len() > 0 { Some(owner_table) } else { None },
+            owner_column: if owner_column.len() > 0 { Some(owner_column) } else { None },
+            grant: YGrant::new(input["grant"].as_vec()),
+        })
+    }
+
+    /// full name as schema.name
+    #[inline]
+    pub fn full_name(&self) -> String {
+        format!("{}.{}", self.schema, self.name)
+    }
+
+    /// schema.table.column of OWNED BY if set
+    pub fn owned_by(&self) -> Option<String> {
+        match (&self.owner_table, &self.owner_column) {
+            (Some(t), Some(c)) => Some(if t.contains('.') {
+                format!("{}.{}", t, c)
+            } else {
+                format!("{}.{}.{}", self.schema, t, c)
+            }),
+            _ => None,
+        }
+    }
+
+    /// statement to create or alter the sequence (excluding OWNED BY), if needed
+    pub fn deploy_sql(&self, existing: &InfoSequenceType) -> Option<String> {
+        let mut options = String::new();
+        match existing.get(&self.full_name()) {
+            None => {
+                if let Some(v) = self.increment {
+                    let _ = write!(options, " INCREMENT BY {}", v);
+                }
+                if let Some(v) = self.min_value {
+                    let _ = write!(options, " MINVALUE {}", v);
+                }
+                if let Some(v) = self.max_value {
+                    let _ = write!(options, " MAXVALUE {}", v);
+                }
+                if let Some(v) = self.start {
+                    let _ = write!(options, " START WITH {}", v);
+                }
+                if self.cycle {
+                    options.push_str(" CYCLE");
+                }
+                Some(format!("CREATE SEQUENCE IF NOT EXISTS {}{}", self.full_name(), options))
+            }
+            Some(pg) => {
+                if let Some(v) = self.increment.filter(|v| *v != pg.increment) {
+                    let _ = write!(options, " INCREMENT BY {}", v);
+                }
+                if let Some(v) = self.min_value.filter(|v| *v != pg.min_value) {
+                    let _ = write!(options, " MINVALUE {}", v);
+                }
+                if let Some(v) = self.max_value.filter(|v| *v != pg.max_value) {
+                    let _ = write!(options, " MAXVALUE {}", v);
+                }
+                if let Some(v) = self.start.filter(|v| *v != pg.start) {
+                    let _ = write!(options, " START WITH {}", v);
+                }
+                if self.cycle != pg.cycle {
+                    options.push_str(if self.cycle { " CYCLE" } else { " NO CYCLE" });
+                }
+                if options.len() > 0 {
+                    Some(format!("ALTER SEQUENCE {}{}", self.full_name(), options))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// statements to grant missing privileges and revoke extra ones if with_revoke
+    pub fn grant_sql(&self, existing: &mut InfoSequenceType, with_revoke: bool) -> Result<Vec<String>, String> {
+        let gb = GrantBuilder::new(&self.grant, &SEQUENCE_PRIVILEGES, &[])
+            .map_err(|e| format!("{} on sequence {}", e, self.full_name()))?;
+        let mut empty = HashMap::new();
+        let (grants, owner) = match existing.get_mut(&self.full_name()) {
+            Some(pg) => (&mut pg.grants, if pg.owner.len() > 0 { Some(pg.owner.clone()) } else { None }),
+            None => (&mut empty, None),
+        };
+        let sql = gb.generate_sql(format!("SEQUENCE {}", self.full_name()).as_str(), grants, owner.as_ref(), with_revoke);
+        gb.update_dbc(grants, with_revoke);
+        Ok(sql)
+    }
+
+    /// statement to set OWNED BY, if changed. Must run after the table created
+    pub fn owner_sql(&self, existing: &InfoSequenceType) -> Option<String> {
+        let owned_by = self.owned_by()?;
+        match existing.get(&self.full_name()) {
+            Some(pg) if pg.owned_by.as_ref() == Some(&owned_by) => None,
+            _ => Some(format!("ALTER SEQUENCE {} OWNED BY {}", self.full_name(), owned_by)),
+        }
+    }
+}
+
+#[cfg(feature = "db")]
+pub fn load_info_sequences(db_name: &str, db: &mut dyn DbExec) -> Result<InfoSequenceType, String> {
+    let mut data = HashMap::new();
+    let result = db.query("SELECT s.sequence_schema, s.sequence_name, s.start_value, s.increment, s.minimum_value, s.maximum_value,
+ s.cycle_option, tn.nspname, t.relname, a.attname
+ FROM information_schema.sequences s
+ LEFT JOIN pg_depend d ON d.objid = format('%I.%I', s.sequence_schema, s.sequence_name)::regclass
+  AND d.classid = 'pg_class'::regclass AND d.refclassid = 'pg_class'::regclass AND d.deptype = 'a'
+ LEFT JOIN pg_class t ON t.oid = d.refobjid
+ LEFT JOIN pg_namespace tn ON tn.oid = t.relnamespace
+ LEFT JOIN pg_attribute a ON a.attrelid = d.refobjid AND a.attnum = d.refobjsubid
+ WHERE s.sequence_schema not in ('pg_catalog', 'information_schema') AND s.sequence_catalog = $1", &[&db_name])
+        .map_err(|e| format!("on loading information_schema.sequences [{}]: {}", db_name, e))?;
+    for r in result {
+        let sequence_schema: &str = r.get(0);
+        let sequence_name: &str = r.get(1);
+        let start: &str = r.get(2);
+        let increment: &str = r.get(3);
+        let min_value: &str = r.get(4);
+        let max_value: &str = r.get(5);
+        let cycle: &str = r.get(6);
+        let owner_schema: Option<&str> = r.get(7);
+        let owner_table: Option<&str> = r.get(8);
+        let owner_column: Option<&str> = r.get(9);
+        data.insert(format!("{}.{}", sequence_schema, sequence_name), PgSequence {
+            start: start.parse().unwrap_or_default(),
+            increment: increment.parse().unwrap_or_default(),
+            min_value: min_value.parse().unwrap_or_default(),
+            max_value: max_value.parse().unwrap_or_default(),
+            cycle: cycle == "YES",
+            owned_by: match (owner_schema, owner_table, owner_column) {
+                (Some(s), Some(t), Some(c)) => Some(format!("{}.{}.{}", s, t, c)),
+                _ => None,
+            },
+            owner: "".to_string(),
+            grants: HashMap::new(),
+        });
+    }
+    let result = db.query("SELECT n.nspname, c.relname, pg_get_userbyid(c.relowner), coalesce(r.rolname, 'public'), a.privilege_type, a.is_grantable
+ FROM pg_class c
+ JOIN pg_namespace n ON n.oid = c.relnamespace
+ CROSS JOIN aclexplode(coalesce(c.relacl, acldefault('s', c.relowner))) a
+ LEFT JOIN pg_roles r ON r.oid = a.grantee
+ WHERE c.relkind = 'S' AND n.nspname not in ('pg_catalog', 'information_schema')", &[])
+        .map_err(|e| format!("on loading sequence privileges [{}]: {}", db_name, e))?;
+    for r in result {
+        let sequence_schema: &str = r.get(0);
+        let sequence_name: &str = r.get(1);
+        let owner: &str = r.get(2);
+        let grantee: &str = r.get(3);
+        let privilege: &str = r.get(4);
+        let is_grantable: bool = r.get(5);
+        if let Some(pg) = data.get_mut(&format!("{}.{}", sequence_schema, sequence_name)) {
+            pg.owner = owner.to_string();
+            let g = pg.grants.entry(grantee.to_string())
+                .or_insert_with(|| PgGrant { grantee: grantee.to_string(), ..Default::default() });
+            g.privileges.insert(privilege.to_string());
+            g.with_grant_option |= is_grantable;
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::sequence::{PgSequence, YamlSequence};
+    use crate::table::YGrant;
+
+    #[test]
+    fn sequence_test() {
+        let s = YamlSequence {
+            name: "foo_seq".to_string(),
+            schema: "test_schema".to_string(),
+            start: Some(1),
+            increment: Some(1),
+            min_value: None,
+            max_value: Some(9999),
+            cycle: false,
+            owner_table: Some("foo".to_string()),
+            owner_column: Some("id".to_string()),
+            grant: vec![YGrant { usage: "reader".to_string(), ..Default::default() }],
+        };
+        let mut existing = HashMap::new();
+        assert_eq!(s.deploy_sql(&existing).unwrap().as_str(),
+                   "CREATE SEQUENCE IF NOT EXISTS test_schema.foo_seq INCREMENT BY 1 MAXVALUE 9999 START WITH 1");
+        assert_eq!(s.owner_sql(&existing).unwrap().as_str(), "ALTER SEQUENCE test_schema.foo_seq OWNED BY test_schema.foo.id");
+        existing.insert("test_schema.foo_seq".to_string(), PgSequence {
+            start: 1,
+            increment: 1,
+            min_value: 1,
+            max_value: 100,
+            cycle: false,
+            owned_by: Some("test_schema.foo.id".to_string()),
+            owner: "postgres".to_string(),
+            grants: HashMap::new(),
+        });
+        assert_eq!(s.deploy_sql(&existing).unwrap().as_str(), "ALTER SEQUENCE test_schema.foo_seq MAXVALUE 9999");
+        assert!(s.owner_sql(&existing).is_none());
+        assert_eq!(s.grant_sql(&mut existing, false).unwrap(), vec!["GRANT USAGE ON SEQUENCE test_schema.foo_seq TO reader".to_string()]);
+        assert!(s.grant_sql(&mut existing, true).unwrap().is_empty());
+    }
+}
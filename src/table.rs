@@ -1,20 +1,31 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
 use std::fmt::Write;
 
-use postgres::Transaction;
-use serde::Serialize;
+#[cfg(feature = "db")]
+use postgres::types::ToSql;
+use serde::{Deserialize, Serialize};
 use yaml_rust::Yaml;
 use yaml_rust::yaml::Array;
 
-use crate::column::{Column, Trig};
-use crate::loader::{FKTable, InfoSchemaType, PgTable};
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+use crate::column::{Column, FkMatch, ForeignKey, IdentityMode, Index, SecurityLabel, Trig};
+use crate::grant::{COLUMN_PRIVILEGES, GrantBuilder, TABLE_PRIVILEGES};
+use crate::index::{normalize_expr, DEFAULT_INDEX_METHOD, IndexBuilder};
+use crate::loader::{normalize_check, normalize_default, same_pg_type, DEFAULT_TABLESPACE, FKTable, InfoSchemaType, PgColumnDfn, PgPolicy, PgTable};
 #[cfg(feature = "slog")]
-use crate::log_debug;
+use crate::{log_debug, log_warn};
+use crate::options::MigrationOptions;
+use crate::plan::PlanEntry;
+use crate::report::TableReport;
 use crate::schema::Schema;
 use crate::table::CreateST::{SchemaAndTable, TableOnly};
-use crate::utils::{Named, OrderedHashMap};
+use crate::utils::{name_part, quote_if_needed, Named, OrderedHashMap};
+use crate::validate::{SchemaWarning, WarningKind};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Table {
     #[serde(rename = "tableName")]
     pub table_name: String,
@@ -24,19 +35,64 @@ pub struct Table {
     /// transaction: -- single (default) OR table OR column OR retry (wrap to psql)
     #[serde(skip_serializing_if = "String::is_empty")]
     pub transaction: String,
+    /// SET TRANSACTION ISOLATION LEVEL of the migration, on the table transaction mode only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isolation_level: Option<String>,
     /// suffix on table create
     #[serde(skip_serializing_if = "String::is_empty")]
     pub sql: String,
+    /// CREATE UNLOGGED TABLE
+    pub unlogged: bool,
+    /// CREATE TEMPORARY TABLE, always created in the session's pg_temp schema
+    pub temporary: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tablespace: Option<String>,
+    /// storage parameters of WITH (...), i.e. fillfactor: 80
+    #[serde(rename = "with", skip_serializing_if = "BTreeMap::is_empty")]
+    pub with_options: BTreeMap<String, String>,
+    /// rows identity for logical replication
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replica_identity: Option<ReplicaIdentity>,
+    /// partitioned table
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition_by: Option<YPartition>,
+    /// partition of a partitioned table
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition_of: Option<YPartitionOf>,
+    /// INHERITS (parent, ...) on create only, schema of the table if not qualified
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inherits: Vec<String>,
+    /// CREATE TABLE (LIKE template ...) on create only, schema of the table if not qualified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub like_table: Option<String>,
+    /// INCLUDING options of the LIKE, one of LIKE_OPTIONS
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub like_options: Vec<String>,
     #[serde(skip_serializing_if = "String::is_empty")]
     pub constraint: String,
+    /// table level CHECK expressions, i.e. spanning multiple columns
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<String>,
+    /// EXCLUDE constraints, i.e. no overlapping ranges
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub excludes: Vec<YExclude>,
     #[serde(with = "ycolumns")]
     pub columns: OrderedHashMap<Column>,
-    #[serde(skip_serializing_if = "OrderedHashMap::is_empty")]
+    #[serde(with = "crate::utils::enveloped", skip_serializing_if = "OrderedHashMap::is_empty")]
     pub triggers: OrderedHashMap<Trig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data_file: Option<String>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub data: Vec<Vec<String>>,
+    /// on conflict action for data rows: nothing (default) OR update OR -- raw SQL after DO
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub data_conflict: String,
+    /// TRUNCATE before the data rows inserted, so the table matches yaml exactly, requires allow_truncate option
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncate_before_seed: bool,
+    /// TRUNCATE CASCADE, also the tables referencing this one, needed once the foreign keys exist
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncate_cascade: bool,
 
     #[serde(skip_serializing_if = "String::is_empty")]
     pub owner: String,
@@ -44,11 +100,19 @@ pub struct Table {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub grant: Vec<YGrant>,
 
+    /// ENABLE ROW LEVEL SECURITY
+    pub row_security: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<YPolicy>,
+    /// SECURITY LABEL, requires the security_labels feature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub security_label: Option<SecurityLabel>,
 }
 
 
 /// grant data
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct YGrant {
     #[serde(skip_serializing_if = "String::is_empty")]
     pub all: String,
@@ -79,6 +143,78 @@ pub struct YGrant {
     pub with_grant_option: bool,
     #[serde(skip_serializing_if = "String::is_empty")]
     pub by: String,
+    /// grant the privileges on those columns only
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<String>,
+    /// a schema grant on all the objects in it, one of BATCH_GRANT_SCOPES, i.e. all_tables
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub scope: String,
+}
+
+/// PARTITION BY method (key)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YPartition {
+    /// RANGE, LIST or HASH
+    pub method: String,
+    /// columns or expressions
+    pub key: Vec<String>,
+}
+
+/// REPLICA IDENTITY of a table, DEFAULT is the primary key
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplicaIdentity {
+    Default,
+    Nothing,
+    Full,
+    /// USING INDEX, unique, not partial on NOT NULL columns
+    Index(String),
+}
+
+/// PARTITION OF parent FOR VALUES
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YPartitionOf {
+    /// parent table, might be in other schema as schema.table
+    pub parent: String,
+    /// raw SQL after FOR VALUES, i.e. FROM ('2024-01-01') TO ('2025-01-01') or DEFAULT
+    pub for_values: String,
+}
+
+/// EXCLUDE USING method (element WITH operator, ..) WHERE (predicate)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YExclude {
+    /// index method, gist by default
+    pub using: String,
+    pub elements: Vec<ExcludeElement>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub where_clause: Option<String>,
+}
+
+/// column or expression compared with the operator
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExcludeElement {
+    pub column_or_expr: String,
+    /// i.e. = or &&
+    pub operator: String,
+}
+
+/// row level security policy
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YPolicy {
+    pub name: String,
+    /// SELECT, INSERT, UPDATE, DELETE or ALL (default)
+    pub command: String,
+    /// PERMISSIVE (default) or RESTRICTIVE
+    pub permissive: bool,
+    /// PUBLIC if empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub to_roles: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub using_expr: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check_expr: Option<String>,
 }
 
 /// wrapper for table to get a format as on lb
@@ -94,12 +230,25 @@ struct YcVO<'a> {
     column: &'a Column,
 }
 
+/// owned wrapper to read the table back
+#[derive(Deserialize)]
+struct YtDO {
+    table: Table,
+}
+
+/// owned wrapper to read the column back
+#[derive(Deserialize)]
+struct YcDO {
+    column: Column,
+}
+
 mod ycolumns {
-    use serde::{Deserializer, Serializer};
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
     use serde::ser::SerializeSeq;
 
     use crate::column::Column;
-    use crate::table::YcVO;
+    use crate::table::{YcDO, YcVO};
     use crate::utils::OrderedHashMap;
 
     pub fn serialize<S>(columns: &OrderedHashMap<Column>, serializer: S) -> Result<<S as Serializer>::Ok, <S as Serializer>::Error> where
@@ -113,16 +262,21 @@ mod ycolumns {
         seq.end()
     }
 
-    #[allow(dead_code)]
-    pub fn deserialize<'de, D>(_deserializer: D) -> Result<OrderedHashMap<Column>, D::Error> where D: Deserializer<'de> { unimplemented!() }
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<OrderedHashMap<Column>, D::Error> where D: Deserializer<'de> {
+        let mut columns = OrderedHashMap::new();
+        for c in Vec::<YcDO>::deserialize(deserializer)? {
+            columns.append(c.column).map_err(|e| D::Error::custom(format!("{} (column name)", e)))?;
+        }
+        Ok(columns)
+    }
 }
 
 pub(crate) mod ytables {
-    use serde::{Deserializer, Serializer};
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
     use serde::ser::SerializeSeq;
 
-    use crate::column::Column;
-    use crate::table::{Table, YtVO};
+    use crate::table::{Table, YtDO, YtVO};
     use crate::utils::OrderedHashMap;
 
     pub fn serialize<S>(tables: &OrderedHashMap<Table>, serializer: S) -> Result<<S as Serializer>::Ok, <S as Serializer>::Error> where
@@ -136,8 +290,13 @@ pub(crate) mod ytables {
         seq.end()
     }
 
-    #[allow(dead_code)]
-    pub fn deserialize<'de, D>(_deserializer: D) -> Result<OrderedHashMap<Column>, D::Error> where D: Deserializer<'de> { unimplemented!() }
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<OrderedHashMap<Table>, D::Error> where D: Deserializer<'de> {
+        let mut tables = OrderedHashMap::new();
+        for t in Vec::<YtDO>::deserialize(deserializer)? {
+            tables.append(t.table).map_err(|e| D::Error::custom(format!("{} (table name)", e)))?;
+        }
+        Ok(tables)
+    }
 }
 
 impl Named for Table {
@@ -152,19 +311,43 @@ impl Default for Table {
             table_name: "".to_string(),
             description: "".to_string(),
             transaction: "".to_string(),
+            isolation_level: None,
             sql: "".to_string(),
+            unlogged: false,
+            temporary: false,
+            tablespace: None,
+            with_options: BTreeMap::new(),
+            replica_identity: None,
+            partition_by: None,
+            partition_of: None,
+            inherits: vec![],
+            like_table: None,
+            like_options: vec![],
             constraint: "".to_string(),
+            checks: vec![],
+            excludes: vec![],
             columns: OrderedHashMap::new(),
             triggers: OrderedHashMap::new(),
             data_file: None,
             data: vec![],
+            data_conflict: "".to_string(),
+            truncate_before_seed: false,
+            truncate_cascade: false,
             owner: "".to_string(),
             grant: vec![],
+            row_security: false,
+            policies: vec![],
+            security_label: None,
         }
     }
 }
 
 impl Table {
+    /// drop the table, also the dependent views and foreign keys of other tables if cascade
+    pub fn drop_sql(schema: &str, table_name: &str, cascade: bool) -> String {
+        format!("DROP TABLE IF EXISTS {}.{}{}", schema, table_name, if cascade { " CASCADE" } else { "" })
+    }
+
     pub fn new(
         input: &Yaml,
         table_name: &str,
@@ -178,7 +361,8 @@ impl Table {
                 let c = &cl["column"];
                 if !c.is_null() {
                     if let Some(_name) = c["name"].as_str() {
-                        let yc = Column::new(c);
+                        let yc = Column::try_from(c).map_err(|e| format!("{} on table: {}{}", e, table_name,
+                                                                         file.map_or("".to_string(), |f| format!(", found in file: {}", f))))?;
                         /*
                         match file {
                             None => {
@@ -240,13 +424,97 @@ impl Table {
                 }
             }
         }
+        let checks: Vec<String> = crate::utils::as_vec_str(input, "checks").iter()
+            .map(|c| crate::utils::as_esc(c))
+            .filter(|c| c.len() > 0)
+            .collect();
+        let data = crate::utils::as_vec(input, "data");
+        if let Some((i, row)) = data.iter().enumerate().find(|(_, row)| row.len() > columns.len()) {
+            return Err(format!("table: {} data row {} has {} values, but {} columns defined{}", table_name, i + 1, row.len(), columns.len(),
+                               file.map_or("".to_string(), |f| format!(", found in file: {}", f))));
+        }
+        let data_conflict = crate::utils::as_str_esc(input, "data_conflict");
+        if !["", "nothing", "update"].contains(&data_conflict.to_lowercase().as_str()) && !columns.list.iter().any(|c| c.is_pk()) {
+            return Err(format!("table: {} data_conflict {} requires a primary key as the conflict target{}", table_name, data_conflict,
+                               file.map_or("".to_string(), |f| format!(", found in file: {}", f))));
+        }
+        let excludes = YExclude::new(input["excludes"].as_vec());
+        if let Some(e) = excludes.iter().find(|e| e.elements.len() == 0) {
+            return Err(format!("table: {} exclude using {} without elements{}", table_name, e.using,
+                               file.map_or("".to_string(), |f| format!(", found in file: {}", f))));
+        }
+        let security_label = SecurityLabel::new(&input["security_label"]);
+        if cfg!(not(feature = "security_labels"))
+            && (security_label.is_some() || columns.list.iter().any(|c| c.security_label.is_some())) {
+            return Err(format!("table: {} security labels require the security_labels feature{}", table_name,
+                               file.map_or("".to_string(), |f| format!(", found in file: {}", f))));
+        }
+        let isolation_level = Some(crate::utils::as_str(input, "isolation_level", "").trim().to_uppercase())
+            .filter(|l| l.len() > 0);
+        if let Some(l) = isolation_level.as_ref().filter(|l| !ISOLATION_LEVELS.contains(&l.as_str())) {
+            return Err(format!("table: {} isolation level {} is not one of {}{}", table_name, l, ISOLATION_LEVELS.join(", "),
+                               file.map_or("".to_string(), |f| format!(", found in file: {}", f))));
+        }
         let etl = &input["data_file"];
-        Ok(Table {
-            table_name: table_name.into(),
+        let tablespace = crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "tablespace"));
+        if crate::utils::as_bool(input, "unlogged", false) && crate::utils::as_bool(input, "temporary", false) {
+            return Err(format!("table: {} can not be both unlogged and temporary{}{}", table_name,
+                               match file {
+                                   None => "",
+                                   Some(_) => ", found in file: ",
+                               },
+                               match file {
+                                   None => "",
+                                   Some(f) => f.as_str(),
+                               },
+            ));
+        }
+        let with_options = crate::utils::as_storage_params(input, "with");
+        let partition_by = YPartition::new(&input["partition_by"]);
+        let partition_of = YPartitionOf::new(&input["partition_of"]);
+        if with_options.len() > 0 && partition_by.is_some() {
+            return Err(format!("table: {} partitioned table can not have storage parameters, set them on the partitions{}", table_name,
+                               file.map_or("".to_string(), |f| format!(", found in file: {}", f))));
+        }
+        let inherits: Vec<String> = crate::utils::as_vec_str(input, "inherits").into_iter()
+            .map(crate::utils::sql_qualified_name)
+            .filter(|p| p.len() > 0)
+            .collect();
+        if inherits.len() > 0 && (partition_by.is_some() || partition_of.is_some()) {
+            return Err(format!("table: {} partition or partitioned table can not inherit{}", table_name,
+                               file.map_or("".to_string(), |f| format!(", found in file: {}", f))));
+        }
+        let like_table = crate::utils::sql_qualified_name(crate::utils::as_str_esc(input, "like_table"));
+        let like_options: Vec<String> = crate::utils::as_vec_str(input, "like_options").iter()
+            .map(|o| o.trim().to_uppercase())
+            .collect();
+        if let Some(o) = like_options.iter().find(|o| !LIKE_OPTIONS.contains(&o.as_str())) {
+            return Err(format!("table: {} like option {} is not one of {}{}", table_name, o, LIKE_OPTIONS.join(", "),
+                               file.map_or("".to_string(), |f| format!(", found in file: {}", f))));
+        }
+        if like_table.len() > 0 && partition_of.is_some() {
+            return Err(format!("table: {} partition can not be created like a table{}", table_name,
+                               file.map_or("".to_string(), |f| format!(", found in file: {}", f))));
+        }
+        let table = Table {
+            table_name: crate::utils::sql_identifier(table_name.to_string()),
             description: crate::utils::as_str(input, "description", ""),
             transaction: crate::utils::as_str(input, "transaction", ""),
-            sql: crate::utils::as_str_esc(input, "sql"),
+            isolation_level,
+            sql: crate::utils::as_multiline(input, "sql"),
+            unlogged: crate::utils::as_bool(input, "unlogged", false),
+            temporary: crate::utils::as_bool(input, "temporary", false),
+            tablespace: if tablespace.len() > 0 { Some(tablespace) } else { None },
+            with_options,
+            replica_identity: ReplicaIdentity::new(&input["replica_identity"]),
+            partition_by,
+            partition_of,
+            inherits,
+            like_table: if like_table.len() > 0 { Some(like_table) } else { None },
+            like_options,
             constraint: crate::utils::as_str_esc(input, "constraint"),
+            checks,
+            excludes,
             columns,
             triggers,
             data_file: if etl.is_null() {
@@ -258,60 +526,326 @@ impl Table {
                 // }
                 etl.as_str().map(|s| s.to_string())
             },
-            data: crate::utils::as_vec(input, "data"),
+            data,
+            data_conflict,
+            truncate_before_seed: crate::utils::as_bool(input, "truncate_before_seed", false),
+            truncate_cascade: crate::utils::as_bool(input, "truncate_cascade", false),
             owner: crate::utils::as_str(input, "owner", ""),
             grant: YGrant::new(input["grant"].as_vec()),
-        })
+            row_security: crate::utils::as_bool(input, "row_security", false),
+            policies: YPolicy::new(input["policies"].as_vec()),
+            security_label,
+        };
+        for idx in IndexBuilder::new(&table).indexes.iter().filter(|i| i.nulls_not_distinct) {
+            if cfg!(not(feature = "pg15")) {
+                return Err(format!("table: {} index {} nulls not distinct requires the pg15 feature{}", table.table_name, idx.name,
+                                   file.map_or("".to_string(), |f| format!(", found in file: {}", f))));
+            }
+            if !idx.unique {
+                return Err(format!("table: {} index {} nulls not distinct is for a unique index only{}", table.table_name, idx.name,
+                                   file.map_or("".to_string(), |f| format!(", found in file: {}", f))));
+            }
+        }
+        Ok(table)
     }
 
 
     /// build a create or alter sql
     #[allow(unused_mut)]
+    #[cfg(feature = "db")]
     pub fn deploy(
         &self,
         dbc: &mut InfoSchemaType,
-        db: &mut Transaction,
+        db: &mut dyn DbExec,
         schema: &String, // this
         is_retry: bool,
         file: &str,
         dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>,
-    ) -> Result<bool, String> {
+        opts: &MigrationOptions,
+    ) -> Result<Option<TableReport>, String> {
+        opts.check_cancelled()?;
         let mut sql = String::new();
         let mut comments = String::new();
         let mut exec = false;
+        let temp_schema = "pg_temp".to_string();
+        let schema = if self.temporary { &temp_schema } else { schema };
+        let mut report = TableReport { schema: schema.clone(), table: self.table_name.clone(), ..Default::default() };
         let do_create = match dbc.get_mut(schema) {
             None => SchemaAndTable,
             Some(mut ss) => match ss.get_mut(&self.table_name) {
                 None => TableOnly,
                 Some(mut ts) => {
-                    let pks = ts.pks();
+                    let persistence = self.persistence();
+                    if ts.persistence != persistence {
+                        let msg = format!("table {}.{} persistence '{}' differs from '{}' as defined in file: {}",
+                                          schema, self.table_name, ts.persistence, persistence, file);
+                        if !opts.allow_logged_change || ts.persistence == 't' {
+                            return Err(msg);
+                        }
+                        #[cfg(feature = "slog")] log_warn(msg);
+                        append(format!("ALTER TABLE {}.{} SET {}",
+                                       schema, self.table_name, if self.unlogged { "UNLOGGED" } else { "LOGGED" }
+                        ).as_str(), &mut sql, is_retry, opts);
+                        ts.persistence = persistence;
+                        exec = true;
+                    }
+                    if let Some(tablespace) = &self.tablespace {
+                        if ts.tablespace.as_ref() != Some(tablespace) {
+                            append(format!("ALTER TABLE {}.{} SET TABLESPACE {}",
+                                           schema, self.table_name, tablespace
+                            ).as_str(), &mut sql, is_retry, opts);
+                            ts.tablespace = Some(tablespace.clone());
+                            exec = true;
+                        }
+                    }
+                    let params = crate::utils::changed_storage_params(&self.with_options, &ts.with_options);
+                    if params.len() > 0 {
+                        append(format!("ALTER TABLE {}.{} SET ({})",
+                                       schema, self.table_name, crate::utils::storage_params_sql(&params)
+                        ).as_str(), &mut sql, is_retry, opts);
+                        ts.with_options.extend(params);
+                        exec = true;
+                    }
+                    if let Some(p) = &self.partition_by {
+                        if ts.partition_key.as_ref().map(|k| normalize_expr(k)) != Some(normalize_expr(&p.key_def())) {
+                            return Err(format!("table {}.{} partition key {:?} differs from '{}' as defined in file: {}",
+                                               schema, self.table_name, ts.partition_key, p.key_def(), file));
+                        }
+                    }
+                    if let Some(like) = &self.like_table {
+                        let w = SchemaWarning::warning(WarningKind::LikeOnExistingTable, format!("{}.{}", schema, self.table_name),
+                            format!("table exists, like {} applies on create only", like));
+                        #[cfg(feature = "slog")] log_warn(w.to_string());
+                        report.warnings.push(w);
+                    }
+                    let mut inherits = self.inherits_qualified(schema);
+                    let mut parents = ts.parents.clone();
+                    inherits.sort();
+                    parents.sort();
+                    if inherits != parents {
+                        let w = SchemaWarning::warning(WarningKind::InheritsMismatch, format!("{}.{}", schema, self.table_name),
+                            format!("table inherits ({}) in DB, but ({}) in yaml, recreate the table to change",
+                                    ts.parents.join(", "), self.inherits_qualified(schema).join(", ")));
+                        #[cfg(feature = "slog")] log_warn(w.to_string());
+                        report.warnings.push(w);
+                    }
+                    if let Some(w) = self.column_order_warning(ts, schema) {
+                        if opts.fail_on_column_reorder {
+                            return Err(format!("{} in file: {}", w, file));
+                        }
+                        #[cfg(feature = "slog")] log_warn(w.to_string());
+                        report.warnings.push(w);
+                    }
+                    let had_pk = ts.columns.values().any(|c| c.pk);
+                    let mut pk_added = false;
                     for dc in &self.columns.list {
-                        if !ts.columns.contains_key(&dc.name) {
+                        if let Some(old) = dc.rename_from.as_ref()
+                            .filter(|old| !ts.columns.contains_key(&dc.name) && ts.columns.contains_key(*old)) {
+                            append(format!("ALTER TABLE {}.{} RENAME COLUMN {} TO {}",
+                                           schema, self.table_name, old, dc.name
+                            ).as_str(), &mut sql, is_retry, opts);
+                            ts.rename_column(old, &dc.name);
+                            exec = true;
+                        }
+                        if let Some(pc) = ts.columns.get_mut(&dc.name) {
+                            // the expression can not be altered, only dropped and added with the column
+                            let generated = |g: &String| crate::column::normalize_generated(g);
+                            if dc.generated_as.as_ref().map(generated) != pc.generated_as.as_ref().map(generated) {
+                                return Err(format!("column {}.{}.{} generated as {:?} differs from {:?} as defined in file: {}, drop the column to recreate",
+                                                   schema, self.table_name, dc.name, pc.generated_as, dc.generated_as, file));
+                            }
+                            if !same_pg_type(&dc.column_type, &pc.column_type) {
+                                let w = SchemaWarning::warning(WarningKind::ColumnTypeMismatch, format!("{}.{}.{}", schema, self.table_name, dc.name),
+                                    format!("column type {} in DB, but {} in yaml, not altered", pc.column_type, dc.column_type));
+                                #[cfg(feature = "slog")] log_warn(w.to_string());
+                                report.warnings.push(w);
+                            }
+                            let not_null = dc.is_not_null();
+                            if !not_null && !pc.nullable && !pc.pk && pc.identity.is_none() {
+                                append(format!("ALTER TABLE {}.{} ALTER COLUMN {} DROP NOT NULL",
+                                               schema, self.table_name, dc.name
+                                ).as_str(), &mut sql, is_retry, opts);
+                                pc.nullable = true;
+                                exec = true;
+                            } else if not_null && pc.nullable {
+                                if opts.allow_not_null_change {
+                                    append(format!("ALTER TABLE {}.{} ALTER COLUMN {} SET NOT NULL",
+                                                   schema, self.table_name, dc.name
+                                    ).as_str(), &mut sql, is_retry, opts);
+                                    pc.nullable = false;
+                                    exec = true;
+                                } else {
+                                    let w = SchemaWarning::warning(WarningKind::NotNullMismatch, format!("{}.{}.{}", schema, self.table_name, dc.name),
+                                        format!("column is nullable in DB, but NOT NULL in file: {}, set allow_not_null_change to alter", file));
+                                    #[cfg(feature = "slog")] log_warn(w.to_string());
+                                    report.warnings.push(w);
+                                }
+                            }
+                            if opts.allow_default_change && !dc.is_serial() && dc.identity.is_none() && pc.identity.is_none()
+                                && dc.generated_as.is_none() {
+                                let default = dc.default_value.as_ref().filter(|d| d.trim().len() > 0);
+                                let alter = match (default, &pc.column_default) {
+                                    (None, None) => None,
+                                    (Some(d), Some(e)) if normalize_default(d) == normalize_default(e) => None,
+                                    (Some(d), _) => Some(format!("SET DEFAULT {}", d)),
+                                    // sequence of a column created as serial
+                                    (None, Some(e)) if e.starts_with("nextval(") => None,
+                                    (None, Some(_)) => Some("DROP DEFAULT".to_string()),
+                                };
+                                if let Some(alter) = alter {
+                                    append(format!("ALTER TABLE {}.{} ALTER COLUMN {} {}",
+                                                   schema, self.table_name, dc.name, alter
+                                    ).as_str(), &mut sql, is_retry, opts);
+                                    pc.column_default = default.cloned();
+                                    exec = true;
+                                }
+                            }
+                            if dc.description.len() > 0 && pc.column_comment.as_ref() != Some(&dc.description) {
+                                self.comments(&mut comments, schema, &dc.name, &dc.description);
+                                pc.column_comment = Some(dc.description.clone());
+                                exec = true;
+                            }
+                        } else {
                             let def = dc.column_def(schema, &self.table_name, file)?;
-                            append(format!(
-                                "ALTER TABLE {}.{} ADD COLUMN {}",
-                                schema, self.table_name, def.def(pks.is_some())
-                            ).as_str(), &mut sql, is_retry);
+                            if opts.add_column_not_valid && !def.nullable && !def.pk && def.identity.is_none()
+                                && def.column_default.as_ref().map_or(false, |d| d.len() > 0) {
+                                let check = quote_if_needed(&crate::utils::pg_identifier(
+                                    format!("{}_{}_not_null", name_part(&self.table_name), name_part(&dc.name)).to_lowercase()));
+                                let nullable = PgColumnDfn { nullable: true, ..def.clone() };
+                                append(format!(
+                                    "ALTER TABLE {}.{} ADD COLUMN {}, ADD CONSTRAINT {} CHECK ({} IS NOT NULL) NOT VALID",
+                                    schema, self.table_name, nullable.def(true), check, dc.name
+                                ).as_str(), &mut sql, is_retry, opts);
+                                // after the commit, so the validating scan does not hold the lock of ADD COLUMN,
+                                // SET NOT NULL skips the scan having the valid check
+                                for alter in [format!("VALIDATE CONSTRAINT {}", check),
+                                    format!("ALTER COLUMN {} SET NOT NULL", dc.name),
+                                    format!("DROP CONSTRAINT {}", check)] {
+                                    report.post_tx_sql.push(format!("ALTER TABLE {}.{} {}", schema, self.table_name, alter));
+                                }
+                            } else {
+                                append(format!(
+                                    "ALTER TABLE {}.{} ADD COLUMN {}",
+                                    schema, self.table_name, def.def(true)
+                                ).as_str(), &mut sql, is_retry, opts);
+                            }
+                            pk_added |= def.pk;
+                            self.comments(&mut comments, schema, &dc.name, &dc.description);
                             let _ = ts.columns.insert(dc.get_name(), def);
+                            report.columns_added.push(dc.get_name());
                             exec = true;
                         }
                     }
+                    if pk_added {
+                        // the complete set of the key columns, never inline on ADD COLUMN as the table may have a key already
+                        let pk: Vec<&str> = self.columns.list.iter().filter(|c| c.is_pk()).map(|c| c.name.as_str()).collect();
+                        let drop = match &ts.pk_name {
+                            Some(name) => format!("DROP CONSTRAINT {}, ", quote_if_needed(name)),
+                            None if had_pk => format!("DROP CONSTRAINT IF EXISTS {}, ", quote_if_needed(&self.pk_name())),
+                            None => "".to_string(),
+                        };
+                        append(format!("ALTER TABLE {}.{} {}ADD PRIMARY KEY ({})",
+                                       schema, self.table_name, drop, pk.join(", ")
+                        ).as_str(), &mut sql, is_retry, opts);
+                        for c in ts.columns.values_mut() {
+                            c.pk = pk.contains(&c.column_name.as_str());
+                        }
+                        ts.pk_name = Some(self.pk_name());
+                    }
+                    if self.description.len() > 0 && ts.table_comment.as_ref() != Some(&self.description) {
+                        self.table_comment(&mut comments, schema);
+                        ts.table_comment = Some(self.description.clone());
+                        exec = true;
+                    }
                     if let Some(o) = &ts.owner {
                         if self.owner.len() > 0 && &self.owner != o {
                             append(format!("ALTER TABLE {}.{} OWNER TO {}",
                                            schema, self.table_name, self.owner
-                            ).as_str(), &mut sql, is_retry);
+                            ).as_str(), &mut sql, is_retry, opts);
+                            ts.owner = Some(self.owner.clone());
+                            exec = true;
+                        }
+                    }
+                    if self.deploy_identity(ts, schema, &mut sql, is_retry, opts) {
+                        exec = true;
+                    }
+                    if self.deploy_attributes(ts, schema, &mut sql, is_retry, opts) {
+                        exec = true;
+                    }
+                    if self.deploy_checks(ts, schema, &mut sql, is_retry, opts) {
+                        exec = true;
+                    }
+                    if self.deploy_excludes(ts, schema, &mut sql, is_retry, opts) {
+                        exec = true;
+                    }
+                    let ib = IndexBuilder::new(self);
+                    let (in_tx_sql, post_tx_sql) = ib.generate_sql_split(schema, &self.table_name, &ts.indexes, opts);
+                    for isql in in_tx_sql {
+                        report.count_index(&isql);
+                        append(isql.as_str(), &mut sql, is_retry, opts);
+                        exec = true;
+                    }
+                    for isql in post_tx_sql {
+                        report.count_index(&isql);
+                        report.post_tx_sql.push(isql);
+                        exec = true;
+                    }
+                    ib.update_dbc(ts);
+                    if let Some(ri) = &self.replica_identity {
+                        if &ts.replica_identity != ri {
+                            if let ReplicaIdentity::Index(i) = ri {
+                                if !ts.indexes.contains_key(i) {
+                                    return Err(format!("table {}.{} replica identity index {} not found, as defined in file: {}",
+                                                       schema, self.table_name, i, file));
+                                }
+                            }
+                            append(format!("ALTER TABLE {}.{} REPLICA IDENTITY {}",
+                                           schema, self.table_name, ri.sql()
+                            ).as_str(), &mut sql, is_retry, opts);
+                            ts.replica_identity = ri.clone();
+                            exec = true;
+                        }
+                    }
+                    if self.deploy_policies(ts, schema, &mut sql, is_retry, opts) {
+                        exec = true;
+                    }
+                    if self.deploy_security_labels(ts, schema, &mut sql, is_retry, opts) {
+                        exec = true;
+                    }
+                    if opts.drop_triggers {
+                        // before the create, a trigger may be renamed in yaml
+                        let mut dropped: Vec<String> = ts.triggers.keys()
+                            .filter(|t| self.triggers.get(t).is_none())
+                            .cloned()
+                            .collect();
+                        dropped.sort();
+                        for t in dropped {
+                            let _ = writeln!(sql, "DROP TRIGGER IF EXISTS {} ON {}.{};", t, schema, self.table_name);
+                            ts.triggers.remove(&t);
+                            exec = true;
                         }
                     }
                     for dt in &self.triggers.list {
-                        if !ts.triggers.contains_key(&dt.name) {
-                            if let Some(def) = dt.trig_def(schema, &self.table_name) {
-                                let _ = writeln!(sql, "{}\n", def);
-                                let _ = ts.triggers.insert(dt.get_name(), def);
-                                exec = true;
+                        let changed = match ts.triggers.get(&dt.name) {
+                            None => false,
+                            Some(existing) if dt.is_same_def(existing, schema, &self.table_name) => continue,
+                            Some(_) => true,
+                        };
+                        if let Some(def) = dt.trig_def(schema, &self.table_name) {
+                            if changed && !dt.or_replace {
+                                let _ = writeln!(sql, "DROP TRIGGER IF EXISTS {} ON {}.{};", dt.name, schema, self.table_name);
                             }
+                            let _ = writeln!(sql, "{}\n", def);
+                            let _ = ts.triggers.insert(dt.get_name(), def);
+                            exec = true;
                         }
                     }
+                    let (granted, revoked) = self.deploy_grants(ts, schema, &mut sql, is_retry, opts)?;
+                    if granted + revoked > 0 {
+                        report.grants_applied = granted;
+                        report.grants_revoked = revoked;
+                        exec = true;
+                    }
                     CreateST::None
                 }
             },
@@ -332,13 +866,29 @@ impl Table {
                 columns: HashMap::new(),
                 fks: Default::default(),
                 triggers: HashMap::new(),
+                checks: HashMap::new(),
+                excludes: HashMap::new(),
+                pk_name: if self.columns.list.iter().any(|c| c.is_pk()) { Some(self.pk_name()) } else { None },
+                indexes: HashMap::new(),
+                row_security: false,
+                policies: HashMap::new(),
+                persistence: self.persistence(),
+                partition_key: self.partition_by.as_ref().map(|p| p.key_def()),
+                tablespace: self.tablespace.clone(),
+                parents: self.inherits_qualified(schema),
+                with_options: self.with_options.clone(),
+                replica_identity: ReplicaIdentity::Default,
                 sort_order: 0,
-                table_comment: None,
+                table_comment: if self.description.len() > 0 { Some(self.description.clone()) } else { None },
                 owner: if self.owner.len() > 0 { Some(self.owner.clone()) } else { None },
+                grants: HashMap::new(),
+                column_grants: HashMap::new(),
+                security_labels: HashMap::new(),
             };
 
-            for dc in &self.columns.list {
-                let cd = dc.column_def(schema, &self.table_name, file)?;
+            for (i, dc) in self.columns.list.iter().enumerate() {
+                let mut cd = dc.column_def(schema, &self.table_name, file)?;
+                cd.sort_order = i + 1;
                 // let _ = write!(columns, "{}, ", cd.def(true));
                 let _ = st.columns.insert(dc.get_name(), cd);
                 self.comments(&mut comments, schema, &dc.name, &dc.description);
@@ -353,11 +903,11 @@ impl Table {
                 }
             }
             if let Some(pks) = pks {
-                columns.push_str(pks.as_str());
+                columns.push_str(pks.trim_start_matches(", "));
                 columns.push_str(", ");
             }
             if let SchemaAndTable = do_create {
-                if schema.as_str() != "public" {
+                if schema.as_str() != "public" && !self.temporary {
                     let _ = write!(sql, "CREATE SCHEMA IF NOT EXISTS {} ", schema);
                     if self.owner.len() > 0 {
                         let _ = write!(sql, "AUTHORIZATION {}", self.owner);
@@ -368,92 +918,328 @@ impl Table {
             if let Some(idx) = columns.rfind(",") {
                 columns.remove(idx);
             }
-            let csql = format!("CREATE TABLE {}.{} ({}{}{}){}; \n",
-                               schema,
-                               self.table_name,
-                               columns,
-                               if self.constraint.len() > 0 { ", " } else { "" },
-                               self.constraint,
-                               self.sql
-            );
-
-            sql.push_str(csql.as_str());
+            sql.push_str(self.create_sql(schema, &columns, opts.create_if_not_exists).as_str());
 
             if self.owner.len() > 0 {
                 append(format!(
                     "ALTER TABLE {}.{} OWNER TO {}",
                     schema, self.table_name, self.owner
-                ).as_str(), &mut sql, is_retry);
+                ).as_str(), &mut sql, is_retry, opts);
             }
             // }
+            let _ = self.deploy_attributes(&mut st, schema, &mut sql, is_retry, opts);
+            let _ = self.deploy_checks(&mut st, schema, &mut sql, is_retry, opts);
+            let _ = self.deploy_excludes(&mut st, schema, &mut sql, is_retry, opts);
+            let ib = IndexBuilder::new(self);
+            for isql in ib.generate_sql(schema, &self.table_name, &st.indexes) {
+                report.count_index(&isql);
+                append(isql.as_str(), &mut sql, is_retry, opts);
+            }
+            ib.update_dbc(&mut st);
+            if let Some(ri) = self.replica_identity.as_ref().filter(|r| *r != &ReplicaIdentity::Default) {
+                append(format!("ALTER TABLE {}.{} REPLICA IDENTITY {}",
+                               schema, self.table_name, ri.sql()
+                ).as_str(), &mut sql, is_retry, opts);
+                st.replica_identity = ri.clone();
+            }
+            let _ = self.deploy_policies(&mut st, schema, &mut sql, is_retry, opts);
+            let _ = self.deploy_security_labels(&mut st, schema, &mut sql, is_retry, opts);
             for dt in &self.triggers.list {
                 if let Some(td) = dt.trig_def(schema, &self.table_name) {
                     let _ = writeln!(sql, "{}\n", td);
                     st.triggers.insert(dt.get_name(), td);
                 }
             }
+            let (granted, revoked) = self.deploy_grants(&mut st, schema, &mut sql, is_retry, opts)?;
+            report.grants_applied = granted;
+            report.grants_revoked = revoked;
             dbc.get_mut(schema)
                 .unwrap()
                 .insert(self.table_name.clone(), st);
+            self.table_comment(&mut comments, schema);
+            report.created = true;
             exec = true;
         }
-        if exec {
-            if self.description.len() > 0 {
-                let _ = writeln!(
-                    comments,
-                    "COMMENT ON TABLE {}.{} IS '{}'; \n",
-                    schema, self.table_name, self.description
-                );
-            }
-
-        }
-        let mut data = String::new();
-        for row in &self.data {
-            self.insert(&mut data, row, schema);
-        }
+        let data = if opts.schema_only { DataRows::Insert(Vec::new()) } else { self.data_rows(schema, opts)? };
 
         match dry_run {
             Some(store) => {
-                store(vec![sql, comments, data]).map(|_| false)
+                let post_tx_sql: String = report.post_tx_sql.iter().map(|s| format!("{};\n", s)).collect();
+                match opts.plan {
+                    Some(plan) => plan(PlanEntry {
+                        schema: schema.clone(),
+                        table: self.table_name.clone(),
+                        source_file: file.to_string(),
+                        ddl: sql,
+                        comments,
+                        data: data.sql(),
+                        post_tx: post_tx_sql,
+                    }),
+                    None => store(vec![sql, comments, data.sql(), post_tx_sql]),
+                }.map(|_| None)
             }
             None => {
                 #[cfg(feature = "slog")] log_debug(format!("deploy SQL {:?}[{}:{}]> {}", exec, file, schema, sql));
+                let source = if file.len() > 0 { format!(", source: {}", file)} else {"".to_string()};
                 if exec {
-                    let source = if file.len() > 0 { format!(", source: {}", file)} else {"".to_string()};
                     let _ = db.batch_execute(sql.as_str())
                         .map_err(|e| format!("DB execute [{}]: {} {}", sql, e, source))?;
                     let _ = db.batch_execute(comments.as_str())
                         .map_err(|e| format!("DB execute [{}]: {} {}", comments, e, source))?;
-                    let _ = db.batch_execute(data.as_str())
-                        .map_err(|e| format!("DB execute [{}]: {} {}", data, e, source))?;
                 }
-                Ok(exec)
+                opts.check_cancelled()?;
+                report.rows_inserted = data.apply(db, &source)?;
+                report.altered = exec && !report.created;
+                Ok(if exec || report.rows_inserted > 0 || report.warnings.len() > 0 { Some(report) } else { None })
+            }
+        }
+    }
+
+    /// insert the data rows only, the table expected to exist
+    #[cfg(feature = "db")]
+    pub fn deploy_data(
+        &self,
+        db: &mut dyn DbExec,
+        schema: &String,
+        file: &str,
+        dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>,
+        opts: &MigrationOptions,
+    ) -> Result<Option<TableReport>, String> {
+        opts.check_cancelled()?;
+        let temp_schema = "pg_temp".to_string();
+        let schema = if self.temporary { &temp_schema } else { schema };
+        let data = self.data_rows(schema, opts)?;
+        match dry_run {
+            Some(store) => match opts.plan {
+                Some(plan) => plan(PlanEntry {
+                    schema: schema.clone(),
+                    table: self.table_name.clone(),
+                    source_file: file.to_string(),
+                    data: data.sql(),
+                    ..Default::default()
+                }),
+                None => store(vec![data.sql()]),
+            }.map(|_| None),
+            None => {
+                let source = if file.len() > 0 { format!(", source: {}", file)} else {"".to_string()};
+                let rows_inserted = data.apply(db, &source)?;
+                Ok(if rows_inserted > 0 {
+                    Some(TableReport { schema: schema.clone(), table: self.table_name.clone(), rows_inserted, ..Default::default() })
+                } else {
+                    None
+                })
+            }
+        }
+    }
+
+    /// insert statements with values of the data rows,
+    /// or a COPY into a staging table for more rows than copy_threshold if use_copy_for_data set,
+    /// after TRUNCATE if truncate_before_seed
+    fn data_rows(&self, schema: &String, opts: &MigrationOptions) -> Result<DataRows, String> {
+        let copy = if opts.use_copy_for_data && self.data.len() > opts.copy_threshold { self.copy_rows(schema) } else { None };
+        let rows = match copy {
+            Some(copy) => DataRows::Copy(copy),
+            None => DataRows::Insert(self.data.iter().map(|row| self.insert(row, schema)).collect()),
+        };
+        if !self.truncate_before_seed {
+            return Ok(rows);
+        }
+        if !opts.allow_truncate {
+            return Err(format!("table: {}.{} truncate_before_seed requires allow_truncate option", schema, self.table_name));
+        }
+        let truncate = format!("TRUNCATE TABLE {}.{}{}", schema, self.table_name, if self.truncate_cascade { " CASCADE" } else { "" });
+        Ok(DataRows::Truncate(truncate, Box::new(rows)))
+    }
+
+    /// the rows as CSV to COPY, the rows must have the same count of values
+    fn copy_rows(&self, schema: &String) -> Option<CopyRows> {
+        let columns = self.data.get(0)?.len();
+        if columns == 0 || columns > self.columns.list.len() || self.data.iter().any(|row| row.len() != columns) {
+            return None;
+        }
+        let names: Vec<&str> = self.columns.list.iter().take(columns).map(|c| c.name.as_str()).collect();
+        let mut csv = String::new();
+        for row in &self.data {
+            let values: Vec<String> = row.iter().map(|v| format!("\"{}\"", v.replace('"', "\"\""))).collect();
+            let _ = writeln!(csv, "{}", values.join(","));
+        }
+        Some(CopyRows {
+            create: format!("CREATE TEMPORARY TABLE {} ON COMMIT DROP AS SELECT {} FROM {}.{} WITH NO DATA",
+                            COPY_STAGING, names.join(", "), schema, self.table_name),
+            copy: format!("COPY {} ({}) FROM STDIN CSV", COPY_STAGING, names.join(", ")),
+            csv,
+            insert: self.insert_sql(columns, schema, Some(COPY_STAGING)),
+            drop: format!("DROP TABLE {}", COPY_STAGING),
+        })
+    }
+
+    /// pg_class.relpersistence as defined in yaml
+    #[inline]
+    pub fn persistence(&self) -> char {
+        if self.temporary {
+            't'
+        } else if self.unlogged {
+            'u'
+        } else {
+            'p'
+        }
+    }
+
+    /// CREATE TABLE statement of the column definitions, a partition inherits columns of the parent
+    pub(crate) fn create_sql(&self, schema: &String, columns: &String, if_not_exists: bool) -> String {
+        let kind = if self.temporary { "TEMPORARY " } else if self.unlogged { "UNLOGGED " } else { "" };
+        let table = if if_not_exists { format!("{}TABLE IF NOT EXISTS", kind) } else { format!("{}TABLE", kind) };
+        if let Some(p) = &self.partition_of {
+            let parent = if p.parent.contains('.') { p.parent.clone() } else { format!("{}.{}", schema, p.parent) };
+            let values = if p.for_values.to_uppercase() == "DEFAULT" {
+                "DEFAULT".to_string()
+            } else {
+                format!("FOR VALUES {}", p.for_values)
+            };
+            return format!("CREATE {} {}.{} PARTITION OF {} {}{}{}{}{}; \n",
+                           table, schema, self.table_name, parent, values,
+                           self.partition_by.as_ref().map_or("".to_string(), |p| format!(" PARTITION BY {}", p.key_def())),
+                           self.with_sql(),
+                           self.tablespace_sql(),
+                           self.sql);
+        }
+        let columns = match &self.like_table {
+            None => columns.clone(),
+            Some(like) => {
+                let mut like = format!("LIKE {}", if like.contains('.') { like.clone() } else { format!("{}.{}", schema, like) });
+                for o in &self.like_options {
+                    let _ = write!(like, " INCLUDING {}", o);
+                }
+                if columns.trim().len() > 0 {
+                    let _ = write!(like, ", {}", columns);
+                }
+                like
+            }
+        };
+        format!("CREATE {} {}.{} ({}{}{}){}{}{}{}{}; \n",
+                table,
+                schema,
+                self.table_name,
+                columns,
+                if self.constraint.len() > 0 { ", " } else { "" },
+                self.constraint,
+                if self.inherits.len() > 0 { format!(" INHERITS ({})", self.inherits_qualified(schema).join(", ")) } else { "".to_string() },
+                self.partition_by.as_ref().map_or("".to_string(), |p| format!(" PARTITION BY {}", p.key_def())),
+                self.with_sql(),
+                self.tablespace_sql(),
+                self.sql
+        )
+    }
+
+    #[inline]
+    /// existing columns ordered in DB differently than in yaml, the new columns appended anyway
+    fn column_order_warning(&self, ts: &PgTable, schema: &str) -> Option<SchemaWarning> {
+        let declared: Vec<&String> = self.columns.list.iter()
+            .map(|c| &c.name)
+            .filter(|n| ts.columns.contains_key(*n))
+            .collect();
+        let mut existing = declared.clone();
+        existing.sort_by_key(|n| ts.columns[*n].sort_order);
+        if declared == existing {
+            return None;
+        }
+        let join = |names: &Vec<&String>| names.iter().map(|n| n.as_str()).collect::<Vec<&str>>().join(", ");
+        Some(SchemaWarning::warning(WarningKind::ColumnOrderMismatch, format!("{}.{}", schema, self.table_name),
+            format!("columns ordered as ({}) in DB, but ({}) in yaml", join(&existing), join(&declared))))
+    }
+
+    /// parent tables as schema.table
+    fn inherits_qualified(&self, schema: &str) -> Vec<String> {
+        self.inherits.iter()
+            .map(|p| if p.contains('.') { p.clone() } else { format!("{}.{}", schema, p) })
+            .collect()
+    }
+
+    fn with_sql(&self) -> String {
+        if self.with_options.len() > 0 {
+            format!(" WITH ({})", crate::utils::storage_params_sql(&self.with_options))
+        } else {
+            "".to_string()
+        }
+    }
+
+    fn tablespace_sql(&self) -> String {
+        self.tablespace.as_ref().map_or("".to_string(), |ts| format!(" TABLESPACE {}", ts))
+    }
+
+    /// nesting level of partitions and inheriting tables in the schema, parents to create before
+    pub(crate) fn partition_level(&self, tables: &OrderedHashMap<Table>) -> usize {
+        fn level(t: &Table, tables: &OrderedHashMap<Table>, depth: usize) -> usize {
+            if depth >= tables.len() {
+                return depth;
             }
+            t.partition_of.iter().map(|p| &p.parent)
+                .chain(t.inherits.iter())
+                .filter_map(|p| tables.get(p))
+                .map(|parent| level(parent, tables, depth + 1))
+                .max()
+                .unwrap_or(depth)
         }
+        level(self, tables, 0)
+    }
+
+    /// parameterized insert statement with values to bind, each value passed as text and casted to the column type
+    pub(crate) fn insert(&self, row: &Vec<String>, schema: &String) -> (String, Vec<String>) {
+        (self.insert_sql(row.len(), schema, None), row.clone())
     }
 
-    fn insert(&self, data: &mut String, row: &Vec<String>, schema: &String) {
+    /// insert statement of the first columns with the conflict action,
+    /// the values to bind or selected from the staging table
+    fn insert_sql(&self, columns: usize, schema: &String, staging: Option<&str>) -> String {
         let mut names = String::new();
         let mut vals = String::new();
         let mut pks = String::new();
-        for i in 0..row.len() {
+        let mut updates = String::new();
+        let mut overriding = "";
+        for i in 0..columns {
             let c = self.columns.list.get(i).unwrap();
+            if c.identity == Some(IdentityMode::Always) {
+                overriding = "OVERRIDING SYSTEM VALUE ";
+            }
             if c.is_pk() {
                 if pks.len() > 0 {
                     pks.push_str(", ");
                 }
                 pks.push_str(c.name.as_str());
+            } else {
+                if updates.len() > 0 {
+                    updates.push_str(", ");
+                }
+                let _ = write!(updates, "{} = EXCLUDED.{}", c.name, c.name);
             }
             if i > 0 {
                 names.push_str(", ");
                 vals.push_str(", ");
             }
             names.push_str(c.name.as_str());
-            vals.push_str("'");
-            vals.push_str(row[i].as_str());
-            vals.push_str("'");
+            let _ = write!(vals, "${}::text::{}", i + 1, c.cast_type());
+        }
+        let action = match self.data_conflict.to_lowercase().as_str() {
+            "" | "nothing" => "NOTHING".to_string(),
+            "update" => if pks.len() > 0 && updates.len() > 0 {
+                format!("UPDATE SET {}", updates)
+            } else {
+                "NOTHING".to_string()
+            },
+            _ => self.data_conflict.clone(),
+        };
+        // no conflicts on the truncated table
+        let conflict = if self.truncate_before_seed {
+            "".to_string()
+        } else if pks.len() > 0 {
+            format!(" ON CONFLICT ({}) DO {}", pks, action)
+        } else {
+            format!(" ON CONFLICT DO {}", action)
+        };
+        match staging {
+            None => format!("insert into {}.{} ({}) {}values ({}){}", schema, self.table_name, names, overriding, vals, conflict),
+            Some(staging) => format!("insert into {}.{} ({}) {}select {} from {}{}", schema, self.table_name, names, overriding, names, staging, conflict),
         }
-        let _ = writeln!(data, " insert into {}.{} ({}) values ({}) ON CONFLICT ({}) DO NOTHING;", schema, self.table_name, names, vals, pks);
     }
     //YTable
 
@@ -469,6 +1255,173 @@ impl Table {
         yes
     }
 
+    /// the primary key constraint name as PostgreSQL sets it on create
+    fn pk_name(&self) -> String {
+        format!("{}_pkey", name_part(&self.table_name).to_lowercase())
+    }
+
+    /// table level check constraints: the changed or removed dropped, the missing added, return true if any
+    fn deploy_checks(&self, ts: &mut PgTable, schema: &String, sql: &mut String, is_retry: bool, opts: &MigrationOptions) -> bool {
+        let defs: Vec<(String, String)> = self.checks.iter().map(|c| (c.clone(), format!("CHECK ({})", c))).collect();
+        self.deploy_constraints("chk", &defs, &mut ts.checks, schema, sql, is_retry, opts)
+    }
+
+    /// match the constraints named on deploy by the expression as pg_get_constraintdef, not by the position,
+    /// defs are the value as loaded from DB and the definition to add
+    fn deploy_constraints(&self, prefix: &str, defs: &[(String, String)], loaded: &mut HashMap<String, String>,
+                          schema: &String, sql: &mut String, is_retry: bool, opts: &MigrationOptions) -> bool {
+        let max = loaded.len() + defs.len();
+        let mut managed: Vec<(usize, String)> = loaded.keys()
+            .filter_map(|name| constraint_position(prefix, &self.table_name, name, max).map(|i| (i, name.clone())))
+            .collect();
+        managed.sort();
+        let mut kept: Vec<&String> = Vec::new();
+        let mut missing = Vec::new();
+        for def in defs {
+            let normalized = normalize_check(&def.0);
+            match managed.iter().find(|(_, name)| !kept.contains(&name) && normalize_check(&loaded[name]) == normalized) {
+                Some((_, name)) => kept.push(name),
+                None => missing.push(def),
+            }
+        }
+        let mut exec = false;
+        for (_, name) in managed.iter().filter(|(_, name)| !kept.contains(&name)) {
+            append(format!("ALTER TABLE {}.{} DROP CONSTRAINT {}",
+                           schema, self.table_name, quote_if_needed(name)
+            ).as_str(), sql, is_retry, opts);
+            let _ = loaded.remove(name);
+            exec = true;
+        }
+        let mut i = 0;
+        for def in missing {
+            let name = loop {
+                i += 1;
+                let name = constraint_name(prefix, &self.table_name, i);
+                if !loaded.contains_key(&name) {
+                    break name;
+                }
+            };
+            append(format!("ALTER TABLE {}.{} ADD CONSTRAINT {} {}",
+                           schema, self.table_name, quote_if_needed(&name), def.1
+            ).as_str(), sql, is_retry, opts);
+            let _ = loaded.insert(name, def.0.clone());
+            exec = true;
+        }
+        exec
+    }
+
+    /// exclusion constraints: the changed or removed dropped, the missing added, return true if any
+    fn deploy_excludes(&self, ts: &mut PgTable, schema: &String, sql: &mut String, is_retry: bool, opts: &MigrationOptions) -> bool {
+        let defs: Vec<(String, String)> = self.excludes.iter().map(|e| (e.def(), e.def())).collect();
+        self.deploy_constraints("exc", &defs, &mut ts.excludes, schema, sql, is_retry, opts)
+    }
+
+    /// switch the identity mode of existing columns or make a column identity, return true if any
+    fn deploy_identity(&self, ts: &mut PgTable, schema: &String, sql: &mut String, is_retry: bool, opts: &MigrationOptions) -> bool {
+        let mut exec = false;
+        for dc in &self.columns.list {
+            if let (Some(identity), Some(pc)) = (&dc.identity, ts.columns.get_mut(&dc.name)) {
+                match pc.identity {
+                    Some(pi) if pi == *identity => {}
+                    Some(_) => {
+                        append(format!("ALTER TABLE {}.{} ALTER COLUMN {} SET GENERATED {}",
+                                       schema, self.table_name, dc.name, identity.sql()).as_str(), sql, is_retry, opts);
+                        pc.identity = Some(*identity);
+                        exec = true;
+                    }
+                    None => {
+                        pc.identity_seq_options = dc.identity_seq_options.clone();
+                        append(format!("ALTER TABLE {}.{} ALTER COLUMN {} ADD{}",
+                                       schema, self.table_name, dc.name, pc.identity_sql(identity)).as_str(), sql, is_retry, opts);
+                        pc.identity = Some(*identity);
+                        exec = true;
+                    }
+                }
+            }
+        }
+        exec
+    }
+
+    /// set the storage and statistics target of the columns where it differs, return true if any
+    fn deploy_attributes(&self, ts: &mut PgTable, schema: &String, sql: &mut String, is_retry: bool, opts: &MigrationOptions) -> bool {
+        let mut exec = false;
+        for dc in &self.columns.list {
+            if let Some(pc) = ts.columns.get_mut(&dc.name) {
+                if let Some(storage) = dc.storage.filter(|s| pc.storage != Some(s.code())) {
+                    append(format!("ALTER TABLE {}.{} ALTER COLUMN {} SET STORAGE {}",
+                                   schema, self.table_name, dc.name, storage.sql()).as_str(), sql, is_retry, opts);
+                    pc.storage = Some(storage.code());
+                    exec = true;
+                }
+                // a new column has the default target
+                if let Some(n) = dc.statistics.filter(|n| pc.statistics_target.unwrap_or(-1) != *n) {
+                    append(format!("ALTER TABLE {}.{} ALTER COLUMN {} SET STATISTICS {}",
+                                   schema, self.table_name, dc.name, n).as_str(), sql, is_retry, opts);
+                    pc.statistics_target = Some(n);
+                    exec = true;
+                }
+            }
+        }
+        exec
+    }
+
+    /// grant missing privileges, revoke extra ones if with_revoke, return counts of granted and revoked
+    fn deploy_grants(&self, ts: &mut PgTable, schema: &String, sql: &mut String, is_retry: bool, opts: &MigrationOptions) -> Result<(usize, usize), String> {
+        let gb = GrantBuilder::new(&self.grant, &TABLE_PRIVILEGES, &COLUMN_PRIVILEGES)
+            .map_err(|e| format!("{} on table {}.{}", e, schema, self.table_name))?;
+        let object = format!("TABLE {}.{}", schema, self.table_name);
+        let mut gsql = gb.generate_sql(object.as_str(), &ts.grants, ts.owner.as_ref(), opts.with_revoke);
+        gsql.extend(gb.generate_column_sql(object.as_str(), &ts.column_grants, opts.with_revoke));
+        for g in &gsql {
+            append(g.as_str(), sql, is_retry, opts);
+        }
+        gb.update_dbc(&mut ts.grants, opts.with_revoke);
+        gb.update_column_dbc(&mut ts.column_grants, opts.with_revoke);
+        let revoked = gsql.iter().filter(|g| g.starts_with("REVOKE")).count();
+        Ok((gsql.len() - revoked, revoked))
+    }
+
+    /// label the table and columns where the label of the provider differs, return true if any
+    fn deploy_security_labels(&self, ts: &mut PgTable, schema: &String, sql: &mut String, is_retry: bool, opts: &MigrationOptions) -> bool {
+        let mut exec = false;
+        if let Some(sl) = self.security_label.as_ref().filter(|sl| ts.security_labels.get(&sl.provider) != Some(&sl.label)) {
+            append(sl.sql(format!("TABLE {}.{}", schema, self.table_name).as_str()).as_str(), sql, is_retry, opts);
+            ts.security_labels.insert(sl.provider.clone(), sl.label.clone());
+            exec = true;
+        }
+        for dc in &self.columns.list {
+            if let (Some(sl), Some(pc)) = (&dc.security_label, ts.columns.get_mut(&dc.name)) {
+                if pc.security_labels.get(&sl.provider) != Some(&sl.label) {
+                    append(sl.sql(format!("COLUMN {}.{}.{}", schema, self.table_name, dc.name).as_str()).as_str(), sql, is_retry, opts);
+                    pc.security_labels.insert(sl.provider.clone(), sl.label.clone());
+                    exec = true;
+                }
+            }
+        }
+        exec
+    }
+
+    /// enable row level security, create new or re-create changed policies, return true if any
+    fn deploy_policies(&self, ts: &mut PgTable, schema: &String, sql: &mut String, is_retry: bool, opts: &MigrationOptions) -> bool {
+        let mut exec = false;
+        if self.row_security && !ts.row_security {
+            append(format!("ALTER TABLE {}.{} ENABLE ROW LEVEL SECURITY", schema, self.table_name).as_str(), sql, is_retry, opts);
+            ts.row_security = true;
+            exec = true;
+        }
+        for p in &self.policies {
+            match ts.policies.get(&p.name) {
+                Some(pg) if p.matches(pg) => continue,
+                Some(_) => append(format!("DROP POLICY {} ON {}.{}", p.name, schema, self.table_name).as_str(), sql, is_retry, opts),
+                None => {}
+            }
+            append(p.create_sql(schema, &self.table_name).as_str(), sql, is_retry, opts);
+            ts.policies.insert(p.name.clone(), p.to_pg());
+            exec = true;
+        }
+        exec
+    }
+
     /// column defenition to SQL string
     #[inline]
     fn comments(&self, sql: &mut String, schema: &String, column_name: &String, t: &String) {
@@ -482,84 +1435,137 @@ impl Table {
         }
     }
 
+    #[inline]
+    fn table_comment(&self, sql: &mut String, schema: &String) {
+        if self.description.len() > 0 {
+            let _ = writeln!(
+                sql,
+                "COMMENT ON TABLE {}.{} IS '{}'; \n",
+                schema, self.table_name, self.description
+            );
+        }
+    }
+
     /// build a create or alter sql
     #[allow(unused, unused_mut)]
+    #[cfg(feature = "db")]
     pub fn deploy_fk(
         &self,
         // target: &FileVersion,
         schemas: &OrderedHashMap<Schema>, //FilesMap,
         dbc: &mut InfoSchemaType,
-        db: &mut Transaction,
+        db: &mut dyn DbExec,
         schema: &String,
         is_retry: bool,
         file: &str,
         dry_run: Option<&dyn Fn(Vec<String>) -> Result<(), String>>,
+        opts: &MigrationOptions,
     ) -> Result<bool, String> {
+        opts.check_cancelled()?;
         let mut sql = String::new();
-        let mut fk_list = HashMap::new();
+        let mut fk_list: Vec<FKTable> = Vec::new();
+        let mut fk_drop = Vec::new();
+        let mut fk_rename = Vec::new();
         if let Some(ss) = dbc.get(schema) {
             if let Some(ts) = ss.get(&self.table_name) {
+                // desired FKs, columns with the same FK name make a composite key
+                let mut desired: Vec<(&ForeignKey, FKTable)> = Vec::new();
                 for dc in &self.columns.list {
                     if let Some(constraint) = &dc.constraint {
                         if let Some(fk) = &constraint.foreign_key {
-                            let fk_table = &fk.references;
-                            let (fk_schema, fk_table) = match fk_table.find(".") {
-                                None => (schema.clone(), fk_table.to_string()),
-                                Some(i) => {
-                                    (fk_table[0..i].to_string(), fk_table[i + 1..].to_string())
-                                }
-                            };
-                            // check for FK already in DB
-                            if let Some(a) = dbc.get(schema) {
-                                if let Some(b) = a.get(&self.table_name) {
-                                    if let Some(_) = b.fks.get(&dc.name) {
-                                        continue; // FK found as already created in DB, check the next column
-                                    }
+                            let (fk_schema, fk_table) = fk.target(schema);
+                            let name = fk.name(schema, &self.table_name);
+                            if let Some((_, ff)) = desired.iter_mut().find(|(_, f)| f.name == name) {
+                                if ff.schema != fk_schema || ff.table != fk_table || fk.constraint_name.is_none() {
+                                    return Err(format!("duplicate FK name {} on {}.{}, set foreignKey.name source: {}",
+                                                       name, schema, self.table_name, file));
                                 }
+                                ff.local_columns.push(dc.get_name());
+                                continue;
                             }
-                            let fk_columns = pks(&fk_schema, &fk_table, schemas);
-                            let key = format!("{}.{}", fk_schema, fk_table);
-                            if fk_columns.len() > 0 {
-                                fk_list.insert(key, FKTable {
-                                    column: fk_columns,
-                                    name: dc.get_name(),
-                                    schema: fk_schema,
-                                    table: fk_table,
-                                    sql: fk.sql.clone(),
-                                });
+                            desired.push((fk, FKTable {
+                                column: pks(&fk_schema, &fk_table, schemas),
+                                name,
+                                local_columns: vec![dc.get_name()],
+                                schema: fk_schema,
+                                table: fk_table,
+                                sql: fk.actions_sql(),
+                                match_type: fk.match_type.unwrap_or(FkMatch::Simple),
+                                on_update: fk.update_action(),
+                                on_delete: fk.delete_action(),
+                                deferrable: fk.is_deferrable(),
+                                initially_deferred: fk.is_initially_deferred(),
+                            }));
+                        }
+                    }
+                }
+                for (_, ff) in desired.iter() {
+                    // check for FK already in DB, by name or renamed
+                    let existing = ts.fks.get(&ff.name).or_else(|| ts.fks.values()
+                        .find(|f| f.local_columns == ff.local_columns && f.schema == ff.schema && f.table == ff.table
+                            && !desired.iter().any(|(_, d)| d.name == f.name)));
+                    if let Some(existing) = existing {
+                        if existing.deferrable == ff.deferrable
+                            && existing.initially_deferred == ff.initially_deferred
+                            && existing.match_type == ff.match_type
+                            && existing.on_update == ff.on_update && existing.on_delete == ff.on_delete {
+                            if existing.name != ff.name {
+                                fk_rename.push((existing.name.clone(), ff.name.clone()));
                             }
+                            continue; // FK found as already created in DB, check the next one
                         }
+                        fk_drop.push(existing.name.clone());
+                    }
+                    if ff.column.len() > 0 {
+                        fk_list.push(ff.clone());
                     }
                 }
             }
         };
-        let exec = fk_list.len() > 0;
-        for ff in fk_list.values() {
-            if let Some(mut ss) = dbc.get_mut(schema) {
-                if let Some(mut ts) = ss.get_mut(&self.table_name) {
-                    ts.fks.insert(ff.name.clone(), ff.clone());
+        let exec = !fk_list.is_empty() || !fk_rename.is_empty() || !fk_drop.is_empty();
+        if let Some(ts) = dbc.get_mut(schema).and_then(|ss| ss.get_mut(&self.table_name)) {
+            for name in &fk_drop {
+                append(format!("ALTER TABLE {}.{} DROP CONSTRAINT {}", schema, self.table_name, name).as_str(), &mut sql, is_retry, opts);
+                ts.fks.remove(name);
+            }
+            for (from, to) in &fk_rename {
+                append(format!("ALTER TABLE {}.{} RENAME CONSTRAINT {} TO {}", schema, self.table_name, from, to).as_str(), &mut sql, is_retry, opts);
+                if let Some(mut ff) = ts.fks.remove(from) {
+                    ff.name = to.clone();
+                    ts.fks.insert(to.clone(), ff);
                 }
             }
-            append(format!(
-                "ALTER TABLE {}.{} ADD CONSTRAINT fk_{}_{}_{} FOREIGN KEY ({}) REFERENCES {}.{} ({}) {}",
-                schema, self.table_name, schema, self.table_name, ff.table,
-                ff.name, ff.schema, &ff.table, ff.columns(), ff.sql
-            ).as_str(), &mut sql, is_retry);
+            for ff in &fk_list {
+                append(format!(
+                    "ALTER TABLE {}.{} ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {}.{} ({}) {}",
+                    schema, self.table_name, ff.name, ff.local_columns(), ff.schema, &ff.table, ff.columns(),
+                    [ff.match_type.sql(), ff.sql.as_str(), ff.deferrable_sql()].iter()
+                        .filter(|s| s.len() > 0).cloned().collect::<Vec<&str>>().join(" ")
+                ).as_str(), &mut sql, is_retry, opts);
+                ts.fks.insert(ff.name.clone(), ff.clone());
+            }
         }
 
         match dry_run {
             Some(store) => {
-                store(vec![sql]).map(|_| false)
+                match opts.plan {
+                    Some(plan) => plan(PlanEntry {
+                        schema: schema.clone(),
+                        table: self.table_name.clone(),
+                        source_file: file.to_string(),
+                        ddl: sql,
+                        ..Default::default()
+                    }),
+                    None => store(vec![sql]),
+                }.map(|_| false)
             }
             None => {
                 if exec {
                     if let Err(e) = db.batch_execute(sql.as_str()) {
                         return Err(format!("DB FK execute [{}]: {} source: {}", sql, e, file));
                     }
-                    Ok(exec)
-                } else {
-                    Ok(true)
                 }
+                Ok(exec)
             }
         }
     }
@@ -582,34 +1588,394 @@ impl Table {
         self.transaction.as_str() == "table"
             || self.transaction.as_str() == "retry"
     }
+
+    /// isolation level required to deploy the table, if any
+    pub fn isolation_level(&self) -> Option<&str> {
+        self.isolation_level.as_deref().filter(|_| self.is_table_transaction())
+    }
 }
 
 impl YGrant {
-    fn new(input: Option<&Array>) -> Vec<Self> {
+    pub(crate) fn new(input: Option<&Array>) -> Vec<Self> {
         let mut data = Vec::new();
         if let Some(vv) = input {
             for v in vv {
+                let columns = crate::utils::as_vec_str(v, "columns").into_iter()
+                    .map(crate::utils::sql_identifier)
+                    .collect();
                 data.push(YGrant {
-                    all: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "all")),
-                    select: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "select")),
-                    insert: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "insert")),
-                    update: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "update")),
-                    delete: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "delete")),
-                    truncate: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "truncate")),
-                    references: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "references")),
-                    trigger: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "trigger")),
-                    create: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "create")),
-                    connect: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "connect")),
-                    temporary: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "temporary")),
-                    execute: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "execute")),
-                    usage: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "usage")),
+                    all: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "all")),
+                    select: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "select")),
+                    insert: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "insert")),
+                    update: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "update")),
+                    delete: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "delete")),
+                    truncate: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "truncate")),
+                    references: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "references")),
+                    trigger: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "trigger")),
+                    create: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "create")),
+                    connect: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "connect")),
+                    temporary: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "temporary")),
+                    execute: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "execute")),
+                    usage: crate::utils::sql_identifier_list(crate::utils::as_str_esc(v, "usage")),
                     with_grant_option: crate::utils::as_bool(v, "with_grant_option", false),
-                    by: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "by")),
+                    by: crate::utils::sql_identifier(crate::utils::as_str_esc(v, "by")),
+                    columns,
+                    scope: crate::utils::as_str_esc(v, "scope").trim().to_lowercase(),
                 });
             }
         }
         data
     }
+
+    /// privilege and the roles to grant it, set in yaml
+    pub fn privileges(&self) -> Vec<(&'static str, &String)> {
+        vec![("ALL", &self.all), ("SELECT", &self.select), ("INSERT", &self.insert), ("UPDATE", &self.update),
+             ("DELETE", &self.delete), ("TRUNCATE", &self.truncate), ("REFERENCES", &self.references),
+             ("TRIGGER", &self.trigger), ("CREATE", &self.create), ("CONNECT", &self.connect),
+             ("TEMPORARY", &self.temporary), ("EXECUTE", &self.execute), ("USAGE", &self.usage)]
+            .into_iter()
+            .filter(|(_, roles)| roles.len() > 0)
+            .collect()
+    }
+}
+
+/// table node of yaml, the file is not known for the error reference
+impl TryFrom<&Yaml> for Table {
+    type Error = String;
+
+    fn try_from(input: &Yaml) -> Result<Self, Self::Error> {
+        match input["tableName"].as_str() {
+            None => Err("no table name set".to_string()),
+            Some(tn) => Table::new(input, tn, None),
+        }
+    }
+}
+
+/// table definition as loaded from DB, triggers and grants are not included
+impl From<&PgTable> for Table {
+    fn from(pg: &PgTable) -> Self {
+        let mut pcs: Vec<_> = pg.columns.values().collect();
+        pcs.sort_by_key(|c| c.sort_order);
+        let mut columns = OrderedHashMap::new();
+        for c in pcs {
+            let _ = columns.append(Column::from(c));
+        }
+        // table level checks as named on deploy
+        let mut checks: Vec<(usize, &String)> = pg.checks.iter()
+            .filter_map(|(name, expr)| constraint_position("chk", &pg.table_name, name, pg.checks.len())
+                .map(|i| (i, expr)))
+            .collect();
+        checks.sort();
+        let checks: Vec<String> = checks.into_iter().map(|(_, expr)| expr.clone()).collect();
+        let mut excludes: Vec<(usize, YExclude)> = pg.excludes.iter()
+            .filter_map(|(name, def)| constraint_position("exc", &pg.table_name, name, pg.excludes.len())
+                .and_then(|i| YExclude::parse(def).map(|e| (i, e))))
+            .collect();
+        excludes.sort_by_key(|(i, _)| *i);
+        let excludes: Vec<YExclude> = excludes.into_iter().map(|(_, e)| e).collect();
+        for c in &mut columns.list {
+            if let Some(cs) = c.constraint.as_mut() {
+                if cs.check_expr.as_ref().map_or(false, |e| checks.contains(e)) {
+                    cs.check_expr = None;
+                }
+            }
+        }
+        // column indexes, except primary key, unique constraints and expressions
+        let mut indexes: Vec<_> = pg.indexes.values()
+            .filter(|i| !i.index_name.ends_with("_pkey") && !i.index_name.ends_with("_key"))
+            .filter(|i| i.columns.iter().all(|c| columns.map.contains_key(&c.column_name)))
+            .collect();
+        indexes.sort_by(|a, b| a.index_name.cmp(&b.index_name));
+        for i in indexes {
+            for (n, ic) in i.columns.iter().enumerate() {
+                if let Some(c) = columns.get_mut(&ic.column_name) {
+                    if c.index.is_none() {
+                        c.index = Some(Index {
+                            name: i.index_name.clone(),
+                            where_clause: if n == 0 { i.where_clause.clone() } else { None },
+                            include: if n == 0 { i.include_columns.clone() } else { vec![] },
+                            tablespace: i.tablespace.clone().filter(|t| t != DEFAULT_TABLESPACE),
+                            with_options: if n == 0 { i.with_options.clone() } else { BTreeMap::new() },
+                            unique: i.is_unique,
+                            using: if i.index_method != DEFAULT_INDEX_METHOD { i.index_method.clone() } else { "".to_string() },
+                            opclass: ic.opclass.clone(),
+                            order: ic.order.clone(),
+                            nulls: ic.nulls.clone(),
+                            collate: ic.collation.clone(),
+                            nulls_not_distinct: i.nulls_not_distinct,
+                            ..Index::default()
+                        });
+                    }
+                }
+            }
+        }
+        Table {
+            table_name: pg.table_name.clone(),
+            description: pg.table_comment.clone().unwrap_or_default(),
+            unlogged: pg.persistence == 'u',
+            tablespace: pg.tablespace.clone().filter(|t| t != DEFAULT_TABLESPACE),
+            with_options: pg.with_options.clone(),
+            inherits: pg.parents.clone(),
+            replica_identity: Some(pg.replica_identity.clone()).filter(|r| r != &ReplicaIdentity::Default),
+            partition_by: pg.partition_key.as_ref().and_then(|k| YPartition::parse(k)),
+            checks,
+            excludes,
+            columns,
+            owner: pg.owner.clone().unwrap_or_default(),
+            row_security: pg.row_security,
+            security_label: pg.security_labels.iter().min()
+                .map(|(provider, label)| SecurityLabel { provider: provider.clone(), label: label.clone() }),
+            ..Table::default()
+        }
+    }
+}
+
+impl ReplicaIdentity {
+    /// either default, nothing, full or index name as {index: name}
+    fn new(input: &Yaml) -> Option<Self> {
+        match input.as_str() {
+            Some(s) => match s.trim().to_lowercase().as_str() {
+                "default" => Some(ReplicaIdentity::Default),
+                "nothing" => Some(ReplicaIdentity::Nothing),
+                "full" => Some(ReplicaIdentity::Full),
+                _ => None,
+            },
+            None => input["index"].as_str()
+                .map(|i| crate::utils::safe_sql_name(i.trim().to_string()))
+                .filter(|i| i.len() > 0)
+                .map(ReplicaIdentity::Index),
+        }
+    }
+
+    /// from pg_class.relreplident: d, n, f or i with the index name
+    pub fn parse(relreplident: &str, index: Option<&str>) -> Self {
+        match (relreplident, index) {
+            ("n", _) => ReplicaIdentity::Nothing,
+            ("f", _) => ReplicaIdentity::Full,
+            ("i", Some(i)) => ReplicaIdentity::Index(i.to_string()),
+            _ => ReplicaIdentity::Default,
+        }
+    }
+
+    pub fn sql(&self) -> String {
+        match self {
+            ReplicaIdentity::Default => "DEFAULT".to_string(),
+            ReplicaIdentity::Nothing => "NOTHING".to_string(),
+            ReplicaIdentity::Full => "FULL".to_string(),
+            ReplicaIdentity::Index(i) => format!("USING INDEX {}", i),
+        }
+    }
+}
+
+/// as in yaml: default, nothing, full or {index: name}
+impl Serialize for ReplicaIdentity {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        match self {
+            ReplicaIdentity::Index(i) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("index", i)?;
+                map.end()
+            }
+            _ => serializer.serialize_str(self.sql().to_lowercase().as_str()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ReplicaIdentity {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Name(String),
+            Index { index: String },
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Name(n) => ReplicaIdentity::new(&Yaml::String(n.clone()))
+                .ok_or_else(|| serde::de::Error::custom(format!("unknown replica identity: {}", n))),
+            Repr::Index { index } => Ok(ReplicaIdentity::Index(index)),
+        }
+    }
+}
+
+impl YPartition {
+    /// from pg_get_partkeydef, i.e. RANGE (created_at)
+    fn parse(key_def: &str) -> Option<Self> {
+        let i = key_def.find('(')?;
+        let key = key_def[i + 1..].trim_end().trim_end_matches(')');
+        Some(YPartition {
+            method: key_def[..i].trim().to_string(),
+            key: key.split(',').map(|k| k.trim().to_string()).collect(),
+        })
+    }
+
+    fn new(input: &Yaml) -> Option<Self> {
+        let method = crate::utils::as_str_esc(input, "method").to_uppercase();
+        if method.len() == 0 {
+            return None;
+        }
+        let key = crate::utils::as_vec_str(input, "key").iter().map(|k| crate::utils::as_esc(k)).collect();
+        Some(YPartition { method, key })
+    }
+
+    /// as pg_get_partkeydef, i.e. RANGE (created_at)
+    pub fn key_def(&self) -> String {
+        format!("{} ({})", self.method, self.key.join(", "))
+    }
+}
+
+impl YPartitionOf {
+    fn new(input: &Yaml) -> Option<Self> {
+        let parent = crate::utils::as_str_esc(input, "parent");
+        if parent.len() == 0 {
+            None
+        } else {
+            Some(YPartitionOf { parent, for_values: crate::utils::as_str_esc(input, "for_values") })
+        }
+    }
+}
+
+impl YExclude {
+    fn new(input: Option<&Array>) -> Vec<Self> {
+        let mut data = Vec::new();
+        if let Some(vv) = input {
+            for v in vv {
+                let mut elements = Vec::new();
+                if let Some(ee) = v["elements"].as_vec() {
+                    for e in ee {
+                        elements.push(ExcludeElement {
+                            column_or_expr: crate::utils::as_str_esc(e, "column_or_expr"),
+                            operator: crate::utils::as_str_esc(e, "operator"),
+                        });
+                    }
+                }
+                let using = crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "using")).to_lowercase();
+                let where_clause = crate::utils::as_str_esc(v, "where_clause");
+                data.push(YExclude {
+                    using: if using.len() > 0 { using } else { "gist".to_string() },
+                    elements,
+                    where_clause: if where_clause.len() > 0 { Some(where_clause) } else { None },
+                });
+            }
+        }
+        data
+    }
+
+    /// from pg_get_constraintdef, i.e. EXCLUDE USING gist (room WITH =, during WITH &&) WHERE ((room > 0))
+    fn parse(def: &str) -> Option<Self> {
+        let rest = def.strip_prefix("EXCLUDE USING ")?;
+        let open = rest.find('(')?;
+        let mut depth = 0;
+        let mut start = open + 1;
+        let mut close = None;
+        let mut parts = Vec::new();
+        for (i, ch) in rest[open..].char_indices().map(|(i, ch)| (i + open, ch)) {
+            match ch {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(i);
+                        break;
+                    }
+                }
+                ',' if depth == 1 => {
+                    parts.push(&rest[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        let close = close?;
+        parts.push(&rest[start..close]);
+        let mut elements = Vec::new();
+        for p in parts {
+            let (column_or_expr, operator) = p.rsplit_once(" WITH ")?;
+            elements.push(ExcludeElement { column_or_expr: column_or_expr.trim().to_string(), operator: operator.trim().to_string() });
+        }
+        let where_clause = rest[close + 1..].trim().strip_prefix("WHERE ")
+            .map(|w| w.strip_prefix('(').and_then(|w| w.strip_suffix(')')).unwrap_or(w).to_string());
+        Some(YExclude { using: rest[..open].trim().to_string(), elements, where_clause })
+    }
+
+    /// as pg_get_constraintdef, i.e. EXCLUDE USING gist (room WITH =, during WITH &&)
+    pub fn def(&self) -> String {
+        let elements: Vec<String> = self.elements.iter()
+            .map(|e| format!("{} WITH {}", e.column_or_expr, e.operator))
+            .collect();
+        let mut def = format!("EXCLUDE USING {} ({})", self.using, elements.join(", "));
+        if let Some(w) = &self.where_clause {
+            let _ = write!(def, " WHERE ({})", w);
+        }
+        def
+    }
+}
+
+impl YPolicy {
+    fn new(input: Option<&Array>) -> Vec<Self> {
+        let mut data = Vec::new();
+        if let Some(vv) = input {
+            for v in vv {
+                let to_roles = crate::utils::as_vec_str(v, "to_roles").into_iter()
+                    .map(crate::utils::safe_sql_name)
+                    .collect();
+                let command = crate::utils::as_str_esc(v, "command").to_uppercase();
+                let using_expr = crate::utils::as_str_esc(v, "using_expr");
+                let check_expr = crate::utils::as_str_esc(v, "check_expr");
+                data.push(YPolicy {
+                    name: crate::utils::safe_sql_name(crate::utils::as_str_esc(v, "name")),
+                    command: if command.len() > 0 { command } else { "ALL".to_string() },
+                    permissive: crate::utils::as_bool(v, "permissive", true),
+                    to_roles,
+                    using_expr: if using_expr.len() > 0 { Some(using_expr) } else { None },
+                    check_expr: if check_expr.len() > 0 { Some(check_expr) } else { None },
+                });
+            }
+        }
+        data
+    }
+
+    fn roles(&self) -> Vec<String> {
+        if self.to_roles.len() > 0 { self.to_roles.clone() } else { vec!["public".to_string()] }
+    }
+
+    pub(crate) fn create_sql(&self, schema: &str, table_name: &str) -> String {
+        let mut sql = format!("CREATE POLICY {} ON {}.{} AS {} FOR {} TO {}",
+                              self.name, schema, table_name,
+                              if self.permissive { "PERMISSIVE" } else { "RESTRICTIVE" },
+                              self.command, self.roles().join(", "));
+        if let Some(u) = &self.using_expr {
+            let _ = write!(sql, " USING ({})", u);
+        }
+        if let Some(c) = &self.check_expr {
+            let _ = write!(sql, " WITH CHECK ({})", c);
+        }
+        sql
+    }
+
+    /// compare to the policy loaded from DB, expressions as deparsed by PostgreSQL are compared loosely
+    pub(crate) fn matches(&self, pg: &PgPolicy) -> bool {
+        let mut roles = self.roles();
+        let mut pg_roles = pg.roles.clone();
+        roles.sort();
+        pg_roles.sort();
+        self.command == pg.command
+            && self.permissive == pg.permissive
+            && roles == pg_roles
+            && self.using_expr.as_ref().map(|e| normalize_expr(e)) == pg.using_expr.as_ref().map(|e| normalize_expr(e))
+            && self.check_expr.as_ref().map(|e| normalize_expr(e)) == pg.check_expr.as_ref().map(|e| normalize_expr(e))
+    }
+
+    fn to_pg(&self) -> PgPolicy {
+        PgPolicy {
+            name: self.name.clone(),
+            command: self.command.clone(),
+            permissive: self.permissive,
+            roles: self.roles(),
+            using_expr: self.using_expr.clone(),
+            check_expr: self.check_expr.clone(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -619,22 +1985,41 @@ enum CreateST {
     TableOnly,
 }
 
-fn append(sql: &str, buff: &mut String, retry: bool) {
+pub(crate) fn append(sql: &str, buff: &mut String, retry: bool, opts: &MigrationOptions) {
     if retry {
-        buff.push_str(RPT1);
-        buff.push_str(sql);
-        buff.push_str(RPT2);
+        match &opts.retry_template {
+            Some(template) => buff.push_str(template.replace(EXECUTE_SQL, sql).as_str()),
+            None => buff.push_str(retry_block(sql, opts).as_str()),
+        }
     } else {
         buff.push_str(sql);
         buff.push_str(";\n");
     }
 }
 
-const RPT1: &str = r#"DO
+/// name of the n-th table level constraint as created on deploy, i.e. chk_orders_1
+fn constraint_name(prefix: &str, table: &str, n: usize) -> String {
+    crate::utils::pg_identifier(format!("{}_{}_{}", prefix, name_part(table).to_lowercase(), n))
+}
+
+/// the position of a constraint named on deploy, the names cut to the identifier length searched up to max
+fn constraint_position(prefix: &str, table: &str, name: &str, max: usize) -> Option<usize> {
+    name.strip_prefix(format!("{}_{}_", prefix, name_part(table).to_lowercase()).as_str())
+        .and_then(|n| n.parse::<usize>().ok())
+        .filter(|n| constraint_name(prefix, table, *n) == name)
+        .or_else(|| (1..=max).find(|n| constraint_name(prefix, table, *n) == name))
+}
+
+/// placeholder of the statement in a retry template
+pub const EXECUTE_SQL: &str = "$EXECUTE_SQL$";
+
+/// execute the statement with a lock timeout, repeat on the lock not available
+fn retry_block(sql: &str, opts: &MigrationOptions) -> String {
+    format!(r#"DO
 $do$
 DECLARE
-   lock_timeout CONSTANT text := '1000ms';
-   max_attempts CONSTANT INT := 100;
+   lock_timeout CONSTANT text := '{}ms';
+   max_attempts CONSTANT INT := {};
    ddl_completed BOOLEAN := FALSE;
 BEGIN
 
@@ -642,9 +2027,7 @@ BEGIN
 
    FOR i IN 1..max_attempts LOOP
       BEGIN
-         EXECUTE '"#;
-
-const RPT2: &str = r#"';
+         EXECUTE $ddl${}$ddl$;
          ddl_completed := TRUE;
          EXIT;
       EXCEPTION
@@ -660,20 +2043,1510 @@ const RPT2: &str = r#"';
    END IF;
 END
 $do$;
-"#;
+"#, opts.retry_lock_timeout_ms, opts.retry_max_attempts, sql)
+}
+
+
+/// INCLUDING options of CREATE TABLE (LIKE ...)
+pub const LIKE_OPTIONS: [&str; 10] = ["COMMENTS", "COMPRESSION", "CONSTRAINTS", "DEFAULTS", "GENERATED", "IDENTITY",
+    "INDEXES", "STATISTICS", "STORAGE", "ALL"];
+
+/// transaction isolation levels from the weakest
+pub const ISOLATION_LEVELS: [&str; 4] = ["READ UNCOMMITTED", "READ COMMITTED", "REPEATABLE READ", "SERIALIZABLE"];
+
+/// temporary table to COPY the data rows into
+const COPY_STAGING: &str = "schema_guard_copy";
 
+/// data rows of a table to insert
+enum DataRows {
+    /// statement with the values to bind for each row
+    Insert(Vec<(String, Vec<String>)>),
+    Copy(CopyRows),
+    /// TRUNCATE statement before the rows
+    Truncate(String, Box<DataRows>),
+}
+
+/// COPY does not support ON CONFLICT, so the rows copied into a staging table first
+struct CopyRows {
+    create: String,
+    copy: String,
+    csv: String,
+    insert: String,
+    drop: String,
+}
+
+impl DataRows {
+    /// as it would be executed, COPY in the psql notation, for dry run
+    fn sql(&self) -> String {
+        match self {
+            DataRows::Insert(data) => rows_sql(data),
+            DataRows::Copy(c) => format!("{};\n\\{}\n{}\\.\n{};\n{};\n", c.create, c.copy, c.csv, c.insert, c.drop),
+            DataRows::Truncate(truncate, rows) => format!("{};\n{}", truncate, rows.sql()),
+        }
+    }
+
+    /// return count of rows inserted or updated
+    #[cfg(feature = "db")]
+    fn apply(&self, db: &mut dyn DbExec, source: &str) -> Result<usize, String> {
+        match self {
+            DataRows::Insert(data) => insert_rows(data, db, source),
+            DataRows::Copy(c) => {
+                let _ = db.batch_execute(c.create.as_str())
+                    .map_err(|e| format!("DB execute [{}]: {} {}", c.create, e, source))?;
+                let _ = db.copy_in(c.copy.as_str(), c.csv.as_bytes())
+                    .map_err(|e| format!("DB execute [{}]: {} {}", c.copy, e, source))?;
+                let inserted = db.execute(c.insert.as_str(), &[])
+                    .map_err(|e| format!("DB execute [{}]: {} {}", c.insert, e, source))? as usize;
+                let _ = db.batch_execute(c.drop.as_str())
+                    .map_err(|e| format!("DB execute [{}]: {} {}", c.drop, e, source))?;
+                Ok(inserted)
+            }
+            DataRows::Truncate(truncate, rows) => {
+                let _ = db.batch_execute(truncate.as_str())
+                    .map_err(|e| format!("DB execute [{}]: {} {}", truncate, e, source))?;
+                rows.apply(db, source)
+            }
+        }
+    }
+}
 
 #[inline]
-fn pks(schema: &String, table: &String, sks: &OrderedHashMap<Schema>) -> HashSet<String> {
-    let mut pk = HashSet::new();
+/// data rows as they would be executed, for dry run
+fn rows_sql(data: &[(String, Vec<String>)]) -> String {
+    let mut rows = String::new();
+    for (stmt, values) in data {
+        let _ = writeln!(rows, "{}; -- values: {:?}", stmt, values);
+    }
+    rows
+}
+
+/// data rows are conflict safe, so applied on every run to keep the seed in sync
+#[cfg(feature = "db")]
+fn insert_rows(data: &[(String, Vec<String>)], db: &mut dyn DbExec, source: &str) -> Result<usize, String> {
+    let mut inserted = 0;
+    for (stmt, values) in data {
+        let params: Vec<&(dyn ToSql + Sync)> = values.iter().map(|v| v as &(dyn ToSql + Sync)).collect();
+        inserted += db.execute(stmt.as_str(), &params[..])
+            .map_err(|e| format!("DB execute [{}] {:?}: {} {}", stmt, values, e, source))? as usize;
+    }
+    Ok(inserted)
+}
+
+fn pks(schema: &String, table: &String, sks: &OrderedHashMap<Schema>) -> Vec<String> {
+    let mut pk = Vec::new();
     if let Some(fkst) = sks.get(schema) {
         if let Some(tt) = fkst.tables.get(table) {
             for cc in &tt.columns.list {
                 if cc.is_pk() {
-                    pk.insert(cc.get_name());
+                    pk.push(cc.get_name());
                 }
             }
         }
     }
     pk
 }
+
+#[cfg(all(test, feature = "db"))]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::column::{ColumnStorage, FkAction, FkMatch};
+    use crate::db::{dry_run, NoDb};
+    use crate::{load_schema_from_file, load_schema_from_src, parse_yaml_schema, validate_schema};
+    use crate::loader::{normalize_default, FKTable, InfoSchemaType, PgIndex, PgIndexColumn, PgPolicy, PgTable};
+    use crate::options::MigrationOptions;
+    use crate::table::{ReplicaIdentity, Table};
+
+    /// statements of a table deploy dry run on test_schema, one entry per callback
+    fn dry_run_sql(table: &Table, info: &mut InfoSchemaType, opts: &MigrationOptions) -> Result<Vec<String>, String> {
+        dry_run(|store| table.deploy(info, &mut NoDb, &"test_schema".to_string(), false, "", Some(store), opts)).map(|(_, sql)| sql)
+    }
+
+    #[test]
+    fn test_insert_params() {
+        let r = parse_yaml_schema(load_schema_from_file("tests/example.yaml").unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        let (sql, values) = t.insert(t.data.get(0).unwrap(), &"test_schema".to_string());
+        assert_eq!(sql.as_str(), "insert into test_schema.test_table (id, test) values ($1::text::integer, $2::text::varchar(250)) ON CONFLICT (id) DO NOTHING");
+        assert_eq!(values, vec!["1".to_string(), "test1".to_string()]);
+    }
+
+    #[test]
+    fn test_insert_conflict_update() {
+        let mut r = parse_yaml_schema(load_schema_from_file("tests/example.yaml").unwrap(), "").unwrap();
+        let t = r.list.get_mut(0).unwrap().tables.list.get_mut(0).unwrap();
+        t.data_conflict = "update".to_string();
+        let (sql, _) = t.insert(t.data.get(1).unwrap(), &"test_schema".to_string());
+        assert!(sql.ends_with("ON CONFLICT (id) DO UPDATE SET test = EXCLUDED.test"));
+        t.data_conflict = "UPDATE SET test = 'x'".to_string();
+        let (sql, _) = t.insert(t.data.get(1).unwrap(), &"test_schema".to_string());
+        assert!(sql.ends_with("ON CONFLICT (id) DO UPDATE SET test = 'x'"));
+    }
+
+    #[test]
+    fn test_data_validation() {
+        let src = |conflict: &str, row: &str| format!(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          data_conflict: {}
+          columns:
+            - column:
+                name: id
+                type: integer
+            - column:
+                name: name
+                type: text
+          data:
+            - {}
+"#, conflict, row);
+        let err = parse_yaml_schema(load_schema_from_src(src("nothing", "[1, a, extra]")).unwrap(), "t.yaml").unwrap_err();
+        assert_eq!(err.as_str(), "table: test_table data row 1 has 3 values, but 2 columns defined, found in file: t.yaml");
+        let err = parse_yaml_schema(load_schema_from_src(src("UPDATE SET name = 'x'", "[1, a]")).unwrap(), "").unwrap_err();
+        assert_eq!(err.as_str(), "table: test_table data_conflict UPDATE SET name = 'x' requires a primary key as the conflict target, found in file: ");
+        let r = parse_yaml_schema(load_schema_from_src(src("update", "[1, a]")).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        assert!(t.insert(t.data.get(0).unwrap(), &"test_schema".to_string()).0.ends_with(" ON CONFLICT DO NOTHING"));
+    }
+
+    #[test]
+    fn test_checks() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          checks:
+            - lo < hi
+          columns:
+            - column:
+                name: lo
+                type: integer
+                constraint:
+                  check: lo > 0
+            - column:
+                name: hi
+                type: integer
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        assert_eq!(t.checks, vec!["lo < hi".to_string()]);
+        let s = "test_schema".to_string();
+        let c = t.columns.list.get(0).unwrap().column_def(&s, &t.table_name, "").unwrap();
+        assert_eq!(c.def(false).as_str(), "lo integer CHECK (lo > 0)");
+        let c = t.columns.list.get(1).unwrap().column_def(&s, &t.table_name, "").unwrap();
+        assert_eq!(c.def(false).as_str(), "hi integer");
+    }
+
+    #[test]
+    fn test_checks_changed() {
+        let src = r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          checks:
+            - lo < hi
+            - hi BETWEEN 1 AND 100
+          columns:
+            - column:
+                name: lo
+                type: integer
+            - column:
+                name: hi
+                type: integer
+"#;
+        let r = parse_yaml_schema(load_schema_from_src(src.to_string()).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        let opts = MigrationOptions::default();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let mut pt = PgTable { table_name: "test_table".to_string(), ..PgTable::default() };
+        for (i, c) in t.columns.list.iter().enumerate() {
+            let mut cd = c.column_def(&"test_schema".to_string(), &t.table_name, "").unwrap();
+            cd.sort_order = i + 1;
+            pt.columns.insert(c.name.clone(), cd);
+        }
+        // as loaded from DB, deparsed and in the other order
+        pt.checks.insert("chk_test_table_1".to_string(), "(((hi >= 1) AND (hi <= 100)))".to_string());
+        pt.checks.insert("chk_test_table_2".to_string(), "((lo < hi))".to_string());
+        pt.checks.insert("test_table_lo_check".to_string(), "((lo > 0))".to_string());
+        info.insert("test_schema".to_string(), HashMap::from([("test_table".to_string(), pt.clone())]));
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+
+        // the changed expression dropped and added, the removed dropped, the column check kept
+        let r = parse_yaml_schema(load_schema_from_src(src.replace("            - hi BETWEEN 1 AND 100\n", "")
+            .replace("lo < hi", "lo <= hi")).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.test_table DROP CONSTRAINT chk_test_table_1;\n\
+            ALTER TABLE test_schema.test_table DROP CONSTRAINT chk_test_table_2;\n\
+            ALTER TABLE test_schema.test_table ADD CONSTRAINT chk_test_table_1 CHECK (lo <= hi);\n");
+        let pt = &info["test_schema"]["test_table"];
+        assert_eq!(pt.checks.len(), 2);
+        assert_eq!(Table::from(pt).checks, vec!["lo <= hi".to_string()]);
+        assert_eq!(dry_run_sql(t, &mut info, &opts).unwrap()[0].as_str(), "");
+    }
+
+    #[test]
+    fn test_policies() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          row_security: true
+          policies:
+            - name: tenant_isolation
+              command: select
+              to_roles: [app_user]
+              using_expr: tenant_id = current_setting('app.tenant')::integer
+          columns:
+            - column:
+                name: tenant_id
+                type: integer
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        assert!(t.row_security);
+        let p = t.policies.get(0).unwrap();
+        assert_eq!(p.create_sql("test_schema", &t.table_name).as_str(),
+                   "CREATE POLICY tenant_isolation ON test_schema.test_table AS PERMISSIVE FOR SELECT TO app_user USING (tenant_id = current_setting('app.tenant')::integer)");
+        let mut pg = PgPolicy {
+            name: "tenant_isolation".to_string(),
+            command: "SELECT".to_string(),
+            permissive: true,
+            roles: vec!["app_user".to_string()],
+            using_expr: Some("(tenant_id = current_setting('app.tenant')::integer)".to_string()),
+            check_expr: None,
+        };
+        assert!(p.matches(&pg));
+        pg.permissive = false;
+        assert!(!p.matches(&pg));
+    }
+
+    #[test]
+    fn test_unlogged() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          unlogged: true
+          columns:
+            - column:
+                name: id
+                type: integer
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        assert_eq!(t.persistence(), 'u');
+        let sql = t.create_sql(&"test_schema".to_string(), &"id integer".to_string(), false);
+        assert!(sql.starts_with("CREATE UNLOGGED TABLE test_schema.test_table (id integer)"));
+    }
+
+    #[test]
+    fn test_identity() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: bigint
+                identity: always
+                identity_seq_options: (START 100)
+                constraint:
+                  primaryKey: true
+            - column:
+                name: name
+                type: text
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        let s = "test_schema".to_string();
+        let c = t.columns.list.get(0).unwrap().column_def(&s, &t.table_name, "").unwrap();
+        assert_eq!(c.def(true).as_str(), "id bigint GENERATED ALWAYS AS IDENTITY (START 100)");
+        let (sql, _) = t.insert(&vec!["1".to_string(), "a".to_string()], &s);
+        assert!(sql.contains("(id, name) OVERRIDING SYSTEM VALUE values ("));
+    }
+
+    #[test]
+    fn test_partitions() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: events_2024
+          partition_of:
+            parent: events
+            for_values: FROM ('2024-01-01') TO ('2025-01-01')
+      - table:
+          tableName: events
+          partition_by:
+            method: range
+            key: [created_at]
+          columns:
+            - column:
+                name: created_at
+                type: date
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let s = r.list.get(0).unwrap();
+        let child = s.tables.list.get(0).unwrap();
+        let parent = s.tables.list.get(1).unwrap();
+        assert_eq!(child.partition_level(&s.tables), 1);
+        assert_eq!(parent.partition_level(&s.tables), 0);
+        let schema = "test_schema".to_string();
+        assert!(parent.create_sql(&schema, &"created_at date".to_string(), false)
+            .starts_with("CREATE TABLE test_schema.events (created_at date) PARTITION BY RANGE (created_at);"));
+        assert!(child.create_sql(&schema, &"".to_string(), false)
+            .starts_with("CREATE TABLE test_schema.events_2024 PARTITION OF test_schema.events FOR VALUES FROM ('2024-01-01') TO ('2025-01-01');"));
+    }
+
+    #[test]
+    fn test_fk_match_full() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: parent
+          columns:
+            - column:
+                name: a
+                type: integer
+                constraint:
+                  primaryKey: true
+            - column:
+                name: b
+                type: integer
+                constraint:
+                  primaryKey: true
+      - table:
+          tableName: child
+          columns:
+            - column:
+                name: pa
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: parent
+                    name: fk_child_parent
+                    match: full
+            - column:
+                name: pb
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: parent
+                    name: fk_child_parent
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"child".to_string()).unwrap();
+        let s = "test_schema".to_string();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let mut tables = HashMap::new();
+        tables.insert("child".to_string(), PgTable { table_name: "child".to_string(), ..PgTable::default() });
+        info.insert(s.clone(), tables);
+        let (_, out) = dry_run(|store| t.deploy_fk(&r, &mut info, &mut NoDb, &s, false, "", Some(store), &MigrationOptions::default())).unwrap();
+        assert_eq!(out[0].as_str(),
+                   "ALTER TABLE test_schema.child ADD CONSTRAINT fk_child_parent FOREIGN KEY (pa, pb) REFERENCES test_schema.parent (a, b) MATCH FULL;\n");
+
+        // loaded as simple
+        let fks = &mut info.get_mut(&s).unwrap().get_mut("child").unwrap().fks;
+        fks.get_mut("fk_child_parent").unwrap().match_type = FkMatch::Simple;
+        let (_, out) = dry_run(|store| t.deploy_fk(&r, &mut info, &mut NoDb, &s, false, "", Some(store), &MigrationOptions::default())).unwrap();
+        assert!(out[0].starts_with("ALTER TABLE test_schema.child DROP CONSTRAINT fk_child_parent;"));
+        let (_, out) = dry_run(|store| t.deploy_fk(&r, &mut info, &mut NoDb, &s, false, "", Some(store), &MigrationOptions::default())).unwrap();
+        assert_eq!(out[0].as_str(), "");
+    }
+
+    #[test]
+    fn test_comments_on_alter() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          description: the table
+          columns:
+            - column:
+                name: id
+                type: integer
+                description: the id
+            - column:
+                name: name
+                type: text
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap();
+        let s = "test_schema".to_string();
+        let mut pt = PgTable { table_name: "test_table".to_string(), ..PgTable::default() };
+        for c in &t.columns.list {
+            let mut cd = c.column_def(&s, &t.table_name, "").unwrap();
+            cd.column_comment = None;
+            pt.columns.insert(c.name.clone(), cd);
+        }
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert(s.clone(), HashMap::from([("test_table".to_string(), pt)]));
+        let opts = MigrationOptions::default();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[1].as_str(),
+                   "COMMENT ON COLUMN test_schema.test_table.id IS 'the id';\nCOMMENT ON TABLE test_schema.test_table IS 'the table'; \n\n");
+
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[1].as_str(), "");
+    }
+
+    #[test]
+    fn test_not_null_change() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+            - column:
+                name: required
+                type: text
+                constraint:
+                  nullable: false
+            - column:
+                name: optional
+                type: text
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap();
+        let s = "test_schema".to_string();
+        let mut pt = PgTable { table_name: "test_table".to_string(), ..PgTable::default() };
+        for c in &t.columns.list {
+            let mut cd = c.column_def(&s, &t.table_name, "").unwrap();
+            // the other way around in DB, the primary key is NOT NULL
+            cd.nullable = !cd.nullable && !cd.pk;
+            pt.columns.insert(c.name.clone(), cd);
+        }
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert(s.clone(), HashMap::from([("test_table".to_string(), pt)]));
+        let out = dry_run_sql(t, &mut info.clone(), &MigrationOptions::default()).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.test_table ALTER COLUMN optional DROP NOT NULL;\n");
+        let report = t.deploy(&mut info.clone(), &mut NoDb, &s, false, "", None, &MigrationOptions::default()).unwrap().unwrap();
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].kind, crate::validate::WarningKind::NotNullMismatch);
+        assert_eq!(report.warnings[0].location.as_str(), "test_schema.test_table.required");
+
+        let opts = MigrationOptions::default().allow_not_null_change(true);
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.test_table ALTER COLUMN required SET NOT NULL;\n\
+ALTER TABLE test_schema.test_table ALTER COLUMN optional DROP NOT NULL;\n");
+
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+    }
+
+    #[test]
+    fn test_default_change() {
+        assert_eq!(normalize_default("'active'::character varying").as_str(), "active");
+        assert_eq!(normalize_default("'it''s'::text").as_str(), "it's");
+        assert_eq!(normalize_default("'-1'::integer").as_str(), "-1");
+        assert_eq!(normalize_default("('{}'::jsonb)").as_str(), "{}");
+        assert_eq!(normalize_default("'a::b'").as_str(), "a::b");
+        assert_eq!(normalize_default("CURRENT_TIMESTAMP"), normalize_default("current_timestamp"));
+        assert_eq!(normalize_default("nextval('s.seq'::regclass)").as_str(), "nextval('s.seq')");
+        assert_eq!(normalize_default("'2020-01-01 00:00:00'::timestamp without time zone").as_str(), "2020-01-01 00:00:00");
+        assert_eq!(normalize_default("'a'::text || 'b'::text").as_str(), "'a' || 'b'");
+        assert_eq!(normalize_default("(now() + '1 day'::interval)"), normalize_default("now() + '1 day'"));
+
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: serial
+            - column:
+                name: status
+                type: varchar(10)
+                defaultValue: "'inactive'"
+            - column:
+                name: created
+                type: timestamp
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap();
+        let s = "test_schema".to_string();
+        let mut pt = PgTable { table_name: "test_table".to_string(), ..PgTable::default() };
+        for (c, default) in t.columns.list.iter().zip(["nextval('test_schema.test_table_id_seq'::regclass)", "'active'::character varying", "now()"]) {
+            let mut cd = c.column_def(&s, &t.table_name, "").unwrap();
+            cd.column_default = Some(default.to_string());
+            pt.columns.insert(c.name.clone(), cd);
+        }
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert(s.clone(), HashMap::from([("test_table".to_string(), pt)]));
+        let opts = MigrationOptions::default().allow_default_change(false);
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+
+        let opts = MigrationOptions::default();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.test_table ALTER COLUMN status SET DEFAULT 'inactive';\n\
+ALTER TABLE test_schema.test_table ALTER COLUMN created DROP DEFAULT;\n");
+
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+    }
+
+    #[test]
+    fn test_tablespace() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          tablespace: fast_ssd
+          columns:
+            - column:
+                name: id
+                type: integer
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap();
+        let s = "test_schema".to_string();
+        let opts = MigrationOptions::default();
+        let out = dry_run_sql(t, &mut BTreeMap::new(), &opts).unwrap();
+        assert!(out[0].contains(") TABLESPACE fast_ssd"));
+
+        let mut pt = PgTable { table_name: "test_table".to_string(), ..PgTable::default() };
+        let c = t.columns.list.get(0).unwrap();
+        pt.columns.insert(c.name.clone(), c.column_def(&s, &t.table_name, "").unwrap());
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert(s.clone(), HashMap::from([("test_table".to_string(), pt)]));
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.test_table SET TABLESPACE fast_ssd;\n");
+
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+    }
+
+    #[test]
+    fn test_column_reorder() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+            - column:
+                name: name
+                type: text
+            - column:
+                name: email
+                type: text
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap();
+        let s = "test_schema".to_string();
+        let mut pt = PgTable { table_name: "test_table".to_string(), ..PgTable::default() };
+        for (c, sort_order) in t.columns.list.iter().zip([1, 3, 2]) {
+            let mut cd = c.column_def(&s, &t.table_name, "").unwrap();
+            cd.sort_order = sort_order;
+            pt.columns.insert(c.name.clone(), cd);
+        }
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert(s.clone(), HashMap::from([("test_table".to_string(), pt)]));
+        let opts = MigrationOptions::default();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+
+        let opts = MigrationOptions::default().fail_on_column_reorder(true);
+        let err = dry_run_sql(t, &mut info, &opts).unwrap_err();
+        assert_eq!(err.as_str(), "Warning on test_schema.test_table: columns ordered as (id, email, name) in DB, but (id, name, email) in yaml in file: ");
+
+        info.get_mut(&s).unwrap().get_mut("test_table").unwrap().columns.get_mut("email").unwrap().sort_order = 4;
+        assert!(dry_run_sql(t, &mut info, &opts).is_ok());
+    }
+
+    #[test]
+    fn test_replica_identity() {
+        let src = |ri: &str| format!(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          replica_identity: {}
+          columns:
+            - column:
+                name: id
+                type: integer
+"#, ri);
+        let r = parse_yaml_schema(load_schema_from_src(src("full")).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap();
+        assert_eq!(t.replica_identity, Some(ReplicaIdentity::Full));
+        assert_eq!(serde_yaml::to_string(&ReplicaIdentity::Index("idx".to_string())).unwrap().as_str(), "index: idx\n");
+        assert_eq!(serde_yaml::from_str::<ReplicaIdentity>("full").unwrap(), ReplicaIdentity::Full);
+        let s = "test_schema".to_string();
+        let opts = MigrationOptions::default();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert!(out[0].ends_with("ALTER TABLE test_schema.test_table REPLICA IDENTITY FULL;\n"));
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+
+        let r = parse_yaml_schema(load_schema_from_src(src("{index: test_table_id_key}")).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap();
+        let err = dry_run_sql(t, &mut info, &opts).unwrap_err();
+        assert_eq!(err.as_str(), "table test_schema.test_table replica identity index test_table_id_key not found, as defined in file: ");
+        info.get_mut(&s).unwrap().get_mut("test_table").unwrap().indexes.insert("test_table_id_key".to_string(), PgIndex {
+            index_name: "test_table_id_key".to_string(),
+            is_unique: true,
+            is_valid: true,
+            nulls_not_distinct: false,
+            index_method: "btree".to_string(),
+            columns: vec![PgIndexColumn { column_name: "id".to_string(), opclass: String::new(), ..Default::default() }],
+            include_columns: vec![],
+            where_clause: None,
+            tablespace: None,
+            with_options: Default::default(),
+            comment: None,
+        });
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.test_table REPLICA IDENTITY USING INDEX test_table_id_key;\n");
+    }
+
+    #[test]
+    fn test_exclude() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: booking
+          excludes:
+            - using: gist
+              elements:
+                - column_or_expr: room
+                  operator: "="
+                - column_or_expr: during
+                  operator: "&&"
+              where_clause: not cancelled
+          columns:
+            - column:
+                name: room
+                type: integer
+            - column:
+                name: during
+                type: tsrange
+            - column:
+                name: cancelled
+                type: boolean
+"#.to_string()).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"booking".to_string()).unwrap();
+        assert_eq!(t.excludes.len(), 1);
+        let s = "test_schema".to_string();
+        let opts = MigrationOptions::default();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert!(out[0].contains("ALTER TABLE test_schema.booking ADD CONSTRAINT exc_booking_1 \
+            EXCLUDE USING gist (room WITH =, during WITH &&) WHERE (not cancelled);\n"));
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+
+        // as loaded from DB
+        let pt = info.get_mut(&s).unwrap().get_mut("booking").unwrap();
+        pt.excludes.insert("exc_booking_1".to_string(),
+                           "EXCLUDE USING gist (room WITH =, during WITH &&) WHERE ((NOT cancelled))".to_string());
+        let dumped = Table::from(&*pt);
+        assert_eq!(dumped.excludes.len(), 1);
+        assert_eq!(dumped.excludes[0].elements[1].column_or_expr.as_str(), "during");
+        assert_eq!(dumped.excludes[0].elements[1].operator.as_str(), "&&");
+        assert_eq!(dumped.excludes[0].where_clause.as_deref(), Some("(NOT cancelled)"));
+        assert_eq!(dry_run_sql(t, &mut info, &opts).unwrap()[0].as_str(), "");
+
+        // the changed exclusion dropped and added
+        let pt = info.get_mut(&s).unwrap().get_mut("booking").unwrap();
+        pt.excludes.insert("exc_booking_1".to_string(), "EXCLUDE USING gist (room WITH =, during WITH &&)".to_string());
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.booking DROP CONSTRAINT exc_booking_1;\n\
+            ALTER TABLE test_schema.booking ADD CONSTRAINT exc_booking_1 \
+            EXCLUDE USING gist (room WITH =, during WITH &&) WHERE (not cancelled);\n");
+    }
+
+    #[test]
+    fn test_column_storage() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: docs
+          columns:
+            - column:
+                name: id
+                type: integer
+            - column:
+                name: body
+                type: text
+                storage: external
+"#.to_string()).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"docs".to_string()).unwrap();
+        assert_eq!(t.columns.get(&"body".to_string()).unwrap().storage, Some(ColumnStorage::External));
+        let s = "test_schema".to_string();
+        let opts = MigrationOptions::default();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert!(out[0].ends_with("(id integer, body text ); \nALTER TABLE test_schema.docs ALTER COLUMN body SET STORAGE EXTERNAL;\n"));
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+
+        // as loaded from DB, the type default
+        info.get_mut(&s).unwrap().get_mut("docs").unwrap().columns.get_mut("body").unwrap().storage = Some('x');
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.docs ALTER COLUMN body SET STORAGE EXTERNAL;\n");
+    }
+
+    #[test]
+    fn test_column_statistics() {
+        let src = |n: i32| format!(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: facts
+          columns:
+            - column:
+                name: customer_id
+                type: integer
+                statistics: {}
+"#, n);
+        let err = parse_yaml_schema(load_schema_from_src(src(20000)).unwrap(), "").unwrap_err();
+        assert_eq!(err.as_str(), "column customer_id statistics 20000 out of range -1 to 10000 on table: facts, found in file: ");
+        let r = parse_yaml_schema(load_schema_from_src(src(1000)).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"facts".to_string()).unwrap();
+        let opts = MigrationOptions::default();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert!(out[0].ends_with("ALTER TABLE test_schema.facts ALTER COLUMN customer_id SET STATISTICS 1000;\n"));
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+
+        let r = parse_yaml_schema(load_schema_from_src(src(-1)).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"facts".to_string()).unwrap();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.facts ALTER COLUMN customer_id SET STATISTICS -1;\n");
+    }
+
+    #[test]
+    fn test_security_label() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: patients
+          security_label:
+            provider: selinux
+            label: system_u:object_r:sepgsql_table_t:s0
+          columns:
+            - column:
+                name: ssn
+                type: text
+                security_label:
+                  provider: selinux
+                  label: system_u:object_r:sepgsql_secret_table_t:s0
+"#.to_string()).unwrap(), "");
+        if cfg!(not(feature = "security_labels")) {
+            assert_eq!(r.unwrap_err().as_str(), "table: patients security labels require the security_labels feature, found in file: ");
+            return;
+        }
+        let r = r.unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"patients".to_string()).unwrap();
+        let opts = MigrationOptions::default();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert!(out[0].ends_with("\
+            SECURITY LABEL FOR selinux ON TABLE test_schema.patients IS 'system_u:object_r:sepgsql_table_t:s0';\n\
+            SECURITY LABEL FOR selinux ON COLUMN test_schema.patients.ssn IS 'system_u:object_r:sepgsql_secret_table_t:s0';\n"));
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+    }
+
+    #[test]
+    fn test_generated_column() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: items
+          columns:
+            - column:
+                name: price
+                type: numeric
+            - column:
+                name: qty
+                type: integer
+            - column:
+                name: total
+                type: numeric
+                generated_as: price * qty
+                constraint:
+                  nullable: false
+"#.to_string()).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"items".to_string()).unwrap();
+        let opts = MigrationOptions::default();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert!(out[0].contains("total numeric GENERATED ALWAYS AS (price * qty) STORED "), "{}", out[0]);
+        assert!(!out[0].contains("not null"));
+        // as deparsed by PostgreSQL
+        info.get_mut("test_schema").unwrap().get_mut("items").unwrap().columns.get_mut("total").unwrap()
+            .generated_as = Some("(price * (qty)::numeric)".to_string());
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+        info.get_mut("test_schema").unwrap().get_mut("items").unwrap().columns.get_mut("total").unwrap()
+            .generated_as = Some("(price * 2)".to_string());
+        let err = dry_run_sql(t, &mut info, &opts).unwrap_err();
+        assert!(err.starts_with("column test_schema.items.total generated as"), "{}", err);
+    }
+
+    #[test]
+    fn test_column_rename() {
+        let src = |name: &str| format!(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: people
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+            - column:
+                name: {}
+                type: text
+                rename_from: name
+"#, name);
+        let opts = MigrationOptions::default();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let r = parse_yaml_schema(load_schema_from_src(src("name")).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"people".to_string()).unwrap();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert!(out[0].contains("name text"));
+
+        let r = parse_yaml_schema(load_schema_from_src(src("full_name")).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"people".to_string()).unwrap();
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.people RENAME COLUMN name TO full_name;\n");
+        let pt = &info["test_schema"]["people"];
+        assert!(!pt.columns.contains_key("name"));
+        assert_eq!(pt.columns["full_name"].column_name.as_str(), "full_name");
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+    }
+
+    #[test]
+    fn test_storage_params() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          with:
+            fillfactor: 80
+            autovacuum_vacuum_scale_factor: 0.01
+            autovacuum_enabled: false
+          columns:
+            - column:
+                name: id
+                type: integer
+                index:
+                  with: {fillfactor: 90}
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"test_table".to_string()).unwrap();
+        let s = "test_schema".to_string();
+        let opts = MigrationOptions::default();
+        let out = dry_run_sql(t, &mut BTreeMap::new(), &opts).unwrap();
+        assert!(out[0].contains(") WITH (autovacuum_enabled=false, autovacuum_vacuum_scale_factor=0.01, fillfactor=80);"));
+        assert!(out[0].contains("CREATE INDEX idx_test_table_id ON test_schema.test_table (id) WITH (fillfactor=90)"));
+
+        let mut pt = PgTable { table_name: "test_table".to_string(), ..PgTable::default() };
+        let c = t.columns.list.get(0).unwrap();
+        pt.columns.insert(c.name.clone(), c.column_def(&s, &t.table_name, "").unwrap());
+        pt.with_options.insert("fillfactor".to_string(), "80".to_string());
+        pt.with_options.insert("autovacuum_enabled".to_string(), "true".to_string());
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert(s.clone(), HashMap::from([("test_table".to_string(), pt)]));
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert!(out[0].starts_with("ALTER TABLE test_schema.test_table SET (autovacuum_enabled=false, autovacuum_vacuum_scale_factor=0.01);\n"));
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+
+        let err = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          partition_by: {method: range, key: [id]}
+          with: {fillfactor: 80}
+          columns:
+            - column:
+                name: id
+                type: integer
+"#.to_string()).unwrap(), "").unwrap_err();
+        assert!(err.contains("partitioned table can not have storage parameters"));
+    }
+
+    #[test]
+    fn test_like_table() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_audit
+          like_table: test_table
+          like_options: [defaults, constraints]
+          columns:
+            - column:
+                name: audited_at
+                type: timestamp
+      - table:
+          tableName: test_archive
+          like_table: other.test_table
+          like_options: [all]
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let s = "test_schema".to_string();
+        let t = r.list.get(0).unwrap().tables.get(&"test_audit".to_string()).unwrap();
+        let sql = t.create_sql(&s, &"audited_at timestamp".to_string(), false);
+        assert!(sql.starts_with("CREATE TABLE test_schema.test_audit (LIKE test_schema.test_table INCLUDING DEFAULTS INCLUDING CONSTRAINTS, audited_at timestamp)"));
+        let t = r.list.get(0).unwrap().tables.get(&"test_archive".to_string()).unwrap();
+        let sql = t.create_sql(&s, &"".to_string(), false);
+        assert!(sql.starts_with("CREATE TABLE test_schema.test_archive (LIKE other.test_table INCLUDING ALL)"));
+
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert(s.clone(), HashMap::from([("test_archive".to_string(), PgTable { table_name: "test_archive".to_string(), ..PgTable::default() })]));
+        let report = t.deploy(&mut info, &mut NoDb, &s, false, "", None, &MigrationOptions::default()).unwrap().unwrap();
+        assert!(!report.altered);
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].kind, crate::validate::WarningKind::LikeOnExistingTable);
+
+        let err = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_audit
+          like_table: test_table
+          like_options: [everything]
+"#.to_string()).unwrap(), "").unwrap_err();
+        assert!(err.contains("like option EVERYTHING is not one of"));
+    }
+
+    #[test]
+    fn test_inherits() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_child
+          inherits: [test_parent, other.test_audited]
+          columns:
+            - column:
+                name: extra
+                type: text
+      - table:
+          tableName: test_parent
+          columns:
+            - column:
+                name: id
+                type: integer
+      - table:
+          tableName: test_log
+          inherits: [test_parent]
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let s = "test_schema".to_string();
+        let ts = r.list.get(0).unwrap();
+        let child = ts.tables.get(&"test_child".to_string()).unwrap();
+        let log = ts.tables.get(&"test_log".to_string()).unwrap();
+        assert!(child.create_sql(&s, &"extra text".to_string(), false)
+            .starts_with("CREATE TABLE test_schema.test_child (extra text) INHERITS (test_schema.test_parent, other.test_audited)"));
+        assert!(log.create_sql(&s, &"".to_string(), false).starts_with("CREATE TABLE test_schema.test_log () INHERITS (test_schema.test_parent)"));
+        assert_eq!(child.partition_level(&ts.tables), 1);
+        assert_eq!(ts.tables.get(&"test_parent".to_string()).unwrap().partition_level(&ts.tables), 0);
+
+        let mut pt = PgTable { table_name: "test_log".to_string(), ..PgTable::default() };
+        pt.parents.push("test_schema.test_parent".to_string());
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert(s.clone(), HashMap::from([("test_log".to_string(), pt)]));
+        assert!(log.deploy(&mut info, &mut NoDb, &s, false, "", None, &MigrationOptions::default()).unwrap().is_none());
+        info.get_mut(&s).unwrap().get_mut("test_log").unwrap().parents.push("test_schema.test_child".to_string());
+        let report = log.deploy(&mut info, &mut NoDb, &s, false, "", None, &MigrationOptions::default()).unwrap().unwrap();
+        assert_eq!(report.warnings[0].kind, crate::validate::WarningKind::InheritsMismatch);
+
+        let err = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_child
+          inherits: [test_parent]
+          partition_of: {parent: test_parent, for_values: DEFAULT}
+"#.to_string()).unwrap(), "").unwrap_err();
+        assert!(err.contains("can not inherit"));
+    }
+
+    #[test]
+    fn test_add_column_not_valid() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+            - column:
+                name: status
+                type: text
+                defaultValue: "'new'"
+                constraint:
+                  nullable: false
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let s = "test_schema".to_string();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        let mut pt = PgTable { table_name: "test_table".to_string(), ..PgTable::default() };
+        pt.columns.insert("id".to_string(), t.columns.list.get(0).unwrap().column_def(&s, &t.table_name, "").unwrap());
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert(s.clone(), HashMap::from([("test_table".to_string(), pt)]));
+
+        let opts = MigrationOptions::default().add_column_not_valid(true);
+        let out = dry_run_sql(t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.test_table ADD COLUMN status text default 'new', \
+            ADD CONSTRAINT test_table_status_not_null CHECK (status IS NOT NULL) NOT VALID;\n");
+        // after the commit
+        assert_eq!(out[3].as_str(), "ALTER TABLE test_schema.test_table VALIDATE CONSTRAINT test_table_status_not_null;\n\
+            ALTER TABLE test_schema.test_table ALTER COLUMN status SET NOT NULL;\n\
+            ALTER TABLE test_schema.test_table DROP CONSTRAINT test_table_status_not_null;\n");
+        assert!(!info[&s]["test_table"].columns["status"].nullable);
+    }
+
+    #[test]
+    fn test_retry_template() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let s = "test_schema".to_string();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        let info = || -> InfoSchemaType {
+            BTreeMap::from([(s.clone(), HashMap::from([("test_table".to_string(), PgTable { table_name: "test_table".to_string(), ..PgTable::default() })]))])
+        };
+
+        let opts = MigrationOptions::default();
+        let (_, out) = dry_run(|store| t.deploy(&mut info(), &mut NoDb, &s, true, "", Some(store), &opts)).unwrap();
+        let sql = out.join("\n");
+        assert!(sql.contains("lock_timeout CONSTANT text := '1000ms';"));
+        assert!(sql.contains("max_attempts CONSTANT INT := 100;"));
+        assert!(sql.contains("EXECUTE $ddl$ALTER TABLE test_schema.test_table ADD COLUMN id integer$ddl$;"));
+
+        let opts = MigrationOptions::default().retry_lock_timeout_ms(250).retry_max_attempts(5);
+        let (_, out) = dry_run(|store| t.deploy(&mut info(), &mut NoDb, &s, true, "", Some(store), &opts)).unwrap();
+        let sql = out.join("\n");
+        assert!(sql.contains("lock_timeout CONSTANT text := '250ms';"));
+        assert!(sql.contains("max_attempts CONSTANT INT := 5;"));
+
+        let opts = MigrationOptions::default().retry_template("CALL ddl_retry($q$$EXECUTE_SQL$$q$);\n");
+        let (_, out) = dry_run(|store| t.deploy(&mut info(), &mut NoDb, &s, true, "", Some(store), &opts)).unwrap();
+        let sql = out.join("\n");
+        assert!(sql.starts_with("CALL ddl_retry($q$ALTER TABLE test_schema.test_table ADD COLUMN id integer$q$);"));
+        assert!(!sql.contains("$EXECUTE_SQL$"));
+    }
+
+    #[test]
+    fn test_create_if_not_exists() {
+        let r = parse_yaml_schema(load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          unlogged: true
+          columns:
+            - column:
+                name: id
+                type: integer
+"#.to_string()).unwrap(), "").unwrap();
+        let t = r.list.get(0).unwrap().tables.list.get(0).unwrap();
+        let opts = MigrationOptions::default().create_if_not_exists(true);
+        let out = dry_run_sql(t, &mut BTreeMap::new(), &opts).unwrap();
+        assert!(out[0].starts_with("CREATE SCHEMA IF NOT EXISTS test_schema ;\nCREATE UNLOGGED TABLE IF NOT EXISTS test_schema.test_table (id integer"), "{}", out[0]);
+
+        let out = dry_run_sql(t, &mut BTreeMap::new(), &MigrationOptions::default()).unwrap();
+        assert!(out[0].contains("CREATE UNLOGGED TABLE test_schema.test_table (id integer"));
+    }
+
+    #[test]
+    fn test_truncate_before_seed() {
+        use crate::validate::WarningKind;
+        let mut r = parse_yaml_schema(load_schema_from_file("tests/example.yaml").unwrap(), "").unwrap();
+        let s = "test_schema".to_string();
+        let t = r.list.get_mut(0).unwrap().tables.list.get_mut(0).unwrap();
+        t.truncate_before_seed = true;
+        let err = dry_run(|store| t.deploy_data(&mut NoDb, &s, "", Some(store), &MigrationOptions::default())).unwrap_err();
+        assert!(err.contains("requires allow_truncate"), "{}", err);
+
+        let opts = MigrationOptions::default().allow_truncate(true);
+        let (_, out) = dry_run(|store| t.deploy_data(&mut NoDb, &s, "", Some(store), &opts)).unwrap();
+        assert!(out[0].starts_with("TRUNCATE TABLE test_schema.test_table;\n\
+            insert into test_schema.test_table (id, test) values ($1::text::integer, $2::text::varchar(250)); -- values: "), "{}", out[0]);
+
+        t.truncate_cascade = true;
+        let (_, out) = dry_run(|store| t.deploy_data(&mut NoDb, &s, "", Some(store), &opts)).unwrap();
+        assert!(out[0].starts_with("TRUNCATE TABLE test_schema.test_table CASCADE;\n"));
+
+        let yaml = |cascade: bool| load_schema_from_src(format!(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_lookup
+          truncate_before_seed: true
+          truncate_cascade: {}
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: lookup_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: test_lookup
+"#, cascade)).unwrap();
+        let truncate_referenced = |cascade| validate_schema(&parse_yaml_schema(yaml(cascade), "").unwrap()).iter()
+            .any(|w| w.kind == WarningKind::TruncateReferenced && w.is_error());
+        assert!(truncate_referenced(false));
+        assert!(!truncate_referenced(true));
+    }
+
+    #[test]
+    fn test_add_pk_column() {
+        let table = |pk2: bool| parse_yaml_schema(load_schema_from_src(format!(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: line
+          columns:
+            - column:
+                name: order_id
+                type: integer
+                constraint:
+                  primaryKey: true
+            - column:
+                name: line_no
+                type: integer
+                constraint:
+                  primaryKey: {}
+"#, pk2)).unwrap(), "").unwrap().list.remove(0).tables.list.remove(0);
+        let s = "test_schema".to_string();
+        let opts = MigrationOptions::default();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let mut t = table(true);
+        t.columns.remove("line_no");
+        let out = dry_run_sql(&t, &mut info, &opts).unwrap();
+        assert!(out[0].contains("order_id integer primary key"));
+
+        let t = table(true);
+        let out = dry_run_sql(&t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.line ADD COLUMN line_no integer;\n\
+                   ALTER TABLE test_schema.line DROP CONSTRAINT line_pkey, ADD PRIMARY KEY (order_id, line_no);\n");
+        let out = dry_run_sql(&t, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+        assert!(info[&s]["line"].columns["line_no"].pk);
+
+        // a new column out of the key as before
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let mut t = table(false);
+        t.columns.remove("line_no");
+        dry_run_sql(&t, &mut info, &opts).unwrap();
+        let out = dry_run_sql(&table(false), &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.line ADD COLUMN line_no integer;\n");
+
+        // the key of a custom name as loaded from DB
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let mut t = table(true);
+        t.columns.remove("line_no");
+        dry_run_sql(&t, &mut info, &opts).unwrap();
+        info.get_mut(&s).unwrap().get_mut("line").unwrap().pk_name = Some("line_order_pk".to_string());
+        let out = dry_run_sql(&table(true), &mut info, &opts).unwrap();
+        assert!(out[0].ends_with("ALTER TABLE test_schema.line DROP CONSTRAINT line_order_pk, ADD PRIMARY KEY (order_id, line_no);\n"));
+        assert_eq!(info[&s]["line"].pk_name.as_deref(), Some("line_pkey"));
+    }
+
+    #[test]
+    fn test_drop_triggers() {
+        let table = |triggers: &str| parse_yaml_schema(load_schema_from_src(format!(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: integer
+          triggers:{}
+"#, triggers)).unwrap(), "").unwrap().list.remove(0).tables.list.remove(0);
+        let s = "test_schema".to_string();
+        let opts = MigrationOptions::default().drop_triggers(true);
+        let mut info: InfoSchemaType = BTreeMap::new();
+        dry_run_sql(&table(r#"
+            - trigger:
+                name: tg_audit
+                event: after update
+                for_each: for each row
+                proc: test_schema.audit()"#), &mut info, &opts).unwrap();
+        assert!(info[&s]["test_table"].triggers.contains_key("tg_audit"));
+
+        // renamed in yaml, the old one dropped first
+        let renamed = table(r#"
+            - trigger:
+                name: tg_log
+                event: after update
+                for_each: for each row
+                proc: test_schema.audit()"#);
+        let out = dry_run_sql(&renamed, &mut info.clone(), &MigrationOptions::default()).unwrap();
+        assert!(!out[0].contains("DROP TRIGGER"));
+        let out = dry_run_sql(&renamed, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "DROP TRIGGER IF EXISTS tg_audit ON test_schema.test_table;\n\
+                   CREATE TRIGGER tg_log after update ON test_schema.test_table for each row EXECUTE PROCEDURE test_schema.audit();\n\n");
+
+        // removed from yaml
+        let out = dry_run_sql(&table(" []"), &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "DROP TRIGGER IF EXISTS tg_log ON test_schema.test_table;\n");
+        assert!(info[&s]["test_table"].triggers.is_empty());
+        let out = dry_run_sql(&table(" []"), &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+
+        // a reserved word name, quoted as loaded from DB
+        let reserved = table(r#"
+            - trigger:
+                name: user
+                event: after update
+                for_each: for each row
+                proc: test_schema.audit()"#);
+        dry_run_sql(&reserved, &mut info, &opts).unwrap();
+        assert!(info[&s]["test_table"].triggers.contains_key("\"user\""));
+        let out = dry_run_sql(&reserved, &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "");
+        let out = dry_run_sql(&table(" []"), &mut info, &opts).unwrap();
+        assert_eq!(out[0].as_str(), "DROP TRIGGER IF EXISTS \"user\" ON test_schema.test_table;\n");
+    }
+
+    #[test]
+    fn test_deferrable_fk() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: parent
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+      - table:
+          tableName: child
+          columns:
+            - column:
+                name: parent_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: parent
+                    initiallyDeferred: true
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"child".to_string()).unwrap();
+        let fk = t.columns.list.get(0).unwrap().constraint.as_ref().unwrap().foreign_key.as_ref().unwrap();
+        assert!(fk.is_deferrable());
+        let s = "test_schema".to_string();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let mut tables = HashMap::new();
+        tables.insert("child".to_string(), PgTable { table_name: "child".to_string(), ..PgTable::default() });
+        info.insert(s.clone(), tables);
+        let (_, out) = dry_run(|store| t.deploy_fk(&r, &mut info, &mut NoDb, &s, false, "", Some(store), &MigrationOptions::default())).unwrap();
+        assert!(out[0].contains("FOREIGN KEY (parent_id) REFERENCES test_schema.parent (id) DEFERRABLE INITIALLY DEFERRED"));
+
+        // deferability changed in DB
+        let fks = &mut info.get_mut(&s).unwrap().get_mut("child").unwrap().fks;
+        fks.get_mut("fk_test_schema_child_parent").unwrap().initially_deferred = false;
+        let (_, out) = dry_run(|store| t.deploy_fk(&r, &mut info, &mut NoDb, &s, false, "", Some(store), &MigrationOptions::default())).unwrap();
+        assert!(out[0].starts_with("ALTER TABLE test_schema.child DROP CONSTRAINT fk_test_schema_child_parent;"));
+        let (_, out) = dry_run(|store| t.deploy_fk(&r, &mut info, &mut NoDb, &s, false, "", Some(store), &MigrationOptions::default())).unwrap();
+        assert_eq!(out[0].as_str(), "");
+    }
+
+    #[test]
+    fn test_fk_actions() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: parent
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+      - table:
+          tableName: child
+          columns:
+            - column:
+                name: parent_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: parent
+                    sql: on delete restrict
+                    onDelete: set null
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"child".to_string()).unwrap();
+        let fk = t.columns.list.get(0).unwrap().constraint.as_ref().unwrap().foreign_key.as_ref().unwrap();
+        assert_eq!(fk.on_delete, Some(FkAction::SetNull));
+        assert_eq!(fk.actions_sql().as_str(), "ON DELETE SET NULL");
+        let s = "test_schema".to_string();
+        let mut info: InfoSchemaType = BTreeMap::new();
+        let mut tables = HashMap::new();
+        tables.insert("child".to_string(), PgTable { table_name: "child".to_string(), ..PgTable::default() });
+        info.insert(s.clone(), tables);
+        let (_, out) = dry_run(|store| t.deploy_fk(&r, &mut info, &mut NoDb, &s, false, "", Some(store), &MigrationOptions::default())).unwrap();
+        assert!(out[0].contains("REFERENCES test_schema.parent (id) ON DELETE SET NULL;"));
+
+        // changed in DB
+        let fks = &mut info.get_mut(&s).unwrap().get_mut("child").unwrap().fks;
+        fks.get_mut("fk_test_schema_child_parent").unwrap().on_delete = FkAction::Cascade;
+        let (_, out) = dry_run(|store| t.deploy_fk(&r, &mut info, &mut NoDb, &s, false, "", Some(store), &MigrationOptions::default())).unwrap();
+        assert!(out[0].starts_with("ALTER TABLE test_schema.child DROP CONSTRAINT fk_test_schema_child_parent;"));
+
+        // the actions in the sql suffix only
+        let mut r = r.clone();
+        let child = r.list[0].tables.get_mut(&"child".to_string()).unwrap();
+        let fk = child.columns.list[0].constraint.as_mut().unwrap().foreign_key.as_mut().unwrap();
+        fk.on_delete = None;
+        fk.sql = "on update  cascade on delete set default".to_string();
+        assert_eq!((fk.update_action(), fk.delete_action()), (FkAction::Cascade, FkAction::SetDefault));
+        fk.sql = "".to_string();
+        assert_eq!((fk.update_action(), fk.delete_action()), (FkAction::NoAction, FkAction::NoAction));
+        fk.sql = "ON DELETE SET NULL".to_string();
+        let t = r.list[0].tables.get(&"child".to_string()).unwrap();
+        let (_, out) = dry_run(|store| t.deploy_fk(&r, &mut info, &mut NoDb, &s, false, "", Some(store), &MigrationOptions::default())).unwrap();
+        assert_eq!(out[0].as_str(), "");
+        info.get_mut(&s).unwrap().get_mut("child").unwrap().fks.get_mut("fk_test_schema_child_parent").unwrap().on_delete = FkAction::Restrict;
+        let (_, out) = dry_run(|store| t.deploy_fk(&r, &mut info, &mut NoDb, &s, false, "", Some(store), &MigrationOptions::default())).unwrap();
+        assert!(out[0].starts_with("ALTER TABLE test_schema.child DROP CONSTRAINT fk_test_schema_child_parent;"));
+        assert!(out[0].ends_with("REFERENCES test_schema.parent (id) ON DELETE SET NULL;\n"));
+    }
+
+    #[test]
+    fn test_fk_drop_without_target() {
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: child
+          columns:
+            - column:
+                name: parent_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: other.parent
+                    onDelete: cascade
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let t = r.list.get(0).unwrap().tables.get(&"child".to_string()).unwrap();
+        let s = "test_schema".to_string();
+        let fk = FKTable {
+            schema: "other".to_string(),
+            table: "parent".to_string(),
+            column: vec!["id".to_string()],
+            name: "fk_test_schema_child_parent".to_string(),
+            local_columns: vec!["parent_id".to_string()],
+            sql: "".to_string(),
+            match_type: FkMatch::Simple,
+            on_update: FkAction::NoAction,
+            on_delete: FkAction::NoAction,
+            deferrable: false,
+            initially_deferred: false,
+        };
+        let mut info: InfoSchemaType = BTreeMap::new();
+        info.insert(s.clone(), HashMap::from([("child".to_string(), PgTable {
+            table_name: "child".to_string(),
+            fks: HashMap::from([(fk.name.clone(), fk)]),
+            ..PgTable::default()
+        })]));
+        // the primary key of other.parent is not known to add the changed one
+        let (_, out) = dry_run(|store| t.deploy_fk(&r, &mut info.clone(), &mut NoDb, &s, false, "", Some(store), &MigrationOptions::default())).unwrap();
+        assert_eq!(out[0].as_str(), "ALTER TABLE test_schema.child DROP CONSTRAINT fk_test_schema_child_parent;\n");
+        assert!(t.deploy_fk(&r, &mut info, &mut NoDb, &s, false, "", None, &MigrationOptions::default()).unwrap());
+        assert!(info[&s]["child"].fks.is_empty());
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use yaml_rust::Yaml;
+
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+use crate::loader::{normalize_check, normalize_default, outer_parentheses, same_pg_type};
+use crate::options::MigrationOptions;
+use crate::utils::{Enveloped, Named};
+
+/// custom type: enum, domain or composite
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YamlType {
+    pub name: String,
+    /// the type created in, the enclosing schemaName by default
+    pub schema: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub enum_values: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain_base_type: Option<String>,
+    /// domain CHECK (expression), use VALUE to refer the value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check_expr: Option<String>,
+    /// domain NOT NULL
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub not_null: bool,
+    /// domain DEFAULT expression
+    #[serde(rename = "default", skip_serializing_if = "Option::is_none")]
+    pub default_value: Option<String>,
+    /// composite type attributes in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<TypeAttribute>,
+}
+
+/// attribute of a composite type
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TypeAttribute {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub attr_type: String,
+}
+
+impl Enveloped for YamlType {
+    const ENVELOPE: &'static str = "type";
+}
+
+impl Named for YamlType {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl YamlType {
+    pub(crate) fn new(input: &Yaml, schema: &str) -> Self {
+        let enum_values = crate::utils::as_vec_str(input, "enum_values");
+        // kind: domain is optional, the base type makes the type a domain
+        let mut domain_base_type = crate::utils::as_str_esc(input, "domain_base_type");
+        if domain_base_type.len() == 0 {
+            domain_base_type = crate::utils::as_str_esc(input, "base_type");
+        }
+        let check_expr = crate::utils::as_str_esc(input, "check_expr");
+        let default_value = crate::utils::as_str_esc(input, "default");
+        // kind: composite is optional, the attributes make the type composite
+        let attributes = input["attributes"].as_vec().map_or(Vec::new(), |aa| aa.iter()
+            .map(|a| TypeAttribute {
+                name: crate::utils::sql_identifier(crate::utils::as_str_esc(a, "name")),
+                attr_type: crate::utils::as_str_esc(a, "type"),
+            })
+            .collect());
+        YamlType {
+            name: crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "name")),
+            schema: crate::utils::safe_sql_name(crate::utils::as_str(input, "schema", schema)),
+            enum_values,
+            domain_base_type: if domain_base_type.len() > 0 { Some(domain_base_type) } else { None },
+            check_expr: if check_expr.len() > 0 { Some(check_expr) } else { None },
+            not_null: crate::utils::as_bool(input, "not_null", false),
+            default_value: if default_value.len() > 0 { Some(default_value) } else { None },
+            attributes,
+        }
+    }
+
+    /// full name as schema.name
+    #[inline]
+    pub fn full_name(&self) -> String {
+        format!("{}.{}", self.schema, self.name)
+    }
+
+    /// the name of a domain check as PostgreSQL names it on CREATE DOMAIN
+    fn check_name(&self) -> String {
+        crate::utils::name_part(&format!("{}_check", self.name))
+    }
+
+    /// domain as created by deploy_sql
+    pub(crate) fn pg_domain(&self) -> PgDomain {
+        PgDomain {
+            base_type: self.domain_base_type.clone().unwrap_or_default(),
+            not_null: self.not_null,
+            default_value: self.default_value.clone(),
+            checks: self.check_expr.iter().map(|c| (self.check_name(), format!("CHECK ({})", c))).collect(),
+        }
+    }
+
+    /// statements to create or alter the type comparing to loaded from DB
+    pub fn deploy_sql(&self, existing: &TypesLoader, opts: &MigrationOptions) -> Result<Vec<String>, String> {
+        let mut sql = Vec::new();
+        let full_name = self.full_name();
+        if let Some(base) = &self.domain_base_type {
+            match existing.domains.get(&full_name) {
+                None => {
+                    let mut create = format!("CREATE DOMAIN {} AS {}", full_name, base);
+                    if let Some(default) = &self.default_value {
+                        let _ = write!(create, " DEFAULT {}", default);
+                    }
+                    if self.not_null {
+                        create.push_str(" NOT NULL");
+                    }
+                    if let Some(check) = &self.check_expr {
+                        let _ = write!(create, " CHECK ({})", check);
+                    }
+                    sql.push(create);
+                }
+                Some(pg) => {
+                    if !same_pg_type(base, &pg.base_type) {
+                        return Err(format!("domain base type change is not supported: {} to {} on domain {}", pg.base_type, base, full_name));
+                    }
+                    if self.not_null != pg.not_null {
+                        sql.push(format!("ALTER DOMAIN {} {} NOT NULL", full_name, if self.not_null { "SET" } else { "DROP" }));
+                    }
+                    match (&self.default_value, &pg.default_value) {
+                        (None, None) => {}
+                        (Some(d), Some(e)) if normalize_default(d) == normalize_default(e) => {}
+                        (Some(d), _) => sql.push(format!("ALTER DOMAIN {} SET DEFAULT {}", full_name, d)),
+                        (None, Some(_)) => sql.push(format!("ALTER DOMAIN {} DROP DEFAULT", full_name)),
+                    }
+                    // a changed check dropped and added again, the constraints of DB not in yaml dropped
+                    let same = |def: &String| self.check_expr.as_ref()
+                        .map_or(false, |c| normalize_check(c) == normalize_check(def.trim_start_matches("CHECK ")));
+                    for (name, def) in &pg.checks {
+                        if !same(def) {
+                            sql.push(format!("ALTER DOMAIN {} DROP CONSTRAINT {}", full_name, name));
+                        }
+                    }
+                    if let Some(check) = &self.check_expr {
+                        if !pg.checks.values().any(same) {
+                            sql.push(format!("ALTER DOMAIN {} ADD CONSTRAINT {} CHECK ({})", full_name, self.check_name(), check));
+                        }
+                    }
+                }
+            }
+        } else if self.enum_values.len() > 0 {
+            match existing.enums.get(&full_name) {
+                None => {
+                    let values: Vec<String> = self.enum_values.iter().map(|v| quote(v)).collect();
+                    sql.push(format!("CREATE TYPE {} AS ENUM ({})", full_name, values.join(", ")));
+                }
+                Some(labels) => {
+                    for l in labels {
+                        if !self.enum_values.contains(l) {
+                            return Err(format!("enum value removal is not supported: {} on type {}", l, full_name));
+                        }
+                    }
+                    for (i, v) in self.enum_values.iter().enumerate() {
+                        if !labels.contains(v) {
+                            let position = if i > 0 {
+                                format!(" AFTER {}", quote(&self.enum_values[i - 1]))
+                            } else if let Some(first) = labels.first() {
+                                format!(" BEFORE {}", quote(first))
+                            } else {
+                                "".to_string()
+                            };
+                            sql.push(format!("ALTER TYPE {} ADD VALUE {}{}", full_name, quote(v), position));
+                        }
+                    }
+                }
+            }
+        } else if self.attributes.len() > 0 {
+            if let Some(a) = self.attributes.iter().find(|a| a.name.len() == 0 || a.attr_type.len() == 0) {
+                return Err(format!("attribute {} without a name or type on type: {}", a.name, full_name));
+            }
+            match existing.composites.get(&full_name) {
+                None => {
+                    let attributes: Vec<String> = self.attributes.iter().map(|a| format!("{} {}", a.name, a.attr_type)).collect();
+                    sql.push(format!("CREATE TYPE {} AS ({})", full_name, attributes.join(", ")));
+                }
+                Some(loaded) => {
+                    for l in loaded {
+                        if !self.attributes.iter().any(|a| a.name == l.name) {
+                            if !opts.drop_type_attributes {
+                                return Err(format!("attribute {} of type {} not in yaml, set drop_type_attributes to drop", l.name, full_name));
+                            }
+                            sql.push(format!("ALTER TYPE {} DROP ATTRIBUTE {}", full_name, l.name));
+                        }
+                    }
+                    for a in &self.attributes {
+                        match loaded.iter().find(|l| l.name == a.name) {
+                            None => sql.push(format!("ALTER TYPE {} ADD ATTRIBUTE {} {}", full_name, a.name, a.attr_type)),
+                            Some(l) if !same_pg_type(&a.attr_type, &l.attr_type) =>
+                                sql.push(format!("ALTER TYPE {} ALTER ATTRIBUTE {} TYPE {}", full_name, a.name, a.attr_type)),
+                            Some(_) => {}
+                        }
+                    }
+                }
+            }
+        } else {
+            return Err(format!("neither enum_values, domain_base_type nor attributes set on type: {}", full_name));
+        }
+        Ok(sql)
+    }
+}
+
+#[inline]
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace("'", "''"))
+}
+
+/// domain loaded from DB
+#[derive(Debug, Clone, Default)]
+pub struct PgDomain {
+    pub base_type: String,
+    pub not_null: bool,
+    pub default_value: Option<String>,
+    /// constraint name: CHECK (expression)
+    pub checks: BTreeMap<String, String>,
+}
+
+impl PgDomain {
+    /// the checks as one expression of yaml
+    pub fn check_expr(&self) -> Option<String> {
+        let checks: Vec<String> = self.checks.values()
+            .map(|def| {
+                let e = def.trim_start_matches("CHECK ").trim();
+                if e.starts_with('(') && outer_parentheses(e) { e[1..e.len() - 1].to_string() } else { e.to_string() }
+            })
+            .collect();
+        match checks.len() {
+            0 => None,
+            1 => checks.into_iter().next(),
+            _ => Some(checks.iter().map(|c| format!("({})", c)).collect::<Vec<String>>().join(" AND ")),
+        }
+    }
+}
+
+/// enums, domains and composite types loaded from DB
+#[derive(Debug, Clone, Default)]
+pub struct TypesLoader {
+    /// schema.name: labels in sort order
+    pub enums: HashMap<String, Vec<String>>,
+    /// schema.name: domain
+    pub domains: HashMap<String, PgDomain>,
+    /// schema.name: attributes in order
+    pub composites: HashMap<String, Vec<TypeAttribute>>,
+}
+
+impl TypesLoader {
+    #[cfg(feature = "db")]
+    pub fn load(db: &mut dyn DbExec) -> Result<Self, String> {
+        let mut types = TypesLoader::default();
+        let result = db.query("SELECT n.nspname, t.typname, t.typtype::text, e.enumlabel, format_type(t.typbasetype, t.typtypmod),
+ t.typnotnull, t.typdefault, c.conname::text, pg_get_constraintdef(c.oid)
+ FROM pg_type t
+ JOIN pg_namespace n ON n.oid = t.typnamespace
+ LEFT JOIN pg_enum e ON e.enumtypid = t.oid
+ LEFT JOIN pg_constraint c ON c.contypid = t.oid AND c.contype = 'c'
+ WHERE t.typtype in ('e', 'd') AND n.nspname not in ('pg_catalog', 'information_schema')
+ ORDER BY 1, 2, e.enumsortorder, c.conname", &[])
+            .map_err(|e| format!("on loading types: {}", e))?;
+        for r in result {
+            let type_schema: &str = r.get(0);
+            let type_name: &str = r.get(1);
+            let type_type: &str = r.get(2);
+            let label: Option<&str> = r.get(3);
+            let base_type: Option<&str> = r.get(4);
+            let full_name = format!("{}.{}", type_schema, type_name);
+            if type_type == "e" {
+                let labels = types.enums.entry(full_name).or_insert_with(Vec::new);
+                if let Some(l) = label {
+                    labels.push(l.to_string());
+                }
+            } else {
+                let domain = types.domains.entry(full_name).or_insert_with(|| PgDomain {
+                    base_type: base_type.unwrap_or("").to_string(),
+                    not_null: r.get(5),
+                    default_value: r.get(6),
+                    checks: BTreeMap::new(),
+                });
+                let check: Option<&str> = r.get(7);
+                if let Some(check) = check {
+                    domain.checks.insert(crate::utils::quote_if_needed(check), r.get(8));
+                }
+            }
+        }
+        // the row types of tables are not composite types to deploy
+        let result = db.query("SELECT n.nspname, t.typname, a.attname, format_type(a.atttypid, a.atttypmod)
+ FROM pg_type t
+ JOIN pg_namespace n ON n.oid = t.typnamespace
+ JOIN pg_class c ON c.oid = t.typrelid AND c.relkind = 'c'
+ JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped
+ WHERE t.typtype = 'c' AND n.nspname not in ('pg_catalog', 'information_schema')
+ ORDER BY 1, 2, a.attnum", &[])
+            .map_err(|e| format!("on loading composite types: {}", e))?;
+        for r in result {
+            let type_schema: &str = r.get(0);
+            let type_name: &str = r.get(1);
+            let attr_name: &str = r.get(2);
+            let attr_type: &str = r.get(3);
+            types.composites.entry(format!("{}.{}", type_schema, type_name)).or_insert_with(Vec::new)
+                .push(TypeAttribute { name: crate::utils::quote_if_needed(attr_name), attr_type: attr_type.to_string() });
+        }
+        Ok(types)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::options::MigrationOptions;
+    use crate::types::{PgDomain, TypeAttribute, TypesLoader, YamlType};
+
+    fn status() -> YamlType {
+        YamlType {
+            name: "status".to_string(),
+            schema: "test_schema".to_string(),
+            enum_values: vec!["active".to_string(), "paused".to_string(), "inactive".to_string()],
+            domain_base_type: None,
+            check_expr: None,
+            not_null: false,
+            default_value: None,
+            attributes: vec![],
+        }
+    }
+
+    #[test]
+    fn enum_test() {
+        let mut existing = TypesLoader::default();
+        assert_eq!(status().deploy_sql(&existing, &MigrationOptions::default()).unwrap(),
+                   vec!["CREATE TYPE test_schema.status AS ENUM ('active', 'paused', 'inactive')".to_string()]);
+        existing.enums.insert("test_schema.status".to_string(), vec!["active".to_string(), "inactive".to_string()]);
+        assert_eq!(status().deploy_sql(&existing, &MigrationOptions::default()).unwrap(),
+                   vec!["ALTER TYPE test_schema.status ADD VALUE 'paused' AFTER 'active'".to_string()]);
+        existing.enums.insert("test_schema.status".to_string(), vec!["active".to_string(), "deleted".to_string()]);
+        assert!(status().deploy_sql(&existing, &MigrationOptions::default()).is_err());
+    }
+
+    fn attr(name: &str, attr_type: &str) -> TypeAttribute {
+        TypeAttribute { name: name.to_string(), attr_type: attr_type.to_string() }
+    }
+
+    #[test]
+    fn composite_test() {
+        let address = YamlType {
+            name: "address".to_string(),
+            schema: "test_schema".to_string(),
+            attributes: vec![attr("street", "text"), attr("city", "text"), attr("zip", "varchar(10)")],
+            ..Default::default()
+        };
+        let opts = MigrationOptions::default();
+        let mut existing = TypesLoader::default();
+        assert_eq!(address.deploy_sql(&existing, &opts).unwrap(),
+                   vec!["CREATE TYPE test_schema.address AS (street text, city text, zip varchar(10))".to_string()]);
+        existing.composites.insert("test_schema.address".to_string(),
+                                   vec![attr("street", "text"), attr("city", "character varying(20)"), attr("zip", "character varying(10)")]);
+        assert_eq!(address.deploy_sql(&existing, &opts).unwrap(),
+                   vec!["ALTER TYPE test_schema.address ALTER ATTRIBUTE city TYPE text".to_string()]);
+        existing.composites.insert("test_schema.address".to_string(), vec![attr("street", "text"), attr("country", "text")]);
+        assert!(address.deploy_sql(&existing, &opts).is_err());
+        assert_eq!(address.deploy_sql(&existing, &MigrationOptions::default().drop_type_attributes(true)).unwrap(),
+                   vec!["ALTER TYPE test_schema.address DROP ATTRIBUTE country".to_string(),
+                        "ALTER TYPE test_schema.address ADD ATTRIBUTE city text".to_string(),
+                        "ALTER TYPE test_schema.address ADD ATTRIBUTE zip varchar(10)".to_string()]);
+    }
+
+    #[test]
+    fn domain_test() {
+        let positive = YamlType {
+            name: "positive_int".to_string(),
+            schema: "test_schema".to_string(),
+            domain_base_type: Some("integer".to_string()),
+            check_expr: Some("VALUE > 0".to_string()),
+            not_null: true,
+            default_value: Some("1".to_string()),
+            ..Default::default()
+        };
+        let opts = MigrationOptions::default();
+        let mut existing = TypesLoader::default();
+        assert_eq!(positive.deploy_sql(&existing, &opts).unwrap(),
+                   vec!["CREATE DOMAIN test_schema.positive_int AS integer DEFAULT 1 NOT NULL CHECK (VALUE > 0)".to_string()]);
+        // as loaded from DB
+        let pg = PgDomain {
+            base_type: "integer".to_string(),
+            not_null: true,
+            default_value: Some("1".to_string()),
+            checks: BTreeMap::from([("positive_int_check".to_string(), "CHECK ((VALUE > 0))".to_string())]),
+        };
+        assert_eq!(pg.check_expr().unwrap().as_str(), "(VALUE > 0)");
+        existing.domains.insert("test_schema.positive_int".to_string(), pg.clone());
+        assert!(positive.deploy_sql(&existing, &opts).unwrap().is_empty());
+        let range = YamlType { check_expr: Some("VALUE >= 0 AND VALUE < 100".to_string()), ..positive.clone() };
+        existing.domains.get_mut("test_schema.positive_int").unwrap().checks.insert("positive_int_check".to_string(),
+                                                                                   "CHECK (((VALUE >= 0) AND (VALUE < 100)))".to_string());
+        assert!(range.deploy_sql(&existing, &opts).unwrap().is_empty());
+        // the parentheses changing the precedence are kept
+        let grouped = YamlType { check_expr: Some("(VALUE > 0 OR VALUE < -10) AND VALUE <> 5".to_string()), ..positive.clone() };
+        existing.domains.get_mut("test_schema.positive_int").unwrap().checks.insert("positive_int_check".to_string(),
+                                                                                   "CHECK (((VALUE > 0) OR ((VALUE < '-10'::integer) AND (VALUE <> 5))))".to_string());
+        assert_eq!(grouped.deploy_sql(&existing, &opts).unwrap().len(), 2);
+        existing.domains.get_mut("test_schema.positive_int").unwrap().checks.insert("positive_int_check".to_string(),
+                                                                                   "CHECK ((((VALUE > 0) OR (VALUE < '-10'::integer)) AND (VALUE <> 5)))".to_string());
+        assert!(grouped.deploy_sql(&existing, &opts).unwrap().is_empty());
+
+        existing.domains.insert("test_schema.positive_int".to_string(), PgDomain {
+            not_null: false,
+            default_value: None,
+            checks: BTreeMap::from([("positive_int_check".to_string(), "CHECK ((VALUE >= 0))".to_string())]),
+            ..pg.clone()
+        });
+        assert_eq!(positive.deploy_sql(&existing, &opts).unwrap(), vec![
+            "ALTER DOMAIN test_schema.positive_int SET NOT NULL".to_string(),
+            "ALTER DOMAIN test_schema.positive_int SET DEFAULT 1".to_string(),
+            "ALTER DOMAIN test_schema.positive_int DROP CONSTRAINT positive_int_check".to_string(),
+            "ALTER DOMAIN test_schema.positive_int ADD CONSTRAINT positive_int_check CHECK (VALUE > 0)".to_string()]);
+
+        existing.domains.insert("test_schema.positive_int".to_string(), PgDomain { base_type: "bigint".to_string(), ..pg });
+        assert!(positive.deploy_sql(&existing, &opts).is_err());
+    }
+}
@@ -1,6 +1,10 @@
 use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
 
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{Error, SeqAccess, Visitor};
 use serde::ser::SerializeSeq;
 use yaml_rust::Yaml;
 
@@ -9,6 +13,28 @@ pub fn as_str_esc(input: &Yaml, field: &str) -> String {
     as_esc(as_str(input, field, "").as_str())
 }
 
+/// multi-line value as is, i.e. sql of a yaml block scalar, as_esc on the last line only
+#[inline]
+pub fn as_multiline(input: &Yaml, field: &str) -> String {
+    esc_multiline(as_str(input, field, "").as_str())
+}
+
+/// a comment ends with the line, so kept inside, but not on the last line, as the generated statement continues there
+fn esc_multiline(val: &str) -> String {
+    let mut lines: Vec<&str> = val.trim_end().lines().collect();
+    if !lines.last().map_or(false, |l| l.contains("--")) {
+        return val.to_string();
+    }
+    while let Some(last) = lines.pop() {
+        let last = as_esc(last);
+        if last.len() > 0 {
+            lines.push(last.as_str());
+            return lines.join("\n");
+        }
+    }
+    "".into()
+}
+
 #[inline]
 pub fn as_esc(val: &str) -> String {
     match val.find("--") {
@@ -57,6 +83,26 @@ pub fn as_vec(input: &Yaml, field: &str) -> Vec<Vec<String>> {
     data
 }
 
+/// flat list of the scalars as as_str does, i.e. [a, 1, 2.5, true]; nested lists and maps skipped
+#[inline]
+pub fn as_vec_str(input: &Yaml, field: &str) -> Vec<String> {
+    let mut data = Vec::new();
+    if !input.is_null() {
+        if let Yaml::Array(aa) = &input[field] {
+            for a in aa {
+                match a {
+                    Yaml::Real(v) => data.push(v.to_string()),
+                    Yaml::Integer(v) => data.push(v.to_string()),
+                    Yaml::String(v) => data.push(v.to_string()),
+                    Yaml::Boolean(v) => data.push(v.to_string()),
+                    _ => {}
+                }
+            }
+        }
+    }
+    data
+}
+
 #[inline]
 pub fn as_stro(input: &Yaml, field: &str) -> Option<String> {
     if input.is_null() {
@@ -99,8 +145,24 @@ pub fn str2bool(input: &str, default: bool) -> bool {
     }
 }
 
+/// cut the name on the first space, dot, semicolon or line break,
+/// a double quoted name is kept verbatim up to its closing quote
 #[inline]
 pub fn safe_sql_name(input: String) -> String {
+    if input.starts_with('"') {
+        let mut chars = input.char_indices().skip(1).peekable();
+        while let Some((i, c)) = chars.next() {
+            if c == '"' {
+                match chars.peek() {
+                    // escaped quote
+                    Some((_, '"')) => { let _ = chars.next(); }
+                    _ => return input[0..=i].into(),
+                }
+            }
+        }
+        // not closed
+        return "".into();
+    }
     match input
         .chars()
         .position(|c| c == ' ' || c == '.' || c == ';' || c == '\n' || c == '\t')
@@ -110,10 +172,252 @@ pub fn safe_sql_name(input: String) -> String {
     }
 }
 
+/// reserved key words of PostgreSQL, not allowed as a table or column name unless quoted
+pub const RESERVED_WORDS: [&str; 101] = [
+    "all", "analyse", "analyze", "and", "any", "array", "as", "asc", "asymmetric", "authorization",
+    "binary", "both", "case", "cast", "check", "collate", "collation", "column", "concurrently", "constraint",
+    "create", "cross", "current_catalog", "current_date", "current_role", "current_schema", "current_time",
+    "current_timestamp", "current_user", "default", "deferrable", "desc", "distinct", "do", "else", "end",
+    "except", "false", "fetch", "for", "foreign", "freeze", "from", "full", "grant", "group", "having",
+    "ilike", "in", "initially", "inner", "intersect", "into", "is", "isnull", "join", "lateral", "leading",
+    "left", "like", "limit", "localtime", "localtimestamp", "natural", "not", "notnull", "null", "offset",
+    "on", "only", "or", "order", "outer", "overlaps", "placing", "primary", "references", "returning",
+    "right", "select", "session_user", "similar", "some", "symmetric", "system_user", "table", "tablesample",
+    "then", "to", "trailing", "true", "union", "unique", "user", "using", "variadic", "verbose", "when",
+    "where", "window", "with",
+];
+
+/// wrap into double quotes, the quotes inside doubled
+#[inline]
+pub fn quote_sql_name(input: String) -> String {
+    format!("\"{}\"", input.replace('"', "\"\""))
+}
+
+/// the canonical form of a name as stored in DB: unquoted if it matches [a-z_][a-z0-9_$]* and is not a reserved word,
+/// quoted otherwise
+pub fn quote_if_needed(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '$');
+    if plain && !RESERVED_WORDS.contains(&name) {
+        name.to_string()
+    } else {
+        quote_sql_name(name.to_string())
+    }
+}
+
+/// table, column or role name from yaml in the canonical form of quote_if_needed:
+/// a quoted name taken as is, a name with spaces quoted as is, otherwise lower cased as by PostgreSQL
+pub fn sql_identifier(input: String) -> String {
+    let name = input.trim();
+    if name.starts_with('"') {
+        let quoted = safe_sql_name(name.to_string());
+        if quoted.len() < 2 {
+            return quoted;
+        }
+        quote_if_needed(&quoted[1..quoted.len() - 1].replace("\"\"", "\""))
+    } else if name.contains(' ') {
+        quote_sql_name(name.to_string())
+    } else {
+        let name = safe_sql_name(name.to_string());
+        if name.len() == 0 {
+            return name;
+        }
+        quote_if_needed(&name.to_lowercase())
+    }
+}
+
+/// comma separated names, i.e. the roles of a grant, each as sql_identifier
+pub fn sql_identifier_list(input: String) -> String {
+    let mut names = Vec::new();
+    let mut name = String::new();
+    let mut quoted = false;
+    for c in input.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                names.push(sql_identifier(std::mem::take(&mut name)));
+                continue;
+            }
+            _ => {}
+        }
+        name.push(c);
+    }
+    names.push(sql_identifier(name));
+    names.retain(|n| n.len() > 0);
+    names.join(",")
+}
+
+/// optionally schema qualified name, each part as sql_identifier
+pub fn sql_qualified_name(input: String) -> String {
+    let name = input.trim();
+    let (first, rest) = if name.starts_with('"') {
+        let first = safe_sql_name(name.to_string());
+        let rest = &name[first.len()..];
+        (sql_identifier(first), rest)
+    } else {
+        let i = name.find('.').unwrap_or(name.len());
+        (sql_identifier(name[..i].to_string()), &name[i..])
+    };
+    match rest.strip_prefix('.') {
+        Some(rest) if first.len() > 0 => format!("{}.{}", first, sql_identifier(rest.to_string())),
+        _ => first,
+    }
+}
+
+/// storage parameters, i.e. with: {fillfactor: 80, toast.autovacuum_enabled: false}, names lower cased
+pub fn as_storage_params(input: &Yaml, field: &str) -> BTreeMap<String, String> {
+    let mut params = BTreeMap::new();
+    if let Some(hash) = input[field].as_hash() {
+        for (k, v) in hash {
+            let name: String = k.as_str().unwrap_or("").trim().to_lowercase().chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+                .collect();
+            let value = match v {
+                Yaml::Real(v) => v.to_string(),
+                Yaml::Integer(v) => v.to_string(),
+                Yaml::String(v) => v.trim().to_string(),
+                Yaml::Boolean(v) => v.to_string(),
+                _ => "".into(),
+            };
+            if name.len() > 0 && value.len() > 0 {
+                params.insert(name, value);
+            }
+        }
+    }
+    params
+}
+
+/// pg_class.reloptions as name=value
+pub fn parse_reloptions(options: Option<Vec<String>>) -> BTreeMap<String, String> {
+    options.unwrap_or_default().iter()
+        .filter_map(|o| o.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// name=value list of WITH (...) or SET (...), a value quoted unless a number or a word
+pub fn storage_params_sql(params: &BTreeMap<String, String>) -> String {
+    params.iter()
+        .map(|(k, v)| if v.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+            format!("{}={}", k, v)
+        } else {
+            format!("{}='{}'", k, v.replace('\'', "''"))
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// parameters to set: missing or having another value in DB
+pub fn changed_storage_params(desired: &BTreeMap<String, String>, existing: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    desired.iter()
+        .filter(|(k, v)| existing.get(*k) != Some(*v))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+/// file name matches the pattern with * as any chars and ? as one char
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    // last * position in the pattern and the name position it matched from
+    let (mut pi, mut ni, mut star) = (0, 0, None);
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// identifier as a part of a generated name, without quotes and spaces
+#[inline]
+pub fn name_part(name: &str) -> String {
+    name.replace('"', "").replace(' ', "_")
+}
+
+/// PostgreSQL identifier length limit, NAMEDATALEN - 1
+pub const MAX_IDENTIFIER_LEN: usize = 63;
+
+/// cut a generated name to the identifier length limit, a hash suffix keeps cut names distinct
+pub fn pg_identifier(name: String) -> String {
+    if name.len() <= MAX_IDENTIFIER_LEN {
+        return name;
+    }
+    let suffix = format!("_{:08x}", fnv1a(name.as_str()) as u32);
+    let mut cut = MAX_IDENTIFIER_LEN - suffix.len();
+    while !name.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    format!("{}{}", &name[..cut], suffix)
+}
+
+/// FNV-1a hash
+pub(crate) const fn fnv1a(name: &str) -> u64 {
+    let bytes = name.as_bytes();
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+    hash
+}
+
 pub trait Named {
     fn get_name(&self) -> String;
 }
 
+/// key of a list element wrapper in yaml, i.e. - trigger: {...}
+pub trait Enveloped {
+    const ENVELOPE: &'static str;
+}
+
+/// serde of the list with each element in the wrapper
+pub(crate) mod enveloped {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde::de::Error;
+    use serde::ser::SerializeSeq;
+
+    use crate::utils::{Enveloped, Named, OrderedHashMap};
+
+    pub fn serialize<T, S>(list: &OrderedHashMap<T>, serializer: S) -> Result<<S as Serializer>::Ok, <S as Serializer>::Error> where
+        T: Named + Serialize + Enveloped, S: Serializer
+    {
+        let mut seq = serializer.serialize_seq(Some(list.len()))?;
+        for e in &list.list {
+            let mut vo = BTreeMap::new();
+            vo.insert(T::ENVELOPE, e);
+            seq.serialize_element(&vo)?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<OrderedHashMap<T>, D::Error> where
+        T: Named + Serialize + Deserialize<'de> + Enveloped, D: Deserializer<'de>
+    {
+        let mut list = OrderedHashMap::new();
+        for mut vo in Vec::<BTreeMap<String, T>>::deserialize(deserializer)? {
+            if let Some(e) = vo.remove(T::ENVELOPE) {
+                list.append(e).map_err(|e| D::Error::custom(format!("{} ({} name)", e, T::ENVELOPE)))?;
+            }
+        }
+        Ok(list)
+    }
+}
+
 /// String key for BTreeMap, sorted by adding order
 #[derive(Debug, Clone)]
 pub struct OrderedHashMap<T: Named + Serialize> {
@@ -167,6 +471,77 @@ impl<T: Named + Serialize> OrderedHashMap<T> {
     pub fn is_empty(&self) -> bool {
         self.map.len() == 0
     }
+
+    /// names in adding order
+    pub fn keys(&self) -> impl Iterator<Item=&str> {
+        let mut keys: Vec<(usize, &str)> = self.map.iter().map(|(k, i)| (*i, k.as_str())).collect();
+        keys.sort();
+        keys.into_iter().map(|(_, k)| k)
+    }
+
+    /// elements in adding order
+    #[inline]
+    pub fn values(&self) -> impl Iterator<Item=&T> {
+        self.list.iter()
+    }
+
+    /// keep the elements matching the predicate, in adding order
+    pub fn retain<F: Fn(&T) -> bool>(&mut self, f: F) {
+        self.list.retain(|e| f(e));
+        self.reindex();
+    }
+
+    /// remove an element by name, the following elements shift down
+    pub fn remove(&mut self, key: &str) -> Option<T> {
+        let id = self.map.remove(key)?;
+        let value = self.list.remove(id);
+        self.reindex();
+        Some(value)
+    }
+
+    fn reindex(&mut self) {
+        self.map = self.list.iter().enumerate().map(|(i, e)| (e.get_name(), i)).collect();
+    }
+}
+
+impl<T: Named + Serialize> IntoIterator for OrderedHashMap<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.list.into_iter()
+    }
+}
+
+impl<'a, T: Named + Serialize> IntoIterator for &'a OrderedHashMap<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.list.iter()
+    }
+}
+
+/// panic on a duplicate or empty name, use append to handle it
+impl<T: Named + Serialize> FromIterator<T> for OrderedHashMap<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> Self {
+        let mut map = OrderedHashMap::new();
+        for value in iter {
+            if let Err(e) = map.append(value) {
+                panic!("{}", e);
+            }
+        }
+        map
+    }
+}
+
+/// elements with a duplicate or empty name are skipped
+impl<T: Named + Serialize> Extend<T> for OrderedHashMap<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, iter: I) {
+        for value in iter {
+            let _ = self.append(value);
+        }
+    }
 }
 
 impl<T: Named + Serialize> Serialize for OrderedHashMap<T> {
@@ -180,6 +555,37 @@ impl<T: Named + Serialize> Serialize for OrderedHashMap<T> {
     }
 }
 
+struct OrderedHashMapVisitor<T> {
+    marker: PhantomData<T>,
+}
+
+impl<'de, T: Named + Serialize + Deserialize<'de>> Visitor<'de> for OrderedHashMapVisitor<T> {
+    type Value = OrderedHashMap<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of uniquely named elements")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error> where A: SeqAccess<'de> {
+        let mut map = OrderedHashMap::new();
+        while let Some(element) = seq.next_element()? {
+            map.append(element).map_err(A::Error::custom)?;
+        }
+        Ok(map)
+    }
+}
+
+impl<'de, T: Named + Serialize + Deserialize<'de>> Deserialize<'de> for OrderedHashMap<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        deserializer.deserialize_seq(OrderedHashMapVisitor { marker: PhantomData })
+    }
+}
+
+impl<T: Named + Serialize> Default for OrderedHashMap<T> {
+    fn default() -> Self {
+        OrderedHashMap::new()
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -211,6 +617,29 @@ mod tests {
     }
 
 
+    #[test]
+    fn as_vec_str_test() {
+        let yaml = yaml_rust::YamlLoader::load_from_str("list: [a, 1, 2.50, true, [b], {c: d}, '3']\nname: x\n").unwrap().remove(0);
+        assert_eq!(as_vec_str(&yaml, "list"), vec!["a", "1", "2.50", "true", "3"]);
+        assert!(as_vec_str(&yaml, "name").is_empty());
+        assert!(as_vec_str(&yaml, "missing").is_empty());
+        assert!(as_vec_str(&yaml["missing"], "list").is_empty());
+    }
+
+    #[test]
+    fn multiline_test() {
+        assert_eq!(as_esc("a -- b"), "a");
+        assert_eq!(as_esc("a\n-- inside\nb -- last\n"), "a");
+        assert_eq!(esc_multiline("a\n-- inside\nb -- last\n"), "a\n-- inside\nb");
+        assert_eq!(esc_multiline("a -- one\nb\n"), "a -- one\nb\n");
+        assert_eq!(esc_multiline("a\nb\n-- trailing\n\n"), "a\nb");
+        let yaml = yaml_rust::YamlLoader::load_from_str("sql: |\n  CHECK (qty > 0 -- no returns\n    OR refund)\nflat: a -- b\n").unwrap().remove(0);
+        assert_eq!(as_multiline(&yaml, "sql"), "CHECK (qty > 0 -- no returns\n  OR refund)\n");
+        assert_eq!(as_multiline(&yaml, "flat"), "a");
+        // the other fields cut at the first comment as before
+        assert_eq!(as_str_esc(&yaml, "sql"), "CHECK (qty > 0");
+    }
+
     #[test]
     fn safe_test() {
         assert_eq!("a".to_string(), safe_sql_name("a;".to_string()));
@@ -222,17 +651,98 @@ mod tests {
         assert_eq!("a".to_string(), safe_sql_name("a\t ".to_string()));
     }
 
+    #[test]
+    fn quote_test() {
+        assert_eq!("\"First \"\"Name\"\"\"".to_string(), quote_sql_name("First \"Name\"".to_string()));
+        assert_eq!("\"order\"".to_string(), sql_identifier("order".to_string()));
+        assert_eq!("\"user\"".to_string(), sql_identifier("USER".to_string()));
+        assert_eq!("orders".to_string(), sql_identifier("Orders".to_string()));
+        assert_eq!("orders".to_string(), sql_identifier("\"orders\"".to_string()));
+        assert_eq!("\"Orders\"".to_string(), sql_identifier("\"Orders\"".to_string()));
+        assert_eq!("\"order\"".to_string(), sql_identifier("\"order\"".to_string()));
+        assert_eq!("\"1st\"".to_string(), quote_if_needed("1st"));
+        assert_eq!("a$1".to_string(), quote_if_needed("a$1"));
+        assert_eq!("\"Orders\"".to_string(), quote_if_needed("Orders"));
+        assert_eq!("app,\"user\",\"My, Role\"".to_string(), sql_identifier_list("app, user ,\"My, Role\",".to_string()));
+        assert_eq!("\"table\"".to_string(), sql_identifier(" table ".to_string()));
+        assert_eq!("\"First Name\"".to_string(), sql_identifier("First Name".to_string()));
+        assert_eq!("\"a;b\"".to_string(), sql_identifier("\"a;b\"; drop table t".to_string()));
+        assert_eq!("\"a\"\"b\"".to_string(), safe_sql_name("\"a\"\"b\" c".to_string()));
+        assert_eq!("".to_string(), safe_sql_name("\"a; drop table t".to_string()));
+        assert_eq!("orders".to_string(), sql_identifier("orders".to_string()));
+        assert_eq!("a".to_string(), sql_identifier("a;".to_string()));
+        assert_eq!("\"order\"".to_string(), quote_if_needed("order"));
+        assert_eq!("orders".to_string(), quote_if_needed("orders"));
+        assert_eq!("First_Name".to_string(), name_part("\"First Name\""));
+        assert_eq!("s.\"order\"".to_string(), sql_qualified_name("s.order".to_string()));
+        assert_eq!("\"my.s\".\"user\"".to_string(), sql_qualified_name("\"my.s\".user".to_string()));
+        assert_eq!("\"order\"".to_string(), sql_qualified_name(" order ".to_string()));
+    }
+
+    #[test]
+    fn wildcard_test() {
+        assert!(wildcard_match("*.yaml", "a.yaml"));
+        assert!(wildcard_match("0?_*.y*ml", "01_core.yml"));
+        assert!(!wildcard_match("0?_*.yaml", "1_core.yaml"));
+        assert!(!wildcard_match("*.yaml", "a.yml"));
+        assert!(wildcard_match("*", ""));
+    }
+
     #[test]
     fn serialize_test() {
         let mut l: OrderedHashMap<Trig> = OrderedHashMap::new();
         let _ = l.append(Trig {
             name: "a".to_string(),
             event: "b".to_string(),
-            when: "c".to_string(),
+            for_each: "c".to_string(),
+            when_condition: None,
             proc: "d".to_string(),
+            or_replace: false,
         });
-        assert_eq!("[{'name':'a','event':'b','when':'c','proc':'d'}]".replace("'", "\""),
+        assert_eq!("[{'name':'a','event':'b','for_each':'c','proc':'d'}]".replace("'", "\""),
                    serde_json::to_string(&l).unwrap());
     }
+
+    fn trig(name: &str) -> Trig {
+        Trig { name: name.to_string(), event: "".to_string(), for_each: "".to_string(), when_condition: None, proc: "".to_string(), or_replace: false }
+    }
+
+    #[test]
+    fn retain_test() {
+        let mut l: OrderedHashMap<Trig> = vec![trig("b"), trig("a"), trig("c"), trig("d")].into_iter().collect();
+        l.retain(|t| t.name != "a");
+        assert_eq!(l.keys().collect::<Vec<&str>>(), vec!["b", "c", "d"]);
+        assert!(l.get(&"a".to_string()).is_none());
+        assert_eq!(l.get(&"c".to_string()).unwrap().name.as_str(), "c");
+        assert_eq!(l.remove("c").unwrap().name.as_str(), "c");
+        assert!(l.remove("c").is_none());
+        assert_eq!(l.get(&"d".to_string()).unwrap().name.as_str(), "d");
+        assert_eq!(l.len(), 2);
+        l.retain(|_| false);
+        assert!(l.is_empty());
+        assert!(l.append(trig("b")).is_ok());
+    }
+
+    #[test]
+    fn iter_test() {
+        let mut l: OrderedHashMap<Trig> = vec![trig("b"), trig("a")].into_iter().collect();
+        l.extend(vec![trig("c"), trig("a")]);
+        assert_eq!(l.keys().collect::<Vec<&str>>(), vec!["b", "a", "c"]);
+        assert_eq!(l.values().map(|t| t.name.as_str()).collect::<Vec<&str>>(), vec!["b", "a", "c"]);
+        let mut names = Vec::new();
+        for t in &l {
+            names.push(t.name.clone());
+        }
+        assert_eq!(names, l.into_iter().map(|t| t.name).collect::<Vec<String>>());
+    }
+
+    #[test]
+    fn deserialize_test() {
+        let l: OrderedHashMap<Trig> = serde_json::from_str("[{'name':'a','event':'b','when':'c','proc':'d'}]".replace("'", "\"").as_str()).unwrap();
+        assert_eq!(l.len(), 1);
+        assert_eq!(l.get(&"a".to_string()).unwrap().proc.as_str(), "d");
+        let dup = "[{'name':'a','event':'b','when':'c','proc':'d'},{'name':'a','event':'','when':'','proc':''}]".replace("'", "\"");
+        assert!(serde_json::from_str::<OrderedHashMap<Trig>>(dup.as_str()).is_err());
+    }
 }
 
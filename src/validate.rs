@@ -0,0 +1,480 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::loader::normalize_pg_type;
+use crate::schema::Schema;
+use crate::table::Table;
+use crate::utils::OrderedHashMap;
+
+/// how bad a finding of the schema validation is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Severity {
+    /// the deploy fails or does not do what defined
+    Error,
+    /// the deploy works, but likely not as expected
+    Warning,
+}
+
+/// what is wrong, to filter the findings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum WarningKind {
+    NullablePrimaryKey,
+    /// foreign key to a schema not defined in yaml
+    UndefinedFkSchema,
+    /// foreign key to a table not defined in yaml
+    UndefinedFkTable,
+    /// foreign key to a table not defined in the schema of another file, might be in a file not loaded
+    UnresolvedFkTarget,
+    FkTargetWithoutPrimaryKey,
+    FkColumnCount,
+    UndefinedIncludeColumn,
+    FkCycle,
+    /// existing table columns ordered differently than in yaml, found on deploy
+    ColumnOrderMismatch,
+    /// column nullable in DB, but NOT NULL in yaml, not altered without allow_not_null_change, found on deploy
+    NotNullMismatch,
+    /// LIKE of an existing table is not applied, found on deploy
+    LikeOnExistingTable,
+    /// parents of an existing table differ from INHERITS in yaml, found on deploy
+    InheritsMismatch,
+    /// column type in DB differs from yaml, not altered, found on deploy
+    ColumnTypeMismatch,
+    /// column type is neither a standard PostgreSQL type nor defined in yaml
+    UnknownColumnType,
+    /// foreign key references a table truncated before seed without truncate_cascade
+    TruncateReferenced,
+}
+
+/// semantic problem of the yaml schema, found without a database or on deploy
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchemaWarning {
+    pub severity: Severity,
+    pub kind: WarningKind,
+    /// schema.table or schema.table.column
+    pub location: String,
+    pub message: String,
+}
+
+impl SchemaWarning {
+    pub fn error(kind: WarningKind, location: String, message: String) -> Self {
+        SchemaWarning { severity: Severity::Error, kind, location, message }
+    }
+
+    pub fn warning(kind: WarningKind, location: String, message: String) -> Self {
+        SchemaWarning { severity: Severity::Warning, kind, location, message }
+    }
+
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for SchemaWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} on {}: {}", self.severity, self.location, self.message)
+    }
+}
+
+/// check FK targets, PK and index columns, FK cycles
+pub(crate) fn validate(schemas: &OrderedHashMap<Schema>) -> Vec<SchemaWarning> {
+    let mut res = Vec::new();
+    for s in &schemas.list {
+        for t in &s.tables.list {
+            let location = format!("{}.{}", s.schema_name, t.table_name);
+            // FK name -> local columns, target table
+            let mut fks: BTreeMap<String, (usize, String, String)> = BTreeMap::new();
+            for c in &t.columns.list {
+                let column = format!("{}.{}", location, c.name);
+                if let Some(constraint) = &c.constraint {
+                    if constraint.primary_key == Some(true) && constraint.nullable {
+                        res.push(SchemaWarning::warning(WarningKind::NullablePrimaryKey, column.clone(),
+                            "primary key column is nullable in yaml, created as NOT NULL, set nullable: false".to_string()));
+                    }
+                    if let Some(fk) = &constraint.foreign_key {
+                        let (fk_schema, fk_table) = fk.target(&s.schema_name);
+                        let fk_entry = fks.entry(fk.name(&s.schema_name, &t.table_name))
+                            .or_insert((0, fk_schema.clone(), fk_table.clone()));
+                        fk_entry.0 += 1;
+                        match fk_target(schemas, s, &column, &fk_schema, &fk_table) {
+                            Err(w) => res.push(w),
+                            Ok(target) => {
+                                if !target.columns.list.iter().any(|tc| tc.is_pk()) {
+                                    res.push(SchemaWarning::error(WarningKind::FkTargetWithoutPrimaryKey, column.clone(),
+                                        format!("foreign key references {}.{} without primary key", fk_schema, fk_table)));
+                                }
+                                if target.truncate_before_seed && !target.truncate_cascade && !std::ptr::eq(target, t) {
+                                    res.push(SchemaWarning::error(WarningKind::TruncateReferenced, column.clone(),
+                                        format!("foreign key references {}.{} truncated before seed, TRUNCATE fails without truncate_cascade", fk_schema, fk_table)));
+                                }
+                            }
+                        }
+                    }
+                }
+                if let Some(index) = &c.index {
+                    for i in &index.include {
+                        if t.columns.get(i).is_none() {
+                            res.push(SchemaWarning::error(WarningKind::UndefinedIncludeColumn, column.clone(),
+                                format!("index includes column {} not defined on the table", i)));
+                        }
+                    }
+                }
+            }
+            for (name, (columns, fk_schema, fk_table)) in fks {
+                let pk = schemas.get(&fk_schema).and_then(|ts| ts.tables.get(&fk_table))
+                    .map_or(0, |target| target.columns.list.iter().filter(|tc| tc.is_pk()).count());
+                if pk > 0 && pk != columns {
+                    res.push(SchemaWarning::error(WarningKind::FkColumnCount, location.clone(),
+                        format!("foreign key {} has {} columns, but primary key of {}.{} has {}", name, columns, fk_schema, fk_table, pk)));
+                }
+            }
+        }
+    }
+    for cycle in fk_cycles(&fk_graph(schemas)) {
+        res.push(SchemaWarning::warning(WarningKind::FkCycle, cycle[0].clone(),
+            format!("foreign key cycle: {}, data rows might not insert in any order", cycle.join(" → "))));
+    }
+    res
+}
+
+/// standard PostgreSQL types as normalize_pg_type names them, without the type modifiers
+pub const PG_TYPES: [&str; 53] = ["smallint", "integer", "bigint", "smallserial", "serial", "bigserial", "real",
+    "double precision", "numeric", "money", "boolean", "text", "varchar", "char", "name", "bytea", "date", "time",
+    "time with time zone", "timestamp", "timestamp with time zone", "interval", "uuid", "json", "jsonb", "xml",
+    "inet", "cidr", "macaddr", "macaddr8", "bit", "varbit", "point", "line", "lseg", "box", "path", "polygon",
+    "circle", "tsvector", "tsquery", "int4range", "int8range", "numrange", "tsrange", "tstzrange", "daterange",
+    "oid", "regclass", "regtype", "pg_lsn", "txid_snapshot", "pg_snapshot"];
+
+/// the standard types and the types of yaml, both as schema.name and name
+pub(crate) fn known_types(schemas: &OrderedHashMap<Schema>) -> HashSet<String> {
+    let mut known: HashSet<String> = PG_TYPES.iter().map(|t| t.to_string()).collect();
+    for s in &schemas.list {
+        for t in &s.types.list {
+            known.insert(normalize_pg_type(&format!("{}.{}", t.schema, t.name)));
+            known.insert(normalize_pg_type(&t.name));
+        }
+    }
+    known
+}
+
+/// column types neither known nor an interval with fields, the type modifiers and array brackets not compared
+pub(crate) fn validate_column_types(schemas: &OrderedHashMap<Schema>, known_types: &HashSet<String>) -> Vec<SchemaWarning> {
+    let mut res = Vec::new();
+    for s in &schemas.list {
+        for t in &s.tables.list {
+            for c in &t.columns.list {
+                let mut base = normalize_pg_type(&c.column_type);
+                while let Some(element) = base.strip_suffix("[]") {
+                    base = element.to_string();
+                }
+                if let Some(i) = base.find('(') {
+                    base.truncate(i);
+                }
+                if !known_types.contains(&base) && !base.starts_with("interval ") {
+                    res.push(SchemaWarning::warning(WarningKind::UnknownColumnType,
+                        format!("{}.{}.{}", s.schema_name, t.table_name, c.name),
+                        format!("type {} is neither a standard PostgreSQL type nor defined in yaml", c.column_type)));
+                }
+            }
+        }
+    }
+    res
+}
+
+/// the referenced table of yaml; a table missed in the schema of another file is a warning,
+/// as the table might be in a file not loaded, i.e. filtered out by file_pattern, but an error in the same file
+fn fk_target<'a>(schemas: &'a OrderedHashMap<Schema>, s: &Schema, column: &str, fk_schema: &String, fk_table: &String) -> Result<&'a Table, SchemaWarning> {
+    match schemas.get(fk_schema) {
+        None => Err(SchemaWarning::warning(WarningKind::UndefinedFkSchema, column.to_string(),
+            format!("foreign key references {}.{} of the schema not defined in yaml", fk_schema, fk_table))),
+        Some(ts) => match ts.tables.get(fk_table) {
+            Some(target) => Ok(target),
+            None if ts.file == s.file => Err(SchemaWarning::error(WarningKind::UndefinedFkTable, column.to_string(),
+                format!("foreign key references {}.{} not defined in yaml", fk_schema, fk_table))),
+            None => Err(SchemaWarning::warning(WarningKind::UnresolvedFkTarget, column.to_string(),
+                format!("foreign key references {}.{} not defined in file: {}, expected in another file", fk_schema, fk_table, ts.file))),
+        }
+    }
+}
+
+/// foreign keys of a table not found in yaml
+pub(crate) fn validate_fk_targets(schemas: &OrderedHashMap<Schema>) -> Vec<SchemaWarning> {
+    let mut res = Vec::new();
+    for s in &schemas.list {
+        for t in &s.tables.list {
+            for c in &t.columns.list {
+                if let Some(fk) = c.constraint.as_ref().and_then(|c| c.foreign_key.as_ref()) {
+                    let (fk_schema, fk_table) = fk.target(&s.schema_name);
+                    let column = format!("{}.{}.{}", s.schema_name, t.table_name, c.name);
+                    if let Err(w) = fk_target(schemas, s, &column, &fk_schema, &fk_table) {
+                        res.push(w);
+                    }
+                }
+            }
+        }
+    }
+    res
+}
+
+/// schema.table -> referenced schema.table defined in yaml, without self references
+fn fk_graph(schemas: &OrderedHashMap<Schema>) -> BTreeMap<String, Vec<String>> {
+    let mut graph: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for s in &schemas.list {
+        for t in &s.tables.list {
+            let location = format!("{}.{}", s.schema_name, t.table_name);
+            for fk in t.columns.list.iter().filter_map(|c| c.constraint.as_ref().and_then(|c| c.foreign_key.as_ref())) {
+                let (fk_schema, fk_table) = fk.target(&s.schema_name);
+                let target = format!("{}.{}", fk_schema, fk_table);
+                if target != location && schemas.get(&fk_schema).map_or(false, |ts| ts.tables.get(&fk_table).is_some()) {
+                    graph.entry(location.clone()).or_default().push(target);
+                }
+            }
+        }
+    }
+    graph
+}
+
+/// the first foreign key cycle as the path from its first table back to it
+pub(crate) fn detect_fk_cycles(schemas: &OrderedHashMap<Schema>) -> Option<Vec<String>> {
+    fk_cycles(&fk_graph(schemas)).into_iter().next()
+}
+
+/// each cycle once, as the path from its first table back to it
+fn fk_cycles(graph: &BTreeMap<String, Vec<String>>) -> Vec<Vec<String>> {
+    fn visit<'a>(node: &'a String, graph: &'a BTreeMap<String, Vec<String>>, path: &mut Vec<&'a String>,
+                 done: &mut HashSet<&'a String>, cycles: &mut Vec<Vec<String>>) {
+        if let Some(i) = path.iter().position(|n| *n == node) {
+            let mut cycle: Vec<String> = path[i..].iter().map(|n| n.to_string()).collect();
+            cycle.push(node.clone());
+            cycles.push(cycle);
+            return;
+        }
+        if !done.insert(node) {
+            return;
+        }
+        path.push(node);
+        if let Some(targets) = graph.get(node) {
+            for t in targets {
+                visit(t, graph, path, done, cycles);
+            }
+        }
+        path.pop();
+    }
+    let mut cycles = Vec::new();
+    let mut done = HashSet::new();
+    for node in graph.keys() {
+        visit(node, graph, &mut Vec::new(), &mut done, &mut cycles);
+    }
+    cycles
+}
+
+#[cfg(all(test, feature = "db"))]
+mod tests {
+    use crate::{load_schema_from_file, load_schema_from_src, parse_yaml_schema, validate_schema};
+
+    #[test]
+    fn test_validate_schema() {
+        let r = parse_yaml_schema(load_schema_from_file("tests/example.yaml").unwrap(), "").unwrap();
+        assert!(validate_schema(&r).iter().all(|w| !w.is_error()));
+
+        let src = r#"
+database:
+  - schemaName: test_schema
+    tables:
+      - table:
+          tableName: orders
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+            - column:
+                name: customer_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: customer
+            - column:
+                name: item_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: item
+                index:
+                  include: [id, missed]
+            - column:
+                name: ext_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: other_schema.ext
+      - table:
+          tableName: customer
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+                  nullable: false
+            - column:
+                name: last_order_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: orders
+"#;
+        let err = parse_yaml_schema(load_schema_from_src(src.to_string()).unwrap(), "").unwrap_err();
+        assert_eq!(err.as_str(), "foreign key cycle: test_schema.customer -> test_schema.orders -> test_schema.customer");
+        // a cycle of the tables merged after parsing, as the files of migrate_from_dir
+        let (orders, customer) = src.split_at(src.find("      - table:\n          tableName: customer").unwrap());
+        let mut r = parse_yaml_schema(load_schema_from_src(orders.to_string()).unwrap(), "").unwrap();
+        let customer = format!("database:\n  - schemaName: test_schema\n    tables:\n{}", customer);
+        let other = parse_yaml_schema(load_schema_from_src(customer).unwrap(), "").unwrap();
+        r.get_mut(&"test_schema".to_string()).unwrap().merge(other.list.into_iter().next().unwrap()).unwrap();
+        let warnings: Vec<String> = validate_schema(&r).iter().map(|w| w.to_string()).collect();
+        assert_eq!(warnings, vec![
+            "Warning on test_schema.orders.id: primary key column is nullable in yaml, created as NOT NULL, set nullable: false",
+            "Error on test_schema.orders.item_id: foreign key references test_schema.item not defined in yaml",
+            "Error on test_schema.orders.item_id: index includes column missed not defined on the table",
+            "Warning on test_schema.orders.ext_id: foreign key references other_schema.ext of the schema not defined in yaml",
+            "Warning on test_schema.customer: foreign key cycle: test_schema.customer → test_schema.orders → test_schema.customer, data rows might not insert in any order",
+        ]);
+        assert_eq!(crate::detect_fk_cycles(&r), Some(vec!["test_schema.customer".to_string(), "test_schema.orders".to_string(), "test_schema.customer".to_string()]));
+        let r = parse_yaml_schema(load_schema_from_file("tests/example.yaml").unwrap(), "").unwrap();
+        assert_eq!(crate::detect_fk_cycles(&r), None);
+    }
+
+    #[test]
+    fn test_validate_column_types() {
+        use crate::validate::WarningKind;
+        let r = parse_yaml_schema(load_schema_from_file("tests/example.yaml").unwrap(), "").unwrap();
+        assert!(validate_schema(&r).iter().all(|w| w.kind != WarningKind::UnknownColumnType));
+
+        let yaml = load_schema_from_src(r#"
+database:
+  - schemaName: test_schema
+    types:
+      - type:
+          name: status
+          enum: [active, inactive]
+      - type:
+          name: positive_int
+          kind: domain
+          base_type: integer
+          not_null: true
+          check_expr: VALUE > 0
+    tables:
+      - table:
+          tableName: test_table
+          columns:
+            - column:
+                name: id
+                type: INTERGER
+            - column:
+                name: qty
+                type: positive_int
+            - column:
+                name: status
+                type: test_schema.status
+            - column:
+                name: tags
+                type: character varying(20)[]
+            - column:
+                name: created
+                type: timestamp(3) with time zone
+            - column:
+                name: spent
+                type: interval hour to minute
+            - column:
+                name: attrs
+                type: hstore
+"#.to_string()).unwrap();
+        let r = parse_yaml_schema(yaml, "").unwrap();
+        let domain = r.list[0].types.get(&"positive_int".to_string()).unwrap();
+        assert_eq!(domain.domain_base_type.as_ref().unwrap().as_str(), "integer");
+        assert!(domain.not_null);
+        let warnings: Vec<String> = validate_schema(&r).iter()
+            .filter(|w| w.kind == WarningKind::UnknownColumnType).map(|w| w.to_string()).collect();
+        assert_eq!(warnings, vec![
+            "Warning on test_schema.test_table.id: type INTERGER is neither a standard PostgreSQL type nor defined in yaml",
+            "Warning on test_schema.test_table.attrs: type hstore is neither a standard PostgreSQL type nor defined in yaml",
+        ]);
+        let mut known = crate::validate::known_types(&r);
+        known.insert("hstore".to_string());
+        assert_eq!(crate::validate_column_types(&r, &known).len(), 1);
+    }
+
+    #[test]
+    fn test_validate_fk_targets() {
+        use crate::validate::{Severity, WarningKind};
+        let orders = |references: &str| load_schema_from_src(format!(r#"
+database:
+  - schemaName: sales
+    tables:
+      - table:
+          tableName: orders
+          columns:
+            - column:
+                name: customer_id
+                type: integer
+                constraint:
+                  foreignKey:
+                    references: {}
+      - table:
+          tableName: item
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+                  nullable: false
+"#, references)).unwrap();
+        let customers = load_schema_from_src(r#"
+database:
+  - schemaName: crm
+    tables:
+      - table:
+          tableName: customer
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+                  nullable: false
+"#.to_string()).unwrap();
+
+        // intra-file
+        let r = parse_yaml_schema(orders("item"), "orders.yaml").unwrap();
+        assert!(crate::validate_fk_targets(&r).is_empty());
+        let w = crate::validate_fk_targets(&parse_yaml_schema(orders("missed"), "orders.yaml").unwrap());
+        assert_eq!(w.len(), 1);
+        assert_eq!((w[0].severity, w[0].kind), (Severity::Error, WarningKind::UndefinedFkTable));
+
+        // cross-file
+        let mut r = parse_yaml_schema(orders("crm.customer"), "orders.yaml").unwrap();
+        for s in parse_yaml_schema(customers.clone(), "crm.yaml").unwrap() {
+            r.append(s).unwrap();
+        }
+        assert!(crate::validate_fk_targets(&r).is_empty());
+        let mut r = parse_yaml_schema(orders("crm.client"), "orders.yaml").unwrap();
+        for s in parse_yaml_schema(customers, "crm.yaml").unwrap() {
+            r.append(s).unwrap();
+        }
+        let w = crate::validate_fk_targets(&r);
+        assert_eq!(w.len(), 1);
+        assert_eq!((w[0].severity, w[0].kind), (Severity::Warning, WarningKind::UnresolvedFkTarget));
+        assert_eq!(w[0].to_string().as_str(),
+                   "Warning on sales.orders.customer_id: foreign key references crm.client not defined in file: crm.yaml, expected in another file");
+        assert_eq!(validate_schema(&r).iter().filter(|v| v.kind == WarningKind::UnresolvedFkTarget).count(), 1);
+
+        // missing schema
+        let w = crate::validate_fk_targets(&parse_yaml_schema(orders("crm.customer"), "orders.yaml").unwrap());
+        assert_eq!((w[0].severity, w[0].kind), (Severity::Warning, WarningKind::UndefinedFkSchema));
+    }
+}
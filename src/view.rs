@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use yaml_rust::Yaml;
+
+#[cfg(feature = "db")]
+use crate::db::DbExec;
+use crate::utils::{Enveloped, Named};
+
+/// view or materialized view defined in yaml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YamlView {
+    pub name: String,
+    /// the view created in, the enclosing schemaName by default
+    pub schema: String,
+    /// SELECT body of the view
+    pub sql: String,
+    pub materialized: bool,
+    /// materialized view populated on create, otherwise WITH NO DATA
+    pub with_data: bool,
+    /// REFRESH MATERIALIZED VIEW on every deploy
+    pub refresh_on_deploy: bool,
+}
+
+/// view loaded from DB
+#[derive(Debug, Clone, Serialize)]
+pub struct PgView {
+    /// hash of the yaml sql the view created from, kept in the view comment as VIEW_HASH_PREFIX hash,
+    /// the stored definition is rewritten by PostgreSQL so not comparable
+    pub hash: String,
+    pub materialized: bool,
+}
+
+/// comment of a view created by the migration, followed by the sql hash
+pub const VIEW_HASH_PREFIX: &str = "schema_guard sql hash: ";
+
+/// schema.name: view
+pub type InfoViewType = HashMap<String, PgView>;
+
+impl Enveloped for YamlView {
+    const ENVELOPE: &'static str = "view";
+}
+
+impl Named for YamlView {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl YamlView {
+    pub(crate) fn new(input: &Yaml, schema: &str) -> Self {
+        YamlView {
+            name: crate::utils::safe_sql_name(crate::utils::as_str_esc(input, "name")),
+            schema: crate::utils::safe_sql_name(crate::utils::as_str(input, "schema", schema)),
+            sql: crate::utils::as_str(input, "sql", "").trim().trim_end_matches(';').to_string(),
+            materialized: crate::utils::as_bool(input, "materialized", false),
+            with_data: crate::utils::as_bool(input, "with_data", true),
+            refresh_on_deploy: crate::utils::as_bool(input, "refresh_on_deploy", false),
+        }
+    }
+
+    /// full name as schema.name
+    #[inline]
+    pub fn full_name(&self) -> String {
+        format!("{}.{}", self.schema, self.name)
+    }
+
+    /// hash of the sql, single spaced, to detect a change
+    pub fn hash(&self) -> String {
+        format!("{:016x}", crate::utils::fnv1a(normalize_view(&self.sql).as_str()))
+    }
+
+    /// statements to create, replace or refresh the view comparing to loaded from DB,
+    /// a materialized view with the changed sql dropped and created
+    pub fn deploy_sql(&self, existing: &InfoViewType) -> Result<Vec<String>, String> {
+        if self.sql.len() == 0 {
+            return Err(format!("no sql set on view: {}", self.full_name()));
+        }
+        let mut sql = Vec::new();
+        let full_name = self.full_name();
+        let pg = existing.get(&full_name);
+        if let Some(pg) = pg {
+            if pg.materialized != self.materialized {
+                sql.push(format!("DROP {}VIEW {}", if pg.materialized { "MATERIALIZED " } else { "" }, full_name));
+            }
+        }
+        let pg = pg.filter(|pg| pg.materialized == self.materialized);
+        let hash = self.hash();
+        let changed = pg.map_or(true, |pg| pg.hash != hash);
+        if self.materialized {
+            match pg {
+                Some(_) if !changed => if self.refresh_on_deploy {
+                    sql.push(format!("REFRESH MATERIALIZED VIEW {}", full_name));
+                },
+                _ => {
+                    if pg.is_some() {
+                        sql.push(format!("DROP MATERIALIZED VIEW {}", full_name));
+                    }
+                    sql.push(format!("CREATE MATERIALIZED VIEW IF NOT EXISTS {} AS {}{}",
+                                     full_name, self.sql, if self.with_data { "" } else { " WITH NO DATA" }));
+                }
+            }
+        } else if changed {
+            sql.push(format!("CREATE OR REPLACE VIEW {} AS {}", full_name, self.sql));
+        }
+        if changed {
+            sql.push(format!("COMMENT ON {}VIEW {} IS '{}{}'", if self.materialized { "MATERIALIZED " } else { "" },
+                             full_name, VIEW_HASH_PREFIX, hash));
+        }
+        Ok(sql)
+    }
+}
+
+/// definition to compare: single spaced, no trailing semicolon
+fn normalize_view(sql: &str) -> String {
+    sql.trim().trim_end_matches(';').split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(feature = "db")]
+pub fn load_info_views(db_name: &str, db: &mut dyn DbExec) -> Result<InfoViewType, String> {
+    let mut data = HashMap::new();
+    let result = db.query("SELECT n.nspname::text, c.relname::text, c.relkind = 'm', coalesce(obj_description(c.oid, 'pg_class'), '')
+ FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace
+ WHERE c.relkind in ('v', 'm') AND n.nspname not in ('pg_catalog', 'information_schema')", &[])
+        .map_err(|e| format!("on loading views [{}]: {}", db_name, e))?;
+    for r in result {
+        let view_schema: &str = r.get(0);
+        let view_name: &str = r.get(1);
+        let comment: &str = r.get(3);
+        data.insert(format!("{}.{}", view_schema, view_name), PgView {
+            hash: comment.strip_prefix(VIEW_HASH_PREFIX).unwrap_or("").to_string(),
+            materialized: r.get(2),
+        });
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::view::{PgView, YamlView};
+
+    #[test]
+    fn view_test() {
+        let mut v = YamlView {
+            name: "active_users".to_string(),
+            schema: "test_schema".to_string(),
+            sql: "SELECT id FROM test_schema.users\n WHERE active".to_string(),
+            materialized: false,
+            with_data: true,
+            refresh_on_deploy: true,
+        };
+        let mut existing = HashMap::new();
+        let comment = format!("COMMENT ON VIEW test_schema.active_users IS 'schema_guard sql hash: {}'", v.hash());
+        assert_eq!(v.deploy_sql(&existing).unwrap(),
+                   vec!["CREATE OR REPLACE VIEW test_schema.active_users AS SELECT id FROM test_schema.users\n WHERE active".to_string(), comment]);
+        // as rewritten by PostgreSQL, the hash kept in the comment compared
+        existing.insert("test_schema.active_users".to_string(), PgView { hash: v.hash(), materialized: false });
+        v.sql = "SELECT id  FROM test_schema.users WHERE active".to_string();
+        assert!(v.deploy_sql(&existing).unwrap().is_empty());
+        // created elsewhere, without the comment
+        existing.get_mut("test_schema.active_users").unwrap().hash = "".to_string();
+        assert_eq!(v.deploy_sql(&existing).unwrap().len(), 2);
+        existing.get_mut("test_schema.active_users").unwrap().hash = v.hash();
+
+        v.materialized = true;
+        v.with_data = false;
+        let sql = v.deploy_sql(&existing).unwrap();
+        assert_eq!(sql.len(), 3);
+        assert_eq!(sql[0].as_str(), "DROP VIEW test_schema.active_users");
+        assert!(sql[1].starts_with("CREATE MATERIALIZED VIEW IF NOT EXISTS test_schema.active_users AS SELECT"));
+        assert!(sql[1].ends_with(" WITH NO DATA"));
+        assert!(sql[2].starts_with("COMMENT ON MATERIALIZED VIEW test_schema.active_users IS 'schema_guard sql hash: "));
+
+        existing.get_mut("test_schema.active_users").unwrap().materialized = true;
+        assert_eq!(v.deploy_sql(&existing).unwrap(), vec!["REFRESH MATERIALIZED VIEW test_schema.active_users".to_string()]);
+
+        v.sql = "SELECT id FROM test_schema.users".to_string();
+        let sql = v.deploy_sql(&existing).unwrap();
+        assert_eq!(sql[0].as_str(), "DROP MATERIALIZED VIEW test_schema.active_users");
+        assert_eq!(sql[1].as_str(), "CREATE MATERIALIZED VIEW IF NOT EXISTS test_schema.active_users AS SELECT id FROM test_schema.users WITH NO DATA");
+        assert_eq!(sql.len(), 3);
+    }
+}
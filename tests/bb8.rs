@@ -0,0 +1,66 @@
+#![cfg(feature = "bb8")]
+
+use bb8_postgres::PostgresConnectionManager;
+use schema_guard::options::MigrationOptions;
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use tokio_postgres::NoTls;
+use yaml_rust::YamlLoader;
+
+const SCHEMA: &str = r#"
+database:
+  - schemaName: pool_schema
+    tables:
+      - table:
+          tableName: pool_table
+          columns:
+            - column:
+                name: id
+                type: integer
+                constraint:
+                  primaryKey: true
+            - column:
+                name: name
+                type: varchar(50)
+          data:
+            - id: "1"
+              name: one
+"#;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn migrate_async_round_trip() {
+    let node = Postgres::default().start().await.unwrap();
+    let port = node.get_host_port_ipv4(5432).await.unwrap();
+    let manager = PostgresConnectionManager::new_from_stringlike(
+        format!("host=127.0.0.1 port={} user=postgres password=postgres dbname=postgres", port), NoTls).unwrap();
+    let pool = bb8::Pool::builder().max_size(2).build(manager).await.unwrap();
+
+    let yaml = YamlLoader::load_from_str(SCHEMA).unwrap().remove(0);
+    let report = schema_guard::migrate_async(yaml.clone(), &pool, MigrationOptions::default(), "").await.unwrap();
+    assert_eq!(report.tables_created, vec!["pool_schema.pool_table".to_string()]);
+    assert_eq!(report.rows_inserted, 1);
+
+    let report = schema_guard::migrate_async(yaml, &pool, MigrationOptions::default(), "").await.unwrap();
+    assert!(report.tables_created.is_empty());
+    assert!(report.tables_altered.is_empty());
+
+    let dbc = pool.get().await.unwrap();
+    let name: String = dbc.query_one("SELECT name FROM pool_schema.pool_table WHERE id = 1", &[]).await.unwrap().get(0);
+    assert_eq!(name, "one");
+}
+
+#[tokio::test]
+async fn migrate_async_current_thread() {
+    let node = Postgres::default().start().await.unwrap();
+    let port = node.get_host_port_ipv4(5432).await.unwrap();
+    let manager = PostgresConnectionManager::new_from_stringlike(
+        format!("host=127.0.0.1 port={} user=postgres password=postgres dbname=postgres", port), NoTls).unwrap();
+    let pool = bb8::Pool::builder().max_size(1).build(manager).await.unwrap();
+
+    let yaml = YamlLoader::load_from_str(SCHEMA).unwrap().remove(0);
+    let statements = std::cell::RefCell::new(Vec::new());
+    let store = |sql: Vec<String>| { statements.borrow_mut().extend(sql); Ok(()) };
+    let report = schema_guard::migrate_async(yaml, &pool, MigrationOptions::default().dry_run(&store), "").await.unwrap();
+    assert!(report.tables_created.is_empty());
+    assert!(statements.into_inner().iter().any(|sql| sql.contains("pool_schema.pool_table")));
+}